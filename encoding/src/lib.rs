@@ -1,5 +1,6 @@
 use std::{error::Error, fmt::Display};
 
+pub mod message;
 pub mod wire_types;
 
 #[derive(PartialEq, Debug)]
@@ -0,0 +1,372 @@
+use crate::wire_types::{
+    decode_tag, encode_tag, varint::zigzag_decode, Decode, Encode, Tag, WireTypeEnum,
+};
+use crate::{ByteIterator, ProtoscopeRsError, Result};
+
+/// Append the encoding of `value` to the end of `buffer`, growing it as needed
+pub fn encode_to_vec<'a, T: Encode<'a>>(value: &'a T, buffer: &mut Vec<u8>) -> Result<usize> {
+    let start = buffer.len();
+    let mut headroom = 16;
+    loop {
+        buffer.resize(start + headroom, 0);
+        match value.encode(&mut buffer[start..].iter_mut()) {
+            Ok(number_of_bytes_encoded) => {
+                buffer.truncate(start + number_of_bytes_encoded);
+                return Ok(number_of_bytes_encoded);
+            }
+            Err(ProtoscopeRsError::BufferFull) => headroom *= 2,
+            Err(err) => {
+                buffer.truncate(start);
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Builds the wire format representation of a message one field at a time
+#[derive(Default, Debug, Clone)]
+pub struct MessageWriter {
+    buffer: Vec<u8>,
+}
+
+impl MessageWriter {
+    pub fn new() -> Self {
+        MessageWriter { buffer: Vec::new() }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn write_tag(&mut self, field_number: u64, wire_type: WireTypeEnum) -> Result<()> {
+        let mut scratch = [0u8; 10];
+        let number_of_bytes_encoded = encode_tag(
+            &Tag {
+                field_number,
+                wire_type,
+            },
+            &mut scratch.iter_mut(),
+        )?;
+        self.buffer
+            .extend_from_slice(&scratch[..number_of_bytes_encoded]);
+        Ok(())
+    }
+
+    /// Append a varint without a tag, e.g. an element of a packed field
+    pub fn push_varint(&mut self, value: u64) -> Result<()> {
+        encode_to_vec(&value, &mut self.buffer)?;
+        Ok(())
+    }
+
+    /// Append a zigzag encoded varint without a tag
+    pub fn push_sint64(&mut self, value: i64) -> Result<()> {
+        encode_to_vec(&value, &mut self.buffer)?;
+        Ok(())
+    }
+
+    /// Append the raw bits of a 4 byte value without a tag
+    pub fn push_fixed32(&mut self, value: u32) -> Result<()> {
+        encode_to_vec(&f32::from_bits(value), &mut self.buffer)?;
+        Ok(())
+    }
+
+    /// Append the raw bits of an 8 byte value without a tag
+    pub fn push_fixed64(&mut self, value: u64) -> Result<()> {
+        encode_to_vec(&f64::from_bits(value), &mut self.buffer)?;
+        Ok(())
+    }
+
+    /// Append a length prefix followed by `value`, without a tag
+    pub fn push_length_delimited(&mut self, value: &[u8]) -> Result<()> {
+        if value.len() > i32::MAX as usize {
+            return Err(ProtoscopeRsError::EncodeOverflow);
+        }
+        self.push_varint(value.len() as u64)?;
+        self.buffer.extend_from_slice(value);
+        Ok(())
+    }
+
+    /// uint32, uint64 and enum fields
+    pub fn write_uint64(&mut self, field_number: u64, value: u64) -> Result<()> {
+        self.write_tag(field_number, WireTypeEnum::Varint)?;
+        self.push_varint(value)
+    }
+
+    /// int32 and int64 fields, negative values are sign extended to 10 bytes
+    pub fn write_int64(&mut self, field_number: u64, value: i64) -> Result<()> {
+        self.write_uint64(field_number, value as u64)
+    }
+
+    /// sint32 and sint64 fields, which use the zigzag encoding
+    pub fn write_sint64(&mut self, field_number: u64, value: i64) -> Result<()> {
+        self.write_tag(field_number, WireTypeEnum::Varint)?;
+        self.push_sint64(value)
+    }
+
+    pub fn write_bool(&mut self, field_number: u64, value: bool) -> Result<()> {
+        self.write_uint64(field_number, value as u64)
+    }
+
+    /// fixed32, sfixed32 and float fields, passed as their raw bits
+    pub fn write_fixed32(&mut self, field_number: u64, value: u32) -> Result<()> {
+        self.write_tag(field_number, WireTypeEnum::I32)?;
+        self.push_fixed32(value)
+    }
+
+    /// fixed64, sfixed64 and double fields, passed as their raw bits
+    pub fn write_fixed64(&mut self, field_number: u64, value: u64) -> Result<()> {
+        self.write_tag(field_number, WireTypeEnum::I64)?;
+        self.push_fixed64(value)
+    }
+
+    pub fn write_string(&mut self, field_number: u64, value: &str) -> Result<()> {
+        self.write_bytes(field_number, value.as_bytes())
+    }
+
+    /// bytes fields and embedded messages
+    pub fn write_bytes(&mut self, field_number: u64, value: &[u8]) -> Result<()> {
+        if value.len() > i32::MAX as usize {
+            return Err(ProtoscopeRsError::EncodeOverflow);
+        }
+        self.write_tag(field_number, WireTypeEnum::Len)?;
+        self.push_length_delimited(value)
+    }
+
+    pub fn write_message(&mut self, field_number: u64, message: &MessageWriter) -> Result<()> {
+        self.write_bytes(field_number, message.as_bytes())
+    }
+
+    /// Packed repeated varint fields
+    pub fn write_packed_uint64(&mut self, field_number: u64, values: &[u64]) -> Result<()> {
+        let mut payload = MessageWriter::new();
+        for value in values {
+            payload.push_varint(*value)?;
+        }
+        self.write_message(field_number, &payload)
+    }
+}
+
+/// The payload of a single field, as read off the wire
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FieldValue<'a> {
+    Varint(u64),
+    /// Raw bits of a fixed64, sfixed64 or double field
+    I64(u64),
+    Len(&'a [u8]),
+    /// Raw bits of a fixed32, sfixed32 or float field
+    I32(u32),
+}
+
+impl<'a> FieldValue<'a> {
+    /// uint32, uint64 and enum fields
+    pub fn as_u64(&self) -> Result<u64> {
+        match self {
+            FieldValue::Varint(value) => Ok(*value),
+            _ => Err(ProtoscopeRsError::InvalidWireType),
+        }
+    }
+
+    /// int32 and int64 fields
+    pub fn as_i64(&self) -> Result<i64> {
+        self.as_u64().map(|value| value as i64)
+    }
+
+    /// sint32 and sint64 fields
+    pub fn as_sint64(&self) -> Result<i64> {
+        self.as_u64().map(zigzag_decode)
+    }
+
+    pub fn as_bool(&self) -> Result<bool> {
+        match self.as_u64()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ProtoscopeRsError::DecodeOverflow),
+        }
+    }
+
+    /// bytes fields and embedded messages
+    pub fn as_bytes(&self) -> Result<&'a [u8]> {
+        match self {
+            FieldValue::Len(bytes) => Ok(bytes),
+            _ => Err(ProtoscopeRsError::InvalidWireType),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&'a str> {
+        std::str::from_utf8(self.as_bytes()?).map_err(|_| ProtoscopeRsError::UtfDecoding)
+    }
+
+    /// fixed32, sfixed32 and float fields
+    pub fn as_fixed32(&self) -> Result<u32> {
+        match self {
+            FieldValue::I32(value) => Ok(*value),
+            _ => Err(ProtoscopeRsError::InvalidWireType),
+        }
+    }
+
+    /// fixed64, sfixed64 and double fields
+    pub fn as_fixed64(&self) -> Result<u64> {
+        match self {
+            FieldValue::I64(value) => Ok(*value),
+            _ => Err(ProtoscopeRsError::InvalidWireType),
+        }
+    }
+
+    /// Repeated varint fields, which may be either packed or written one element at a time
+    pub fn as_packed_u64(&self) -> Result<Vec<u64>> {
+        match self {
+            FieldValue::Varint(value) => Ok(vec![*value]),
+            FieldValue::Len(bytes) => {
+                let mut iter = bytes.iter();
+                let mut values = Vec::new();
+                while iter.len() > 0 {
+                    values.push(u64::decode(&mut iter)?);
+                }
+                Ok(values)
+            }
+            _ => Err(ProtoscopeRsError::InvalidWireType),
+        }
+    }
+}
+
+/// Reads the fields of an encoded message in the order they appear on the wire
+#[derive(Debug, Clone)]
+pub struct MessageReader<'a> {
+    iter: ByteIterator<'a>,
+}
+
+impl<'a> MessageReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        MessageReader { iter: bytes.iter() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter.len() == 0
+    }
+
+    /// Returns the field number and value of the next field, or None at the end of the message
+    pub fn read_field(&mut self) -> Result<Option<(u64, FieldValue<'a>)>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let tag = decode_tag(&mut self.iter)?;
+        let value = self.read_value(tag.wire_type)?;
+        Ok(Some((tag.field_number, value)))
+    }
+
+    /// Read a value without a preceding tag, e.g. an element of a packed field
+    pub fn read_value(&mut self, wire_type: WireTypeEnum) -> Result<FieldValue<'a>> {
+        let value = match wire_type {
+            WireTypeEnum::Varint => FieldValue::Varint(u64::decode(&mut self.iter)?),
+            WireTypeEnum::I64 => FieldValue::I64(f64::decode(&mut self.iter)?.to_bits()),
+            WireTypeEnum::I32 => FieldValue::I32(f32::decode(&mut self.iter)?.to_bits()),
+            WireTypeEnum::Len => {
+                let length = u64::decode(&mut self.iter)?;
+                let remaining = self.iter.as_slice();
+                if length > remaining.len() as u64 {
+                    return Err(ProtoscopeRsError::Eof);
+                }
+                let (payload, rest) = remaining.split_at(length as usize);
+                self.iter = rest.iter();
+                FieldValue::Len(payload)
+            }
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_writer() {
+        // Examples from https://protobuf.dev/programming-guides/encoding/
+        let mut writer = MessageWriter::new();
+        writer.write_uint64(1, 150).unwrap();
+        assert_eq!(writer.as_bytes(), &[0x08, 0x96, 0x01]);
+
+        let mut writer = MessageWriter::new();
+        writer.write_string(2, "testing").unwrap();
+        assert_eq!(
+            writer.as_bytes(),
+            &[0x12, 0x07, 0x74, 0x65, 0x73, 0x74, 0x69, 0x6e, 0x67]
+        );
+
+        let mut writer = MessageWriter::new();
+        writer.write_int64(1, -2).unwrap();
+        assert_eq!(writer.len(), 11);
+        writer = MessageWriter::new();
+        writer.write_sint64(1, -2).unwrap();
+        assert_eq!(writer.as_bytes(), &[0x08, 0x03]);
+
+        let mut writer = MessageWriter::new();
+        writer.write_packed_uint64(4, &[3, 270, 86942]).unwrap();
+        assert_eq!(
+            writer.as_bytes(),
+            &[0x22, 0x06, 0x03, 0x8e, 0x02, 0x9e, 0xa7, 0x05]
+        );
+    }
+
+    #[test]
+    fn test_encode_to_vec_grows_buffer() {
+        let large_string = "a".repeat(1000);
+        let mut buffer = vec![1u8];
+        assert!(encode_to_vec(&large_string, &mut buffer).is_ok_and(|n| n == 1002));
+        assert_eq!(buffer.len(), 1003);
+    }
+
+    #[test]
+    fn test_message_reader() {
+        let mut writer = MessageWriter::new();
+        writer.write_uint64(1, 150).unwrap();
+        writer.write_string(2, "testing").unwrap();
+        writer.write_fixed32(3, 1.5f32.to_bits()).unwrap();
+        writer.write_fixed64(4, 42).unwrap();
+        writer.write_packed_uint64(5, &[3, 270, 86942]).unwrap();
+        writer.write_int64(6, -2).unwrap();
+        writer.write_sint64(7, -2).unwrap();
+
+        let mut reader = MessageReader::new(writer.as_bytes());
+        assert_eq!(reader.read_field(), Ok(Some((1, FieldValue::Varint(150)))));
+        let (field_number, value) = reader.read_field().unwrap().unwrap();
+        assert_eq!(field_number, 2);
+        assert_eq!(value.as_str(), Ok("testing"));
+        assert_eq!(
+            reader.read_field(),
+            Ok(Some((3, FieldValue::I32(1.5f32.to_bits()))))
+        );
+        assert_eq!(reader.read_field(), Ok(Some((4, FieldValue::I64(42)))));
+        let (_, value) = reader.read_field().unwrap().unwrap();
+        assert_eq!(value.as_packed_u64(), Ok(vec![3, 270, 86942]));
+        let (_, value) = reader.read_field().unwrap().unwrap();
+        assert_eq!(value.as_i64(), Ok(-2));
+        let (_, value) = reader.read_field().unwrap().unwrap();
+        assert_eq!(value.as_sint64(), Ok(-2));
+        assert_eq!(reader.read_field(), Ok(None));
+    }
+
+    #[test]
+    fn test_message_reader_truncated() {
+        let mut reader = MessageReader::new(&[0x12, 0x07, 0x74, 0x65]);
+        assert_eq!(reader.read_field(), Err(ProtoscopeRsError::Eof));
+
+        let mut reader = MessageReader::new(&[0x08]);
+        assert_eq!(reader.read_field(), Err(ProtoscopeRsError::Eof));
+
+        let mut reader = MessageReader::new(&[0x0b]);
+        assert_eq!(reader.read_field(), Err(ProtoscopeRsError::InvalidWireType));
+    }
+}
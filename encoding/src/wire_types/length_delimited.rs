@@ -7,7 +7,7 @@ fn encode_internal<'a, T: EncodeLengthDelimited<'a>>(
 ) -> Result<usize> {
     let mut total_number_of_bytes_encoded = 0;
     let length = value.get_length()?;
-    total_number_of_bytes_encoded += length.encode(iter)?;
+    total_number_of_bytes_encoded += (length as u64).encode(iter)?; // Lengths are plain varints, not zigzag encoded
     let mut payload_iterator = value.get_payload_iterator();
     for _ in 0..length {
         let payload_byte = match payload_iterator.next() {
@@ -30,7 +30,10 @@ trait EncodeLengthDelimited<'a>: Encode<'a> {
 }
 
 fn decode_internal<T: DecodeLengthDelimited>(iter: &mut ByteIterator) -> Result<T> {
-    let length = i32::decode(iter)?;
+    let length = u64::decode(iter)?;
+    if length > i32::MAX as u64 {
+        return Err(ProtoscopeRsError::DecodeOverflow);
+    }
     let output_buffer: Vec<u8> = iter.copied().take(length as usize).collect();
     if output_buffer.len() != length as usize {
         return Err(ProtoscopeRsError::LengthMismatch);
@@ -122,7 +125,7 @@ mod tests {
         assert!(String::from("Hello_world")
             .encode(&mut buffer.iter_mut())
             .is_ok_and(|num_bytes_encoded| {
-                String::decode(&mut buffer[0..num_bytes_encoded].into_iter())
+                String::decode(&mut buffer[0..num_bytes_encoded].iter())
                     .is_ok_and(|output_string| output_string == "Hello_world")
             }));
     }
@@ -134,7 +137,7 @@ mod tests {
         assert!(large_string
             .encode(&mut buffer.iter_mut())
             .is_ok_and(|num_bytes_encoded| {
-                String::decode(&mut buffer[0..num_bytes_encoded].into_iter())
+                String::decode(&mut buffer[0..num_bytes_encoded].iter())
                     .is_ok_and(|output_string| output_string == large_string)
             }));
     }
@@ -154,7 +157,7 @@ mod tests {
         assert!(message_buffer
             .encode(&mut buffer.iter_mut())
             .is_ok_and(|num_bytes_encoded| {
-                Vec::<u8>::decode(&mut buffer[0..num_bytes_encoded].into_iter())
+                Vec::<u8>::decode(&mut buffer[0..num_bytes_encoded].iter())
                     .is_ok_and(|decoded_buffer| decoded_buffer.into_iter().all(|byte| byte == 2))
             }));
     }
@@ -166,7 +169,7 @@ mod tests {
         assert!(message_buffer
             .encode(&mut buffer.iter_mut())
             .is_ok_and(|num_bytes_encoded| {
-                Vec::<u8>::decode(&mut buffer[0..num_bytes_encoded].into_iter())
+                Vec::<u8>::decode(&mut buffer[0..num_bytes_encoded].iter())
                     .is_ok_and(|decoded_buffer| decoded_buffer.into_iter().all(|byte| byte == 2))
             }));
     }
@@ -64,7 +64,7 @@ pub trait Decode: Sized + private::Sealed {
 }
 
 pub fn encode_tag(tag: &Tag, iter: &mut OutputByteIterator) -> Result<usize> {
-    let tag_repr: u64 = (tag.field_number << 3) & u64::from(tag.wire_type.clone());
+    let tag_repr: u64 = (tag.field_number << 3) | u64::from(tag.wire_type.clone());
     tag_repr.encode(iter)
 }
 
@@ -92,4 +92,18 @@ mod tests {
         let payload = u64::decode(&mut iter);
         assert!(payload.is_ok_and(|payload| payload == 150));
     }
+
+    #[test]
+    fn test_encode_tag() {
+        let mut buffer: Vec<u8> = vec![0; 10];
+        let tag = Tag {
+            field_number: 2,
+            wire_type: WireTypeEnum::Len,
+        };
+        assert!(encode_tag(&tag, &mut buffer.iter_mut()).is_ok_and(|num_bytes| num_bytes == 1));
+        assert_eq!(buffer[0], 0x12);
+        let decoded_tag = decode_tag(&mut buffer.iter()).unwrap();
+        assert_eq!(decoded_tag.field_number, 2);
+        assert_eq!(decoded_tag.wire_type, WireTypeEnum::Len);
+    }
 }
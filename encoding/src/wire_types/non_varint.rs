@@ -56,7 +56,7 @@ trait DecodeFixed<const N: usize> {
         for output_byte in &mut raw_bytes {
             *output_byte = match iter.next() {
                 None => return Err(ProtoscopeRsError::Eof),
-                Some(input_byte) => (*input_byte).clone(),
+                Some(input_byte) => *input_byte,
             };
         }
         Ok(Self::decode_from_bytes(raw_bytes))
@@ -64,15 +64,11 @@ trait DecodeFixed<const N: usize> {
     fn decode_from_bytes(raw_bytes: [u8; N]) -> Self;
 }
 
-trait DecodeI64: DecodeFixed<8> {}
-trait DecodeI32: DecodeFixed<4> {}
-
 impl DecodeFixed<8> for f64 {
     fn decode_from_bytes(raw_bytes: [u8; 8]) -> Self {
         f64::from_le_bytes(raw_bytes)
     }
 }
-impl DecodeI64 for f64 {}
 
 impl Decode for f64 {
     fn decode(iter: &mut ByteIterator) -> Result<Self> {
@@ -85,7 +81,6 @@ impl DecodeFixed<4> for f32 {
         f32::from_le_bytes(raw_bytes)
     }
 }
-impl DecodeI32 for f32 {}
 
 impl Decode for f32 {
     fn decode(iter: &mut ByteIterator) -> Result<Self> {
@@ -103,7 +98,7 @@ mod tests {
         assert!(1.0f32
             .encode(&mut buffer.iter_mut())
             .is_ok_and(|num_bytes_encoded| {
-                f32::decode(&mut buffer[0..num_bytes_encoded].into_iter()).is_ok_and(|f32_value| {
+                f32::decode(&mut buffer[0..num_bytes_encoded].iter()).is_ok_and(|f32_value| {
                     f32_value
                         .to_le_bytes()
                         .into_iter()
@@ -115,7 +110,7 @@ mod tests {
         assert!(f32::MIN
             .encode(&mut buffer.iter_mut())
             .is_ok_and(|num_bytes_encoded| {
-                f32::decode(&mut buffer[0..num_bytes_encoded].into_iter()).is_ok_and(|f32_value| {
+                f32::decode(&mut buffer[0..num_bytes_encoded].iter()).is_ok_and(|f32_value| {
                     f32_value
                         .to_le_bytes()
                         .into_iter()
@@ -127,7 +122,7 @@ mod tests {
         assert!(f64::MIN
             .encode(&mut buffer.iter_mut())
             .is_ok_and(|num_bytes_encoded| {
-                f64::decode(&mut buffer[0..num_bytes_encoded].into_iter()).is_ok_and(|f64_value| {
+                f64::decode(&mut buffer[0..num_bytes_encoded].iter()).is_ok_and(|f64_value| {
                     f64_value
                         .to_le_bytes()
                         .into_iter()
@@ -139,7 +134,7 @@ mod tests {
         assert!(f64::MAX
             .encode(&mut buffer.iter_mut())
             .is_ok_and(|num_bytes_encoded| {
-                f64::decode(&mut buffer[0..num_bytes_encoded].into_iter()).is_ok_and(|f64_value| {
+                f64::decode(&mut buffer[0..num_bytes_encoded].iter()).is_ok_and(|f64_value| {
                     f64_value
                         .to_le_bytes()
                         .into_iter()
@@ -2,7 +2,7 @@ use crate::wire_types::{Decode, Encode};
 use crate::{ByteIterator, OutputByteIterator, ProtoscopeRsError, Result};
 use num_traits::NumCast;
 
-const MAX_NUMBER_OF_BYTES: usize = ((std::mem::size_of::<u64>() * 8) + 7 - 1) / 7;
+const MAX_NUMBER_OF_BYTES: usize = (std::mem::size_of::<u64>() * 8).div_ceil(7);
 
 macro_rules! expand_encode_trait_of_unsigned_types {
     ( $( $type:ty ),* ) => {
@@ -20,11 +20,7 @@ expand_encode_trait_of_unsigned_types![u32, u64];
 
 impl<'a> Encode<'a> for bool {
     fn encode(&self, iter: &mut OutputByteIterator) -> Result<usize> {
-        if *self {
-            1i32.encode(iter)
-        } else {
-            0i32.encode(iter)
-        }
+        encode_varint_impl(*self as u64, iter)
     }
 }
 
@@ -44,32 +40,29 @@ fn encode_varint_impl(value: u64, iter: &mut OutputByteIterator) -> Result<usize
             return Ok(bytes_encoded);
         }
         *output_byte = ((value_copy & 0x7f) as u8) | 0x80; // Extract payload and append to output byte and also set the continue bit
-        value_copy = value_copy >> 7;
+        value_copy >>= 7;
     }
     Ok(bytes_encoded)
 }
 
 #[unroll::unroll_for_loops]
 fn decode_varint_impl(iter: &mut ByteIterator) -> Result<u64> {
-    match iter.clone().peekable().peek() {
-        None => return Err(ProtoscopeRsError::Eof),
-        _ => {}
+    if iter.clone().peekable().peek().is_none() {
+        return Err(ProtoscopeRsError::Eof);
     }
     let mut decoded_value: u64 = 0;
     for byte_idx in 0..MAX_NUMBER_OF_BYTES {
         match &iter.next() {
             Some(byte) => {
                 let payload = 0x7f & *byte;
-                decoded_value = (decoded_value) | ((payload as u64) << (7 * byte_idx));
+                decoded_value |= (payload as u64) << (7 * byte_idx);
                 if 0x80 & *byte == 0 {
                     break;
                 } else if byte_idx == (MAX_NUMBER_OF_BYTES - 1) {
                     return Err(ProtoscopeRsError::VarintOverflow);
                 }
             }
-            None => {
-                break;
-            }
+            None => return Err(ProtoscopeRsError::Eof), // Truncated in the middle of the varint
         }
     }
     Ok(decoded_value)
@@ -100,24 +93,22 @@ impl Decode for bool {
     where
         Self: Sized,
     {
-        let i32_value = i32::decode(iter)?;
-        if i32_value == 0 {
-            return Ok(false);
-        } else if i32_value == 1 {
-            return Ok(true);
-        } else {
-            return Err(ProtoscopeRsError::DecodeOverflow);
+        let u64_value = decode_varint_impl(iter)?;
+        match u64_value {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ProtoscopeRsError::DecodeOverflow),
         }
     }
 }
 
-fn zigzag_encode(input: i64) -> u64 {
-    return ((input >> (64 - 1)) as u64 /*Arithmetic right shift here just propagates the sign-bit from the most significant bit to all the other bits */)
+pub(crate) fn zigzag_encode(input: i64) -> u64 {
+    ((input >> (64 - 1)) as u64 /*Arithmetic right shift here just propagates the sign-bit from the most significant bit to all the other bits */)
             ^  /* XOR */
-            ((input << 1) as u64)/*Regular logical bitwise left-shit operation*/;
+            ((input << 1) as u64) /*Regular logical bitwise left-shit operation*/
 }
 
-fn zigzag_decode(input: u64) -> i64 {
+pub(crate) fn zigzag_decode(input: u64) -> i64 {
     (input >> 1) as i64
     ^ /* XOR */
     -((input & 1) as i64) /*Extract the sign bit from the least-significant bit and propagate it to the rest of the bits*/
@@ -172,6 +163,9 @@ mod tests {
 
         let value = decode_varint_impl(&mut [0xacu8, 0x02u8].iter());
         assert!(value.is_ok_and(|value| value == 300));
+
+        let value = decode_varint_impl(&mut [0xacu8].iter());
+        assert!(value.is_err_and(|err| err == ProtoscopeRsError::Eof));
     }
 
     #[test]
@@ -199,7 +193,7 @@ mod tests {
     #[test]
     fn test_encode_varint() {
         let mut buffer: Vec<u8> = vec![0; 10];
-        for value in (std::u64::MAX - 2000)..=std::u64::MAX {
+        for value in (u64::MAX - 2000)..=u64::MAX {
             assert!(encode_varint_impl(value, &mut buffer.iter_mut()).is_ok_and(
                 |num_bytes_encode| {
                     decode_varint_impl(&mut buffer[0..num_bytes_encode].iter())
@@ -319,14 +313,11 @@ mod tests {
                 bool::decode(&mut buffer[0..num_bytes_encoded].iter()).is_ok_and(|value| value)
             }));
 
-        assert!(
-            false
-                .encode(&mut buffer.iter_mut())
-                .is_ok_and(|num_bytes_encoded| {
-                    bool::decode(&mut buffer[0..num_bytes_encoded].iter()).is_ok_and(|value| value)
-                })
-                == false
-        );
+        assert!(false
+            .encode(&mut buffer.iter_mut())
+            .is_ok_and(|num_bytes_encoded| {
+                bool::decode(&mut buffer[0..num_bytes_encoded].iter()).is_ok_and(|value| !value)
+            }));
     }
 
     #[test]
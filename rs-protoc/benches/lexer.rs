@@ -0,0 +1,102 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rs_protoc::lexer::{Lexer, LosslessLexer};
+use rs_protoc::parser::Parser;
+use std::fmt::Write;
+use std::hint::black_box;
+
+// Large synthetic schemas, with the mix of declarations, comments and literals found in real
+// .proto files. Throughput is reported in bytes of source text.
+
+/// A schema with `messages` messages, each followed by an enum, and a service using them
+fn synthetic_schema(messages: usize) -> String {
+    let mut text = String::from(
+        "// Synthetic schema for benchmarks\nsyntax = \"proto3\";\n\npackage bench.synthetic.v1;\n\n",
+    );
+    text.push_str("import \"google/protobuf/timestamp.proto\";\n");
+    text.push_str("option java_package = \"com.example.bench\";\n\n");
+    for index in 0..messages {
+        writeln!(
+            text,
+            "/* Message number {index}\n * with a block comment */\nmessage Message{index} {{"
+        )
+        .unwrap();
+        writeln!(text, "  // The name, \"quoted\" and with escapes\\n").unwrap();
+        writeln!(
+            text,
+            "  string name = 1 [json_name = \"the_name_{index}\"];"
+        )
+        .unwrap();
+        writeln!(text, "  int64 id = 2;").unwrap();
+        writeln!(text, "  repeated double values = 3 [packed = true];").unwrap();
+        writeln!(text, "  map<string, Kind{index}> kinds = 4;").unwrap();
+        writeln!(
+            text,
+            "  optional bytes payload = 5 [default = \"\\x00\\377\\u00e9\"];"
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "  oneof choice {{\n    uint32 small = 6;\n    fixed64 large = 7;\n  }}"
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "  float ratio = 8 [deprecated = true]; // Trailing comment"
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "  reserved 100 to 199, 0x1000 to max;\n  reserved \"old_field\";\n}}\n"
+        )
+        .unwrap();
+        writeln!(text, "enum Kind{index} {{").unwrap();
+        writeln!(text, "  KIND{index}_UNSPECIFIED = 0;").unwrap();
+        writeln!(
+            text,
+            "  KIND{index}_FIRST = 1;\n  KIND{index}_SECOND = -2;\n}}\n"
+        )
+        .unwrap();
+    }
+    text.push_str("service BenchService {\n");
+    for index in 0..messages {
+        writeln!(
+            text,
+            "  rpc Call{index}(Message{index}) returns (stream Message{index}) {{\n    option deadline = 1.5e3;\n  }}"
+        )
+        .unwrap();
+    }
+    text.push_str("}\n");
+    text
+}
+
+const SIZES: [usize; 2] = [100, 2000];
+
+fn lexer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexer");
+    for messages in SIZES {
+        let text = synthetic_schema(messages);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::new("tokens", messages), &text, |b, text| {
+            b.iter(|| Lexer::new(black_box(text)).count())
+        });
+        group.bench_with_input(BenchmarkId::new("lossless", messages), &text, |b, text| {
+            b.iter(|| LosslessLexer::new(black_box(text)).count())
+        });
+    }
+    group.finish();
+}
+
+fn parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser");
+    for messages in SIZES {
+        let text = synthetic_schema(messages);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::new("parse", messages), &text, |b, text| {
+            b.iter(|| Parser::new(black_box(text)).parse().is_ok())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lexer, parser);
+criterion_main!(benches);
@@ -1 +1,293 @@
+use crate::lexer::{Comment, TokenMetadata};
 
+/// A value along with the metadata of the token it was parsed from
+#[derive(Clone, PartialEq, Debug)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub metadata: TokenMetadata,
+}
+
+/// Comments attached to a declaration, following the rules protoc uses for SourceCodeInfo.
+/// Comments keep their line breaks but lose their `//`, `/*` and `*/` delimiters.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Comments {
+    /// The comment block right before the declaration
+    pub leading: Option<String>,
+    /// The comment after the end of the declaration (its ";" or the "{" opening its body), on
+    /// the same line or on the next lines when a blank line follows
+    pub trailing: Option<String>,
+    /// Comment blocks before the leading one, separated from the declaration by blank lines
+    pub detached: Vec<String>,
+}
+
+/// The result of parsing a single .proto file
+#[derive(Clone, Debug)]
+pub struct ProtoFile {
+    /// The syntax named by the syntax declaration, `None` when the declaration is missing
+    pub syntax: Option<Spanned<String>>,
+    /// The whole syntax declaration, from `syntax` to its `;`
+    pub syntax_span: Option<TokenMetadata>,
+    pub imports: Vec<Import>,
+    pub options: Vec<OptionStatement>,
+    pub package: Package,
+    /// Every comment of the file in source order, elements cover the comments between their
+    /// name and their `end` token
+    pub comments: Vec<Comment>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Package {
+    /// `None` when the file has no package declaration
+    pub name: Option<Spanned<String>>,
+    /// The whole package declaration, from `package` to its `;`
+    pub span: Option<TokenMetadata>,
+    /// Comments of the package declaration
+    pub comments: Comments,
+    pub named_elements: Vec<NamedElement>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ImportKind {
+    Default,
+    Weak,
+    Public,
+}
+
+#[derive(Clone, Debug)]
+pub struct Import {
+    pub kind: ImportKind,
+    pub path: Spanned<String>,
+    /// The whole import statement, from `import` to its `;`
+    pub span: TokenMetadata,
+    pub comments: Comments,
+}
+
+#[derive(Clone, Debug)]
+pub enum ElementType {
+    Message(MessagePayload),
+    Field(FieldPayload),
+    OneOf(OneOfPayload),
+    Enum(EnumPayload),
+    EnumValue(EnumValuePayload),
+    Extension(ExtensionPayload),
+    Service(ServicePayload),
+}
+
+#[derive(Clone, Debug)]
+pub struct NamedElement {
+    pub name: String,
+    /// Metadata of the token holding the element's name
+    pub metadata: TokenMetadata,
+    /// Metadata of the token starting the declaration: its keyword, the label or type of a
+    /// field, or the name of an enum value
+    pub start: TokenMetadata,
+    /// Metadata of the token ending the declaration, the ";" or the closing "}"
+    pub end: TokenMetadata,
+    pub comments: Comments,
+    pub type_t: ElementType,
+}
+
+#[derive(Clone, Debug)]
+pub struct MessagePayload {
+    /// Fields, oneofs, nested messages, nested enums and extensions in declaration order
+    pub named_elements: Vec<NamedElement>,
+    pub reserved: Vec<Reserved>,
+    pub extension_ranges: Vec<Spanned<NumberRange>>,
+    pub options: Vec<OptionStatement>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FieldLabel {
+    None,
+    Optional,
+    Repeated,
+    Required,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ScalarType {
+    Double,
+    Float,
+    Int32,
+    Int64,
+    Uint32,
+    Uint64,
+    Sint32,
+    Sint64,
+    Fixed32,
+    Fixed64,
+    SFixed32,
+    SFixed64,
+    Bool,
+    String,
+    Bytes,
+}
+
+impl ScalarType {
+    /// The keyword naming the type in .proto files
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScalarType::Double => "double",
+            ScalarType::Float => "float",
+            ScalarType::Int32 => "int32",
+            ScalarType::Int64 => "int64",
+            ScalarType::Uint32 => "uint32",
+            ScalarType::Uint64 => "uint64",
+            ScalarType::Sint32 => "sint32",
+            ScalarType::Sint64 => "sint64",
+            ScalarType::Fixed32 => "fixed32",
+            ScalarType::Fixed64 => "fixed64",
+            ScalarType::SFixed32 => "sfixed32",
+            ScalarType::SFixed64 => "sfixed64",
+            ScalarType::Bool => "bool",
+            ScalarType::String => "string",
+            ScalarType::Bytes => "bytes",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum FieldType {
+    Scalar(ScalarType),
+    /// Reference to a message or enum, possibly qualified (`foo.Bar`) or fully-qualified (`.foo.Bar`)
+    Named(String),
+    Map(Box<Spanned<FieldType>>, Box<Spanned<FieldType>>),
+}
+
+#[derive(Clone, Debug)]
+pub struct FieldPayload {
+    pub label: FieldLabel,
+    /// Spans the whole type, up to the ">" of a map type
+    pub field_type: Spanned<FieldType>,
+    pub number: Spanned<u64>,
+    pub options: Vec<OptionStatement>,
+    /// The brackets holding the options, `None` without brackets
+    pub options_span: Option<TokenMetadata>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OneOfPayload {
+    /// Only ever holds `ElementType::Field` elements
+    pub fields: Vec<NamedElement>,
+    pub options: Vec<OptionStatement>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EnumPayload {
+    /// Only ever holds `ElementType::EnumValue` elements
+    pub values: Vec<NamedElement>,
+    pub reserved: Vec<Reserved>,
+    pub options: Vec<OptionStatement>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EnumValuePayload {
+    pub number: Spanned<i64>,
+    pub options: Vec<OptionStatement>,
+    /// The brackets holding the options, `None` without brackets
+    pub options_span: Option<TokenMetadata>,
+}
+
+/// An `extend <extendee> { ... }` block, the element name is the extendee as written
+#[derive(Clone, Debug)]
+pub struct ExtensionPayload {
+    /// Only ever holds `ElementType::Field` elements
+    pub fields: Vec<NamedElement>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServicePayload {
+    pub methods: Vec<Method>,
+    pub options: Vec<OptionStatement>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Method {
+    /// Metadata of the `rpc` keyword
+    pub start: TokenMetadata,
+    pub name: Spanned<String>,
+    pub input_type: Spanned<String>,
+    pub output_type: Spanned<String>,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+    pub options: Vec<OptionStatement>,
+    /// Metadata of the token ending the declaration, the ";" or the closing "}"
+    pub end: TokenMetadata,
+    pub comments: Comments,
+}
+
+/// Inclusive range of field or enum value numbers
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct NumberRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl NumberRange {
+    pub fn contains(&self, number: i64) -> bool {
+        self.start <= number && number <= self.end
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Reserved {
+    Range(Spanned<NumberRange>),
+    Name(Spanned<String>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum OptionNamePart {
+    /// A plain identifier such as `deprecated`
+    Simple(String),
+    /// A parenthesized extension name such as `(my.ext)`, or `[my.ext]` in a message literal
+    Extension(String),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Constant {
+    Identifier(String),
+    Integer(i128),
+    Float(f64),
+    String(String),
+    /// A string literal whose escapes do not produce valid UTF-8, only valid for bytes
+    Bytes(Vec<u8>),
+    Bool(bool),
+    /// A message literal in text format such as `{ name: "x" count: 3 }`
+    Message(Vec<MessageLiteralField>),
+    /// A list of values, only found inside message literals
+    List(Vec<Spanned<Constant>>),
+}
+
+/// A `name: value` field of a message literal
+#[derive(Clone, PartialEq, Debug)]
+pub struct MessageLiteralField {
+    pub name: Spanned<OptionNamePart>,
+    pub value: Spanned<Constant>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OptionStatement {
+    pub name: Spanned<Vec<OptionNamePart>>,
+    pub value: Spanned<Constant>,
+    /// From `option` to the `;` of a statement, or from the name to the end of the value
+    /// between brackets
+    pub span: TokenMetadata,
+}
+
+impl OptionStatement {
+    /// Returns true for options with a single plain name part equal to `name`
+    pub fn is_named(&self, name: &str) -> bool {
+        matches!(self.name.value.as_slice(), [OptionNamePart::Simple(simple)] if simple == name)
+    }
+}
+
+/// Find the value of the built-in option `name` in a list of options
+pub fn find_option<'a>(
+    options: &'a [OptionStatement],
+    name: &str,
+) -> Option<&'a Spanned<Constant>> {
+    options
+        .iter()
+        .find(|option| option.is_named(name))
+        .map(|option| &option.value)
+}
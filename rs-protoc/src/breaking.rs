@@ -0,0 +1,870 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use crate::{
+    ast_elements::{NumberRange, ScalarType},
+    diagnostic::Diagnostic,
+    lexer::TokenMetadata,
+    schema::{
+        EnumDescriptor, FieldDescriptor, FieldType, Label, MessageDescriptor, Schema,
+        ServiceDescriptor,
+    },
+    source_map::{LineColumn, SourceMap},
+    source_text::{FileId, SourceRegistry},
+};
+
+// Compatibility of a new version of a schema with an old one. Elements are matched by full
+// name, fields and enum values by number. Every change is reported at the strictest level it
+// breaks:
+//
+// - Wire: messages serialized with one version can no longer be parsed with the other, e.g. a
+//   field number reused with an incompatible type
+// - JSON: the JSON mapping changes, e.g. a renamed field or enum value
+// - Source: code generated from the old version no longer compiles against the new one, e.g.
+//   a removed message, even when its field numbers stay reserved
+
+/// How strict a comparison is, every level includes the checks of the levels before it
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum CompatibilityLevel {
+    Wire,
+    Json,
+    #[default]
+    Source,
+}
+
+impl CompatibilityLevel {
+    /// Code of the diagnostics of changes breaking this level
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompatibilityLevel::Wire => "breaking-wire",
+            CompatibilityLevel::Json => "breaking-json",
+            CompatibilityLevel::Source => "breaking-source",
+        }
+    }
+}
+
+impl FromStr for CompatibilityLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level {
+            "wire" => Ok(CompatibilityLevel::Wire),
+            "json" => Ok(CompatibilityLevel::Json),
+            "source" => Ok(CompatibilityLevel::Source),
+            _ => Err(format!("Unknown compatibility level: {}", level)),
+        }
+    }
+}
+
+/// Where an element is declared, with zero based lines and columns counted the way protoc does
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DeclarationSpan {
+    pub file: FileId,
+    pub start: LineColumn,
+    pub end: LineColumn,
+}
+
+/// A change of the new schema breaking compatibility with the old one
+#[derive(Clone, PartialEq, Debug)]
+pub struct BreakingChange {
+    pub level: CompatibilityLevel,
+    /// Name of the file holding the changed element, in the new schema unless it was removed
+    pub file_name: String,
+    /// Declaration of the changed element in `file_name`, when the schema has source locations
+    pub span: Option<DeclarationSpan>,
+    pub message: String,
+}
+
+impl BreakingChange {
+    /// The change located at its declaration when the text of its file is in `sources`, at
+    /// `file_name` otherwise
+    pub fn to_diagnostic(&self, sources: &SourceRegistry) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.message.clone()).with_code(self.level.code());
+        let Some((span, source)) = self
+            .span
+            .and_then(|span| Some((span, sources.get(span.file)?)))
+        else {
+            return diagnostic;
+        };
+        let source_map = SourceMap::new(source.text());
+        let metadata = TokenMetadata::from_offsets(
+            span.file,
+            &source_map,
+            source_map.protoc_offset(span.start),
+            source_map.protoc_offset(span.end),
+        );
+        diagnostic.with_primary_label(&metadata, "")
+    }
+}
+
+impl Display for BreakingChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.file_name, self.message)
+    }
+}
+
+/// Every change from `old` to `new` breaking compatibility at `level` or a less strict one, in
+/// the order of the elements of `old`
+pub fn find_breaking_changes(
+    old: &Schema,
+    new: &Schema,
+    level: CompatibilityLevel,
+) -> Vec<BreakingChange> {
+    let mut checker = Checker {
+        level,
+        messages: HashMap::new(),
+        enums: HashMap::new(),
+        services: HashMap::new(),
+        old_spans: declaration_spans(old),
+        new_spans: declaration_spans(new),
+        changes: Vec::new(),
+    };
+    for file in &new.files {
+        for message in &file.messages {
+            checker.index_message(&file.name, message);
+        }
+        for enum_descriptor in &file.enums {
+            checker
+                .enums
+                .insert(&enum_descriptor.full_name, (&file.name, enum_descriptor));
+        }
+        for service in &file.services {
+            checker
+                .services
+                .insert(&service.full_name, (&file.name, service));
+        }
+    }
+    for file in &old.files {
+        for message in &file.messages {
+            checker.check_message(&file.name, message);
+        }
+        for enum_descriptor in &file.enums {
+            checker.check_enum(&file.name, enum_descriptor);
+        }
+        for service in &file.services {
+            checker.check_service(&file.name, service);
+        }
+    }
+    checker.changes
+}
+
+/// Declarations of the elements of `schema` keyed by `FileDescriptor::element_name`
+fn declaration_spans(schema: &Schema) -> HashMap<String, DeclarationSpan> {
+    let mut spans = HashMap::new();
+    for file in &schema.files {
+        for location in &file.source_code_info {
+            let (start, end) = match *location.span.as_slice() {
+                [line, start, end] => ((line, start), (line, end)),
+                [start_line, start, end_line, end] => ((start_line, start), (end_line, end)),
+                _ => continue,
+            };
+            let position = |(line, column): (i32, i32)| LineColumn {
+                line: line as usize,
+                column: column as usize,
+            };
+            if let Some(name) = file.element_name(&location.path) {
+                spans.insert(
+                    name,
+                    DeclarationSpan {
+                        file: file.source_file,
+                        start: position(start),
+                        end: position(end),
+                    },
+                );
+            }
+        }
+    }
+    spans
+}
+
+/// Elements of the new schema by full name, along with the name of their file
+struct Checker<'a> {
+    level: CompatibilityLevel,
+    messages: HashMap<&'a str, (&'a str, &'a MessageDescriptor)>,
+    enums: HashMap<&'a str, (&'a str, &'a EnumDescriptor)>,
+    services: HashMap<&'a str, (&'a str, &'a ServiceDescriptor)>,
+    /// Declarations of the old and new elements, see `declaration_spans`
+    old_spans: HashMap<String, DeclarationSpan>,
+    new_spans: HashMap<String, DeclarationSpan>,
+    changes: Vec<BreakingChange>,
+}
+
+/// Name of an element of the old or the new schema, as in `declaration_spans`
+#[derive(Clone, Copy)]
+enum Declaration<'n> {
+    Old(&'n str),
+    New(&'n str),
+}
+
+impl<'a> Checker<'a> {
+    fn index_message(&mut self, file_name: &'a str, message: &'a MessageDescriptor) {
+        self.messages
+            .insert(&message.full_name, (file_name, message));
+        for nested_message in &message.nested_messages {
+            self.index_message(file_name, nested_message);
+        }
+        for enum_descriptor in &message.enums {
+            self.enums
+                .insert(&enum_descriptor.full_name, (file_name, enum_descriptor));
+        }
+    }
+
+    /// Report a change located at `declaration`, the new element unless it was removed
+    fn report(
+        &mut self,
+        level: CompatibilityLevel,
+        file_name: &str,
+        declaration: Declaration,
+        message: String,
+    ) {
+        if level <= self.level {
+            let span = match declaration {
+                Declaration::Old(name) => self.old_spans.get(name),
+                Declaration::New(name) => self.new_spans.get(name),
+            };
+            self.changes.push(BreakingChange {
+                level,
+                file_name: file_name.to_string(),
+                span: span.copied(),
+                message,
+            });
+        }
+    }
+
+    fn check_message(&mut self, old_file: &str, old: &MessageDescriptor) {
+        let Some(&(file_name, new)) = self.messages.get(old.full_name.as_str()) else {
+            // Removed map fields are reported along with their field
+            if !old.is_map_entry() {
+                self.report(
+                    CompatibilityLevel::Source,
+                    old_file,
+                    Declaration::Old(&old.full_name),
+                    format!("Message \"{}\" was removed", old.full_name),
+                );
+            }
+            return;
+        };
+        for old_field in &old.fields {
+            let element = format!("Field {} \"{}\"", old_field.number, old_field.name);
+            match new
+                .fields
+                .iter()
+                .find(|field| field.number == old_field.number)
+            {
+                Some(new_field) => self.check_field(file_name, old, old_field, new, new_field),
+                None => self.check_removed(
+                    file_name,
+                    &element,
+                    &old.full_name,
+                    (old_field.number as i64, &old_field.name),
+                    new.fields
+                        .iter()
+                        .find(|field| field.name == old_field.name)
+                        .map(|field| field.number as i64),
+                    (&new.reserved_ranges, &new.reserved_names),
+                ),
+            }
+        }
+        self.check_reservations(
+            file_name,
+            &old.full_name,
+            (&old.reserved_ranges, &old.reserved_names),
+            (&new.reserved_ranges, &new.reserved_names),
+        );
+        for nested_message in &old.nested_messages {
+            self.check_message(old_file, nested_message);
+        }
+        for enum_descriptor in &old.enums {
+            self.check_enum(old_file, enum_descriptor);
+        }
+    }
+
+    fn check_field(
+        &mut self,
+        file_name: &str,
+        old_message: &MessageDescriptor,
+        old: &FieldDescriptor,
+        new_message: &MessageDescriptor,
+        new: &FieldDescriptor,
+    ) {
+        let element = format!("Field {} of \"{}\"", old.number, old_message.full_name);
+        let declaration = format!("{}.{}", new_message.full_name, new.name);
+        if old.name != new.name {
+            self.report(
+                CompatibilityLevel::Json,
+                file_name,
+                Declaration::New(&declaration),
+                format!(
+                    "{} was renamed from \"{}\" to \"{}\"",
+                    element, old.name, new.name
+                ),
+            );
+        } else if old.json_name != new.json_name {
+            self.report(
+                CompatibilityLevel::Json,
+                file_name,
+                Declaration::New(&declaration),
+                format!(
+                    "{} changed JSON name from \"{}\" to \"{}\"",
+                    element, old.json_name, new.json_name
+                ),
+            );
+        }
+
+        if old.field_type != new.field_type {
+            let level = if wire_kind(&old.field_type) != wire_kind(&new.field_type) {
+                CompatibilityLevel::Wire
+            } else if let (FieldType::Message(_), FieldType::Message(_)) =
+                (&old.field_type, &new.field_type)
+            {
+                // Only the generated type changes, messages are encoded alike
+                CompatibilityLevel::Source
+            } else {
+                CompatibilityLevel::Json
+            };
+            self.report(
+                level,
+                file_name,
+                Declaration::New(&declaration),
+                format!(
+                    "{} changed type from {} to {}",
+                    element,
+                    type_name(&old.field_type),
+                    type_name(&new.field_type)
+                ),
+            );
+        }
+
+        if old.label != new.label {
+            // Packed and unpacked numbers share field numbers but not wire types
+            let level = if old.label == Label::Required
+                || new.label == Label::Required
+                || is_packable(&old.field_type)
+            {
+                CompatibilityLevel::Wire
+            } else {
+                CompatibilityLevel::Json
+            };
+            self.report(
+                level,
+                file_name,
+                Declaration::New(&declaration),
+                format!(
+                    "{} changed label from {} to {}",
+                    element,
+                    label_name(old.label),
+                    label_name(new.label)
+                ),
+            );
+        } else if old.proto3_optional != new.proto3_optional {
+            self.report(
+                CompatibilityLevel::Source,
+                file_name,
+                Declaration::New(&declaration),
+                format!(
+                    "{} {} explicit presence",
+                    element,
+                    if new.proto3_optional {
+                        "gained"
+                    } else {
+                        "lost"
+                    }
+                ),
+            );
+        }
+
+        let old_oneof = oneof_name(old_message, old);
+        let new_oneof = oneof_name(new_message, new);
+        if old_oneof != new_oneof {
+            let describe = |oneof: Option<&str>| match oneof {
+                Some(oneof) => format!("oneof \"{}\"", oneof),
+                None => "no oneof".to_string(),
+            };
+            self.report(
+                CompatibilityLevel::Wire,
+                file_name,
+                Declaration::New(&declaration),
+                format!(
+                    "{} moved from {} to {}",
+                    element,
+                    describe(old_oneof),
+                    describe(new_oneof)
+                ),
+            );
+        }
+    }
+
+    /// Report a field or enum value missing from the new schema, `renumbered` is the number of
+    /// the element of the same name in the new schema, if there is one
+    fn check_removed(
+        &mut self,
+        file_name: &str,
+        element: &str,
+        parent: &str,
+        (number, name): (i64, &str),
+        renumbered: Option<i64>,
+        (reserved_ranges, reserved_names): (&[NumberRange], &[String]),
+    ) {
+        let declaration = format!("{}.{}", parent, name);
+        let (level, message) = if let Some(new_number) = renumbered {
+            (
+                CompatibilityLevel::Wire,
+                format!(
+                    "{} of \"{}\" changed number to {}",
+                    element, parent, new_number
+                ),
+            )
+        } else if !reserved_ranges.iter().any(|range| range.contains(number)) {
+            (
+                CompatibilityLevel::Wire,
+                format!(
+                    "{} of \"{}\" was removed without reserving its number",
+                    element, parent
+                ),
+            )
+        } else if !reserved_names.iter().any(|reserved| reserved == name) {
+            (
+                CompatibilityLevel::Json,
+                format!(
+                    "{} of \"{}\" was removed without reserving its name",
+                    element, parent
+                ),
+            )
+        } else {
+            (
+                CompatibilityLevel::Source,
+                format!("{} of \"{}\" was removed", element, parent),
+            )
+        };
+        let declaration = match renumbered {
+            Some(_) => Declaration::New(&declaration),
+            None => Declaration::Old(&declaration),
+        };
+        self.report(level, file_name, declaration, message);
+    }
+
+    fn check_reservations(
+        &mut self,
+        file_name: &str,
+        parent: &str,
+        (old_ranges, old_names): (&[NumberRange], &[String]),
+        (new_ranges, new_names): (&[NumberRange], &[String]),
+    ) {
+        for range in old_ranges {
+            if !covers(new_ranges, range) {
+                let numbers = if range.start == range.end {
+                    range.start.to_string()
+                } else {
+                    format!("{} to {}", range.start, range.end)
+                };
+                self.report(
+                    CompatibilityLevel::Wire,
+                    file_name,
+                    Declaration::New(parent),
+                    format!(
+                        "Reserved numbers {} of \"{}\" are no longer reserved",
+                        numbers, parent
+                    ),
+                );
+            }
+        }
+        for name in old_names {
+            if !new_names.contains(name) {
+                self.report(
+                    CompatibilityLevel::Json,
+                    file_name,
+                    Declaration::New(parent),
+                    format!(
+                        "Reserved name \"{}\" of \"{}\" is no longer reserved",
+                        name, parent
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_enum(&mut self, old_file: &str, old: &EnumDescriptor) {
+        let Some(&(file_name, new)) = self.enums.get(old.full_name.as_str()) else {
+            self.report(
+                CompatibilityLevel::Source,
+                old_file,
+                Declaration::Old(&old.full_name),
+                format!("Enum \"{}\" was removed", old.full_name),
+            );
+            return;
+        };
+        for old_value in &old.values {
+            let same_number: Vec<_> = new
+                .values
+                .iter()
+                .filter(|value| value.number == old_value.number)
+                .collect();
+            if same_number.iter().any(|value| value.name == old_value.name) {
+                continue;
+            }
+            let element = format!("Enum value {} \"{}\"", old_value.number, old_value.name);
+            match same_number.first() {
+                Some(new_value) => self.report(
+                    CompatibilityLevel::Json,
+                    file_name,
+                    Declaration::New(&format!("{}.{}", new.full_name, new_value.name)),
+                    format!(
+                        "{} of \"{}\" was renamed to \"{}\"",
+                        element, old.full_name, new_value.name
+                    ),
+                ),
+                None => self.check_removed(
+                    file_name,
+                    &element,
+                    &old.full_name,
+                    (old_value.number as i64, &old_value.name),
+                    new.values
+                        .iter()
+                        .find(|value| value.name == old_value.name)
+                        .map(|value| value.number as i64),
+                    (&new.reserved_ranges, &new.reserved_names),
+                ),
+            }
+        }
+        self.check_reservations(
+            file_name,
+            &old.full_name,
+            (&old.reserved_ranges, &old.reserved_names),
+            (&new.reserved_ranges, &new.reserved_names),
+        );
+    }
+
+    fn check_service(&mut self, old_file: &str, old: &ServiceDescriptor) {
+        let Some(&(file_name, new)) = self.services.get(old.full_name.as_str()) else {
+            self.report(
+                CompatibilityLevel::Source,
+                old_file,
+                Declaration::Old(&old.full_name),
+                format!("Service \"{}\" was removed", old.full_name),
+            );
+            return;
+        };
+        for old_method in &old.methods {
+            let declaration = format!("{}.{}", old.full_name, old_method.name);
+            let element = format!("Method \"{}\"", declaration);
+            let Some(new_method) = new
+                .methods
+                .iter()
+                .find(|method| method.name == old_method.name)
+            else {
+                self.report(
+                    CompatibilityLevel::Source,
+                    file_name,
+                    Declaration::Old(&declaration),
+                    format!("{} was removed", element),
+                );
+                continue;
+            };
+            for (kind, old_type, new_type) in [
+                ("request", &old_method.input_type, &new_method.input_type),
+                ("response", &old_method.output_type, &new_method.output_type),
+            ] {
+                if old_type != new_type {
+                    self.report(
+                        CompatibilityLevel::Wire,
+                        file_name,
+                        Declaration::New(&declaration),
+                        format!(
+                            "{} changed {} type from \"{}\" to \"{}\"",
+                            element, kind, old_type, new_type
+                        ),
+                    );
+                }
+            }
+            for (kind, old_streaming, new_streaming) in [
+                (
+                    "request",
+                    old_method.client_streaming,
+                    new_method.client_streaming,
+                ),
+                (
+                    "response",
+                    old_method.server_streaming,
+                    new_method.server_streaming,
+                ),
+            ] {
+                if old_streaming != new_streaming {
+                    self.report(
+                        CompatibilityLevel::Wire,
+                        file_name,
+                        Declaration::New(&declaration),
+                        format!(
+                            "{} {} streaming its {}",
+                            element,
+                            if new_streaming { "started" } else { "stopped" },
+                            kind
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Types sharing a kind are encoded alike: values written as one can be read as the other
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum WireKind {
+    Varint,
+    ZigZag,
+    Fixed32,
+    Fixed64,
+    Float,
+    Double,
+    LengthDelimited,
+    Message,
+}
+
+fn wire_kind(field_type: &FieldType) -> WireKind {
+    match field_type {
+        FieldType::Enum(_) => WireKind::Varint,
+        FieldType::Message(_) => WireKind::Message,
+        FieldType::Scalar(scalar_type) => match scalar_type {
+            ScalarType::Int32
+            | ScalarType::Int64
+            | ScalarType::Uint32
+            | ScalarType::Uint64
+            | ScalarType::Bool => WireKind::Varint,
+            ScalarType::Sint32 | ScalarType::Sint64 => WireKind::ZigZag,
+            ScalarType::Fixed32 | ScalarType::SFixed32 => WireKind::Fixed32,
+            ScalarType::Fixed64 | ScalarType::SFixed64 => WireKind::Fixed64,
+            ScalarType::Float => WireKind::Float,
+            ScalarType::Double => WireKind::Double,
+            ScalarType::String | ScalarType::Bytes => WireKind::LengthDelimited,
+        },
+    }
+}
+
+fn is_packable(field_type: &FieldType) -> bool {
+    !matches!(
+        wire_kind(field_type),
+        WireKind::LengthDelimited | WireKind::Message
+    )
+}
+
+/// Name of the oneof holding `field`, `None` for fields outside of oneofs and for proto3
+/// optional fields
+fn oneof_name<'m>(message: &'m MessageDescriptor, field: &FieldDescriptor) -> Option<&'m str> {
+    field
+        .oneof_index
+        .filter(|_| !field.proto3_optional)
+        .and_then(|index| message.oneofs.get(index))
+        .map(|oneof| oneof.name.as_str())
+}
+
+fn type_name(field_type: &FieldType) -> String {
+    match field_type {
+        FieldType::Scalar(scalar_type) => scalar_type.as_str().to_string(),
+        FieldType::Message(name) | FieldType::Enum(name) => format!("\"{}\"", name),
+    }
+}
+
+fn label_name(label: Label) -> &'static str {
+    match label {
+        Label::Optional => "optional",
+        Label::Required => "required",
+        Label::Repeated => "repeated",
+    }
+}
+
+/// True if every number of `range` is in one of `ranges`
+fn covers(ranges: &[NumberRange], range: &NumberRange) -> bool {
+    let mut sorted = ranges.to_vec();
+    sorted.sort_by_key(|range| range.start);
+    let mut next = range.start;
+    for candidate in sorted {
+        if candidate.start > next {
+            break;
+        }
+        if candidate.end >= next {
+            next = candidate.end + 1;
+        }
+        if next > range.end {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::{
+        diagnostic::DiagnosticFormat,
+        parser::Parser,
+        resolver::{resolve, LoadedFile},
+    };
+
+    fn schema(source: &str) -> Schema {
+        let file = LoadedFile::from_source("test.proto", source.to_string()).unwrap();
+        resolve(&[file]).unwrap()
+    }
+
+    const OLD: &str = r#"syntax = "proto3";
+        package p;
+        message M {
+            int32 a = 1;
+            string b = 2;
+            int64 c = 3;
+            repeated int32 d = 4;
+            Inner e = 5;
+            reserved 10 to 12;
+            reserved "old";
+            message Inner {}
+        }
+        message Gone {}
+        enum E { E_UNSPECIFIED = 0; E_ONE = 1; E_TWO = 2; }
+        service S {
+            rpc Call(M) returns (M);
+            rpc Removed(M) returns (M);
+        }"#;
+
+    const NEW: &str = r#"syntax = "proto3";
+        package p;
+        message M {
+            string a = 1;
+            bytes b = 2;
+            int64 renamed = 3;
+            int32 d = 4;
+            reserved 5, 10 to 11;
+            message Inner {}
+        }
+        enum E { E_UNSPECIFIED = 0; E_UNO = 1; reserved 2; reserved "E_TWO"; }
+        service S { rpc Call(M) returns (stream M); }"#;
+
+    #[test]
+    fn breaking_changes_test() {
+        let changes = find_breaking_changes(&schema(OLD), &schema(NEW), CompatibilityLevel::Source);
+        let changes: Vec<_> = changes
+            .iter()
+            .map(|change| (change.level, change.message.as_str()))
+            .collect();
+        use CompatibilityLevel::*;
+        assert_eq!(
+            changes,
+            vec![
+                (Wire, "Field 1 of \"p.M\" changed type from int32 to string"),
+                (Json, "Field 2 of \"p.M\" changed type from string to bytes"),
+                (
+                    Json,
+                    "Field 3 of \"p.M\" was renamed from \"c\" to \"renamed\""
+                ),
+                (
+                    Wire,
+                    "Field 4 of \"p.M\" changed label from repeated to optional"
+                ),
+                (
+                    Json,
+                    "Field 5 \"e\" of \"p.M\" was removed without reserving its name"
+                ),
+                (
+                    Wire,
+                    "Reserved numbers 10 to 12 of \"p.M\" are no longer reserved"
+                ),
+                (
+                    Json,
+                    "Reserved name \"old\" of \"p.M\" is no longer reserved"
+                ),
+                (Source, "Message \"p.Gone\" was removed"),
+                (
+                    Json,
+                    "Enum value 1 \"E_ONE\" of \"p.E\" was renamed to \"E_UNO\""
+                ),
+                (Source, "Enum value 2 \"E_TWO\" of \"p.E\" was removed"),
+                (Wire, "Method \"p.S.Call\" started streaming its response"),
+                (Source, "Method \"p.S.Removed\" was removed"),
+            ]
+        );
+
+        let wire_changes = find_breaking_changes(&schema(OLD), &schema(NEW), Wire);
+        assert_eq!(wire_changes.len(), 4);
+        assert!(wire_changes.iter().all(|change| change.level == Wire));
+        assert_eq!(wire_changes[0].file_name, "test.proto");
+        assert!(find_breaking_changes(&schema(OLD), &schema(OLD), Source).is_empty());
+    }
+
+    #[test]
+    fn renumbered_field_test() {
+        let changes = find_breaking_changes(
+            &schema(r#"syntax = "proto3"; message M { int32 a = 1; int32 b = 2; }"#),
+            &schema(r#"syntax = "proto3"; message M { int32 a = 3; int32 b = 2; reserved 1; }"#),
+            CompatibilityLevel::Wire,
+        );
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            changes[0].message,
+            "Field 1 \"a\" of \"M\" changed number to 3"
+        );
+    }
+
+    #[test]
+    fn change_location_test() {
+        let mut registry = SourceRegistry::default();
+        let mut load = |path: &str, text: &str| {
+            let source = registry.add("test.proto", Path::new(path), text.to_string());
+            let ast = Parser::for_buffer(&source).parse().unwrap();
+            let file = LoadedFile {
+                name: "test.proto".to_string(),
+                path: PathBuf::from(path),
+                source,
+                ast,
+            };
+            resolve(&[file]).unwrap()
+        };
+        let old = load(
+            "old/test.proto",
+            "syntax = \"proto3\";\nmessage M {\n  int32 a = 1;\n}\nmessage Gone {}\n",
+        );
+        let new = load(
+            "new/test.proto",
+            "syntax = \"proto3\";\n\nmessage M {\n\tstring a = 1;\n}\n",
+        );
+        let changes = find_breaking_changes(&old, &new, CompatibilityLevel::Source);
+        let locations: Vec<String> = changes
+            .iter()
+            .map(|change| {
+                change.to_diagnostic(&registry).format_in(
+                    DiagnosticFormat::Gcc,
+                    &change.file_name,
+                    &registry,
+                    false,
+                )
+            })
+            .collect();
+        assert_eq!(
+            locations,
+            [
+                "test.proto:4:9: Field 1 of \"M\" changed type from int32 to string\n",
+                "test.proto:5:1: Message \"Gone\" was removed\n"
+            ]
+        );
+        assert_eq!(
+            changes[1].span.map(|span| (span.start, span.end)),
+            Some((
+                LineColumn { line: 4, column: 0 },
+                LineColumn {
+                    line: 4,
+                    column: 15
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn covers_test() {
+        let ranges = [
+            NumberRange { start: 1, end: 3 },
+            NumberRange { start: 7, end: 9 },
+            NumberRange { start: 4, end: 5 },
+        ];
+        assert!(covers(&ranges, &NumberRange { start: 2, end: 5 }));
+        assert!(covers(&ranges, &NumberRange { start: 8, end: 8 }));
+        assert!(!covers(&ranges, &NumberRange { start: 5, end: 7 }));
+        assert!(!covers(&[], &NumberRange { start: 1, end: 1 }));
+    }
+}
@@ -0,0 +1,49 @@
+// protoc ships descriptor.proto and the well-known types along with the compiler, so files can
+// import them without having them on an include path. The loader falls back to the copies here
+// for the files it does not find on the include paths, the way protoc falls back to its own
+// include directory. The copies are the unmodified sources of protobuf 21.12, under the license
+// in include/google/protobuf/LICENSE.
+
+/// Files provided without being on an include path, by name
+const BUILTIN_FILES: &[(&str, &str)] = &[
+    (
+        "google/protobuf/any.proto",
+        include_str!("../include/google/protobuf/any.proto"),
+    ),
+    (
+        "google/protobuf/descriptor.proto",
+        include_str!("../include/google/protobuf/descriptor.proto"),
+    ),
+    (
+        "google/protobuf/duration.proto",
+        include_str!("../include/google/protobuf/duration.proto"),
+    ),
+    (
+        "google/protobuf/empty.proto",
+        include_str!("../include/google/protobuf/empty.proto"),
+    ),
+    (
+        "google/protobuf/field_mask.proto",
+        include_str!("../include/google/protobuf/field_mask.proto"),
+    ),
+    (
+        "google/protobuf/struct.proto",
+        include_str!("../include/google/protobuf/struct.proto"),
+    ),
+    (
+        "google/protobuf/timestamp.proto",
+        include_str!("../include/google/protobuf/timestamp.proto"),
+    ),
+    (
+        "google/protobuf/wrappers.proto",
+        include_str!("../include/google/protobuf/wrappers.proto"),
+    ),
+];
+
+/// The text of the built-in file called `name`
+pub fn builtin_file(name: &str) -> Option<&'static str> {
+    BUILTIN_FILES
+        .iter()
+        .find(|(file_name, _)| *file_name == name)
+        .map(|(_, text)| *text)
+}
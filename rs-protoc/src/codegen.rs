@@ -0,0 +1,1401 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    ast_elements::ScalarType,
+    schema::{
+        EnumDescriptor, FieldDescriptor, FieldType, FileDescriptor, Label, MessageDescriptor,
+        Schema,
+    },
+};
+
+// Generated code is laid out following the package hierarchy: every package component becomes
+// a module, every message a struct next to a module of the same name in snake case holding its
+// nested messages, enums and oneofs.
+//
+// `foo.bar.Outer.Inner` becomes `foo::bar::outer::Inner`
+//
+// Messages get inherent `encode`/`decode` methods built on the `MessageWriter` and
+// `MessageReader` of the encoding crate. Standard library types are referred to by their full
+// path, so a message named `Option` or `String` does not shadow them.
+
+/// Path of the crate providing the runtime support of the generated code
+const RUNTIME: &str = "::encoding";
+
+/// Name of the file including every generated package file into a module tree
+pub const MODULE_TREE_FILE: &str = "mod.rs";
+
+pub struct GeneratedFile {
+    pub name: String,
+    pub content: String,
+}
+
+/// Name of the file holding the code generated for `package`
+pub fn package_file_name(package: &str) -> String {
+    if package.is_empty() {
+        "_.rs".to_string()
+    } else {
+        format!("{}.rs", package)
+    }
+}
+
+/// Generate Rust code for the files of `schema` named in `file_names`, one file per package
+/// plus the module tree in `MODULE_TREE_FILE`. Types defined in other files of the schema are
+/// referred to by their path in the module tree, so code has to be generated for them as well.
+pub fn generate(schema: &Schema, file_names: &[String]) -> Vec<GeneratedFile> {
+    let generator = Generator::new(schema);
+    // Files of the same package share a module
+    let mut packages: BTreeMap<&str, Vec<&FileDescriptor>> = BTreeMap::new();
+    for file in &schema.files {
+        if file_names.contains(&file.name) {
+            packages.entry(&file.package).or_default().push(file);
+        }
+    }
+    let mut generated_files = Vec::new();
+    for (package, files) in &packages {
+        let mut writer = CodeWriter::default();
+        writer.line("// This file is @generated by rs-protoc.");
+        let scope = package_modules(package);
+        for file in files {
+            for message in &file.messages {
+                generator.generate_message(&mut writer, message, &scope);
+            }
+            for enum_descriptor in &file.enums {
+                generator.generate_enum(&mut writer, enum_descriptor);
+            }
+        }
+        generated_files.push(GeneratedFile {
+            name: package_file_name(package),
+            content: writer.output,
+        });
+    }
+    generated_files.push(GeneratedFile {
+        name: MODULE_TREE_FILE.to_string(),
+        content: generate_module_tree(packages.keys().copied()),
+    });
+    generated_files
+}
+
+fn generate_module_tree<'a>(packages: impl Iterator<Item = &'a str>) -> String {
+    #[derive(Default)]
+    struct Module<'a> {
+        package: Option<&'a str>,
+        children: BTreeMap<String, Module<'a>>,
+    }
+    fn write_module(writer: &mut CodeWriter, module: &Module) {
+        if let Some(package) = module.package {
+            writer.line(&format!("include!(\"{}\");", package_file_name(package)));
+        }
+        for (name, child) in &module.children {
+            writer.open(&format!("pub mod {}", name));
+            write_module(writer, child);
+            writer.close();
+        }
+    }
+
+    let mut root = Module::default();
+    for package in packages {
+        let mut module = &mut root;
+        for name in package_modules(package) {
+            module = module.children.entry(name).or_default();
+        }
+        module.package = Some(package);
+    }
+    let mut writer = CodeWriter::default();
+    writer.line("// This file is @generated by rs-protoc.");
+    write_module(&mut writer, &root);
+    writer.output
+}
+
+#[derive(Default)]
+struct CodeWriter {
+    output: String,
+    indent: usize,
+}
+
+impl CodeWriter {
+    fn line(&mut self, text: &str) {
+        if text.is_empty() && self.output.ends_with("{\n") {
+            return; // No blank line at the start of a block
+        }
+        if !text.is_empty() {
+            self.output.push_str(&"    ".repeat(self.indent));
+            self.output.push_str(text);
+        }
+        self.output.push('\n');
+    }
+
+    /// Write `text` followed by an opening brace and indent what follows
+    fn open(&mut self, text: &str) {
+        self.line(&format!("{} {{", text));
+        self.indent += 1;
+    }
+
+    /// Write `text` as doc comment lines
+    fn doc(&mut self, text: Option<&String>) {
+        let Some(text) = text else {
+            return;
+        };
+        for line in text.trim_end_matches('\n').split('\n') {
+            self.line(&format!("///{}", line.trim_end()));
+        }
+    }
+
+    fn close(&mut self) {
+        self.close_with("}");
+    }
+
+    fn close_with(&mut self, text: &str) {
+        self.indent -= 1;
+        self.line(text);
+    }
+}
+
+const KEYWORDS: [&str; 51] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual",
+];
+
+/// Escape identifiers that collide with Rust keywords
+fn escape_keyword(ident: String) -> String {
+    if !KEYWORDS.contains(&ident.as_str()) {
+        return ident;
+    }
+    match ident.as_str() {
+        // These cannot be raw identifiers
+        "self" | "Self" | "super" | "crate" => format!("{}_", ident),
+        _ => format!("r#{}", ident),
+    }
+}
+
+pub(crate) fn to_snake_case(name: &str) -> String {
+    let mut output = String::with_capacity(name.len() + 4);
+    let mut previous_is_lower = false;
+    for ch in name.chars() {
+        if ch.is_ascii_uppercase() {
+            if previous_is_lower {
+                output.push('_');
+            }
+            output.push(ch.to_ascii_lowercase());
+            previous_is_lower = false;
+        } else {
+            output.push(ch);
+            previous_is_lower = ch.is_ascii_lowercase() || ch.is_ascii_digit();
+        }
+    }
+    output
+}
+
+pub(crate) fn to_upper_camel_case(name: &str) -> String {
+    let mut output = String::with_capacity(name.len());
+    let mut capitalize_next = true;
+    for ch in name.chars() {
+        if ch == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            output.push(ch.to_ascii_uppercase());
+            capitalize_next = false;
+        } else {
+            output.push(ch);
+        }
+    }
+    output
+}
+
+/// `SCREAMING_SNAKE_CASE` enum value names become `UpperCamelCase`
+fn enum_value_variant(enum_name: &str, value_name: &str) -> String {
+    // Values are often prefixed with the name of their enum, `COLOR_RED` in `Color`
+    let prefix = format!("{}_", to_snake_case(enum_name).to_ascii_uppercase());
+    let name = match value_name.strip_prefix(&prefix) {
+        Some(stripped) if stripped.starts_with(|ch: char| ch.is_ascii_alphabetic()) => stripped,
+        _ => value_name,
+    };
+    escape_keyword(to_upper_camel_case(&name.to_ascii_lowercase()))
+}
+
+fn type_ident(name: &str) -> String {
+    escape_keyword(to_upper_camel_case(name))
+}
+
+fn field_ident(name: &str) -> String {
+    escape_keyword(to_snake_case(name))
+}
+
+fn module_ident(name: &str) -> String {
+    escape_keyword(to_snake_case(name))
+}
+
+fn package_modules(package: &str) -> Vec<String> {
+    if package.is_empty() {
+        return Vec::new();
+    }
+    package.split('.').map(module_ident).collect()
+}
+
+/// Where a message or enum ends up in the module tree
+struct TypeLocation {
+    modules: Vec<String>,
+    ident: String,
+}
+
+impl TypeLocation {
+    /// Path to the type relative to the module `scope`
+    fn relative_path(&self, scope: &[String]) -> String {
+        let common = self
+            .modules
+            .iter()
+            .zip(scope.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let mut parts: Vec<&str> = vec!["super"; scope.len() - common];
+        parts.extend(self.modules[common..].iter().map(|module| module.as_str()));
+        parts.push(&self.ident);
+        parts.join("::")
+    }
+}
+
+/// Types the generated code refers to by name, which a module of the same name would shadow
+const PRIMITIVE_TYPES: [&str; 9] = [
+    "bool", "f32", "f64", "i32", "i64", "str", "u32", "u64", "u8",
+];
+
+/// Identifiers taken in one namespace of the generated code. Names that differ in the .proto
+/// file can be the same after case conversion, `Foo` and `foo` are both `Foo` as a type, so
+/// later ones get underscores appended until they are unique.
+#[derive(Default)]
+struct Names(HashSet<String>);
+
+impl Names {
+    fn with_reserved(reserved: &[&str]) -> Self {
+        Names(reserved.iter().map(|name| name.to_string()).collect())
+    }
+
+    fn reserve(&mut self, ident: &str) {
+        self.0.insert(ident.to_string());
+    }
+
+    fn claim(&mut self, mut ident: String) -> String {
+        while !self.0.insert(ident.clone()) {
+            ident.push('_');
+        }
+        ident
+    }
+}
+
+fn module_names<'s>(
+    scopes: &'s mut HashMap<Vec<String>, Names>,
+    modules: &[String],
+) -> &'s mut Names {
+    scopes
+        .entry(modules.to_vec())
+        .or_insert_with(|| Names::with_reserved(&PRIMITIVE_TYPES))
+}
+
+/// Module generated next to a message for its nested messages, enums and oneofs
+struct NestedModule {
+    ident: String,
+    /// Index and enum identifier of every oneof, except the synthetic oneofs of proto3 optional
+    /// fields which are generated as Option
+    oneofs: Vec<(usize, String)>,
+}
+
+/// Identifiers of the members of the struct generated for a message
+struct MemberIdents<'m> {
+    /// Struct field of every field outside of a oneof and of every oneof, by name
+    fields: HashMap<&'m str, String>,
+    /// Variant of every oneof member in the enum of its oneof, by field name
+    variants: HashMap<&'m str, String>,
+}
+
+struct Generator<'a> {
+    types: HashMap<&'a str, TypeLocation>,
+    messages: HashMap<&'a str, &'a MessageDescriptor>,
+    nested_modules: HashMap<&'a str, NestedModule>,
+    /// Message types each message holds directly, through singular or oneof fields
+    singular_edges: HashMap<&'a str, Vec<&'a str>>,
+    /// Leading and trailing comments of elements, keyed by `FileDescriptor::element_name`
+    docs: HashMap<String, String>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(schema: &'a Schema) -> Self {
+        let mut generator = Generator {
+            types: HashMap::new(),
+            messages: HashMap::new(),
+            nested_modules: HashMap::new(),
+            singular_edges: HashMap::new(),
+            docs: schema.element_docs(),
+        };
+        // Identifiers in each module, keyed by module path. Package modules keep their names as
+        // they are also the file layout.
+        let mut scopes = HashMap::new();
+        for file in &schema.files {
+            let modules = package_modules(&file.package);
+            for depth in 0..modules.len() {
+                module_names(&mut scopes, &modules[..depth]).reserve(&modules[depth]);
+            }
+        }
+        for file in &schema.files {
+            generator.index_messages(&file.messages);
+            generator.index_scope(
+                &mut scopes,
+                &package_modules(&file.package),
+                &file.messages,
+                &file.enums,
+            );
+        }
+        generator
+    }
+
+    fn index_messages(&mut self, messages: &'a [MessageDescriptor]) {
+        for message in messages {
+            self.messages.insert(&message.full_name, message);
+            let edges = message
+                .fields
+                .iter()
+                .filter(|field| field.label != Label::Repeated)
+                .filter_map(|field| match &field.field_type {
+                    FieldType::Message(type_name) => Some(type_name.as_str()),
+                    _ => None,
+                })
+                .collect();
+            self.singular_edges.insert(&message.full_name, edges);
+            self.index_messages(&message.nested_messages);
+        }
+    }
+
+    /// Name the messages and enums defined in the module `modules`, then what is nested in them
+    fn index_scope(
+        &mut self,
+        scopes: &mut HashMap<Vec<String>, Names>,
+        modules: &[String],
+        messages: &'a [MessageDescriptor],
+        enums: &'a [EnumDescriptor],
+    ) {
+        let messages: Vec<&'a MessageDescriptor> = messages
+            .iter()
+            .filter(|message| !message.is_map_entry())
+            .collect();
+        let names = module_names(scopes, modules);
+        let type_names = messages
+            .iter()
+            .map(|message| (&message.full_name, &message.name))
+            .chain(enums.iter().map(|e| (&e.full_name, &e.name)));
+        for (full_name, name) in type_names {
+            let ident = names.claim(type_ident(name));
+            self.types.insert(
+                full_name,
+                TypeLocation {
+                    modules: modules.to_vec(),
+                    ident,
+                },
+            );
+        }
+
+        let mut nested = Vec::new();
+        for message in messages {
+            let oneofs: Vec<(usize, &str)> = message
+                .oneofs
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| {
+                    !message
+                        .fields
+                        .iter()
+                        .any(|field| field.oneof_index == Some(*index) && field.proto3_optional)
+                })
+                .map(|(index, oneof)| (index, oneof.name.as_str()))
+                .collect();
+            let has_nested_types = !message.enums.is_empty()
+                || message
+                    .nested_messages
+                    .iter()
+                    .any(|nested_message| !nested_message.is_map_entry());
+            if has_nested_types || !oneofs.is_empty() {
+                nested.push((message, names.claim(module_ident(&message.name)), oneofs));
+            }
+        }
+        for (message, ident, oneofs) in nested {
+            let mut nested_modules = modules.to_vec();
+            nested_modules.push(ident.clone());
+            self.index_scope(
+                scopes,
+                &nested_modules,
+                &message.nested_messages,
+                &message.enums,
+            );
+            // Oneof enums live next to the nested types
+            let names = module_names(scopes, &nested_modules);
+            let oneofs = oneofs
+                .into_iter()
+                .map(|(index, name)| (index, names.claim(type_ident(name))))
+                .collect();
+            self.nested_modules
+                .insert(&message.full_name, NestedModule { ident, oneofs });
+        }
+    }
+
+    fn type_path(&self, full_name: &str, scope: &[String]) -> String {
+        match self.types.get(full_name) {
+            Some(location) => location.relative_path(scope),
+            // Not part of the schema, which the resolver or the descriptor set loader rejects
+            None => type_ident(full_name.rsplit('.').next().unwrap_or(full_name)),
+        }
+    }
+
+    /// A message field has to be boxed if the field type contains `container`, directly or
+    /// through other messages, as the struct would otherwise have an infinite size
+    fn needs_box(&self, container: &str, field_type: &str) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![field_type];
+        while let Some(type_name) = stack.pop() {
+            if type_name == container {
+                return true;
+            }
+            if visited.insert(type_name) {
+                if let Some(edges) = self.singular_edges.get(type_name) {
+                    stack.extend(edges.iter().copied());
+                }
+            }
+        }
+        false
+    }
+
+    fn map_entry(&self, field: &FieldDescriptor) -> Option<&'a MessageDescriptor> {
+        match &field.field_type {
+            FieldType::Message(type_name) if field.label == Label::Repeated => self
+                .messages
+                .get(type_name.as_str())
+                .filter(|message| message.is_map_entry())
+                .copied(),
+            _ => None,
+        }
+    }
+
+    /// Rust type of a single value of `field_type`
+    fn value_type(&self, field_type: &FieldType, scope: &[String]) -> String {
+        match field_type {
+            FieldType::Scalar(scalar_type) => scalar_rust_type(*scalar_type).to_string(),
+            FieldType::Enum(_) => "i32".to_string(),
+            FieldType::Message(type_name) => self.type_path(type_name, scope),
+        }
+    }
+
+    /// Expression reading a value of `field_type` from the `FieldValue` named `value`
+    fn read_value(&self, field_type: &FieldType, scope: &[String]) -> String {
+        match field_type {
+            FieldType::Scalar(scalar_type) => read_scalar(*scalar_type).to_string(),
+            FieldType::Enum(_) => "value.as_i64()? as i32".to_string(),
+            FieldType::Message(type_name) => format!(
+                "{}::decode(value.as_bytes()?)?",
+                self.type_path(type_name, scope)
+            ),
+        }
+    }
+
+    fn member_idents<'m>(
+        message: &'m MessageDescriptor,
+        nested: Option<&NestedModule>,
+    ) -> MemberIdents<'m> {
+        let oneofs = nested.map_or(&[][..], |nested| &nested.oneofs);
+        let mut names = Names::default();
+        let mut fields = HashMap::new();
+        for field in &message.fields {
+            let is_oneof_member = oneofs
+                .iter()
+                .any(|(index, _)| field.oneof_index == Some(*index));
+            if !is_oneof_member {
+                fields.insert(field.name.as_str(), names.claim(field_ident(&field.name)));
+            }
+        }
+        let mut variants = HashMap::new();
+        for (index, _) in oneofs {
+            let oneof_name = message.oneofs[*index].name.as_str();
+            fields.insert(oneof_name, names.claim(field_ident(oneof_name)));
+            let mut variant_names = Names::default();
+            for field in &message.fields {
+                if field.oneof_index == Some(*index) {
+                    variants.insert(
+                        field.name.as_str(),
+                        variant_names.claim(type_ident(&field.name)),
+                    );
+                }
+            }
+        }
+        MemberIdents { fields, variants }
+    }
+
+    fn generate_message(
+        &self,
+        writer: &mut CodeWriter,
+        message: &MessageDescriptor,
+        scope: &[String],
+    ) {
+        if message.is_map_entry() {
+            return; // Map fields are generated as HashMap
+        }
+        let ident = &self.types[message.full_name.as_str()].ident;
+        let nested = self.nested_modules.get(message.full_name.as_str());
+        let members = Self::member_idents(message, nested);
+
+        writer.line("");
+        writer.doc(self.docs.get(&message.full_name));
+        writer.line("#[derive(Clone, PartialEq, Debug, Default)]");
+        writer.open(&format!("pub struct {}", ident));
+        for field in &message.fields {
+            let Some(field_ident) = members.fields.get(field.name.as_str()) else {
+                continue; // Member of a oneof
+            };
+            writer.doc(
+                self.docs
+                    .get(&format!("{}.{}", message.full_name, field.name)),
+            );
+            if let FieldType::Enum(type_name) = &field.field_type {
+                writer.line(&format!(
+                    "/// Holds values of `{}`",
+                    self.type_path(type_name, scope)
+                ));
+            }
+            writer.line(&format!(
+                "pub {}: {},",
+                field_ident,
+                self.field_type(message, field, scope)
+            ));
+        }
+        if let Some(nested) = nested {
+            for (index, oneof_ident) in &nested.oneofs {
+                let oneof_name = &message.oneofs[*index].name;
+                writer.doc(
+                    self.docs
+                        .get(&format!("{}.{}", message.full_name, oneof_name)),
+                );
+                writer.line(&format!(
+                    "pub {}: ::core::option::Option<{}::{}>,",
+                    members.fields[oneof_name.as_str()],
+                    nested.ident,
+                    oneof_ident
+                ));
+            }
+        }
+        writer.close();
+
+        self.generate_message_impl(writer, message, scope, &members, nested);
+
+        let Some(nested) = nested else {
+            return;
+        };
+        let mut nested_scope = scope.to_vec();
+        nested_scope.push(nested.ident.clone());
+        writer.line("");
+        writer.line(&format!(
+            "/// Nested messages, enums and oneofs of `{}`",
+            ident
+        ));
+        writer.open(&format!("pub mod {}", nested.ident));
+        for nested_message in &message.nested_messages {
+            self.generate_message(writer, nested_message, &nested_scope);
+        }
+        for enum_descriptor in &message.enums {
+            self.generate_enum(writer, enum_descriptor);
+        }
+        for (oneof_index, oneof_ident) in &nested.oneofs {
+            writer.line("");
+            writer.doc(self.docs.get(&format!(
+                "{}.{}",
+                message.full_name, message.oneofs[*oneof_index].name
+            )));
+            writer.line("#[derive(Clone, PartialEq, Debug)]");
+            writer.open(&format!("pub enum {}", oneof_ident));
+            for field in &message.fields {
+                if field.oneof_index == Some(*oneof_index) {
+                    writer.doc(
+                        self.docs
+                            .get(&format!("{}.{}", message.full_name, field.name)),
+                    );
+                    let mut value_type = self.value_type(&field.field_type, &nested_scope);
+                    if self.is_boxed(message, field) {
+                        value_type = format!("::std::boxed::Box<{}>", value_type);
+                    }
+                    writer.line(&format!(
+                        "{}({}),",
+                        members.variants[field.name.as_str()],
+                        value_type
+                    ));
+                }
+            }
+            writer.close();
+        }
+        writer.close();
+    }
+
+    fn is_boxed(&self, message: &MessageDescriptor, field: &FieldDescriptor) -> bool {
+        match &field.field_type {
+            FieldType::Message(type_name) if field.label != Label::Repeated => {
+                self.needs_box(&message.full_name, type_name)
+            }
+            _ => false,
+        }
+    }
+
+    /// Rust type of the struct field generated for a field outside of a oneof
+    fn field_type(
+        &self,
+        message: &MessageDescriptor,
+        field: &FieldDescriptor,
+        scope: &[String],
+    ) -> String {
+        if let Some(entry) = self.map_entry(field) {
+            return format!(
+                "::std::collections::HashMap<{}, {}>",
+                self.value_type(&entry.fields[0].field_type, scope),
+                self.value_type(&entry.fields[1].field_type, scope)
+            );
+        }
+        let value_type = self.value_type(&field.field_type, scope);
+        match &field.field_type {
+            _ if field.label == Label::Repeated => format!("::std::vec::Vec<{}>", value_type),
+            FieldType::Message(_) if self.is_boxed(message, field) => {
+                format!("::core::option::Option<::std::boxed::Box<{}>>", value_type)
+            }
+            FieldType::Message(_) => format!("::core::option::Option<{}>", value_type),
+            _ if field.proto3_optional => format!("::core::option::Option<{}>", value_type),
+            _ => value_type,
+        }
+    }
+
+    fn generate_message_impl(
+        &self,
+        writer: &mut CodeWriter,
+        message: &MessageDescriptor,
+        scope: &[String],
+        members: &MemberIdents,
+        nested: Option<&NestedModule>,
+    ) {
+        let message_writer = format!("{}::message::MessageWriter", RUNTIME);
+        let message_reader = format!("{}::message::MessageReader", RUNTIME);
+        let mut fields: Vec<&FieldDescriptor> = message.fields.iter().collect();
+        fields.sort_by_key(|field| field.number);
+        // (oneof field, variant path) for fields that are members of a oneof
+        let oneof_member = |field: &FieldDescriptor| {
+            let nested = nested?;
+            let (index, oneof_ident) = nested
+                .oneofs
+                .iter()
+                .find(|(index, _)| field.oneof_index == Some(*index))?;
+            Some((
+                &members.fields[message.oneofs[*index].name.as_str()],
+                format!(
+                    "{}::{}::{}",
+                    nested.ident,
+                    oneof_ident,
+                    members.variants[field.name.as_str()]
+                ),
+            ))
+        };
+
+        writer.line("");
+        writer.open(&format!(
+            "impl {}",
+            self.types[message.full_name.as_str()].ident
+        ));
+        writer.line("/// The fields that are set, in field number order");
+        writer.open(&format!(
+            "pub fn encode(&self) -> {}::Result<{}>",
+            RUNTIME, message_writer
+        ));
+        if fields.is_empty() {
+            writer.line(&format!("Ok({}::new())", message_writer));
+        } else {
+            writer.line(&format!("let mut writer = {}::new();", message_writer));
+        }
+        for field in &fields {
+            let number = field.number;
+            if let Some((oneof_ident, variant)) = oneof_member(field) {
+                writer.open(&format!(
+                    "if let Some({}(value)) = &self.{}",
+                    variant, oneof_ident
+                ));
+                writer.line(&write_value(
+                    "writer",
+                    &field.field_type,
+                    number,
+                    "value",
+                    true,
+                ));
+                writer.close();
+                continue;
+            }
+            let ident = &members.fields[field.name.as_str()];
+            if let Some(entry) = self.map_entry(field) {
+                writer.open(&format!("for (key, value) in &self.{}", ident));
+                writer.line(&format!("let mut entry = {}::new();", message_writer));
+                writer.line(&write_value(
+                    "entry",
+                    &entry.fields[0].field_type,
+                    1,
+                    "key",
+                    true,
+                ));
+                writer.line(&write_value(
+                    "entry",
+                    &entry.fields[1].field_type,
+                    2,
+                    "value",
+                    true,
+                ));
+                writer.line(&format!("writer.write_message({}, &entry)?;", number));
+                writer.close();
+            } else if field.label == Label::Repeated {
+                match scalar_wire(&field.field_type) {
+                    Some(wire) if field.packed => {
+                        writer.open(&format!("if !self.{}.is_empty()", ident));
+                        writer.line(&format!("let mut elements = {}::new();", message_writer));
+                        writer.open(&format!("for value in &self.{}", ident));
+                        writer.line(&format!(
+                            "elements.{}({})?;",
+                            wire.push,
+                            wire.conversion.replace("{}", "*value")
+                        ));
+                        writer.close();
+                        writer.line(&format!("writer.write_message({}, &elements)?;", number));
+                        writer.close();
+                    }
+                    _ => {
+                        writer.open(&format!("for value in &self.{}", ident));
+                        writer.line(&write_value(
+                            "writer",
+                            &field.field_type,
+                            number,
+                            "value",
+                            true,
+                        ));
+                        writer.close();
+                    }
+                }
+            } else if field.proto3_optional || matches!(field.field_type, FieldType::Message(_)) {
+                writer.open(&format!("if let Some(value) = &self.{}", ident));
+                writer.line(&write_value(
+                    "writer",
+                    &field.field_type,
+                    number,
+                    "value",
+                    true,
+                ));
+                writer.close();
+            } else {
+                // Fields without presence are not written when they hold the default value
+                let value = format!("self.{}", ident);
+                writer.open(&format!("if {}", is_set(&field.field_type, &value)));
+                writer.line(&write_value(
+                    "writer",
+                    &field.field_type,
+                    number,
+                    &value,
+                    false,
+                ));
+                writer.close();
+            }
+        }
+        if !fields.is_empty() {
+            writer.line("Ok(writer)");
+        }
+        writer.close();
+
+        writer.line("");
+        writer.open(&format!(
+            "pub fn encode_to_vec(&self) -> {}::Result<::std::vec::Vec<u8>>",
+            RUNTIME
+        ));
+        writer.line("Ok(self.encode()?.into_bytes())");
+        writer.close();
+
+        writer.line("");
+        writer.open(&format!(
+            "pub fn decode(bytes: &[u8]) -> {}::Result<Self>",
+            RUNTIME
+        ));
+        writer.line("let mut message = <Self as ::core::default::Default>::default();");
+        writer.line("message.merge(bytes)?;");
+        writer.line("Ok(message)");
+        writer.close();
+
+        writer.line("");
+        writer.line(
+            "/// Merge the fields of an encoded message into `self`, unknown fields are skipped",
+        );
+        if fields.len() == 1 {
+            // The match below reads best the same way whatever the number of fields
+            writer.line("#[allow(clippy::single_match)]");
+        }
+        writer.open(&format!(
+            "pub fn merge(&mut self, bytes: &[u8]) -> {}::Result<()>",
+            RUNTIME
+        ));
+        writer.line(&format!("let mut reader = {}::new(bytes);", message_reader));
+        if fields.is_empty() {
+            writer.line("while reader.read_field()?.is_some() {}");
+        } else {
+            writer.open("while let Some((field_number, value)) = reader.read_field()?");
+            writer.open("match field_number");
+        }
+        for field in &fields {
+            let number = field.number;
+            let read = self.read_value(&field.field_type, scope);
+            if let Some((oneof_ident, variant)) = oneof_member(field) {
+                if !matches!(field.field_type, FieldType::Message(_)) {
+                    writer.line(&format!(
+                        "{} => self.{} = Some({}({})),",
+                        number, oneof_ident, variant, read
+                    ));
+                    continue;
+                }
+                // A message already set in the oneof is merged into
+                let read = if self.is_boxed(message, field) {
+                    format!("::std::boxed::Box::new({})", read)
+                } else {
+                    read
+                };
+                writer.open(&format!("{} => match &mut self.{}", number, oneof_ident));
+                writer.line(&format!(
+                    "Some({}(target)) => target.merge(value.as_bytes()?)?,",
+                    variant
+                ));
+                writer.line(&format!(
+                    "_ => self.{} = Some({}({})),",
+                    oneof_ident, variant, read
+                ));
+                writer.close_with("},");
+                continue;
+            }
+            let ident = &members.fields[field.name.as_str()];
+            if let Some(entry) = self.map_entry(field) {
+                let (key, entry_value) = (&entry.fields[0], &entry.fields[1]);
+                let merge_entry_value = match entry_value.field_type {
+                    FieldType::Message(_) => "entry_value.merge(value.as_bytes()?)?".to_string(),
+                    _ => format!(
+                        "entry_value = {}",
+                        self.read_value(&entry_value.field_type, scope)
+                    ),
+                };
+                writer.open(&format!("{} =>", number));
+                writer.line(&format!(
+                    "let mut key: {} = ::core::default::Default::default();",
+                    self.value_type(&key.field_type, scope)
+                ));
+                writer.line(&format!(
+                    "let mut entry_value: {} = ::core::default::Default::default();",
+                    self.value_type(&entry_value.field_type, scope)
+                ));
+                writer.line(&format!(
+                    "let mut entry = {}::new(value.as_bytes()?);",
+                    message_reader
+                ));
+                writer.open("while let Some((field_number, value)) = entry.read_field()?");
+                writer.open("match field_number");
+                writer.line(&format!(
+                    "1 => key = {},",
+                    self.read_value(&key.field_type, scope)
+                ));
+                writer.line(&format!("2 => {},", merge_entry_value));
+                writer.line("_ => {}");
+                writer.close();
+                writer.close();
+                writer.line(&format!("self.{}.insert(key, entry_value);", ident));
+                writer.close();
+            } else if field.label == Label::Repeated {
+                let push = format!("self.{}.push({})", ident, read);
+                let Some(wire) = scalar_wire(&field.field_type) else {
+                    writer.line(&format!("{} => {},", number, push));
+                    continue;
+                };
+                // Parsers accept both packed and unpacked encodings whatever the field option
+                writer.open(&format!("{} => match value", number));
+                writer.open(&format!("{}::message::FieldValue::Len(bytes) =>", RUNTIME));
+                writer.line(&format!(
+                    "let mut elements = {}::new(bytes);",
+                    message_reader
+                ));
+                writer.open("while !elements.is_empty()");
+                writer.line(&format!(
+                    "let value = elements.read_value({}::wire_types::WireTypeEnum::{})?;",
+                    RUNTIME, wire.wire_type
+                ));
+                writer.line(&format!("{};", push));
+                writer.close();
+                writer.close();
+                writer.line(&format!("value => {},", push));
+                writer.close_with("},");
+            } else if let FieldType::Message(_) = field.field_type {
+                writer.line(&format!(
+                    "{} => self.{}.get_or_insert_with(::core::default::Default::default).merge(value.as_bytes()?)?,",
+                    number, ident
+                ));
+            } else if field.proto3_optional {
+                writer.line(&format!("{} => self.{} = Some({}),", number, ident, read));
+            } else {
+                writer.line(&format!("{} => self.{} = {},", number, ident, read));
+            }
+        }
+        if !fields.is_empty() {
+            writer.line("_ => {}");
+            writer.close();
+            writer.close();
+        }
+        writer.line("Ok(())");
+        writer.close();
+        writer.close();
+    }
+
+    fn generate_enum(&self, writer: &mut CodeWriter, enum_descriptor: &EnumDescriptor) {
+        let ident = &self.types[enum_descriptor.full_name.as_str()].ident;
+        // Variants, alias constants and methods share a namespace
+        let mut names = Names::with_reserved(&["from_i32", "as_str_name"]);
+        // Aliases share the number of an earlier value and become associated constants
+        let mut variants: Vec<(String, i32, &str)> = Vec::new();
+        let mut aliases = Vec::new();
+        for value in &enum_descriptor.values {
+            match variants
+                .iter()
+                .find(|(_, number, _)| *number == value.number)
+            {
+                Some((variant, _, _)) => aliases.push((value.name.as_str(), variant.clone())),
+                None => variants.push((
+                    names.claim(enum_value_variant(&enum_descriptor.name, &value.name)),
+                    value.number,
+                    value.name.as_str(),
+                )),
+            }
+        }
+
+        writer.line("");
+        writer.doc(self.docs.get(&enum_descriptor.full_name));
+        writer.line("#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]");
+        writer.line("#[repr(i32)]");
+        writer.open(&format!("pub enum {}", ident));
+        for (index, (variant, number, value_name)) in variants.iter().enumerate() {
+            writer.doc(
+                self.docs
+                    .get(&format!("{}.{}", enum_descriptor.full_name, value_name)),
+            );
+            if index == 0 {
+                writer.line("#[default]");
+            }
+            writer.line(&format!("{} = {},", variant, number));
+        }
+        writer.close();
+
+        writer.line("");
+        writer.open(&format!("impl {}", ident));
+        for (alias, variant) in &aliases {
+            writer.line(&format!(
+                "pub const {}: Self = Self::{};",
+                names.claim(escape_keyword(alias.to_string())),
+                variant
+            ));
+        }
+        if !aliases.is_empty() {
+            writer.line("");
+        }
+        writer.line("/// The value with the given number, if there is one");
+        writer.open("pub fn from_i32(value: i32) -> ::core::option::Option<Self>");
+        writer.open("match value");
+        for (variant, number, _) in &variants {
+            writer.line(&format!("{} => Some(Self::{}),", number, variant));
+        }
+        writer.line("_ => None,");
+        writer.close();
+        writer.close();
+        writer.line("");
+        writer.line("/// The name of the value in the .proto file");
+        writer.open("pub fn as_str_name(&self) -> &'static str");
+        writer.open("match self");
+        for (variant, _, name) in &variants {
+            writer.line(&format!("Self::{} => \"{}\",", variant, name));
+        }
+        writer.close();
+        writer.close();
+        writer.close();
+    }
+}
+
+fn scalar_rust_type(scalar_type: ScalarType) -> &'static str {
+    match scalar_type {
+        ScalarType::Double => "f64",
+        ScalarType::Float => "f32",
+        ScalarType::Int32 | ScalarType::Sint32 | ScalarType::SFixed32 => "i32",
+        ScalarType::Int64 | ScalarType::Sint64 | ScalarType::SFixed64 => "i64",
+        ScalarType::Uint32 | ScalarType::Fixed32 => "u32",
+        ScalarType::Uint64 | ScalarType::Fixed64 => "u64",
+        ScalarType::Bool => "bool",
+        ScalarType::String => "::std::string::String",
+        ScalarType::Bytes => "::std::vec::Vec<u8>",
+    }
+}
+
+/// Expression reading a scalar from the `FieldValue` named `value`
+fn read_scalar(scalar_type: ScalarType) -> &'static str {
+    match scalar_type {
+        ScalarType::Double => "f64::from_bits(value.as_fixed64()?)",
+        ScalarType::Float => "f32::from_bits(value.as_fixed32()?)",
+        ScalarType::Int32 => "value.as_i64()? as i32",
+        ScalarType::Int64 => "value.as_i64()?",
+        ScalarType::Uint32 => "value.as_u64()? as u32",
+        ScalarType::Uint64 => "value.as_u64()?",
+        ScalarType::Sint32 => "value.as_sint64()? as i32",
+        ScalarType::Sint64 => "value.as_sint64()?",
+        ScalarType::Fixed32 => "value.as_fixed32()?",
+        ScalarType::Fixed64 => "value.as_fixed64()?",
+        ScalarType::SFixed32 => "value.as_fixed32()? as i32",
+        ScalarType::SFixed64 => "value.as_fixed64()? as i64",
+        ScalarType::Bool => "value.as_bool()?",
+        ScalarType::String => "::std::borrow::ToOwned::to_owned(value.as_str()?)",
+        ScalarType::Bytes => "value.as_bytes()?.to_vec()",
+    }
+}
+
+/// How values of a scalar or enum type are written
+struct ScalarWire {
+    /// `MessageWriter` method writing a value with its tag
+    write: &'static str,
+    /// `MessageWriter` method writing a value without a tag, for packed fields
+    push: &'static str,
+    /// Conversion of the value `{}` to the argument of `write` and `push`
+    conversion: &'static str,
+    /// The `WireTypeEnum` variant, to read the elements of packed fields
+    wire_type: &'static str,
+}
+
+/// `None` for the length delimited types, which cannot be packed
+fn scalar_wire(field_type: &FieldType) -> Option<ScalarWire> {
+    let varint = |conversion| ("write_uint64", "push_varint", conversion, "Varint");
+    let (write, push, conversion, wire_type) = match field_type {
+        FieldType::Scalar(scalar_type) => match scalar_type {
+            ScalarType::Double => ("write_fixed64", "push_fixed64", "f64::to_bits({})", "I64"),
+            ScalarType::Float => ("write_fixed32", "push_fixed32", "f32::to_bits({})", "I32"),
+            ScalarType::Int32 => varint("{} as i64 as u64"),
+            ScalarType::Int64 | ScalarType::Uint32 | ScalarType::Bool => varint("{} as u64"),
+            ScalarType::Uint64 => varint("{}"),
+            ScalarType::Sint32 => ("write_sint64", "push_sint64", "{} as i64", "Varint"),
+            ScalarType::Sint64 => ("write_sint64", "push_sint64", "{}", "Varint"),
+            ScalarType::Fixed32 => ("write_fixed32", "push_fixed32", "{}", "I32"),
+            ScalarType::SFixed32 => ("write_fixed32", "push_fixed32", "{} as u32", "I32"),
+            ScalarType::Fixed64 => ("write_fixed64", "push_fixed64", "{}", "I64"),
+            ScalarType::SFixed64 => ("write_fixed64", "push_fixed64", "{} as u64", "I64"),
+            ScalarType::String | ScalarType::Bytes => return None,
+        },
+        FieldType::Enum(_) => varint("{} as i64 as u64"),
+        FieldType::Message(_) => return None,
+    };
+    Some(ScalarWire {
+        write,
+        push,
+        conversion,
+        wire_type,
+    })
+}
+
+/// Statement writing `value` as field `number` with the `MessageWriter` named `writer`. `value`
+/// is an expression of the Rust type of `field_type`, or of a reference to it if `is_reference`.
+fn write_value(
+    writer: &str,
+    field_type: &FieldType,
+    number: i32,
+    value: &str,
+    is_reference: bool,
+) -> String {
+    let reference = if is_reference {
+        value.to_string()
+    } else {
+        format!("&{}", value)
+    };
+    let call = match (field_type, scalar_wire(field_type)) {
+        (_, Some(wire)) => {
+            let value = if is_reference {
+                format!("*{}", value)
+            } else {
+                value.to_string()
+            };
+            format!(
+                "{}({}, {})",
+                wire.write,
+                number,
+                wire.conversion.replace("{}", &value)
+            )
+        }
+        (FieldType::Message(_), _) => format!("write_message({}, &{}.encode()?)", number, value),
+        (FieldType::Scalar(ScalarType::String), _) => {
+            format!("write_string({}, {})", number, reference)
+        }
+        _ => format!("write_bytes({}, {})", number, reference),
+    };
+    format!("{}.{}?;", writer, call)
+}
+
+/// Condition for a field without presence not to hold the default value of `field_type`
+fn is_set(field_type: &FieldType, value: &str) -> String {
+    match field_type {
+        FieldType::Scalar(ScalarType::Bool) => value.to_string(),
+        // Compare bits, -0.0 is not the default
+        FieldType::Scalar(ScalarType::Double) => format!("f64::to_bits({}) != 0", value),
+        FieldType::Scalar(ScalarType::Float) => format!("f32::to_bits({}) != 0", value),
+        FieldType::Scalar(ScalarType::String | ScalarType::Bytes) => {
+            format!("!{}.is_empty()", value)
+        }
+        _ => format!("{} != 0", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamic::DynamicMessage,
+        resolver::{resolve, LoadedFile},
+    };
+
+    // Compiling the checked in output of the generator shows that it builds whatever the names
+    // in the schema are
+    #[allow(dead_code)]
+    mod generated {
+        include!("../testdata/codegen/mod.rs");
+    }
+
+    const NAMES_SOURCES: [(&str, &str); 2] = [
+        (
+            "names.proto",
+            include_str!("../testdata/codegen/names.proto"),
+        ),
+        (
+            "names_everything.proto",
+            include_str!("../testdata/codegen/names_everything.proto"),
+        ),
+    ];
+
+    fn resolve_sources(sources: &[(&str, &str)]) -> Schema {
+        let files: Vec<LoadedFile> = sources
+            .iter()
+            .map(|(name, text)| LoadedFile::from_source(name, text.to_string()).unwrap())
+            .collect();
+        resolve(&files).unwrap()
+    }
+
+    fn generate_sources(sources: &[(&str, &str)]) -> Vec<GeneratedFile> {
+        let names: Vec<String> = sources.iter().map(|(name, _)| name.to_string()).collect();
+        generate(&resolve_sources(sources), &names)
+    }
+
+    fn file<'a>(files: &'a [GeneratedFile], name: &str) -> &'a str {
+        &files.iter().find(|file| file.name == name).unwrap().content
+    }
+
+    #[test]
+    fn naming_test() {
+        assert_eq!(to_snake_case("FooBar"), "foo_bar");
+        assert_eq!(to_snake_case("foo_bar2Baz"), "foo_bar2_baz");
+        assert_eq!(to_upper_camel_case("foo_bar"), "FooBar");
+        assert_eq!(field_ident("type"), "r#type");
+        assert_eq!(field_ident("self"), "self_");
+        assert_eq!(enum_value_variant("Color", "COLOR_DARK_RED"), "DarkRed");
+        assert_eq!(enum_value_variant("Color", "COLOR_1"), "Color1");
+        assert_eq!(enum_value_variant("Color", "UNKNOWN"), "Unknown");
+    }
+
+    #[test]
+    fn module_tree_test() {
+        let files = generate_sources(&[
+            (
+                "dep.proto",
+                r#"syntax = "proto3"; package foo; enum Color { COLOR_RED = 0; }"#,
+            ),
+            (
+                "main.proto",
+                r#"syntax = "proto3";
+                package foo.bar;
+                import "dep.proto";
+                message Outer {
+                    message Inner { foo.Color color = 1; }
+                    Inner inner = 1;
+                }"#,
+            ),
+        ]);
+        assert_eq!(
+            file(&files, MODULE_TREE_FILE),
+            "// This file is @generated by rs-protoc.
+pub mod foo {
+    include!(\"foo.rs\");
+    pub mod bar {
+        include!(\"foo.bar.rs\");
+    }
+}
+"
+        );
+        let code = file(&files, "foo.bar.rs");
+        assert!(code
+            .contains("pub struct Outer {\n    pub inner: ::core::option::Option<outer::Inner>,"));
+        assert!(code.contains("pub mod outer {"));
+        assert!(code.contains("/// Holds values of `super::super::Color`"));
+    }
+
+    #[test]
+    fn doc_comments_test() {
+        let files = generate_sources(&[(
+            "main.proto",
+            r#"syntax = "proto3";
+            // A message
+            //
+            // with two paragraphs
+            message M {
+                int32 a = 1; // Trailing comment of a
+                /* Leading comment
+                 * of choice */
+                oneof choice { string b = 2; }
+                enum E {
+                    // The default
+                    E_UNKNOWN = 0;
+                }
+            }"#,
+        )]);
+        let code = file(&files, "_.rs");
+        assert!(code.contains(
+            "/// A message\n///\n/// with two paragraphs\n#[derive(Clone, PartialEq, Debug, Default)]\npub struct M {"
+        ));
+        assert!(code.contains("    /// Trailing comment of a\n    pub a: i32,"));
+        assert!(code.contains(
+            "    /// Leading comment\n    /// of choice\n    pub choice: ::core::option::Option<m::Choice>,"
+        ));
+        assert!(code.contains("        /// The default\n        #[default]\n        Unknown = 0,"));
+    }
+
+    #[test]
+    fn field_types_test() {
+        let files = generate_sources(&[(
+            "main.proto",
+            r#"syntax = "proto3";
+            message Node {
+                optional string name = 1;
+                map<string, Node> children = 2;
+                repeated sint32 values = 3 [packed = false];
+                Node parent = 4;
+                oneof payload { bytes data = 5; Node next = 6; }
+            }"#,
+        )]);
+        let code = file(&files, "_.rs");
+        assert!(code.contains("pub name: ::core::option::Option<::std::string::String>,"));
+        assert!(code
+            .contains("pub children: ::std::collections::HashMap<::std::string::String, Node>,"));
+        assert!(code.contains("pub values: ::std::vec::Vec<i32>,"));
+        assert!(code.contains("pub parent: ::core::option::Option<::std::boxed::Box<Node>>,"));
+        assert!(code.contains("pub payload: ::core::option::Option<node::Payload>,"));
+        assert!(code
+            .contains("Data(::std::vec::Vec<u8>),\n        Next(::std::boxed::Box<super::Node>),"));
+        assert!(code.contains(
+            "for value in &self.values {\n            writer.write_sint64(3, *value as i64)?;"
+        ));
+        assert!(!code.contains("ChildrenEntry"));
+    }
+
+    #[test]
+    fn enum_test() {
+        let files = generate_sources(&[(
+            "main.proto",
+            r#"syntax = "proto3";
+            enum Color { option allow_alias = true; COLOR_UNKNOWN = 0; COLOR_RED = 1; COLOR_CRIMSON = 1; }"#,
+        )]);
+        let code = file(&files, "_.rs");
+        assert!(code.contains("    #[default]\n    Unknown = 0,\n    Red = 1,\n}"));
+        assert!(code.contains("pub const COLOR_CRIMSON: Self = Self::Red;"));
+        assert!(code.contains("Self::Red => \"COLOR_RED\","));
+    }
+
+    #[test]
+    fn generated_code_test() {
+        // Regenerate with `rs-protoc -Itestdata/codegen --rust_out=testdata/codegen` and the
+        // .proto files when the generator changes
+        let files = generate_sources(&NAMES_SOURCES);
+        let expected = [
+            (MODULE_TREE_FILE, include_str!("../testdata/codegen/mod.rs")),
+            ("names.rs", include_str!("../testdata/codegen/names.rs")),
+            (
+                "names.everything.rs",
+                include_str!("../testdata/codegen/names.everything.rs"),
+            ),
+        ];
+        assert_eq!(files.len(), expected.len());
+        for (name, content) in expected {
+            assert_eq!(file(&files, name), content, "{} is out of date", name);
+        }
+    }
+
+    #[test]
+    fn generated_round_trip_test() {
+        use generated::names::{self, everything_::Choice, foo::Kind};
+
+        let option = names::Option { value: -1 };
+        let message = names::Everything {
+            double_value: 1.5,
+            float_value: -2.5,
+            int32_value: -3,
+            int64_value: i64::MIN,
+            uint32_value: u32::MAX,
+            uint64_value: u64::MAX,
+            sint32_value: -7,
+            sint64_value: i64::MIN,
+            fixed32_value: 9,
+            fixed64_value: 10,
+            sfixed32_value: -11,
+            sfixed64_value: -12,
+            bool_value: true,
+            string_value: "fourteen".to_string(),
+            bytes_value: vec![0, 15],
+            kind: Kind::A_ as i32,
+            optional_value: Some(0),
+            option: Some(option.clone()),
+            packed: vec![-1, 0, 1],
+            unpacked: vec![20.0, -0.0],
+            strings: vec![String::new(), "21".to_string()],
+            options: [
+                ("a".to_string(), option),
+                ("b".to_string(), Default::default()),
+            ]
+            .into(),
+            kinds: [(1, Kind::A as i32), (-1, 7)].into(),
+            choice: Some(Choice::Recursive(Box::new(names::Everything {
+                choice: Some(Choice::Number(25.0)),
+                ..Default::default()
+            }))),
+        };
+        let bytes = message.encode_to_vec().unwrap();
+        assert_eq!(names::Everything::decode(&bytes).unwrap(), message);
+
+        // The dynamic messages agree on the encoding
+        let schema = resolve_sources(&NAMES_SOURCES);
+        let dynamic = DynamicMessage::decode(&schema, "names.Everything", &bytes).unwrap();
+        let reencoded = dynamic.encode(&schema).unwrap();
+        assert_eq!(names::Everything::decode(&reencoded).unwrap(), message);
+
+        // Default values of fields without presence are left out, sint32 elements are zigzag
+        // encoded and packed, doubles are not packed as the field is `[packed = false]`
+        let repeated = names::Everything {
+            packed: vec![-1, 0, 1],
+            unpacked: vec![1.0],
+            ..Default::default()
+        };
+        assert_eq!(
+            repeated.encode_to_vec().unwrap(),
+            [0x9a, 0x01, 0x03, 0x01, 0x00, 0x02, 0xa1, 0x01, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f]
+        );
+
+        let mut merged = names::Everything::decode(&bytes).unwrap();
+        let update = names::Everything {
+            int32_value: 3,
+            option: Some(names::Option::default()),
+            ..Default::default()
+        };
+        merged.merge(&update.encode_to_vec().unwrap()).unwrap();
+        assert_eq!(merged.double_value, 1.5);
+        assert_eq!(merged.int32_value, 3);
+        assert_eq!(merged.option, Some(names::Option { value: -1 }));
+    }
+}
@@ -0,0 +1,192 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    codegen,
+    error::{CompileError, FileError, RsProtocError},
+    resolver,
+    source_text::SourceRegistry,
+};
+
+/// Code generation settings for use from a `build.rs` script
+///
+/// ```no_run
+/// // In build.rs
+/// rs_protoc::Config::new()
+///     .compile_protos(&["protos/service.proto"], &["protos"])
+///     .unwrap();
+/// ```
+///
+/// The generated code depends on the `encoding` crate. It is included with
+/// `include!(concat!(env!("OUT_DIR"), "/mod.rs"));`, which brings in one module per package.
+#[derive(Debug, Clone)]
+pub struct Config {
+    out_dir: Option<PathBuf>,
+    emit_rerun_if_changed: bool,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config {
+            out_dir: None,
+            emit_rerun_if_changed: true,
+        }
+    }
+
+    /// Write the generated files to `path` rather than to `OUT_DIR`
+    pub fn out_dir(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.out_dir = Some(path.into());
+        self
+    }
+
+    /// Whether to print a `cargo:rerun-if-changed` line for every .proto file read, on by default
+    pub fn emit_rerun_if_changed(&mut self, enabled: bool) -> &mut Self {
+        self.emit_rerun_if_changed = enabled;
+        self
+    }
+
+    /// Generate code for `protos` and for every file they import. Imports are looked up in
+    /// `includes`, and every proto has to reside within one of them.
+    pub fn compile_protos(
+        &self,
+        protos: &[impl AsRef<Path>],
+        includes: &[impl AsRef<Path>],
+    ) -> Result<(), CompileError> {
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => out_dir.clone(),
+            None => match std::env::var_os("OUT_DIR") {
+                Some(out_dir) => PathBuf::from(out_dir),
+                None => {
+                    return Err(error(
+                        "OUT_DIR",
+                        RsProtocError::FilesystemError(
+                            "OUT_DIR is not set, compile_protos is meant to be called from a build script"
+                                .to_string(),
+                        ),
+                    ))
+                }
+            },
+        };
+        let input_files: Vec<String> = protos
+            .iter()
+            .map(|proto| proto.as_ref().to_string_lossy().to_string())
+            .collect();
+        let include_paths: Vec<PathBuf> = includes
+            .iter()
+            .map(|include| include.as_ref().to_path_buf())
+            .collect();
+        if self.emit_rerun_if_changed {
+            // Inputs are printed even if they fail to load, so a fix triggers a rebuild
+            for input_file in &input_files {
+                println!("cargo:rerun-if-changed={}", input_file);
+            }
+        }
+        let loaded =
+            resolver::load_schema(&input_files, &include_paths, &mut SourceRegistry::default())?;
+        if self.emit_rerun_if_changed {
+            for path in &loaded.paths {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+
+        // Imported files are generated as well, the generated code refers to their types
+        let file_names: Vec<String> = loaded
+            .schema
+            .files
+            .iter()
+            .map(|file| file.name.clone())
+            .collect();
+        std::fs::create_dir_all(&out_dir).map_err(|io_error| {
+            error(
+                &out_dir.to_string_lossy(),
+                RsProtocError::FilesystemError(io_error.to_string()),
+            )
+        })?;
+        for generated_file in codegen::generate(&loaded.schema, &file_names) {
+            let path = out_dir.join(&generated_file.name);
+            // Leave unchanged files alone so that cargo does not rebuild needlessly
+            if std::fs::read_to_string(&path).is_ok_and(|content| content == generated_file.content)
+            {
+                continue;
+            }
+            std::fs::write(&path, generated_file.content).map_err(|io_error| {
+                error(
+                    &path.to_string_lossy(),
+                    RsProtocError::FilesystemError(io_error.to_string()),
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
+}
+
+fn error(file_name: &str, error: RsProtocError) -> CompileError {
+    CompileError {
+        errors: vec![FileError {
+            file_name: file_name.to_string(),
+            error,
+        }],
+    }
+}
+
+/// Generate code for `protos` into `OUT_DIR` with the default `Config`
+pub fn compile_protos(
+    protos: &[impl AsRef<Path>],
+    includes: &[impl AsRef<Path>],
+) -> Result<(), CompileError> {
+    Config::new().compile_protos(protos, includes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_protos_test() {
+        let directory =
+            std::env::temp_dir().join(format!("rs-protoc-config-{}", std::process::id()));
+        let includes = directory.join("protos");
+        let out_dir = directory.join("out");
+        std::fs::create_dir_all(includes.join("foo")).unwrap();
+        std::fs::write(
+            includes.join("foo/dep.proto"),
+            r#"syntax = "proto3"; package foo; message Dep {}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            includes.join("main.proto"),
+            r#"syntax = "proto3"; package bar; import "foo/dep.proto"; message M { foo.Dep dep = 1; }"#,
+        )
+        .unwrap();
+
+        let mut config = Config::new();
+        config.out_dir(&out_dir).emit_rerun_if_changed(false);
+        config
+            .compile_protos(&[includes.join("main.proto")], &[&includes])
+            .unwrap();
+        let mut generated: Vec<String> = std::fs::read_dir(&out_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        generated.sort();
+        assert_eq!(generated, vec!["bar.rs", "foo.rs", "mod.rs"]);
+
+        // Errors name the file they were found in
+        std::fs::write(
+            includes.join("main.proto"),
+            r#"syntax = "proto3"; package bar; message M { Missing dep = 1; }"#,
+        )
+        .unwrap();
+        let error = config
+            .compile_protos(&[includes.join("main.proto")], &[&includes])
+            .unwrap_err();
+        assert!(format!("{:?}", error).starts_with("main.proto: "));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
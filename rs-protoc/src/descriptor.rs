@@ -0,0 +1,1412 @@
+use std::collections::HashSet;
+
+use encoding::message::{MessageReader, MessageWriter};
+
+use crate::{
+    ast_elements::{Constant, NumberRange, OptionNamePart, ScalarType},
+    error::{Result, RsProtocError},
+    resolver::qualified_name,
+    schema::{
+        is_packed, EnumDescriptor, EnumValueDescriptor, FieldDescriptor, FieldType, FileDescriptor,
+        Label, MessageDescriptor, MethodDescriptor, OneofDescriptor, OptionValue, Schema,
+        SchemaOption, ServiceDescriptor, SourceLocation, Syntax,
+    },
+    source_text::FileId,
+    validator,
+};
+
+// Field numbers from google/protobuf/descriptor.proto
+
+pub mod file_descriptor_proto {
+    pub const NAME: u64 = 1;
+    pub const PACKAGE: u64 = 2;
+    pub const DEPENDENCY: u64 = 3;
+    pub const MESSAGE_TYPE: u64 = 4;
+    pub const ENUM_TYPE: u64 = 5;
+    pub const SERVICE: u64 = 6;
+    pub const EXTENSION: u64 = 7;
+    pub const OPTIONS: u64 = 8;
+    pub const SOURCE_CODE_INFO: u64 = 9;
+    pub const PUBLIC_DEPENDENCY: u64 = 10;
+    pub const WEAK_DEPENDENCY: u64 = 11;
+    pub const SYNTAX: u64 = 12;
+}
+
+pub mod descriptor_proto {
+    pub const NAME: u64 = 1;
+    pub const FIELD: u64 = 2;
+    pub const NESTED_TYPE: u64 = 3;
+    pub const ENUM_TYPE: u64 = 4;
+    pub const EXTENSION_RANGE: u64 = 5;
+    pub const EXTENSION: u64 = 6;
+    pub const OPTIONS: u64 = 7;
+    pub const ONEOF_DECL: u64 = 8;
+    pub const RESERVED_RANGE: u64 = 9;
+    pub const RESERVED_NAME: u64 = 10;
+    /// Shared by ExtensionRange, ReservedRange and EnumReservedRange
+    pub const RANGE_START: u64 = 1;
+    pub const RANGE_END: u64 = 2;
+}
+
+pub mod field_descriptor_proto {
+    pub const NAME: u64 = 1;
+    pub const EXTENDEE: u64 = 2;
+    pub const NUMBER: u64 = 3;
+    pub const LABEL: u64 = 4;
+    pub const TYPE: u64 = 5;
+    pub const TYPE_NAME: u64 = 6;
+    pub const DEFAULT_VALUE: u64 = 7;
+    pub const OPTIONS: u64 = 8;
+    pub const ONEOF_INDEX: u64 = 9;
+    pub const JSON_NAME: u64 = 10;
+    pub const PROTO3_OPTIONAL: u64 = 17;
+}
+
+pub mod oneof_descriptor_proto {
+    pub const NAME: u64 = 1;
+    pub const OPTIONS: u64 = 2;
+}
+
+pub mod enum_descriptor_proto {
+    pub const NAME: u64 = 1;
+    pub const VALUE: u64 = 2;
+    pub const OPTIONS: u64 = 3;
+    pub const RESERVED_RANGE: u64 = 4;
+    pub const RESERVED_NAME: u64 = 5;
+}
+
+pub mod enum_value_descriptor_proto {
+    pub const NAME: u64 = 1;
+    pub const NUMBER: u64 = 2;
+    pub const OPTIONS: u64 = 3;
+}
+
+pub mod service_descriptor_proto {
+    pub const NAME: u64 = 1;
+    pub const METHOD: u64 = 2;
+    pub const OPTIONS: u64 = 3;
+}
+
+pub mod method_descriptor_proto {
+    pub const NAME: u64 = 1;
+    pub const INPUT_TYPE: u64 = 2;
+    pub const OUTPUT_TYPE: u64 = 3;
+    pub const OPTIONS: u64 = 4;
+    pub const CLIENT_STREAMING: u64 = 5;
+    pub const SERVER_STREAMING: u64 = 6;
+}
+
+pub mod source_code_info {
+    pub const LOCATION: u64 = 1;
+    pub const LOCATION_PATH: u64 = 1;
+    pub const LOCATION_SPAN: u64 = 2;
+    pub const LOCATION_LEADING_COMMENTS: u64 = 3;
+    pub const LOCATION_TRAILING_COMMENTS: u64 = 4;
+    pub const LOCATION_LEADING_DETACHED_COMMENTS: u64 = 6;
+}
+
+pub const FILE_DESCRIPTOR_SET_FILE: u64 = 1;
+
+/// Values of `FieldDescriptorProto.Type`
+pub fn field_type_number(field_type: &FieldType) -> u64 {
+    match field_type {
+        FieldType::Scalar(ScalarType::Double) => 1,
+        FieldType::Scalar(ScalarType::Float) => 2,
+        FieldType::Scalar(ScalarType::Int64) => 3,
+        FieldType::Scalar(ScalarType::Uint64) => 4,
+        FieldType::Scalar(ScalarType::Int32) => 5,
+        FieldType::Scalar(ScalarType::Fixed64) => 6,
+        FieldType::Scalar(ScalarType::Fixed32) => 7,
+        FieldType::Scalar(ScalarType::Bool) => 8,
+        FieldType::Scalar(ScalarType::String) => 9,
+        FieldType::Message(_) => 11,
+        FieldType::Scalar(ScalarType::Bytes) => 12,
+        FieldType::Scalar(ScalarType::Uint32) => 13,
+        FieldType::Enum(_) => 14,
+        FieldType::Scalar(ScalarType::SFixed32) => 15,
+        FieldType::Scalar(ScalarType::SFixed64) => 16,
+        FieldType::Scalar(ScalarType::Sint32) => 17,
+        FieldType::Scalar(ScalarType::Sint64) => 18,
+    }
+}
+
+/// Values of `FieldDescriptorProto.Label`
+pub fn label_number(label: Label) -> u64 {
+    match label {
+        Label::Optional => 1,
+        Label::Required => 2,
+        Label::Repeated => 3,
+    }
+}
+
+/// The options messages of descriptor.proto
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OptionsKind {
+    File,
+    Message,
+    Field,
+    Oneof,
+    Enum,
+    EnumValue,
+    Service,
+    Method,
+    ExtensionRange,
+}
+
+impl OptionsKind {
+    /// Full name of the options message, which custom options of this kind extend
+    pub fn message_name(self) -> &'static str {
+        match self {
+            OptionsKind::File => "google.protobuf.FileOptions",
+            OptionsKind::Message => "google.protobuf.MessageOptions",
+            OptionsKind::Field => "google.protobuf.FieldOptions",
+            OptionsKind::Oneof => "google.protobuf.OneofOptions",
+            OptionsKind::Enum => "google.protobuf.EnumOptions",
+            OptionsKind::EnumValue => "google.protobuf.EnumValueOptions",
+            OptionsKind::Service => "google.protobuf.ServiceOptions",
+            OptionsKind::Method => "google.protobuf.MethodOptions",
+            OptionsKind::ExtensionRange => "google.protobuf.ExtensionRangeOptions",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BuiltinOptionType {
+    Bool,
+    String,
+    /// Enum option along with its (name, number) values
+    Enum(&'static [(&'static str, i32)]),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BuiltinOption {
+    pub name: &'static str,
+    pub number: u64,
+    pub option_type: BuiltinOptionType,
+}
+
+const fn builtin(name: &'static str, number: u64, option_type: BuiltinOptionType) -> BuiltinOption {
+    BuiltinOption {
+        name,
+        number,
+        option_type,
+    }
+}
+
+const FILE_OPTIONS: [BuiltinOption; 20] = [
+    builtin("java_package", 1, BuiltinOptionType::String),
+    builtin("java_outer_classname", 8, BuiltinOptionType::String),
+    builtin(
+        "optimize_for",
+        9,
+        BuiltinOptionType::Enum(&[("SPEED", 1), ("CODE_SIZE", 2), ("LITE_RUNTIME", 3)]),
+    ),
+    builtin("java_multiple_files", 10, BuiltinOptionType::Bool),
+    builtin("go_package", 11, BuiltinOptionType::String),
+    builtin("cc_generic_services", 16, BuiltinOptionType::Bool),
+    builtin("java_generic_services", 17, BuiltinOptionType::Bool),
+    builtin("py_generic_services", 18, BuiltinOptionType::Bool),
+    builtin("java_generate_equals_and_hash", 20, BuiltinOptionType::Bool),
+    builtin("deprecated", 23, BuiltinOptionType::Bool),
+    builtin("java_string_check_utf8", 27, BuiltinOptionType::Bool),
+    builtin("cc_enable_arenas", 31, BuiltinOptionType::Bool),
+    builtin("objc_class_prefix", 36, BuiltinOptionType::String),
+    builtin("csharp_namespace", 37, BuiltinOptionType::String),
+    builtin("swift_prefix", 39, BuiltinOptionType::String),
+    builtin("php_class_prefix", 40, BuiltinOptionType::String),
+    builtin("php_namespace", 41, BuiltinOptionType::String),
+    builtin("php_generic_services", 42, BuiltinOptionType::Bool),
+    builtin("php_metadata_namespace", 44, BuiltinOptionType::String),
+    builtin("ruby_package", 45, BuiltinOptionType::String),
+];
+
+const MESSAGE_OPTIONS: [BuiltinOption; 4] = [
+    builtin("message_set_wire_format", 1, BuiltinOptionType::Bool),
+    builtin(
+        "no_standard_descriptor_accessor",
+        2,
+        BuiltinOptionType::Bool,
+    ),
+    builtin("deprecated", 3, BuiltinOptionType::Bool),
+    builtin("map_entry", 7, BuiltinOptionType::Bool),
+];
+
+const FIELD_OPTIONS: [BuiltinOption; 8] = [
+    builtin(
+        "ctype",
+        1,
+        BuiltinOptionType::Enum(&[("STRING", 0), ("CORD", 1), ("STRING_PIECE", 2)]),
+    ),
+    builtin("packed", 2, BuiltinOptionType::Bool),
+    builtin("deprecated", 3, BuiltinOptionType::Bool),
+    builtin("lazy", 5, BuiltinOptionType::Bool),
+    builtin(
+        "jstype",
+        6,
+        BuiltinOptionType::Enum(&[("JS_NORMAL", 0), ("JS_STRING", 1), ("JS_NUMBER", 2)]),
+    ),
+    builtin("weak", 10, BuiltinOptionType::Bool),
+    builtin("unverified_lazy", 15, BuiltinOptionType::Bool),
+    builtin("debug_redact", 16, BuiltinOptionType::Bool),
+];
+
+const ENUM_OPTIONS: [BuiltinOption; 2] = [
+    builtin("allow_alias", 2, BuiltinOptionType::Bool),
+    builtin("deprecated", 3, BuiltinOptionType::Bool),
+];
+
+const ENUM_VALUE_OPTIONS: [BuiltinOption; 2] = [
+    builtin("deprecated", 1, BuiltinOptionType::Bool),
+    builtin("debug_redact", 3, BuiltinOptionType::Bool),
+];
+
+const SERVICE_OPTIONS: [BuiltinOption; 1] = [builtin("deprecated", 33, BuiltinOptionType::Bool)];
+
+const METHOD_OPTIONS: [BuiltinOption; 2] = [
+    builtin("deprecated", 33, BuiltinOptionType::Bool),
+    builtin(
+        "idempotency_level",
+        34,
+        BuiltinOptionType::Enum(&[
+            ("IDEMPOTENCY_UNKNOWN", 0),
+            ("NO_SIDE_EFFECTS", 1),
+            ("IDEMPOTENT", 2),
+        ]),
+    ),
+];
+
+pub fn builtin_options(kind: OptionsKind) -> &'static [BuiltinOption] {
+    match kind {
+        OptionsKind::File => &FILE_OPTIONS,
+        OptionsKind::Message => &MESSAGE_OPTIONS,
+        OptionsKind::Field => &FIELD_OPTIONS,
+        OptionsKind::Enum => &ENUM_OPTIONS,
+        OptionsKind::EnumValue => &ENUM_VALUE_OPTIONS,
+        OptionsKind::Service => &SERVICE_OPTIONS,
+        OptionsKind::Method => &METHOD_OPTIONS,
+        OptionsKind::Oneof | OptionsKind::ExtensionRange => &[],
+    }
+}
+
+pub fn find_builtin_option(kind: OptionsKind, name: &str) -> Option<&'static BuiltinOption> {
+    builtin_options(kind)
+        .iter()
+        .find(|option| option.name == name)
+}
+
+/// Serialize files as a `google.protobuf.FileDescriptorSet`, the output of `protoc --descriptor_set_out`
+pub fn encode_file_descriptor_set(
+    files: &[&FileDescriptor],
+    include_source_info: bool,
+) -> Result<Vec<u8>> {
+    let mut writer = MessageWriter::new();
+    for file in files {
+        writer.write_message(
+            FILE_DESCRIPTOR_SET_FILE,
+            &encode_file(file, include_source_info)?,
+        )?;
+    }
+    Ok(writer.into_bytes())
+}
+
+pub fn encode_file(file: &FileDescriptor, include_source_info: bool) -> Result<MessageWriter> {
+    use file_descriptor_proto::*;
+    let mut writer = MessageWriter::new();
+    writer.write_string(NAME, &file.name)?;
+    if !file.package.is_empty() {
+        writer.write_string(PACKAGE, &file.package)?;
+    }
+    for dependency in &file.dependencies {
+        writer.write_string(DEPENDENCY, dependency)?;
+    }
+    for message in &file.messages {
+        writer.write_message(MESSAGE_TYPE, &encode_message(message)?)?;
+    }
+    for enum_descriptor in &file.enums {
+        writer.write_message(ENUM_TYPE, &encode_enum(enum_descriptor)?)?;
+    }
+    for service in &file.services {
+        writer.write_message(SERVICE, &encode_service(service)?)?;
+    }
+    for extension in &file.extensions {
+        writer.write_message(EXTENSION, &encode_field(extension)?)?;
+    }
+    write_options(&mut writer, OPTIONS, &file.options, OptionsKind::File)?;
+    if include_source_info {
+        writer.write_message(
+            SOURCE_CODE_INFO,
+            &encode_source_code_info(&file.source_code_info)?,
+        )?;
+    }
+    for index in &file.public_dependencies {
+        writer.write_int64(PUBLIC_DEPENDENCY, *index as i64)?;
+    }
+    for index in &file.weak_dependencies {
+        writer.write_int64(WEAK_DEPENDENCY, *index as i64)?;
+    }
+    if file.syntax == Syntax::Proto3 {
+        writer.write_string(SYNTAX, "proto3")?;
+    }
+    Ok(writer)
+}
+
+fn encode_range(range: &NumberRange, exclusive_end: bool) -> Result<MessageWriter> {
+    let mut writer = MessageWriter::new();
+    writer.write_int64(descriptor_proto::RANGE_START, range.start)?;
+    let end = if exclusive_end {
+        range.end + 1
+    } else {
+        range.end
+    };
+    writer.write_int64(descriptor_proto::RANGE_END, end)?;
+    Ok(writer)
+}
+
+fn encode_message(message: &MessageDescriptor) -> Result<MessageWriter> {
+    use descriptor_proto::*;
+    let mut writer = MessageWriter::new();
+    writer.write_string(NAME, &message.name)?;
+    for field in &message.fields {
+        writer.write_message(FIELD, &encode_field(field)?)?;
+    }
+    for nested_message in &message.nested_messages {
+        writer.write_message(NESTED_TYPE, &encode_message(nested_message)?)?;
+    }
+    for enum_descriptor in &message.enums {
+        writer.write_message(ENUM_TYPE, &encode_enum(enum_descriptor)?)?;
+    }
+    for range in &message.extension_ranges {
+        writer.write_message(EXTENSION_RANGE, &encode_range(range, true)?)?;
+    }
+    for extension in &message.extensions {
+        writer.write_message(EXTENSION, &encode_field(extension)?)?;
+    }
+    write_options(&mut writer, OPTIONS, &message.options, OptionsKind::Message)?;
+    for oneof in &message.oneofs {
+        let mut oneof_writer = MessageWriter::new();
+        oneof_writer.write_string(oneof_descriptor_proto::NAME, &oneof.name)?;
+        write_options(
+            &mut oneof_writer,
+            oneof_descriptor_proto::OPTIONS,
+            &oneof.options,
+            OptionsKind::Oneof,
+        )?;
+        writer.write_message(ONEOF_DECL, &oneof_writer)?;
+    }
+    for range in &message.reserved_ranges {
+        writer.write_message(RESERVED_RANGE, &encode_range(range, true)?)?;
+    }
+    for name in &message.reserved_names {
+        writer.write_string(RESERVED_NAME, name)?;
+    }
+    Ok(writer)
+}
+
+fn encode_field(field: &FieldDescriptor) -> Result<MessageWriter> {
+    use field_descriptor_proto::*;
+    let mut writer = MessageWriter::new();
+    writer.write_string(NAME, &field.name)?;
+    if let Some(extendee) = &field.extendee {
+        writer.write_string(EXTENDEE, &format!(".{}", extendee))?;
+    }
+    writer.write_int64(NUMBER, field.number as i64)?;
+    writer.write_uint64(LABEL, label_number(field.label))?;
+    writer.write_uint64(TYPE, field_type_number(&field.field_type))?;
+    match &field.field_type {
+        FieldType::Message(type_name) | FieldType::Enum(type_name) => {
+            writer.write_string(TYPE_NAME, &format!(".{}", type_name))?
+        }
+        FieldType::Scalar(_) => {}
+    }
+    if let Some(default_value) = &field.default_value {
+        writer.write_string(DEFAULT_VALUE, default_value)?;
+    }
+    write_options(&mut writer, OPTIONS, &field.options, OptionsKind::Field)?;
+    if let Some(oneof_index) = field.oneof_index {
+        writer.write_int64(ONEOF_INDEX, oneof_index as i64)?;
+    }
+    writer.write_string(JSON_NAME, &field.json_name)?;
+    if field.proto3_optional {
+        writer.write_bool(PROTO3_OPTIONAL, true)?;
+    }
+    Ok(writer)
+}
+
+fn encode_enum(enum_descriptor: &EnumDescriptor) -> Result<MessageWriter> {
+    use enum_descriptor_proto::*;
+    let mut writer = MessageWriter::new();
+    writer.write_string(NAME, &enum_descriptor.name)?;
+    for value in &enum_descriptor.values {
+        let mut value_writer = MessageWriter::new();
+        value_writer.write_string(enum_value_descriptor_proto::NAME, &value.name)?;
+        value_writer.write_int64(enum_value_descriptor_proto::NUMBER, value.number as i64)?;
+        write_options(
+            &mut value_writer,
+            enum_value_descriptor_proto::OPTIONS,
+            &value.options,
+            OptionsKind::EnumValue,
+        )?;
+        writer.write_message(VALUE, &value_writer)?;
+    }
+    write_options(
+        &mut writer,
+        OPTIONS,
+        &enum_descriptor.options,
+        OptionsKind::Enum,
+    )?;
+    for range in &enum_descriptor.reserved_ranges {
+        writer.write_message(RESERVED_RANGE, &encode_range(range, false)?)?;
+    }
+    for name in &enum_descriptor.reserved_names {
+        writer.write_string(RESERVED_NAME, name)?;
+    }
+    Ok(writer)
+}
+
+fn encode_service(service: &ServiceDescriptor) -> Result<MessageWriter> {
+    use service_descriptor_proto::*;
+    let mut writer = MessageWriter::new();
+    writer.write_string(NAME, &service.name)?;
+    for method in &service.methods {
+        writer.write_message(METHOD, &encode_method(method)?)?;
+    }
+    write_options(&mut writer, OPTIONS, &service.options, OptionsKind::Service)?;
+    Ok(writer)
+}
+
+fn encode_method(method: &MethodDescriptor) -> Result<MessageWriter> {
+    use method_descriptor_proto::*;
+    let mut writer = MessageWriter::new();
+    writer.write_string(NAME, &method.name)?;
+    writer.write_string(INPUT_TYPE, &format!(".{}", method.input_type))?;
+    writer.write_string(OUTPUT_TYPE, &format!(".{}", method.output_type))?;
+    write_options(&mut writer, OPTIONS, &method.options, OptionsKind::Method)?;
+    if method.client_streaming {
+        writer.write_bool(CLIENT_STREAMING, true)?;
+    }
+    if method.server_streaming {
+        writer.write_bool(SERVER_STREAMING, true)?;
+    }
+    Ok(writer)
+}
+
+/// Write the options message as field `field_number` of `writer`, if any option is set
+fn write_options(
+    writer: &mut MessageWriter,
+    field_number: u64,
+    options: &[SchemaOption],
+    kind: OptionsKind,
+) -> Result<()> {
+    let mut builtin_values: Vec<(&BuiltinOption, &Constant)> = options
+        .iter()
+        .filter_map(|option| match option.name.as_slice() {
+            [OptionNamePart::Simple(name)] => {
+                find_builtin_option(kind, name).map(|builtin| (builtin, &option.value))
+            }
+            _ => None,
+        })
+        .collect();
+    let custom_values: Vec<&(i32, OptionValue)> = options
+        .iter()
+        .filter_map(|option| option.custom.as_ref())
+        .collect();
+    if builtin_values.is_empty() && custom_values.is_empty() {
+        return Ok(());
+    }
+    // protoc serializes options messages in field number order
+    builtin_values.sort_by_key(|(builtin, _)| builtin.number);
+    let mut options_writer = MessageWriter::new();
+    for (builtin, value) in builtin_values {
+        match (builtin.option_type, value) {
+            (BuiltinOptionType::Bool, Constant::Bool(value)) => {
+                options_writer.write_bool(builtin.number, *value)?
+            }
+            (BuiltinOptionType::String, Constant::String(value)) => {
+                options_writer.write_string(builtin.number, value)?
+            }
+            (BuiltinOptionType::Enum(values), Constant::Identifier(identifier)) => {
+                if let Some((_, number)) = values.iter().find(|(name, _)| name == identifier) {
+                    options_writer.write_int64(builtin.number, *number as i64)?
+                }
+            }
+            _ => {} // Type mismatches are reported by the resolver
+        }
+    }
+    // Extension numbers start above every built-in option
+    for (number, value) in custom_values {
+        write_option_value(&mut options_writer, *number as u64, value)?;
+    }
+    writer.write_message(field_number, &options_writer)?;
+    Ok(())
+}
+
+fn write_option_value(writer: &mut MessageWriter, number: u64, value: &OptionValue) -> Result<()> {
+    let written = match value {
+        OptionValue::Scalar(scalar_type, constant) => match (scalar_type, constant) {
+            (ScalarType::Double, Constant::Float(value)) => {
+                writer.write_fixed64(number, value.to_bits())
+            }
+            (ScalarType::Float, Constant::Float(value)) => {
+                writer.write_fixed32(number, (*value as f32).to_bits())
+            }
+            (ScalarType::Int32 | ScalarType::Int64, Constant::Integer(value)) => {
+                writer.write_int64(number, *value as i64)
+            }
+            (ScalarType::Uint32 | ScalarType::Uint64, Constant::Integer(value)) => {
+                writer.write_uint64(number, *value as u64)
+            }
+            (ScalarType::Sint32 | ScalarType::Sint64, Constant::Integer(value)) => {
+                writer.write_sint64(number, *value as i64)
+            }
+            (ScalarType::Fixed32 | ScalarType::SFixed32, Constant::Integer(value)) => {
+                writer.write_fixed32(number, *value as u32)
+            }
+            (ScalarType::Fixed64 | ScalarType::SFixed64, Constant::Integer(value)) => {
+                writer.write_fixed64(number, *value as u64)
+            }
+            (ScalarType::Bool, Constant::Bool(value)) => writer.write_bool(number, *value),
+            (ScalarType::String, Constant::String(value)) => writer.write_string(number, value),
+            (ScalarType::Bytes, Constant::String(value)) => {
+                writer.write_bytes(number, value.as_bytes())
+            }
+            (ScalarType::Bytes, Constant::Bytes(value)) => writer.write_bytes(number, value),
+            _ => Ok(()), // Normalized by the resolver
+        },
+        OptionValue::Enum(value) => writer.write_int64(number, *value as i64),
+        OptionValue::Message(fields) => {
+            let mut message_writer = MessageWriter::new();
+            for (field_number, value) in fields {
+                write_option_value(&mut message_writer, *field_number as u64, value)?;
+            }
+            writer.write_message(number, &message_writer)
+        }
+    };
+    Ok(written?)
+}
+
+fn encode_source_code_info(locations: &[SourceLocation]) -> Result<MessageWriter> {
+    use source_code_info::*;
+    let mut writer = MessageWriter::new();
+    for location in locations {
+        let mut location_writer = MessageWriter::new();
+        // Packed fields with no elements are omitted entirely
+        let path: Vec<u64> = location.path.iter().map(|value| *value as u64).collect();
+        let span: Vec<u64> = location.span.iter().map(|value| *value as u64).collect();
+        if !path.is_empty() {
+            location_writer.write_packed_uint64(LOCATION_PATH, &path)?;
+        }
+        location_writer.write_packed_uint64(LOCATION_SPAN, &span)?;
+        if let Some(comments) = &location.leading_comments {
+            location_writer.write_string(LOCATION_LEADING_COMMENTS, comments)?;
+        }
+        if let Some(comments) = &location.trailing_comments {
+            location_writer.write_string(LOCATION_TRAILING_COMMENTS, comments)?;
+        }
+        for comments in &location.leading_detached_comments {
+            location_writer.write_string(LOCATION_LEADING_DETACHED_COMMENTS, comments)?;
+        }
+        writer.write_message(LOCATION, &location_writer)?;
+    }
+    Ok(writer)
+}
+
+fn decode_error(message: String) -> RsProtocError {
+    RsProtocError::EncodingError(format!("Invalid FileDescriptorSet: {}", message))
+}
+
+/// Strip the leading '.' of a fully-qualified type name
+fn decode_type_name(type_name: &str) -> String {
+    type_name.strip_prefix('.').unwrap_or(type_name).to_string()
+}
+
+/// Decode a serialized `google.protobuf.FileDescriptorSet`, such as the output of
+/// `protoc --descriptor_set_out --include_imports`, into the schema the resolver builds from
+/// .proto files. Every dependency of every file has to be part of the set.
+pub fn decode_file_descriptor_set(bytes: &[u8]) -> Result<Schema> {
+    let mut files = Vec::new();
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        if field_number == FILE_DESCRIPTOR_SET_FILE {
+            files.push(decode_file(value.as_bytes()?)?);
+        }
+    }
+
+    // Order the files so that every file follows its dependencies
+    let mut schema = Schema::default();
+    while !files.is_empty() {
+        let index = files.iter().position(|file| {
+            file.dependencies
+                .iter()
+                .all(|dependency| schema.file(dependency).is_some())
+        });
+        match index {
+            Some(index) => schema.files.push(files.remove(index)),
+            None => {
+                let file = &files[0];
+                let dependency = file
+                    .dependencies
+                    .iter()
+                    .find(|dependency| schema.file(dependency).is_none())
+                    .unwrap();
+                return Err(decode_error(
+                    if files.iter().any(|file| &file.name == dependency) {
+                        format!("\"{}\" is part of an import cycle", file.name)
+                    } else {
+                        format!(
+                            "\"{}\" imports \"{}\" which is missing from the set",
+                            file.name, dependency
+                        )
+                    },
+                ));
+            }
+        }
+    }
+    check_type_references(&schema)?;
+    Ok(schema)
+}
+
+fn decode_file(bytes: &[u8]) -> Result<FileDescriptor> {
+    use file_descriptor_proto::*;
+    let mut file = FileDescriptor {
+        name: String::new(),
+        package: String::new(),
+        syntax: Syntax::Proto2,
+        dependencies: Vec::new(),
+        public_dependencies: Vec::new(),
+        weak_dependencies: Vec::new(),
+        messages: Vec::new(),
+        enums: Vec::new(),
+        services: Vec::new(),
+        extensions: Vec::new(),
+        options: Vec::new(),
+        source_code_info: Vec::new(),
+        source_file: FileId::default(),
+    };
+    let mut syntax = String::new();
+    // Nested elements are decoded once the package is known, since it prefixes their full names
+    let mut messages = Vec::new();
+    let mut enums = Vec::new();
+    let mut services = Vec::new();
+    let mut extensions = Vec::new();
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        match field_number {
+            NAME => file.name = value.as_str()?.to_string(),
+            PACKAGE => file.package = value.as_str()?.to_string(),
+            DEPENDENCY => file.dependencies.push(value.as_str()?.to_string()),
+            MESSAGE_TYPE => messages.push(value.as_bytes()?),
+            ENUM_TYPE => enums.push(value.as_bytes()?),
+            SERVICE => services.push(value.as_bytes()?),
+            EXTENSION => extensions.push(value.as_bytes()?),
+            OPTIONS => file.options = decode_options(value.as_bytes()?, OptionsKind::File)?,
+            SOURCE_CODE_INFO => file.source_code_info = decode_source_code_info(value.as_bytes()?)?,
+            PUBLIC_DEPENDENCY => file
+                .public_dependencies
+                .extend(value.as_packed_u64()?.iter().map(|index| *index as usize)),
+            WEAK_DEPENDENCY => file
+                .weak_dependencies
+                .extend(value.as_packed_u64()?.iter().map(|index| *index as usize)),
+            SYNTAX => syntax = value.as_str()?.to_string(),
+            _ => {}
+        }
+    }
+    // FileDescriptorProto leaves the syntax empty for proto2 files
+    file.syntax = match syntax.as_str() {
+        "" | "proto2" => Syntax::Proto2,
+        "proto3" => Syntax::Proto3,
+        _ => {
+            return Err(decode_error(format!(
+                "\"{}\" uses syntax \"{}\", only proto2 and proto3 are supported",
+                file.name, syntax
+            )))
+        }
+    };
+    if let Some(index) = file
+        .public_dependencies
+        .iter()
+        .chain(file.weak_dependencies.iter())
+        .find(|index| **index >= file.dependencies.len())
+    {
+        return Err(decode_error(format!(
+            "\"{}\" refers to dependency {} which does not exist",
+            file.name, index
+        )));
+    }
+    for message in messages {
+        file.messages
+            .push(decode_message(message, &file.package, file.syntax)?);
+    }
+    for enum_descriptor in enums {
+        file.enums
+            .push(decode_enum(enum_descriptor, &file.package)?);
+    }
+    for service in services {
+        file.services.push(decode_service(service, &file.package)?);
+    }
+    for extension in extensions {
+        file.extensions.push(decode_field(extension, file.syntax)?);
+    }
+    Ok(file)
+}
+
+fn decode_range(bytes: &[u8], exclusive_end: bool) -> Result<NumberRange> {
+    let mut range = NumberRange { start: 0, end: 0 };
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        match field_number {
+            descriptor_proto::RANGE_START => range.start = value.as_i64()? as i32 as i64,
+            descriptor_proto::RANGE_END => range.end = value.as_i64()? as i32 as i64,
+            _ => {}
+        }
+    }
+    if exclusive_end {
+        range.end -= 1;
+    }
+    Ok(range)
+}
+
+fn decode_message(bytes: &[u8], scope: &str, syntax: Syntax) -> Result<MessageDescriptor> {
+    use descriptor_proto::*;
+    let mut message = MessageDescriptor {
+        name: String::new(),
+        full_name: String::new(),
+        fields: Vec::new(),
+        nested_messages: Vec::new(),
+        enums: Vec::new(),
+        extensions: Vec::new(),
+        oneofs: Vec::new(),
+        extension_ranges: Vec::new(),
+        reserved_ranges: Vec::new(),
+        reserved_names: Vec::new(),
+        options: Vec::new(),
+    };
+    let mut nested_messages = Vec::new();
+    let mut enums = Vec::new();
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        match field_number {
+            NAME => message.name = value.as_str()?.to_string(),
+            FIELD => message
+                .fields
+                .push(decode_field(value.as_bytes()?, syntax)?),
+            NESTED_TYPE => nested_messages.push(value.as_bytes()?),
+            ENUM_TYPE => enums.push(value.as_bytes()?),
+            EXTENSION_RANGE => message
+                .extension_ranges
+                .push(decode_range(value.as_bytes()?, true)?),
+            EXTENSION => message
+                .extensions
+                .push(decode_field(value.as_bytes()?, syntax)?),
+            OPTIONS => message.options = decode_options(value.as_bytes()?, OptionsKind::Message)?,
+            ONEOF_DECL => {
+                let mut oneof = OneofDescriptor {
+                    name: String::new(),
+                    options: Vec::new(),
+                };
+                let mut oneof_reader = MessageReader::new(value.as_bytes()?);
+                while let Some((field_number, value)) = oneof_reader.read_field()? {
+                    match field_number {
+                        oneof_descriptor_proto::NAME => oneof.name = value.as_str()?.to_string(),
+                        oneof_descriptor_proto::OPTIONS => {
+                            oneof.options = decode_options(value.as_bytes()?, OptionsKind::Oneof)?
+                        }
+                        _ => {}
+                    }
+                }
+                message.oneofs.push(oneof);
+            }
+            RESERVED_RANGE => message
+                .reserved_ranges
+                .push(decode_range(value.as_bytes()?, true)?),
+            RESERVED_NAME => message.reserved_names.push(value.as_str()?.to_string()),
+            _ => {}
+        }
+    }
+    message.full_name = qualified_name(scope, &message.name);
+    for nested_message in nested_messages {
+        message
+            .nested_messages
+            .push(decode_message(nested_message, &message.full_name, syntax)?);
+    }
+    for enum_descriptor in enums {
+        message
+            .enums
+            .push(decode_enum(enum_descriptor, &message.full_name)?);
+    }
+    if let Some(field) = message.fields.iter().find(|field| {
+        field
+            .oneof_index
+            .is_some_and(|oneof_index| oneof_index >= message.oneofs.len())
+    }) {
+        return Err(decode_error(format!(
+            "Field \"{}\" of \"{}\" refers to a oneof which does not exist",
+            field.name, message.full_name
+        )));
+    }
+    Ok(message)
+}
+
+fn decode_field(bytes: &[u8], syntax: Syntax) -> Result<FieldDescriptor> {
+    use field_descriptor_proto::*;
+    let mut field = FieldDescriptor {
+        name: String::new(),
+        number: 0,
+        label: Label::Optional,
+        field_type: FieldType::Scalar(ScalarType::Int32),
+        json_name: String::new(),
+        oneof_index: None,
+        proto3_optional: false,
+        extendee: None,
+        default_value: None,
+        packed: false,
+        options: Vec::new(),
+    };
+    let mut type_number = None;
+    let mut type_name = None;
+    let mut json_name = None;
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        match field_number {
+            NAME => field.name = value.as_str()?.to_string(),
+            EXTENDEE => field.extendee = Some(decode_type_name(value.as_str()?)),
+            NUMBER => field.number = value.as_i64()? as i32,
+            LABEL => {
+                field.label = match value.as_u64()? {
+                    1 => Label::Optional,
+                    2 => Label::Required,
+                    3 => Label::Repeated,
+                    label => return Err(decode_error(format!("Unknown label {}", label))),
+                }
+            }
+            TYPE => type_number = Some(value.as_u64()?),
+            TYPE_NAME => type_name = Some(decode_type_name(value.as_str()?)),
+            DEFAULT_VALUE => field.default_value = Some(value.as_str()?.to_string()),
+            OPTIONS => field.options = decode_options(value.as_bytes()?, OptionsKind::Field)?,
+            ONEOF_INDEX => field.oneof_index = Some(value.as_i64()? as usize),
+            JSON_NAME => json_name = Some(value.as_str()?.to_string()),
+            PROTO3_OPTIONAL => field.proto3_optional = value.as_bool()?,
+            _ => {}
+        }
+    }
+    field.field_type = match (type_number, type_name) {
+        (Some(11), Some(type_name)) => FieldType::Message(type_name),
+        (Some(14), Some(type_name)) => FieldType::Enum(type_name),
+        (Some(type_number), _) => {
+            match SCALAR_TYPES.iter().find(|scalar_type| {
+                field_type_number(&FieldType::Scalar(**scalar_type)) == type_number
+            }) {
+                Some(scalar_type) => FieldType::Scalar(*scalar_type),
+                None => {
+                    return Err(decode_error(format!(
+                        "Field \"{}\" has unsupported type {}",
+                        field.name, type_number
+                    )))
+                }
+            }
+        }
+        (None, _) => {
+            return Err(decode_error(format!(
+                "Field \"{}\" has no resolved type",
+                field.name
+            )))
+        }
+    };
+    field.json_name = json_name.unwrap_or_else(|| validator::json_name(&field.name));
+    field.packed = is_packed(syntax, field.label, &field.field_type, &field.options);
+    Ok(field)
+}
+
+const SCALAR_TYPES: [ScalarType; 15] = [
+    ScalarType::Double,
+    ScalarType::Float,
+    ScalarType::Int32,
+    ScalarType::Int64,
+    ScalarType::Uint32,
+    ScalarType::Uint64,
+    ScalarType::Sint32,
+    ScalarType::Sint64,
+    ScalarType::Fixed32,
+    ScalarType::Fixed64,
+    ScalarType::SFixed32,
+    ScalarType::SFixed64,
+    ScalarType::Bool,
+    ScalarType::String,
+    ScalarType::Bytes,
+];
+
+fn decode_enum(bytes: &[u8], scope: &str) -> Result<EnumDescriptor> {
+    use enum_descriptor_proto::*;
+    let mut enum_descriptor = EnumDescriptor {
+        name: String::new(),
+        full_name: String::new(),
+        values: Vec::new(),
+        reserved_ranges: Vec::new(),
+        reserved_names: Vec::new(),
+        options: Vec::new(),
+    };
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        match field_number {
+            NAME => enum_descriptor.name = value.as_str()?.to_string(),
+            VALUE => {
+                let mut enum_value = EnumValueDescriptor {
+                    name: String::new(),
+                    number: 0,
+                    options: Vec::new(),
+                };
+                let mut value_reader = MessageReader::new(value.as_bytes()?);
+                while let Some((field_number, value)) = value_reader.read_field()? {
+                    match field_number {
+                        enum_value_descriptor_proto::NAME => {
+                            enum_value.name = value.as_str()?.to_string()
+                        }
+                        enum_value_descriptor_proto::NUMBER => {
+                            enum_value.number = value.as_i64()? as i32
+                        }
+                        enum_value_descriptor_proto::OPTIONS => {
+                            enum_value.options =
+                                decode_options(value.as_bytes()?, OptionsKind::EnumValue)?
+                        }
+                        _ => {}
+                    }
+                }
+                enum_descriptor.values.push(enum_value);
+            }
+            OPTIONS => {
+                enum_descriptor.options = decode_options(value.as_bytes()?, OptionsKind::Enum)?
+            }
+            RESERVED_RANGE => enum_descriptor
+                .reserved_ranges
+                .push(decode_range(value.as_bytes()?, false)?),
+            RESERVED_NAME => enum_descriptor
+                .reserved_names
+                .push(value.as_str()?.to_string()),
+            _ => {}
+        }
+    }
+    enum_descriptor.full_name = qualified_name(scope, &enum_descriptor.name);
+    Ok(enum_descriptor)
+}
+
+fn decode_service(bytes: &[u8], scope: &str) -> Result<ServiceDescriptor> {
+    use service_descriptor_proto::*;
+    let mut service = ServiceDescriptor {
+        name: String::new(),
+        full_name: String::new(),
+        methods: Vec::new(),
+        options: Vec::new(),
+    };
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        match field_number {
+            NAME => service.name = value.as_str()?.to_string(),
+            METHOD => service.methods.push(decode_method(value.as_bytes()?)?),
+            OPTIONS => service.options = decode_options(value.as_bytes()?, OptionsKind::Service)?,
+            _ => {}
+        }
+    }
+    service.full_name = qualified_name(scope, &service.name);
+    Ok(service)
+}
+
+fn decode_method(bytes: &[u8]) -> Result<MethodDescriptor> {
+    use method_descriptor_proto::*;
+    let mut method = MethodDescriptor {
+        name: String::new(),
+        input_type: String::new(),
+        output_type: String::new(),
+        client_streaming: false,
+        server_streaming: false,
+        options: Vec::new(),
+    };
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        match field_number {
+            NAME => method.name = value.as_str()?.to_string(),
+            INPUT_TYPE => method.input_type = decode_type_name(value.as_str()?),
+            OUTPUT_TYPE => method.output_type = decode_type_name(value.as_str()?),
+            OPTIONS => method.options = decode_options(value.as_bytes()?, OptionsKind::Method)?,
+            CLIENT_STREAMING => method.client_streaming = value.as_bool()?,
+            SERVER_STREAMING => method.server_streaming = value.as_bool()?,
+            _ => {}
+        }
+    }
+    Ok(method)
+}
+
+/// Decode the built-in options of an options message
+fn decode_options(bytes: &[u8], kind: OptionsKind) -> Result<Vec<SchemaOption>> {
+    let mut options = Vec::new();
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        // Custom options are skipped as naming them takes their extension declarations
+        let builtin = match builtin_options(kind)
+            .iter()
+            .find(|builtin| builtin.number == field_number)
+        {
+            Some(builtin) => builtin,
+            None => continue,
+        };
+        let value = match builtin.option_type {
+            BuiltinOptionType::Bool => Constant::Bool(value.as_bool()?),
+            BuiltinOptionType::String => Constant::String(value.as_str()?.to_string()),
+            BuiltinOptionType::Enum(values) => {
+                let number = value.as_i64()? as i32;
+                match values.iter().find(|(_, value)| *value == number) {
+                    Some((name, _)) => Constant::Identifier(name.to_string()),
+                    None => {
+                        return Err(decode_error(format!(
+                            "Unknown value {} for option \"{}\"",
+                            number, builtin.name
+                        )))
+                    }
+                }
+            }
+        };
+        options.push(SchemaOption {
+            name: vec![OptionNamePart::Simple(builtin.name.to_string())],
+            value,
+            custom: None,
+        });
+    }
+    Ok(options)
+}
+
+fn decode_source_code_info(bytes: &[u8]) -> Result<Vec<SourceLocation>> {
+    use source_code_info::*;
+    let mut locations = Vec::new();
+    let mut reader = MessageReader::new(bytes);
+    while let Some((field_number, value)) = reader.read_field()? {
+        if field_number != LOCATION {
+            continue;
+        }
+        let mut location = SourceLocation::default();
+        let mut location_reader = MessageReader::new(value.as_bytes()?);
+        while let Some((field_number, value)) = location_reader.read_field()? {
+            match field_number {
+                LOCATION_PATH => location
+                    .path
+                    .extend(value.as_packed_u64()?.iter().map(|value| *value as i32)),
+                LOCATION_SPAN => location
+                    .span
+                    .extend(value.as_packed_u64()?.iter().map(|value| *value as i32)),
+                LOCATION_LEADING_COMMENTS => {
+                    location.leading_comments = Some(value.as_str()?.to_string())
+                }
+                LOCATION_TRAILING_COMMENTS => {
+                    location.trailing_comments = Some(value.as_str()?.to_string())
+                }
+                LOCATION_LEADING_DETACHED_COMMENTS => location
+                    .leading_detached_comments
+                    .push(value.as_str()?.to_string()),
+                _ => {}
+            }
+        }
+        locations.push(location);
+    }
+    Ok(locations)
+}
+
+/// Every type referenced by a field, extension or method has to be defined in the set
+fn check_type_references(schema: &Schema) -> Result<()> {
+    fn collect_messages<'a>(
+        messages: &'a [MessageDescriptor],
+        message_names: &mut HashSet<&'a str>,
+        enum_names: &mut HashSet<&'a str>,
+        fields: &mut Vec<&'a FieldDescriptor>,
+    ) {
+        for message in messages {
+            message_names.insert(&message.full_name);
+            enum_names.extend(message.enums.iter().map(|e| e.full_name.as_str()));
+            fields.extend(message.fields.iter().chain(message.extensions.iter()));
+            collect_messages(&message.nested_messages, message_names, enum_names, fields);
+        }
+    }
+    let mut message_names = HashSet::new();
+    let mut enum_names = HashSet::new();
+    let mut fields = Vec::new();
+    let mut method_types = Vec::new();
+    for file in &schema.files {
+        enum_names.extend(file.enums.iter().map(|e| e.full_name.as_str()));
+        fields.extend(file.extensions.iter());
+        collect_messages(
+            &file.messages,
+            &mut message_names,
+            &mut enum_names,
+            &mut fields,
+        );
+        for service in &file.services {
+            for method in &service.methods {
+                method_types.push(method.input_type.as_str());
+                method_types.push(method.output_type.as_str());
+            }
+        }
+    }
+    let undefined = fields
+        .iter()
+        .flat_map(|field| {
+            let type_reference = match &field.field_type {
+                FieldType::Message(type_name) => Some((type_name.as_str(), &message_names)),
+                FieldType::Enum(type_name) => Some((type_name.as_str(), &enum_names)),
+                FieldType::Scalar(_) => None,
+            };
+            type_reference.into_iter().chain(
+                field
+                    .extendee
+                    .as_deref()
+                    .map(|extendee| (extendee, &message_names)),
+            )
+        })
+        .chain(method_types.into_iter().map(|name| (name, &message_names)))
+        .find(|(name, names)| !names.contains(name));
+    match undefined {
+        Some((name, _)) => Err(decode_error(format!("\"{}\" is not defined", name))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::{resolve, LoadedFile};
+
+    #[test]
+    fn encode_enum_test() {
+        let file = FileDescriptor {
+            name: "a.proto".to_string(),
+            package: String::new(),
+            syntax: Syntax::Proto3,
+            dependencies: Vec::new(),
+            public_dependencies: Vec::new(),
+            weak_dependencies: Vec::new(),
+            messages: Vec::new(),
+            enums: vec![EnumDescriptor {
+                name: "E".to_string(),
+                full_name: "E".to_string(),
+                values: vec![EnumValueDescriptor {
+                    name: "A".to_string(),
+                    number: 0,
+                    options: Vec::new(),
+                }],
+                reserved_ranges: Vec::new(),
+                reserved_names: Vec::new(),
+                options: vec![SchemaOption {
+                    name: vec![OptionNamePart::Simple("allow_alias".to_string())],
+                    value: Constant::Bool(true),
+                    custom: None,
+                }],
+            }],
+            services: Vec::new(),
+            extensions: Vec::new(),
+            options: Vec::new(),
+            source_code_info: Vec::new(),
+            source_file: FileId::default(),
+        };
+        let bytes = encode_file_descriptor_set(&[&file], false).unwrap();
+        // Encoded by hand following descriptor.proto
+        let expected: &[u8] = &[
+            0x0a, 0x21, // file
+            0x0a, 0x07, b'a', b'.', b'p', b'r', b'o', b't', b'o', // name
+            0x2a, 0x0e, // enum_type
+            0x0a, 0x01, b'E', // name
+            0x12, 0x05, 0x0a, 0x01, b'A', 0x10, 0x00, // value
+            0x1a, 0x02, 0x10, 0x01, // options.allow_alias
+            0x62, 0x06, b'p', b'r', b'o', b't', b'o', b'3', // syntax
+        ];
+        assert_eq!(bytes, expected);
+    }
+
+    fn compile(sources: &[(&str, &str)]) -> Schema {
+        let files: Vec<LoadedFile> = sources
+            .iter()
+            .map(|(name, text)| LoadedFile::from_source(name, text.to_string()).unwrap())
+            .collect();
+        resolve(&files).unwrap()
+    }
+
+    #[test]
+    fn write_custom_options_test() {
+        let options = [
+            SchemaOption {
+                name: vec![OptionNamePart::Simple("deprecated".to_string())],
+                value: Constant::Bool(true),
+                custom: None,
+            },
+            SchemaOption {
+                name: vec![OptionNamePart::Extension("rules".to_string())],
+                value: Constant::Message(Vec::new()),
+                custom: Some((
+                    50000,
+                    OptionValue::Message(vec![
+                        (
+                            1,
+                            OptionValue::Scalar(ScalarType::Int32, Constant::Integer(-1)),
+                        ),
+                        (2, OptionValue::Enum(2)),
+                        (
+                            3,
+                            OptionValue::Scalar(ScalarType::Float, Constant::Float(1.5)),
+                        ),
+                    ]),
+                )),
+            },
+        ];
+        let mut writer = MessageWriter::new();
+        write_options(&mut writer, 8, &options, OptionsKind::Field).unwrap();
+        let mut expected = vec![0x42, 24, 0x18, 0x01, 0x82, 0xb5, 0x18, 18, 0x08];
+        expected.extend([0xff; 9]);
+        expected.extend([0x01, 0x10, 0x02, 0x1d, 0x00, 0x00, 0xc0, 0x3f]);
+        assert_eq!(writer.into_bytes(), expected);
+    }
+
+    #[test]
+    fn decode_round_trip_test() {
+        let schema = compile(&[
+            (
+                "dep.proto",
+                r#"syntax = "proto3"; package dep; enum Color { RED = 0; BLUE = 1; }"#,
+            ),
+            (
+                "main.proto",
+                r#"syntax = "proto3";
+                package foo.bar;
+                import public "dep.proto";
+                option java_package = "com.foo";
+                option optimize_for = CODE_SIZE;
+                // Doc of M
+                message M {
+                    optional string name = 1 [json_name = "fullName"]; // Doc of name
+                    map<string, M> children = 2;
+                    repeated dep.Color colors = 3 [packed = false];
+                    oneof choice { int64 a = 4; Inner b = 5; }
+                    message Inner { bytes data = 1; }
+                    reserved 10 to 12, 100 to max;
+                    reserved "foo";
+                }
+                enum E { option allow_alias = true; A = 0; B = 0; reserved 5 to 6; }
+                service S {
+                    rpc Call(stream M) returns (stream M) { option idempotency_level = IDEMPOTENT; }
+                }"#,
+            ),
+        ]);
+        let files: Vec<&FileDescriptor> = schema.files.iter().collect();
+        let bytes = encode_file_descriptor_set(&files, true).unwrap();
+
+        let decoded = decode_file_descriptor_set(&bytes).unwrap();
+        let decoded_files: Vec<&FileDescriptor> = decoded.files.iter().collect();
+        assert_eq!(
+            encode_file_descriptor_set(&decoded_files, true).unwrap(),
+            bytes
+        );
+
+        let file = decoded.file("main.proto").unwrap();
+        assert_eq!(file.public_dependencies, vec![0]);
+        let message = &file.messages[0];
+        assert_eq!(message.full_name, "foo.bar.M");
+        assert_eq!(
+            message.nested_messages[0].full_name,
+            "foo.bar.M.ChildrenEntry"
+        );
+        assert!(message.nested_messages[0].is_map_entry());
+        assert_eq!(message.fields[0].json_name, "fullName");
+        assert_eq!(
+            message.fields[2].field_type,
+            FieldType::Enum("dep.Color".to_string())
+        );
+        assert_eq!(
+            message.reserved_ranges[1],
+            NumberRange {
+                start: 100,
+                end: crate::parser::MAX_FIELD_NUMBER
+            }
+        );
+        assert_eq!(
+            file.enums[0].reserved_ranges[0],
+            NumberRange { start: 5, end: 6 }
+        );
+        assert!(file.services[0].methods[0].client_streaming);
+        let comments = |path: &[i32]| {
+            let location = file
+                .source_code_info
+                .iter()
+                .find(|location| location.path == path)
+                .unwrap();
+            (
+                location.leading_comments.as_deref(),
+                location.trailing_comments.as_deref(),
+            )
+        };
+        assert_eq!(comments(&[4, 0]), (Some(" Doc of M\n"), None));
+        assert_eq!(comments(&[4, 0, 2, 0]), (None, Some(" Doc of name\n")));
+    }
+
+    #[test]
+    fn decode_proto2_test() {
+        let schema = compile(&[(
+            "main.proto",
+            r#"syntax = "proto2";
+            message M {
+                optional int32 count = 1 [default = 7];
+                repeated int32 values = 2;
+                repeated int32 packed_values = 3 [packed = true];
+            }"#,
+        )]);
+        let files: Vec<&FileDescriptor> = schema.files.iter().collect();
+        let bytes = encode_file_descriptor_set(&files, false).unwrap();
+        // FileDescriptorProto.syntax is left empty for proto2
+        assert!(!bytes.windows(6).any(|window| window == b"proto2"));
+
+        let decoded = decode_file_descriptor_set(&bytes).unwrap();
+        let decoded_files: Vec<&FileDescriptor> = decoded.files.iter().collect();
+        assert_eq!(
+            encode_file_descriptor_set(&decoded_files, false).unwrap(),
+            bytes
+        );
+        let file = decoded.file("main.proto").unwrap();
+        assert_eq!(file.syntax, Syntax::Proto2);
+        let fields = &file.messages[0].fields;
+        assert_eq!(fields[0].default_value.as_deref(), Some("7"));
+        assert!(!fields[1].packed);
+        assert!(fields[2].packed);
+    }
+
+    #[test]
+    fn decode_error_test() {
+        let schema = compile(&[
+            ("dep.proto", r#"syntax = "proto3"; message Dep {}"#),
+            (
+                "main.proto",
+                r#"syntax = "proto3"; import "dep.proto"; message M { Dep dep = 1; }"#,
+            ),
+        ]);
+        // Without its dependency the set is incomplete
+        let bytes =
+            encode_file_descriptor_set(&[schema.file("main.proto").unwrap()], false).unwrap();
+        assert!(decode_file_descriptor_set(&bytes)
+            .is_err_and(|error| error.to_string().contains("which is missing from the set")));
+
+        // Files are reordered so dependencies come first
+        let bytes = encode_file_descriptor_set(
+            &[
+                schema.file("main.proto").unwrap(),
+                schema.file("dep.proto").unwrap(),
+            ],
+            false,
+        )
+        .unwrap();
+        let decoded = decode_file_descriptor_set(&bytes).unwrap();
+        assert_eq!(decoded.files[0].name, "dep.proto");
+
+        // A dangling type reference
+        let mut dangling = compile(&[("m.proto", r#"syntax = "proto3"; message M { M m = 1; }"#)]);
+        dangling.files[0].messages[0].fields[0].field_type = FieldType::Message("N".to_string());
+        let bytes = encode_file_descriptor_set(&[&dangling.files[0]], false).unwrap();
+        assert!(decode_file_descriptor_set(&bytes)
+            .is_err_and(|error| error.to_string().contains("\"N\" is not defined")));
+
+        // Truncated input
+        let bytes =
+            encode_file_descriptor_set(&[schema.file("dep.proto").unwrap()], false).unwrap();
+        assert!(decode_file_descriptor_set(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,661 @@
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    json::JsonValue,
+    lexer::TokenMetadata,
+    source_map::{ColumnUnit, SourceMap},
+    source_text::SourceRegistry,
+};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const CYAN: &str = "\x1b[1;36m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// How diagnostics are printed
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DiagnosticFormat {
+    /// The offending lines of the source, underlined
+    #[default]
+    Pretty,
+    /// `file:line:column: message`, exactly like protoc's default format
+    Gcc,
+    /// `file(line) : error in column=column: message`, like protoc's Visual Studio format
+    Msvs,
+    /// One JSON object per diagnostic, on a single line
+    Json,
+}
+
+impl FromStr for DiagnosticFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "pretty" => Ok(DiagnosticFormat::Pretty),
+            "gcc" => Ok(DiagnosticFormat::Gcc),
+            "msvs" => Ok(DiagnosticFormat::Msvs),
+            "json" => Ok(DiagnosticFormat::Json),
+            _ => Err(format!("Unknown error format: {}", format)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => CYAN,
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A token called out by a diagnostic
+#[derive(Clone, PartialEq, Debug)]
+pub struct Label {
+    pub metadata: TokenMetadata,
+    pub message: String,
+    /// Primary labels mark the problem itself, secondary ones add context
+    pub primary: bool,
+}
+
+/// A problem found in a source file, along with everything needed to explain it
+#[derive(Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Short identifier of the kind of problem, e.g. "parse-error"
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code: None,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    pub fn note(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Note, message)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_primary_label(
+        mut self,
+        metadata: &TokenMetadata,
+        message: impl Into<String>,
+    ) -> Self {
+        self.labels.push(Label {
+            metadata: metadata.clone(),
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary_label(
+        mut self,
+        metadata: &TokenMetadata,
+        message: impl Into<String>,
+    ) -> Self {
+        self.labels.push(Label {
+            metadata: metadata.clone(),
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// The label the diagnostic is reported at, the first primary one if there are several
+    pub fn primary_label(&self) -> Option<&Label> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .or(self.labels.first())
+    }
+
+    /// Render the diagnostic with the labelled lines of `source` underlined, rustc style.
+    /// Without the source text only the locations of the labels are printed.
+    pub fn render(&self, file_name: &str, source: Option<&str>, color: bool) -> String {
+        self.render_files(Files::single(file_name, source), color)
+    }
+
+    fn render_files(&self, files: Files, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        let mut output = String::new();
+        let title = match self.code {
+            Some(code) => format!("{}[{}]", self.severity, code),
+            None => self.severity.to_string(),
+        };
+        _ = writeln!(
+            output,
+            "{}{}",
+            paint(self.severity.color(), &title),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        let mut located: Vec<(&Label, &str, LabelLocation)> = self
+            .labels
+            .iter()
+            .map(|label| {
+                let (file_name, source) = files.file(label);
+                (label, file_name, LabelLocation::new(label, source))
+            })
+            .collect();
+        let gutter_width = located
+            .iter()
+            .map(|(_, _, location)| (location.line + 1).to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = paint(BLUE, &format!("{} |", " ".repeat(gutter_width)));
+
+        let primary_file = files.primary_file(self);
+        if let Some(label) = self.primary_label() {
+            let (file_name, source) = files.file(label);
+            let location = LabelLocation::new(label, source);
+            _ = writeln!(
+                output,
+                "{}{} {}:{}:{}",
+                " ".repeat(gutter_width),
+                paint(BLUE, "-->"),
+                file_name,
+                location.line + 1,
+                location.column
+            );
+        } else if !primary_file.is_empty() {
+            // Without a location, still tell which file the diagnostic is about
+            _ = writeln!(
+                output,
+                "{}{} {}",
+                " ".repeat(gutter_width.max(1)),
+                paint(BLUE, "-->"),
+                primary_file
+            );
+        }
+
+        // Print every labelled line once, in source order, with one underline per label. Labels
+        // in other files than the primary one follow, under a header naming their file.
+        located.sort_by_key(|(label, file_name, location)| {
+            (
+                *file_name != primary_file,
+                *file_name,
+                location.line,
+                location.column,
+                !label.primary,
+            )
+        });
+        let mut previous_line = None;
+        for (label, file_name, location) in &located {
+            let Some(line_text) = location.line_text else {
+                continue;
+            };
+            if previous_line.is_none_or(|(previous_file, _)| previous_file != *file_name) {
+                if previous_line.is_some() || *file_name != primary_file {
+                    if previous_line.is_some() {
+                        _ = writeln!(output, "{}", gutter);
+                    }
+                    _ = writeln!(
+                        output,
+                        "{}{} {}:{}:{}",
+                        " ".repeat(gutter_width),
+                        paint(BLUE, ":::"),
+                        file_name,
+                        location.line + 1,
+                        location.column
+                    );
+                }
+                _ = writeln!(output, "{}", gutter);
+            }
+            if previous_line != Some((*file_name, location.line)) {
+                _ = writeln!(
+                    output,
+                    "{} {}",
+                    paint(BLUE, &format!("{:>gutter_width$} |", location.line + 1)),
+                    line_text
+                );
+                previous_line = Some((*file_name, location.line));
+            }
+            // Mirror tabs so that the underline lines up with the source line
+            let padding: String = line_text
+                .graphemes(true)
+                .take(location.column - 1)
+                .map(|grapheme| if grapheme == "\t" { '\t' } else { ' ' })
+                .collect();
+            let width = location.width.max(1);
+            let (marker, style) = if label.primary {
+                ('^', self.severity.color())
+            } else {
+                ('-', BLUE)
+            };
+            let mut underline = marker.to_string().repeat(width);
+            if !label.message.is_empty() {
+                underline.push(' ');
+                underline.push_str(&label.message);
+            }
+            _ = writeln!(output, "{} {}{}", gutter, padding, paint(style, &underline));
+        }
+
+        for note in &self.notes {
+            _ = writeln!(
+                output,
+                "{} {} {}",
+                " ".repeat(gutter_width),
+                paint(BLUE, "="),
+                paint(BOLD, &format!("note: {}", note))
+            );
+        }
+        if let Some(help) = &self.help {
+            _ = writeln!(
+                output,
+                "{} {} {}",
+                " ".repeat(gutter_width),
+                paint(BLUE, "="),
+                paint(BOLD, &format!("help: {}", help))
+            );
+        }
+        output
+    }
+}
+
+impl Diagnostic {
+    /// Print the diagnostic in `format`, the result ends with a newline.
+    /// `color` only applies to the pretty format.
+    pub fn format(
+        &self,
+        format: DiagnosticFormat,
+        file_name: &str,
+        source: Option<&str>,
+        color: bool,
+    ) -> String {
+        self.format_files(format, Files::single(file_name, source), color)
+    }
+
+    /// Like `format`, with the name and text of the file of every label looked up in
+    /// `sources`. Labels in unregistered text are taken to be in `file_name`.
+    pub fn format_in(
+        &self,
+        format: DiagnosticFormat,
+        file_name: &str,
+        sources: &SourceRegistry,
+        color: bool,
+    ) -> String {
+        let files = Files {
+            file_name,
+            source: sources.find(file_name).map(|buffer| buffer.text()),
+            registry: Some(sources),
+        };
+        self.format_files(format, files, color)
+    }
+
+    fn format_files(&self, format: DiagnosticFormat, files: Files, color: bool) -> String {
+        match format {
+            DiagnosticFormat::Pretty => self.render_files(files, color),
+            DiagnosticFormat::Gcc | DiagnosticFormat::Msvs => self.to_protoc_text(format, files),
+            DiagnosticFormat::Json => self.to_json(files),
+        }
+    }
+
+    /// Mirrors how protoc's error collector prints errors and warnings
+    fn to_protoc_text(&self, format: DiagnosticFormat, files: Files) -> String {
+        let mut output = files.primary_file(self).to_string();
+        if let Some(label) = self.primary_label() {
+            let location = LabelLocation::new(label, files.file(label).1);
+            if format == DiagnosticFormat::Msvs {
+                _ = write!(
+                    output,
+                    "({}) : {} in column={}",
+                    location.line + 1,
+                    self.severity,
+                    location.protoc_column + 1
+                );
+            } else {
+                _ = write!(
+                    output,
+                    ":{}:{}",
+                    location.line + 1,
+                    location.protoc_column + 1
+                );
+            }
+        }
+        match self.severity {
+            Severity::Error => _ = writeln!(output, ": {}", self.message),
+            severity => _ = writeln!(output, ": {}: {}", severity, self.message),
+        }
+        output
+    }
+
+    /// `{"file", "range", "severity", "code", "message"}`, with one based lines and columns
+    /// counted in graphemes. The end of the range is exclusive, and it is null when the
+    /// diagnostic is not tied to a location.
+    fn to_json(&self, files: Files) -> String {
+        let range = self.primary_label().map(|label| {
+            let location = LabelLocation::new(label, files.file(label).1);
+            let position = |column: usize| {
+                JsonValue::object([
+                    ("line", JsonValue::from(location.line + 1)),
+                    ("column", JsonValue::from(column)),
+                ])
+            };
+            JsonValue::object([
+                ("start", position(location.column)),
+                ("end", position(location.column + location.width)),
+            ])
+        });
+        let json = JsonValue::object([
+            ("file", JsonValue::from(files.primary_file(self))),
+            ("range", JsonValue::from(range)),
+            ("severity", JsonValue::from(self.severity.to_string())),
+            ("code", JsonValue::from(self.code)),
+            ("message", JsonValue::from(self.message.as_str())),
+        ]);
+        format!("{}\n", json)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.primary_label() {
+            Some(label) => write!(f, "{}: {}", label.metadata, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Where the files labelled by a diagnostic are found
+#[derive(Clone, Copy)]
+struct Files<'a> {
+    /// The file the diagnostic was reported for, and of labels in unregistered text
+    file_name: &'a str,
+    source: Option<&'a str>,
+    registry: Option<&'a SourceRegistry>,
+}
+
+impl<'a> Files<'a> {
+    fn single(file_name: &'a str, source: Option<&'a str>) -> Self {
+        Files {
+            file_name,
+            source,
+            registry: None,
+        }
+    }
+
+    /// Name and text of the file `label` points into
+    fn file(&self, label: &Label) -> (&'a str, Option<&'a str>) {
+        match self
+            .registry
+            .and_then(|registry| registry.get(label.metadata.file()))
+        {
+            Some(buffer) => (buffer.name(), Some(buffer.text())),
+            None => (self.file_name, self.source),
+        }
+    }
+
+    /// Name of the file the diagnostic is reported at
+    fn primary_file(&self, diagnostic: &Diagnostic) -> &'a str {
+        match diagnostic.primary_label() {
+            Some(label) => self.file(label).0,
+            None => self.file_name,
+        }
+    }
+}
+
+/// Where a label lands in the source text
+struct LabelLocation<'a> {
+    /// Zero based line number
+    line: usize,
+    /// One based column number, counted in graphemes
+    column: usize,
+    /// Zero based column number the way protoc counts it, in bytes with tab stops every 8 columns
+    protoc_column: usize,
+    /// Number of graphemes labelled, up to the end of the line
+    width: usize,
+    line_text: Option<&'a str>,
+}
+
+impl<'a> LabelLocation<'a> {
+    fn new(label: &Label, source: Option<&'a str>) -> Self {
+        let fallback = LabelLocation {
+            line: label.metadata.line_number(),
+            column: label.metadata.column_number(),
+            protoc_column: label.metadata.column_number() - 1,
+            width: label.metadata.span_len(),
+            line_text: None,
+        };
+        let offset = label.metadata.offset();
+        let Some(source) = source.filter(|source| source.is_char_boundary(offset)) else {
+            return fallback;
+        };
+        let source_map = SourceMap::new(source);
+        let start = source_map.line_column(offset, ColumnUnit::Grapheme);
+        let line_start = source_map.line_start(start.line);
+        let line_end = line_start + source_map.line_text(start.line).len();
+        let line_text = &source[line_start..line_end];
+        let end = label
+            .metadata
+            .end_offset()
+            .clamp(offset, line_end.max(offset));
+        LabelLocation {
+            line: start.line,
+            column: start.column + 1,
+            protoc_column: source_map.protoc_line_column(offset).column,
+            width: source_map.line_column(end, ColumnUnit::Grapheme).column - start.column,
+            line_text: Some(line_text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Diagnostic, DiagnosticFormat};
+    use crate::{lexer::Lexer, source_text::SourceRegistry};
+
+    #[test]
+    fn diagnostic_render_test() {
+        let source = "syntax = \"proto3\";\nmessage M {\n\tint32 a = 1\n}\n";
+        let tokens: Vec<_> = Lexer::new(source).collect();
+        let message_name = &tokens[5].metadata;
+        let close_brace = &tokens.last().unwrap().metadata;
+        let diagnostic = Diagnostic::error("Expected \";\" but found \"}\"")
+            .with_code("parse-error")
+            .with_primary_label(close_brace, "unexpected token")
+            .with_secondary_label(message_name, "in this message")
+            .with_help("add a \";\" after the field");
+        assert_eq!(
+            diagnostic.render("test.proto", Some(source), false),
+            "error[parse-error]: Expected \";\" but found \"}\"
+ --> test.proto:4:1
+  |
+2 | message M {
+  |         - in this message
+4 | }
+  | ^ unexpected token
+  = help: add a \";\" after the field
+"
+        );
+
+        // Without the source text only the location is known
+        assert_eq!(
+            Diagnostic::error("Missing field")
+                .with_primary_label(message_name, "")
+                .render("test.proto", None, false),
+            format!(
+                "error: Missing field\n --> test.proto:2:{}\n",
+                message_name.column_number()
+            )
+        );
+        assert_eq!(
+            Diagnostic::warning("Unused import").render("test.proto", Some(source), false),
+            "warning: Unused import\n --> test.proto\n"
+        );
+    }
+
+    #[test]
+    fn diagnostic_render_tab_test() {
+        let source = "message M {\n\tint32 a = 1;\n}";
+        let tokens: Vec<_> = Lexer::new(source).collect();
+        let field_name = &tokens[4].metadata;
+        let rendered = Diagnostic::error("Bad field")
+            .with_primary_label(field_name, "")
+            .render("test.proto", Some(source), false);
+        assert!(rendered.contains(" --> test.proto:2:8\n"), "{}", rendered);
+        assert!(rendered.contains("  | \t      ^\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn diagnostic_render_unicode_test() {
+        // A combining accent and an emoji before the label, each one grapheme
+        let source = "option (a) = \"e\u{301}\u{1F600}\"; x";
+        let tokens: Vec<_> = Lexer::new(source).collect();
+        let string = &tokens[5].metadata;
+        let rendered = Diagnostic::error("Bad value")
+            .with_primary_label(string, "")
+            .render("test.proto", Some(source), false);
+        assert!(rendered.contains(" --> test.proto:1:14\n"), "{}", rendered);
+        assert!(rendered.contains("  |              ^^^^\n"), "{}", rendered);
+        // protoc counts bytes
+        let gcc = Diagnostic::error("Bad")
+            .with_primary_label(&tokens[7].metadata, "")
+            .format(DiagnosticFormat::Gcc, "test.proto", Some(source), false);
+        assert_eq!(gcc, "test.proto:1:25: Bad\n");
+    }
+
+    #[test]
+    fn diagnostic_render_files_test() {
+        let mut sources = SourceRegistry::default();
+        let main = sources.add(
+            "main.proto",
+            Path::new("main.proto"),
+            "import \"dep.proto\";\nmessage M {}\n".to_string(),
+        );
+        let dep = sources.add(
+            "dep.proto",
+            Path::new("dep.proto"),
+            "\nmessage M {}\n".to_string(),
+        );
+        let main_name = &Lexer::for_buffer(&main).nth(4).unwrap().metadata;
+        let dep_name = &Lexer::for_buffer(&dep).nth(1).unwrap().metadata;
+        let diagnostic = Diagnostic::error("\"M\" is already defined")
+            .with_primary_label(main_name, "")
+            .with_secondary_label(dep_name, "first defined here");
+        // The file name given only applies to labels in unregistered text
+        assert_eq!(
+            diagnostic.format_in(DiagnosticFormat::Pretty, "other.proto", &sources, false),
+            "error: \"M\" is already defined
+ --> main.proto:2:9
+  |
+2 | message M {}
+  |         ^
+  |
+ ::: dep.proto:2:9
+  |
+2 | message M {}
+  |         - first defined here
+"
+        );
+        assert_eq!(
+            diagnostic.format_in(DiagnosticFormat::Gcc, "other.proto", &sources, false),
+            "main.proto:2:9: \"M\" is already defined\n"
+        );
+    }
+
+    #[test]
+    fn diagnostic_format_test() {
+        let source = "message M {\n\tint32 a = 1;\n}";
+        let tokens: Vec<_> = Lexer::new(source).collect();
+        let field_name = &tokens[4].metadata;
+        let error = Diagnostic::error("Field \"a\" is bad")
+            .with_code("validation-error")
+            .with_primary_label(field_name, "");
+        // protoc counts a tab as advancing to the next multiple of 8 columns
+        assert_eq!(
+            error.format(DiagnosticFormat::Gcc, "test.proto", Some(source), false),
+            "test.proto:2:15: Field \"a\" is bad\n"
+        );
+        assert_eq!(
+            error.format(DiagnosticFormat::Msvs, "test.proto", Some(source), false),
+            "test.proto(2) : error in column=15: Field \"a\" is bad\n"
+        );
+        assert_eq!(
+            error.format(DiagnosticFormat::Json, "test.proto", Some(source), false),
+            r#"{"file":"test.proto","range":{"start":{"line":2,"column":8},"end":{"line":2,"column":9}},"severity":"error","code":"validation-error","message":"Field \"a\" is bad"}"#
+                .to_string()
+                + "\n"
+        );
+
+        let warning = Diagnostic::warning("Import is unused\tand\u{1}");
+        assert_eq!(
+            warning.format(DiagnosticFormat::Gcc, "test.proto", None, false),
+            "test.proto: warning: Import is unused\tand\u{1}\n"
+        );
+        assert_eq!(
+            warning.format(DiagnosticFormat::Json, "test.proto", None, false),
+            r#"{"file":"test.proto","range":null,"severity":"warning","code":null,"message":"Import is unused\tand\u0001"}"#
+                .to_string()
+                + "\n"
+        );
+        assert_eq!("json".parse(), Ok(DiagnosticFormat::Json));
+        assert!("xml".parse::<DiagnosticFormat>().is_err());
+    }
+}
@@ -0,0 +1,628 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    str::FromStr,
+};
+
+use crate::{
+    codegen::GeneratedFile,
+    schema::{
+        EnumDescriptor, FieldDescriptor, FieldType, FileDescriptor, Label, MessageDescriptor,
+        Schema, ServiceDescriptor,
+    },
+};
+
+// Reference documentation of a schema: one page per package listing its messages with their
+// fields, its enums with their values and its services with their methods, along with the doc
+// comments attached to each of them. Every message and enum is the target of an anchor named
+// after its full name, types referenced by fields and methods link to it.
+
+/// Name of the page linking to every package page
+pub const INDEX_PAGE: &str = "index";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DocFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl DocFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            DocFormat::Markdown => "md",
+            DocFormat::Html => "html",
+        }
+    }
+}
+
+impl FromStr for DocFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "markdown" => Ok(DocFormat::Markdown),
+            "html" => Ok(DocFormat::Html),
+            _ => Err(format!("Unknown documentation format: {}", format)),
+        }
+    }
+}
+
+/// Name of the page documenting `package`
+pub fn package_page_name(package: &str, format: DocFormat) -> String {
+    let package = if package.is_empty() { "_" } else { package };
+    format!("{}.{}", package, format.extension())
+}
+
+/// Render the documentation of the packages of the files of `schema` named in `file_names`,
+/// plus an index page linking to them
+pub fn generate_docs(
+    schema: &Schema,
+    file_names: &[String],
+    format: DocFormat,
+) -> Vec<GeneratedFile> {
+    let mut packages: BTreeMap<&str, Vec<&FileDescriptor>> = BTreeMap::new();
+    for file in &schema.files {
+        if file_names.contains(&file.name) {
+            packages.entry(&file.package).or_default().push(file);
+        }
+    }
+    let mut generator = DocGenerator {
+        format,
+        docs: schema.element_docs(),
+        messages: HashMap::new(),
+        type_pages: HashMap::new(),
+    };
+    // Types of packages without a page are shown without a link
+    for file in &schema.files {
+        let page = packages
+            .contains_key(file.package.as_str())
+            .then(|| package_page_name(&file.package, format));
+        for message in &file.messages {
+            generator.index_message(message, page.as_deref());
+        }
+        if let Some(page) = page {
+            for enum_descriptor in &file.enums {
+                generator
+                    .type_pages
+                    .insert(enum_descriptor.full_name.clone(), page.clone());
+            }
+        }
+    }
+
+    let mut generated_files = Vec::new();
+    let mut index = Page::new(format, "Packages");
+    index.heading(1, None, "Packages");
+    let mut package_links = Vec::new();
+    for (package, files) in &packages {
+        let title = if package.is_empty() {
+            "(no package)".to_string()
+        } else {
+            package.to_string()
+        };
+        let name = package_page_name(package, format);
+        package_links.push(vec![vec![Inline::Link(title.clone(), name.clone())]]);
+        generated_files.push(GeneratedFile {
+            name,
+            content: generator.package_page(&title, package, files),
+        });
+    }
+    index.table(&["Package"], package_links);
+    generated_files.push(GeneratedFile {
+        name: format!("{}.{}", INDEX_PAGE, format.extension()),
+        content: index.finish(),
+    });
+    generated_files
+}
+
+struct DocGenerator<'a> {
+    format: DocFormat,
+    /// Comments of elements keyed by `FileDescriptor::element_name`
+    docs: HashMap<String, String>,
+    messages: HashMap<&'a str, &'a MessageDescriptor>,
+    /// Page documenting each message and enum, by full name
+    type_pages: HashMap<String, String>,
+}
+
+impl<'a> DocGenerator<'a> {
+    fn index_message(&mut self, message: &'a MessageDescriptor, page: Option<&str>) {
+        self.messages.insert(&message.full_name, message);
+        if let Some(page) = page {
+            self.type_pages
+                .insert(message.full_name.clone(), page.to_string());
+            for enum_descriptor in &message.enums {
+                self.type_pages
+                    .insert(enum_descriptor.full_name.clone(), page.to_string());
+            }
+        }
+        for nested_message in &message.nested_messages {
+            self.index_message(nested_message, page);
+        }
+    }
+
+    fn package_page(&self, title: &str, package: &str, files: &[&FileDescriptor]) -> String {
+        let mut page = Page::new(self.format, title);
+        page.heading(1, None, &format!("Package {}", title));
+        let file_names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+        page.paragraph(&format!("Defined in {}.", file_names.join(", ")));
+
+        let mut messages = Vec::new();
+        let mut enums = Vec::new();
+        for file in files {
+            for message in &file.messages {
+                collect_types(message, &mut messages, &mut enums);
+            }
+            enums.extend(&file.enums);
+        }
+        let services: Vec<&ServiceDescriptor> =
+            files.iter().flat_map(|file| &file.services).collect();
+
+        // Names are shown relative to the package
+        let local_name = |full_name: &str| -> String {
+            match full_name.strip_prefix(package) {
+                Some(name) if !package.is_empty() => name.trim_start_matches('.').to_string(),
+                _ => full_name.to_string(),
+            }
+        };
+        if !messages.is_empty() {
+            page.heading(2, None, "Messages");
+            for message in &messages {
+                page.heading(3, Some(&message.full_name), &local_name(&message.full_name));
+                self.comment(&mut page, &message.full_name);
+                let rows: Vec<Vec<Cell>> = message
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        vec![
+                            vec![Inline::Code(field.name.clone())],
+                            vec![Inline::Text(field.number.to_string())],
+                            self.field_type(field),
+                            vec![Inline::Text(self.field_label(message, field))],
+                            vec![Inline::Text(
+                                self.summary(&format!("{}.{}", message.full_name, field.name)),
+                            )],
+                        ]
+                    })
+                    .collect();
+                if !rows.is_empty() {
+                    page.table(&["Field", "Number", "Type", "Label", "Description"], rows);
+                }
+            }
+        }
+        if !enums.is_empty() {
+            page.heading(2, None, "Enums");
+            for enum_descriptor in &enums {
+                let full_name = &enum_descriptor.full_name;
+                page.heading(3, Some(full_name), &local_name(full_name));
+                self.comment(&mut page, full_name);
+                let rows = enum_descriptor
+                    .values
+                    .iter()
+                    .map(|value| {
+                        vec![
+                            vec![Inline::Code(value.name.clone())],
+                            vec![Inline::Text(value.number.to_string())],
+                            vec![Inline::Text(
+                                self.summary(&format!("{}.{}", full_name, value.name)),
+                            )],
+                        ]
+                    })
+                    .collect();
+                page.table(&["Name", "Number", "Description"], rows);
+            }
+        }
+        if !services.is_empty() {
+            page.heading(2, None, "Services");
+            for service in &services {
+                page.heading(3, Some(&service.full_name), &local_name(&service.full_name));
+                self.comment(&mut page, &service.full_name);
+                let rows = service
+                    .methods
+                    .iter()
+                    .map(|method| {
+                        vec![
+                            vec![Inline::Code(method.name.clone())],
+                            self.method_type(&method.input_type, method.client_streaming),
+                            self.method_type(&method.output_type, method.server_streaming),
+                            vec![Inline::Text(
+                                self.summary(&format!("{}.{}", service.full_name, method.name)),
+                            )],
+                        ]
+                    })
+                    .collect();
+                page.table(&["Method", "Request", "Response", "Description"], rows);
+            }
+        }
+        page.finish()
+    }
+
+    /// The comments of the element as paragraphs
+    fn comment(&self, page: &mut Page, name: &str) {
+        let Some(doc) = self.docs.get(name) else {
+            return;
+        };
+        for paragraph in doc.split("\n\n") {
+            let lines: Vec<&str> = paragraph.lines().map(str::trim).collect();
+            page.paragraph(&lines.join(" "));
+        }
+    }
+
+    /// The comments of the element on a single line, for table cells
+    fn summary(&self, name: &str) -> String {
+        let Some(doc) = self.docs.get(name) else {
+            return String::new();
+        };
+        doc.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn type_reference(&self, full_name: &str) -> Inline {
+        match self.type_pages.get(full_name) {
+            Some(page) => Inline::Link(full_name.to_string(), format!("{}#{}", page, full_name)),
+            None => Inline::Code(full_name.to_string()),
+        }
+    }
+
+    fn value_type(&self, field_type: &FieldType) -> Inline {
+        match field_type {
+            FieldType::Scalar(scalar_type) => Inline::Code(scalar_type.as_str().to_string()),
+            FieldType::Message(name) | FieldType::Enum(name) => self.type_reference(name),
+        }
+    }
+
+    fn field_type(&self, field: &FieldDescriptor) -> Cell {
+        // Map fields are documented as maps rather than as their entry messages
+        if let Some(entry) = self.map_entry(field) {
+            if let [key, value] = entry.fields.as_slice() {
+                return vec![
+                    Inline::Code("map<".to_string()),
+                    self.value_type(&key.field_type),
+                    Inline::Code(", ".to_string()),
+                    self.value_type(&value.field_type),
+                    Inline::Code(">".to_string()),
+                ];
+            }
+        }
+        vec![self.value_type(&field.field_type)]
+    }
+
+    fn map_entry(&self, field: &FieldDescriptor) -> Option<&'a MessageDescriptor> {
+        match &field.field_type {
+            FieldType::Message(type_name) if field.label == Label::Repeated => self
+                .messages
+                .get(type_name.as_str())
+                .copied()
+                .filter(|message| message.is_map_entry()),
+            _ => None,
+        }
+    }
+
+    fn field_label(&self, message: &MessageDescriptor, field: &FieldDescriptor) -> String {
+        if let Some(oneof) = field
+            .oneof_index
+            .filter(|_| !field.proto3_optional)
+            .and_then(|index| message.oneofs.get(index))
+        {
+            return format!("oneof {}", oneof.name);
+        }
+        match field.label {
+            _ if field.proto3_optional => "optional",
+            Label::Repeated if self.map_entry(field).is_some() => "",
+            Label::Repeated => "repeated",
+            Label::Required => "required",
+            Label::Optional => "",
+        }
+        .to_string()
+    }
+
+    fn method_type(&self, full_name: &str, streaming: bool) -> Cell {
+        let mut cell = Vec::new();
+        if streaming {
+            cell.push(Inline::Text("stream ".to_string()));
+        }
+        cell.push(self.type_reference(full_name));
+        cell
+    }
+}
+
+/// Messages and enums defined in `message` and `message` itself, without map entries
+fn collect_types<'a>(
+    message: &'a MessageDescriptor,
+    messages: &mut Vec<&'a MessageDescriptor>,
+    enums: &mut Vec<&'a EnumDescriptor>,
+) {
+    if message.is_map_entry() {
+        return;
+    }
+    messages.push(message);
+    for nested_message in &message.nested_messages {
+        collect_types(nested_message, messages, enums);
+    }
+    enums.extend(&message.enums);
+}
+
+type Cell = Vec<Inline>;
+
+enum Inline {
+    Text(String),
+    Code(String),
+    /// Text and target of a link
+    Link(String, String),
+}
+
+/// A page being rendered in one of the output formats
+struct Page {
+    format: DocFormat,
+    output: String,
+}
+
+impl Page {
+    fn new(format: DocFormat, title: &str) -> Self {
+        let mut output = String::new();
+        if format == DocFormat::Html {
+            _ = writeln!(
+                output,
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>",
+                escape_html(title)
+            );
+        }
+        Page { format, output }
+    }
+
+    fn heading(&mut self, level: usize, anchor: Option<&str>, text: &str) {
+        match self.format {
+            DocFormat::Markdown => {
+                if let Some(anchor) = anchor {
+                    _ = writeln!(self.output, "<a id=\"{}\"></a>\n", anchor);
+                }
+                _ = writeln!(self.output, "{} {}\n", "#".repeat(level), text);
+            }
+            DocFormat::Html => {
+                let id = anchor
+                    .map(|anchor| format!(" id=\"{}\"", escape_html(anchor)))
+                    .unwrap_or_default();
+                _ = writeln!(
+                    self.output,
+                    "<h{level}{}>{}</h{level}>",
+                    id,
+                    escape_html(text)
+                );
+            }
+        }
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        match self.format {
+            DocFormat::Markdown => _ = writeln!(self.output, "{}\n", text),
+            DocFormat::Html => _ = writeln!(self.output, "<p>{}</p>", escape_html(text)),
+        }
+    }
+
+    fn table(&mut self, headers: &[&str], rows: Vec<Vec<Cell>>) {
+        match self.format {
+            DocFormat::Markdown => {
+                _ = writeln!(self.output, "| {} |", headers.join(" | "));
+                let separators: Vec<&str> = headers.iter().map(|_| "---").collect();
+                _ = writeln!(self.output, "| {} |", separators.join(" | "));
+                for row in rows {
+                    let cells: Vec<String> = row.iter().map(markdown_cell).collect();
+                    _ = writeln!(self.output, "| {} |", cells.join(" | "));
+                }
+                self.output.push('\n');
+            }
+            DocFormat::Html => {
+                self.output.push_str("<table>\n<tr>");
+                for header in headers {
+                    _ = write!(self.output, "<th>{}</th>", escape_html(header));
+                }
+                self.output.push_str("</tr>\n");
+                for row in rows {
+                    self.output.push_str("<tr>");
+                    for cell in row {
+                        _ = write!(self.output, "<td>{}</td>", html_cell(&cell));
+                    }
+                    self.output.push_str("</tr>\n");
+                }
+                self.output.push_str("</table>\n");
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        if self.format == DocFormat::Html {
+            self.output.push_str("</body>\n</html>\n");
+        }
+        self.output
+    }
+}
+
+fn markdown_cell(cell: &Cell) -> String {
+    let mut output = String::new();
+    let mut in_code = false;
+    for inline in cell {
+        // Adjacent code is merged, back to back code spans would read as a longer delimiter
+        match (inline, in_code) {
+            (Inline::Code(_), false) => output.push('`'),
+            (Inline::Text(_) | Inline::Link(_, _), true) => output.push('`'),
+            _ => {}
+        }
+        in_code = matches!(inline, Inline::Code(_));
+        match inline {
+            // Pipes would end the cell
+            Inline::Text(text) => output.push_str(&text.replace('|', "\\|")),
+            Inline::Code(code) => output.push_str(code),
+            Inline::Link(text, target) => _ = write!(output, "[`{}`]({})", text, target),
+        }
+    }
+    if in_code {
+        output.push('`');
+    }
+    output
+}
+
+fn html_cell(cell: &Cell) -> String {
+    let mut output = String::new();
+    for inline in cell {
+        match inline {
+            Inline::Text(text) => output.push_str(&escape_html(text)),
+            Inline::Code(code) => _ = write!(output, "<code>{}</code>", escape_html(code)),
+            Inline::Link(text, target) => {
+                _ = write!(
+                    output,
+                    "<a href=\"{}\"><code>{}</code></a>",
+                    escape_html(target),
+                    escape_html(text)
+                )
+            }
+        }
+    }
+    output
+}
+
+fn escape_html(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            _ => output.push(ch),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::{resolve, LoadedFile};
+
+    fn generate_sources(sources: &[(&str, &str)], format: DocFormat) -> Vec<GeneratedFile> {
+        let files: Vec<LoadedFile> = sources
+            .iter()
+            .map(|(name, text)| LoadedFile::from_source(name, text.to_string()).unwrap())
+            .collect();
+        let schema = resolve(&files).unwrap();
+        generate_docs(&schema, &[sources[1].0.to_string()], format)
+    }
+
+    const SOURCES: [(&str, &str); 2] = [
+        (
+            "dep.proto",
+            r#"syntax = "proto3"; package dep; message Ignored {}"#,
+        ),
+        (
+            "search.proto",
+            r#"syntax = "proto3";
+            package search;
+            import "dep.proto";
+
+            // A search query.
+            //
+            // Sent by clients.
+            message Request {
+                string query = 1; // The text to look for
+                map<string, Kind> filters = 2;
+                oneof page { int32 number = 3; string token = 4; }
+                dep.Ignored other = 5;
+                // What to search for
+                enum Kind {
+                    KIND_UNSPECIFIED = 0;
+                    KIND_WEB = 1; // Web pages | links
+                }
+            }
+            message Response { repeated string results = 1; }
+            service Search {
+                // Runs a query
+                rpc Run(Request) returns (stream Response);
+            }"#,
+        ),
+    ];
+
+    #[test]
+    fn markdown_test() {
+        let files = generate_sources(&SOURCES, DocFormat::Markdown);
+        assert_eq!(
+            files
+                .iter()
+                .map(|file| file.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["search.md", "index.md"]
+        );
+        assert_eq!(
+            files[0].content,
+            r#"# Package search
+
+Defined in search.proto.
+
+## Messages
+
+<a id="search.Request"></a>
+
+### Request
+
+A search query.
+
+Sent by clients.
+
+| Field | Number | Type | Label | Description |
+| --- | --- | --- | --- | --- |
+| `query` | 1 | `string` |  | The text to look for |
+| `filters` | 2 | `map<string, `[`search.Request.Kind`](search.md#search.Request.Kind)`>` |  |  |
+| `number` | 3 | `int32` | oneof page |  |
+| `token` | 4 | `string` | oneof page |  |
+| `other` | 5 | `dep.Ignored` |  |  |
+
+<a id="search.Response"></a>
+
+### Response
+
+| Field | Number | Type | Label | Description |
+| --- | --- | --- | --- | --- |
+| `results` | 1 | `string` | repeated |  |
+
+## Enums
+
+<a id="search.Request.Kind"></a>
+
+### Request.Kind
+
+What to search for
+
+| Name | Number | Description |
+| --- | --- | --- |
+| `KIND_UNSPECIFIED` | 0 |  |
+| `KIND_WEB` | 1 | Web pages \| links |
+
+## Services
+
+<a id="search.Search"></a>
+
+### Search
+
+| Method | Request | Response | Description |
+| --- | --- | --- | --- |
+| `Run` | [`search.Request`](search.md#search.Request) | stream [`search.Response`](search.md#search.Response) | Runs a query |
+
+"#
+        );
+        assert_eq!(
+            files[1].content,
+            "# Packages\n\n| Package |\n| --- |\n| [`search`](search.md) |\n\n"
+        );
+    }
+
+    #[test]
+    fn html_test() {
+        let files = generate_sources(&SOURCES, DocFormat::Html);
+        let page = &files[0].content;
+        assert_eq!(files[0].name, "search.html");
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<h3 id=\"search.Request\">Request</h3>\n<p>A search query.</p>"));
+        assert!(page.contains(
+            "<td><a href=\"search.html#search.Request\"><code>search.Request</code></a></td>"
+        ));
+        assert!(page.contains("<td><code>map&lt;</code><code>string</code>"));
+        assert!(page.ends_with("</body>\n</html>\n"));
+    }
+}
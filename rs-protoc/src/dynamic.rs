@@ -0,0 +1,420 @@
+use std::collections::BTreeMap;
+
+use encoding::{
+    message::{FieldValue, MessageReader, MessageWriter},
+    wire_types::WireTypeEnum,
+};
+
+use crate::{
+    ast_elements::{Constant, ScalarType},
+    error::{Result, RsProtocError},
+    schema::{FieldDescriptor, FieldType, Label, MessageDescriptor, Schema},
+};
+
+// Messages whose type is only known at runtime, from a resolved schema. Fields are keyed by
+// number, extensions alongside the fields of the message, and hold their values in order:
+// singular fields hold a single value, repeated fields one value per element and map fields one
+// entry message per key. The text format and JSON mappings convert to and from this model, the
+// binary encoding is handled here.
+
+/// The value of a field, or of one element of a repeated field
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Bool(bool),
+    /// int32, sint32 and sfixed32
+    I32(i32),
+    /// int64, sint64 and sfixed64
+    I64(i64),
+    /// uint32 and fixed32
+    U32(u32),
+    /// uint64 and fixed64
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    /// Number of the enum value, which does not have to be declared
+    Enum(i32),
+    Message(DynamicMessage),
+}
+
+impl Value {
+    /// Convert a constant normalized by `check_scalar`
+    pub(crate) fn from_constant(scalar_type: ScalarType, constant: Constant) -> Value {
+        match (scalar_type, constant) {
+            (
+                ScalarType::Int32 | ScalarType::Sint32 | ScalarType::SFixed32,
+                Constant::Integer(value),
+            ) => Value::I32(value as i32),
+            (
+                ScalarType::Int64 | ScalarType::Sint64 | ScalarType::SFixed64,
+                Constant::Integer(value),
+            ) => Value::I64(value as i64),
+            (ScalarType::Uint32 | ScalarType::Fixed32, Constant::Integer(value)) => {
+                Value::U32(value as u32)
+            }
+            (ScalarType::Uint64 | ScalarType::Fixed64, Constant::Integer(value)) => {
+                Value::U64(value as u64)
+            }
+            (ScalarType::Float, Constant::Float(value)) => Value::F32(value as f32),
+            (_, Constant::Float(value)) => Value::F64(value),
+            (_, Constant::Bool(value)) => Value::Bool(value),
+            (ScalarType::Bytes, Constant::String(value)) => Value::Bytes(value.into_bytes()),
+            (_, Constant::String(value)) => Value::String(value),
+            (_, Constant::Bytes(value)) => Value::Bytes(value),
+            (_, constant) => unreachable!("{:?} is not normalized", constant),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DynamicMessage {
+    /// Full name of the message type
+    pub type_name: String,
+    /// Values of the fields that are set, by field number
+    pub fields: BTreeMap<i32, Vec<Value>>,
+}
+
+impl DynamicMessage {
+    /// An empty message of the type `type_name`
+    pub fn new(type_name: &str) -> Self {
+        DynamicMessage {
+            type_name: type_name.to_string(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Values of the field `number`, empty when it is not set
+    pub fn get(&self, number: i32) -> &[Value] {
+        self.fields.get(&number).map_or(&[], Vec::as_slice)
+    }
+
+    /// Replace the value of a singular field
+    pub fn set(&mut self, number: i32, value: Value) {
+        self.fields.insert(number, vec![value]);
+    }
+
+    /// Append an element to a repeated field
+    pub fn push(&mut self, number: i32, value: Value) {
+        self.fields.entry(number).or_default().push(value);
+    }
+
+    /// Parse a message of the type `type_name` from the binary encoding
+    pub fn decode(schema: &Schema, type_name: &str, bytes: &[u8]) -> Result<Self> {
+        let mut message = DynamicMessage::new(type_name);
+        message.merge(schema, bytes)?;
+        Ok(message)
+    }
+
+    /// Merge fields from the binary encoding: singular scalars are replaced, singular messages
+    /// merged and repeated fields appended to. Fields missing from the schema are dropped.
+    pub fn merge(&mut self, schema: &Schema, bytes: &[u8]) -> Result<()> {
+        let descriptor = find_message(schema, &self.type_name)?;
+        let mut reader = MessageReader::new(bytes);
+        while let Some((number, value)) = reader.read_field()? {
+            let Some(field) = i32::try_from(number)
+                .ok()
+                .and_then(|number| find_field(schema, descriptor, number))
+            else {
+                continue;
+            };
+            if field.label == Label::Repeated {
+                let values = self.fields.entry(field.number).or_default();
+                match value {
+                    // Packed elements, which are accepted whether or not the field is packed
+                    FieldValue::Len(bytes) if field.field_type.is_packable() => {
+                        let mut elements = MessageReader::new(bytes);
+                        while !elements.is_empty() {
+                            let element = elements.read_value(wire_type(&field.field_type))?;
+                            values.push(read_value(schema, &field.field_type, &element)?);
+                        }
+                    }
+                    value => values.push(read_value(schema, &field.field_type, &value)?),
+                }
+                continue;
+            }
+            if let (Some([Value::Message(message)]), FieldValue::Len(bytes)) = (
+                self.fields.get_mut(&field.number).map(Vec::as_mut_slice),
+                value,
+            ) {
+                message.merge(schema, bytes)?;
+                continue;
+            }
+            // Setting a member of a oneof clears the other members
+            if let Some(oneof_index) = field.oneof_index {
+                for member in &descriptor.fields {
+                    if member.oneof_index == Some(oneof_index) && member.number != field.number {
+                        self.fields.remove(&member.number);
+                    }
+                }
+            }
+            self.set(field.number, read_value(schema, &field.field_type, &value)?);
+        }
+        Ok(())
+    }
+
+    /// Serialize the message to the binary encoding, fields in number order. Repeated scalars
+    /// are packed as their field says.
+    pub fn encode(&self, schema: &Schema) -> Result<Vec<u8>> {
+        let descriptor = find_message(schema, &self.type_name)?;
+        let mut writer = MessageWriter::new();
+        for (number, values) in &self.fields {
+            let field = find_field(schema, descriptor, *number).ok_or_else(|| {
+                RsProtocError::EncodingError(format!(
+                    "Message \"{}\" has no field number {}",
+                    self.type_name, number
+                ))
+            })?;
+            if field.packed {
+                let mut elements = MessageWriter::new();
+                for value in values {
+                    write_value(schema, &mut elements, field, value)?;
+                }
+                writer.write_tag(*number as u64, WireTypeEnum::Len)?;
+                writer.push_length_delimited(elements.as_bytes())?;
+                continue;
+            }
+            for value in values {
+                writer.write_tag(*number as u64, wire_type(&field.field_type))?;
+                write_value(schema, &mut writer, field, value)?;
+            }
+        }
+        Ok(writer.into_bytes())
+    }
+}
+
+fn find_message<'s>(schema: &'s Schema, type_name: &str) -> Result<&'s MessageDescriptor> {
+    schema.find_message(type_name).ok_or_else(|| {
+        RsProtocError::EncodingError(format!("Unknown message type \"{}\"", type_name))
+    })
+}
+
+/// The field `number` of `message`, or the extension of `message` with that number
+pub(crate) fn find_field<'s>(
+    schema: &'s Schema,
+    message: &'s MessageDescriptor,
+    number: i32,
+) -> Option<&'s FieldDescriptor> {
+    message
+        .fields
+        .iter()
+        .find(|field| field.number == number)
+        .or_else(|| {
+            schema
+                .extensions_of(&message.full_name)
+                .into_iter()
+                .map(|(_, extension)| extension)
+                .find(|extension| extension.number == number)
+        })
+}
+
+fn wire_type(field_type: &FieldType) -> WireTypeEnum {
+    match field_type {
+        FieldType::Scalar(scalar_type) => match scalar_type {
+            ScalarType::Double | ScalarType::Fixed64 | ScalarType::SFixed64 => WireTypeEnum::I64,
+            ScalarType::Float | ScalarType::Fixed32 | ScalarType::SFixed32 => WireTypeEnum::I32,
+            ScalarType::String | ScalarType::Bytes => WireTypeEnum::Len,
+            _ => WireTypeEnum::Varint,
+        },
+        FieldType::Enum(_) => WireTypeEnum::Varint,
+        FieldType::Message(_) => WireTypeEnum::Len,
+    }
+}
+
+/// Write `value` without a tag
+fn write_value(
+    schema: &Schema,
+    writer: &mut MessageWriter,
+    field: &FieldDescriptor,
+    value: &Value,
+) -> Result<()> {
+    let written = match (&field.field_type, value) {
+        (FieldType::Scalar(scalar_type), value) => match (scalar_type, value) {
+            (ScalarType::Double, Value::F64(value)) => writer.push_fixed64(value.to_bits()),
+            (ScalarType::Float, Value::F32(value)) => writer.push_fixed32(value.to_bits()),
+            (ScalarType::Int32, Value::I32(value)) => writer.push_varint(*value as i64 as u64),
+            (ScalarType::Int64, Value::I64(value)) => writer.push_varint(*value as u64),
+            (ScalarType::Uint32, Value::U32(value)) => writer.push_varint(*value as u64),
+            (ScalarType::Uint64, Value::U64(value)) => writer.push_varint(*value),
+            (ScalarType::Sint32, Value::I32(value)) => writer.push_sint64(*value as i64),
+            (ScalarType::Sint64, Value::I64(value)) => writer.push_sint64(*value),
+            (ScalarType::Fixed32, Value::U32(value)) => writer.push_fixed32(*value),
+            (ScalarType::Fixed64, Value::U64(value)) => writer.push_fixed64(*value),
+            (ScalarType::SFixed32, Value::I32(value)) => writer.push_fixed32(*value as u32),
+            (ScalarType::SFixed64, Value::I64(value)) => writer.push_fixed64(*value as u64),
+            (ScalarType::Bool, Value::Bool(value)) => writer.push_varint(*value as u64),
+            (ScalarType::String, Value::String(value)) => {
+                writer.push_length_delimited(value.as_bytes())
+            }
+            (ScalarType::Bytes, Value::Bytes(value)) => writer.push_length_delimited(value),
+            _ => return Err(mismatched_value(field, value)),
+        },
+        (FieldType::Enum(_), Value::Enum(value)) => writer.push_varint(*value as i64 as u64),
+        (FieldType::Message(_), Value::Message(message)) => {
+            writer.push_length_delimited(&message.encode(schema)?)
+        }
+        _ => return Err(mismatched_value(field, value)),
+    };
+    Ok(written?)
+}
+
+fn mismatched_value(field: &FieldDescriptor, value: &Value) -> RsProtocError {
+    RsProtocError::EncodingError(format!(
+        "Field \"{}\" cannot hold the value {:?}",
+        field.name, value
+    ))
+}
+
+fn read_value(schema: &Schema, field_type: &FieldType, value: &FieldValue) -> Result<Value> {
+    let value = match field_type {
+        FieldType::Scalar(scalar_type) => match scalar_type {
+            ScalarType::Double => Value::F64(f64::from_bits(value.as_fixed64()?)),
+            ScalarType::Float => Value::F32(f32::from_bits(value.as_fixed32()?)),
+            ScalarType::Int32 => Value::I32(value.as_i64()? as i32),
+            ScalarType::Int64 => Value::I64(value.as_i64()?),
+            ScalarType::Uint32 => Value::U32(value.as_u64()? as u32),
+            ScalarType::Uint64 => Value::U64(value.as_u64()?),
+            ScalarType::Sint32 => Value::I32(value.as_sint64()? as i32),
+            ScalarType::Sint64 => Value::I64(value.as_sint64()?),
+            ScalarType::Fixed32 => Value::U32(value.as_fixed32()?),
+            ScalarType::Fixed64 => Value::U64(value.as_fixed64()?),
+            ScalarType::SFixed32 => Value::I32(value.as_fixed32()? as i32),
+            ScalarType::SFixed64 => Value::I64(value.as_fixed64()? as i64),
+            ScalarType::Bool => Value::Bool(value.as_u64()? != 0),
+            ScalarType::String => Value::String(value.as_str()?.to_string()),
+            ScalarType::Bytes => Value::Bytes(value.as_bytes()?.to_vec()),
+        },
+        FieldType::Enum(_) => Value::Enum(value.as_i64()? as i32),
+        FieldType::Message(type_name) => Value::Message(DynamicMessage::decode(
+            schema,
+            type_name,
+            value.as_bytes()?,
+        )?),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::{resolve, LoadedFile};
+
+    fn schema(source: &str) -> Schema {
+        let file = LoadedFile::from_source("test.proto", source.to_string()).unwrap();
+        resolve(&[file]).unwrap()
+    }
+
+    const SOURCE: &str = r#"syntax = "proto3";
+        package p;
+        message M {
+            int32 a = 1;
+            repeated sint32 b = 2;
+            repeated int32 c = 3 [packed = false];
+            N n = 4;
+            oneof choice {
+                string s = 5;
+                bytes raw = 6;
+            }
+            extensions 100 to 200;
+        }
+        message N {
+            double x = 1;
+            repeated string tags = 2;
+        }
+        extend M {
+            fixed32 ext = 100;
+        }"#;
+
+    #[test]
+    fn encode_test() {
+        let schema = schema(SOURCE);
+        let mut message = DynamicMessage::new("p.M");
+        message.set(1, Value::I32(150));
+        message.push(2, Value::I32(-1));
+        message.push(2, Value::I32(1));
+        message.push(3, Value::I32(1));
+        message.push(3, Value::I32(2));
+        message.set(100, Value::U32(7));
+        let bytes = message.encode(&schema).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x08, 0x96, 0x01, // a
+                0x12, 0x02, 0x01, 0x02, // b, packed and zigzag encoded
+                0x18, 0x01, 0x18, 0x02, // c, one element at a time
+                0xa5, 0x06, 0x07, 0x00, 0x00, 0x00, // ext
+            ]
+        );
+        assert_eq!(
+            DynamicMessage::decode(&schema, "p.M", &bytes).unwrap(),
+            message
+        );
+
+        message.set(1, Value::String("not an int32".to_string()));
+        assert!(message.encode(&schema).is_err());
+    }
+
+    #[test]
+    fn proto2_packing_test() {
+        let schema = schema(
+            r#"syntax = "proto2";
+            message M {
+                repeated int32 a = 1;
+                repeated int32 b = 2 [packed = true];
+            }"#,
+        );
+        let mut message = DynamicMessage::new("M");
+        for number in [1, 2] {
+            message.push(number, Value::I32(1));
+            message.push(number, Value::I32(2));
+        }
+        let bytes = message.encode(&schema).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x08, 0x01, 0x08, 0x02, // a, unpacked by default in proto2
+                0x12, 0x02, 0x01, 0x02, // b
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_test() {
+        let schema = schema(SOURCE);
+        let mut first = DynamicMessage::new("p.N");
+        first.set(1, Value::F64(1.5));
+        first.push(2, Value::String("one".to_string()));
+        let mut second = DynamicMessage::new("p.N");
+        second.push(2, Value::String("two".to_string()));
+        let mut bytes = Vec::new();
+        for (number, nested) in [(4, first), (4, second)] {
+            let mut message = DynamicMessage::new("p.M");
+            message.set(number, Value::Message(nested));
+            bytes.extend(message.encode(&schema).unwrap());
+        }
+        let mut oneof = DynamicMessage::new("p.M");
+        oneof.set(5, Value::String("s".to_string()));
+        bytes.extend(oneof.encode(&schema).unwrap());
+        oneof.fields.clear();
+        oneof.set(6, Value::Bytes(vec![1]));
+        bytes.extend(oneof.encode(&schema).unwrap());
+        // Unknown fields are dropped
+        bytes.extend([0x38, 0x01]);
+
+        let message = DynamicMessage::decode(&schema, "p.M", &bytes).unwrap();
+        let [Value::Message(nested)] = message.get(4) else {
+            panic!("{:?}", message);
+        };
+        assert_eq!(nested.get(1), [Value::F64(1.5)]);
+        assert_eq!(
+            nested.get(2),
+            [
+                Value::String("one".to_string()),
+                Value::String("two".to_string())
+            ]
+        );
+        assert!(message.get(5).is_empty());
+        assert_eq!(message.get(6), [Value::Bytes(vec![1])]);
+        assert!(message.get(7).is_empty());
+    }
+}
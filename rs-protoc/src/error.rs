@@ -1,10 +1,19 @@
 use std::{error::Error, fmt::Display};
 
-#[derive(Debug)]
+use crate::{
+    diagnostic::{Diagnostic, DiagnosticFormat},
+    lexer::TokenMetadata,
+    source_text::SourceRegistry,
+};
+
+#[derive(Clone, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum RsProtocError {
     FilesystemError(String),
-    LexError(String),
-    ParseError(String),
+    LexError(Box<Diagnostic>),
+    ParseError(Box<Diagnostic>),
+    ValidationError(String, TokenMetadata),
+    EncodingError(String),
 }
 
 impl Display for RsProtocError {
@@ -14,11 +23,17 @@ impl Display for RsProtocError {
             RsProtocError::FilesystemError(error_message) => {
                 write!(f, "FilesystemError[{}]", error_message)
             }
-            RsProtocError::LexError(error_message) => {
-                write!(f, "LexError[{}]", error_message)
+            RsProtocError::LexError(diagnostic) => {
+                write!(f, "LexError[{}]", diagnostic)
+            }
+            RsProtocError::ParseError(diagnostic) => {
+                write!(f, "ParseError[{}]", diagnostic)
             }
-            RsProtocError::ParseError(error_message) => {
-                write!(f, "ParseError[{}]", error_message)
+            RsProtocError::ValidationError(error_message, metadata) => {
+                write!(f, "ValidationError[{}: {}]", metadata, error_message)
+            }
+            RsProtocError::EncodingError(error_message) => {
+                write!(f, "EncodingError[{}]", error_message)
             }
         }
     }
@@ -26,4 +41,109 @@ impl Display for RsProtocError {
 
 impl Error for RsProtocError {}
 
+impl RsProtocError {
+    /// Short identifier of the kind of error, used as the code of its diagnostic
+    pub fn code(&self) -> &'static str {
+        match self {
+            RsProtocError::FilesystemError(_) => "filesystem-error",
+            RsProtocError::LexError(_) => "lex-error",
+            RsProtocError::ParseError(_) => "parse-error",
+            RsProtocError::ValidationError(_, _) => "validation-error",
+            RsProtocError::EncodingError(_) => "encoding-error",
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = match self {
+            RsProtocError::LexError(diagnostic) | RsProtocError::ParseError(diagnostic) => {
+                diagnostic.as_ref().clone()
+            }
+            RsProtocError::ValidationError(message, metadata) => {
+                Diagnostic::error(message).with_primary_label(metadata, "")
+            }
+            RsProtocError::FilesystemError(message) | RsProtocError::EncodingError(message) => {
+                Diagnostic::error(message)
+            }
+        };
+        match diagnostic.code {
+            Some(_) => diagnostic,
+            None => diagnostic.with_code(self.code()),
+        }
+    }
+}
+
+impl From<encoding::ProtoscopeRsError> for RsProtocError {
+    fn from(value: encoding::ProtoscopeRsError) -> Self {
+        RsProtocError::EncodingError(value.to_string())
+    }
+}
+
+/// An error along with the name of the file it was found in
+#[derive(Debug)]
+pub struct FileError {
+    pub file_name: String,
+    pub error: RsProtocError,
+}
+
+impl Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.file_name, self.error)
+    }
+}
+
+impl Error for FileError {}
+
+impl FileError {
+    /// Print the error as a diagnostic, `source` is the text of the file if it is available
+    pub fn format(&self, format: DiagnosticFormat, source: Option<&str>, color: bool) -> String {
+        self.error
+            .to_diagnostic()
+            .format(format, &self.file_name, source, color)
+    }
+
+    /// Print the error as a diagnostic, quoting the files it points into from `sources`
+    pub fn format_in(
+        &self,
+        format: DiagnosticFormat,
+        sources: &SourceRegistry,
+        color: bool,
+    ) -> String {
+        self.error
+            .to_diagnostic()
+            .format_in(format, &self.file_name, sources, color)
+    }
+}
+
+/// Every error that made a compilation fail, one per line when displayed
+pub struct CompileError {
+    pub errors: Vec<FileError>,
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, error) in self.errors.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+// `unwrap()` in a build script prints the Debug representation, keep it readable
+impl std::fmt::Debug for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl Error for CompileError {}
+
+impl From<Vec<FileError>> for CompileError {
+    fn from(errors: Vec<FileError>) -> Self {
+        CompileError { errors }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, RsProtocError>;
@@ -0,0 +1,836 @@
+use crate::{
+    ast_elements::{
+        Constant, ElementType, FieldLabel, FieldPayload, FieldType, Import, ImportKind, Method,
+        NamedElement, NumberRange, OptionNamePart, OptionStatement, ProtoFile, Reserved, Spanned,
+    },
+    lexer::{Comment, TokenMetadata},
+    parser::{MAX_ENUM_VALUE, MAX_FIELD_NUMBER},
+};
+
+// Canonical layout of a .proto file:
+//
+// - The syntax declaration, the package, the imports sorted by path, the file options and the
+//   definitions, in that order and separated by blank lines
+// - Two spaces of indentation per block, "option" statements first in every block
+// - A single space around "=" and after ",", none inside brackets and parentheses
+// - The "=" of consecutive fields and enum values aligned
+// - Blank lines between statements kept, runs of them collapsed to one
+//
+// Comments move along with the statement they are attached to: a comment on the same line as
+// the statement before it trails that statement, any other comment leads the statement after it.
+
+const INDENT: &str = "  ";
+
+/// Re-emit a parsed file in the canonical layout, keeping every comment
+pub fn format_file(file: &ProtoFile) -> String {
+    let formatter = Formatter {
+        comments: &file.comments,
+    };
+    let mut items = Vec::new();
+    if let Some(syntax) = &file.syntax {
+        items.push(Item::leaf(Node::Syntax(syntax), &syntax.metadata));
+    }
+    if let Some(package) = &file.package.name {
+        items.push(Item::leaf(Node::Package(package), &package.metadata));
+    }
+    items.extend(
+        file.imports
+            .iter()
+            .map(|import| Item::leaf(Node::Import(import), &import.path.metadata)),
+    );
+    items.extend(options(&file.options));
+    items.extend(file.package.named_elements.iter().map(Item::element));
+
+    let mut lines = Vec::new();
+    let (_, dangling) = formatter.attach(&mut items, 0, usize::MAX);
+    formatter.write_items(&mut lines, 0, items, dangling);
+    let mut output = String::new();
+    // Lines holding a block comment span several lines of output
+    for line in align(lines) {
+        for line in line.split('\n') {
+            output.push_str(line.trim_end());
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// Order of the statements of a block, statements of different groups are separated by a
+/// blank line
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Group {
+    Syntax,
+    Package,
+    Import,
+    Option,
+    Definition,
+}
+
+enum Node<'a> {
+    Syntax(&'a Spanned<String>),
+    Package(&'a Spanned<String>),
+    Import(&'a Import),
+    Option(&'a OptionStatement),
+    Element(&'a NamedElement),
+    Method(&'a Method),
+    /// Reserved ranges or names written on the same line, with the value `max` stands for
+    Reserved(Vec<&'a Reserved>, i64),
+    Extensions(Vec<&'a Spanned<NumberRange>>),
+}
+
+/// A statement along with the comments attached to it
+struct Item<'a> {
+    node: Node<'a>,
+    /// Byte offset of the name of the statement, used to order statements and comments
+    start: usize,
+    /// Byte offset of the token ending the statement when it is known, comments before it
+    /// are part of the statement
+    end: usize,
+    first_line: usize,
+    last_line: usize,
+    /// Comments before the statement, one group per paragraph
+    leading: Vec<&'a Comment>,
+    /// Comments after the statement on the same line, or inside a statement without a body
+    trailing: Vec<&'a Comment>,
+    /// True when a blank line separates the statement from the one before it in the source,
+    /// dropped when canonical order moves the statement before the one it follows
+    gap_before: bool,
+    /// Position of the statement in its block, in source order
+    source_index: usize,
+}
+
+impl<'a> Item<'a> {
+    fn new(node: Node<'a>, start: &TokenMetadata, end: &TokenMetadata) -> Self {
+        Item {
+            node,
+            start: start.offset(),
+            end: end.offset(),
+            first_line: start.line_number(),
+            last_line: end.line_number(),
+            leading: Vec::new(),
+            trailing: Vec::new(),
+            gap_before: false,
+            source_index: 0,
+        }
+    }
+
+    fn leaf(node: Node<'a>, metadata: &TokenMetadata) -> Self {
+        Item::new(node, metadata, metadata)
+    }
+
+    fn element(element: &'a NamedElement) -> Self {
+        Item::new(Node::Element(element), &element.metadata, &element.end)
+    }
+
+    fn group(&self) -> Group {
+        match self.node {
+            Node::Syntax(_) => Group::Syntax,
+            Node::Package(_) => Group::Package,
+            Node::Import(_) => Group::Import,
+            Node::Option(_) => Group::Option,
+            _ => Group::Definition,
+        }
+    }
+
+    /// True for statements with a body holding other statements
+    fn has_body(&self) -> bool {
+        match self.node {
+            Node::Element(element) => !matches!(
+                element.type_t,
+                ElementType::Field(_) | ElementType::EnumValue(_)
+            ),
+            Node::Method(method) => !method.options.is_empty(),
+            _ => false,
+        }
+    }
+
+    fn first_line_with_comments(&self) -> usize {
+        self.leading
+            .first()
+            .map_or(self.first_line, |comment| comment.metadata.line_number())
+    }
+
+    fn last_line_with_comments(&self) -> usize {
+        self.trailing
+            .iter()
+            .map(|comment| comment.end_line_number())
+            .fold(self.last_line, usize::max)
+    }
+}
+
+fn options(options: &[OptionStatement]) -> impl Iterator<Item = Item<'_>> {
+    options
+        .iter()
+        .map(|option| Item::leaf(Node::Option(option), &option.name.metadata))
+}
+
+/// Group reserved entries written on the same line back into a single statement
+fn reserved(reserved: &[Reserved], max: i64) -> Vec<Item<'_>> {
+    let metadata = |entry: &Reserved| match entry {
+        Reserved::Range(range) => range.metadata.clone(),
+        Reserved::Name(name) => name.metadata.clone(),
+    };
+    let mut items: Vec<Item> = Vec::new();
+    for entry in reserved {
+        let entry_metadata = metadata(entry);
+        if let Some(Item {
+            node: Node::Reserved(entries, _),
+            last_line,
+            end,
+            ..
+        }) = items.last_mut()
+        {
+            let same_kind = matches!(
+                (entries[0], entry),
+                (Reserved::Range(_), Reserved::Range(_)) | (Reserved::Name(_), Reserved::Name(_))
+            );
+            if same_kind && *last_line == entry_metadata.line_number() {
+                entries.push(entry);
+                *end = entry_metadata.offset();
+                continue;
+            }
+        }
+        items.push(Item::leaf(
+            Node::Reserved(vec![entry], max),
+            &entry_metadata,
+        ));
+    }
+    items
+}
+
+/// Group extension ranges written on the same line back into a single statement
+fn extensions(ranges: &[Spanned<NumberRange>]) -> Vec<Item<'_>> {
+    let mut items: Vec<Item> = Vec::new();
+    for range in ranges {
+        if let Some(Item {
+            node: Node::Extensions(ranges),
+            last_line,
+            end,
+            ..
+        }) = items.last_mut()
+        {
+            if *last_line == range.metadata.line_number() {
+                ranges.push(range);
+                *end = range.metadata.offset();
+                continue;
+            }
+        }
+        items.push(Item::leaf(Node::Extensions(vec![range]), &range.metadata));
+    }
+    items
+}
+
+/// An output line before alignment
+enum Line {
+    Text(String),
+    /// A field or enum value, the "=" of consecutive ones is aligned by padding `head`
+    Aligned {
+        indent: usize,
+        head: String,
+        tail: String,
+    },
+}
+
+fn align(lines: Vec<Line>) -> Vec<String> {
+    let mut output = Vec::with_capacity(lines.len());
+    let mut index = 0;
+    while index < lines.len() {
+        let Line::Aligned { indent, .. } = lines[index] else {
+            if let Line::Text(text) = &lines[index] {
+                output.push(text.clone());
+            }
+            index += 1;
+            continue;
+        };
+        let run_end = lines[index..]
+            .iter()
+            .position(
+                |line| !matches!(line, Line::Aligned { indent: other, .. } if *other == indent),
+            )
+            .map_or(lines.len(), |length| index + length);
+        let width = lines[index..run_end]
+            .iter()
+            .map(|line| match line {
+                Line::Aligned { head, .. } => head.chars().count(),
+                Line::Text(_) => 0,
+            })
+            .max()
+            .unwrap_or(0);
+        for line in &lines[index..run_end] {
+            if let Line::Aligned { head, tail, .. } = line {
+                let padding = width - head.chars().count();
+                output.push(format!("{}{} {}", head, " ".repeat(padding), tail));
+            }
+        }
+        index = run_end;
+    }
+    output
+}
+
+struct Formatter<'a> {
+    /// Every comment of the file in source order
+    comments: &'a [Comment],
+}
+
+impl<'a> Formatter<'a> {
+    /// Comments starting between the byte offsets `start` and `end`, `end` excluded
+    fn comments_between(&self, start: usize, end: usize) -> &'a [Comment] {
+        let first = self
+            .comments
+            .partition_point(|comment| comment.metadata.offset() < start);
+        let last = self
+            .comments
+            .partition_point(|comment| comment.metadata.offset() < end);
+        &self.comments[first..last.max(first)]
+    }
+
+    /// Attach the comments of the block between `start` and `end` to its statements, `items`
+    /// are in source order on entry and in canonical order on exit. Returns the comments trailing
+    /// the opening of the block and the comments after its last statement.
+    fn attach(
+        &self,
+        items: &mut [Item<'a>],
+        start: usize,
+        end: usize,
+    ) -> (Vec<&'a Comment>, Vec<&'a Comment>) {
+        items.sort_by_key(|item| item.start);
+        let mut header = Vec::new();
+        let mut dangling = Vec::new();
+        for comment in self.comments_between(start, end) {
+            let offset = comment.metadata.offset();
+            let previous = items.iter().rposition(|item| item.start < offset);
+            if let Some(index) = previous {
+                if offset < items[index].end {
+                    // Comments inside a body are attached when the body is written
+                    if !items[index].has_body() {
+                        items[index].trailing.push(comment);
+                    }
+                    continue;
+                }
+            }
+            if comment.trailing {
+                match previous {
+                    Some(index) => items[index].trailing.push(comment),
+                    None => header.push(comment),
+                }
+            } else {
+                match items.get_mut(previous.map_or(0, |index| index + 1)) {
+                    Some(next) => next.leading.push(comment),
+                    None => dangling.push(comment),
+                }
+            }
+        }
+        for index in 0..items.len() {
+            items[index].source_index = index;
+            items[index].gap_before = index > 0
+                && items[index].first_line_with_comments()
+                    > items[index - 1].last_line_with_comments() + 1;
+        }
+        // Stable, so statements of a group stay in source order
+        items.sort_by(|a, b| match (&a.node, &b.node) {
+            (Node::Import(a), Node::Import(b)) => a.path.value.cmp(&b.path.value),
+            _ => a.group().cmp(&b.group()),
+        });
+        for index in 1..items.len() {
+            if items[index].source_index < items[index - 1].source_index {
+                items[index].gap_before = false;
+            }
+        }
+        (header, dangling)
+    }
+
+    fn write_items(
+        &self,
+        lines: &mut Vec<Line>,
+        indent: usize,
+        items: Vec<Item<'a>>,
+        dangling: Vec<&'a Comment>,
+    ) {
+        let top_level = indent == 0;
+        let mut previous: Option<(Group, bool, usize)> = None;
+        for item in items {
+            if let Some((group, has_body, _)) = previous {
+                let separate_definitions =
+                    top_level && (has_body || item.has_body()) && group == Group::Definition;
+                if item.gap_before || group != item.group() || separate_definitions {
+                    lines.push(Line::Text(String::new()));
+                }
+            }
+            previous = Some((
+                item.group(),
+                item.has_body(),
+                item.last_line_with_comments(),
+            ));
+            self.write_comments(lines, indent, &item.leading, item.first_line);
+            self.write_item(lines, indent, item);
+        }
+        if let (Some(first), Some((_, _, last_line))) = (dangling.first(), previous) {
+            if first.metadata.line_number() > last_line + 1 {
+                lines.push(Line::Text(String::new()));
+            }
+        }
+        self.write_comments(lines, indent, &dangling, usize::MAX);
+    }
+
+    /// Write comments on their own lines, keeping the blank lines separating paragraphs and the
+    /// one separating the last comment from the statement on `next_line`
+    fn write_comments(
+        &self,
+        lines: &mut Vec<Line>,
+        indent: usize,
+        comments: &[&Comment],
+        next_line: usize,
+    ) {
+        for (index, comment) in comments.iter().enumerate() {
+            lines.push(Line::Text(format!(
+                "{}{}",
+                INDENT.repeat(indent),
+                comment.text
+            )));
+            let following_line = comments
+                .get(index + 1)
+                .map_or(next_line, |next| next.metadata.line_number());
+            if following_line != usize::MAX && following_line > comment.end_line_number() + 1 {
+                lines.push(Line::Text(String::new()));
+            }
+        }
+    }
+
+    fn write_item(&self, lines: &mut Vec<Line>, indent: usize, item: Item<'a>) {
+        let prefix = INDENT.repeat(indent);
+        let statement = match &item.node {
+            Node::Syntax(syntax) => {
+                format!("syntax = {};", string_literal(syntax.value.as_bytes()))
+            }
+            Node::Package(package) => format!("package {};", package.value),
+            Node::Import(import) => {
+                let kind = match import.kind {
+                    ImportKind::Default => "",
+                    ImportKind::Weak => "weak ",
+                    ImportKind::Public => "public ",
+                };
+                format!(
+                    "import {}{};",
+                    kind,
+                    string_literal(import.path.value.as_bytes())
+                )
+            }
+            Node::Option(option) => format!("option {};", option_body(option)),
+            Node::Reserved(entries, max) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|entry| match entry {
+                        Reserved::Range(range) => number_range(&range.value, *max),
+                        Reserved::Name(name) => string_literal(name.value.as_bytes()),
+                    })
+                    .collect();
+                format!("reserved {};", entries.join(", "))
+            }
+            Node::Extensions(ranges) => {
+                let ranges: Vec<String> = ranges
+                    .iter()
+                    .map(|range| number_range(&range.value, MAX_FIELD_NUMBER))
+                    .collect();
+                format!("extensions {};", ranges.join(", "))
+            }
+            Node::Element(element) => match &element.type_t {
+                ElementType::Field(field) => {
+                    let (head, tail) = field_parts(&element.name, field);
+                    lines.push(Line::Aligned {
+                        indent,
+                        head: format!("{}{}", prefix, head),
+                        tail: with_trailing(tail, &item.trailing),
+                    });
+                    return;
+                }
+                ElementType::EnumValue(value) => {
+                    lines.push(Line::Aligned {
+                        indent,
+                        head: format!("{}{}", prefix, element.name),
+                        tail: with_trailing(
+                            format!(
+                                "= {}{};",
+                                value.number.value,
+                                compact_options(&value.options)
+                            ),
+                            &item.trailing,
+                        ),
+                    });
+                    return;
+                }
+                _ => return self.write_element(lines, indent, element, &item.trailing),
+            },
+            Node::Method(method) => {
+                return self.write_method(lines, indent, method, &item.trailing)
+            }
+        };
+        lines.push(Line::Text(with_trailing(
+            format!("{}{}", prefix, statement),
+            &item.trailing,
+        )));
+    }
+
+    /// Write `header {`, the statements of the body and the closing "}"
+    fn write_block(
+        &self,
+        lines: &mut Vec<Line>,
+        indent: usize,
+        header: String,
+        body: (usize, usize),
+        mut items: Vec<Item<'a>>,
+        trailing: &[&Comment],
+    ) {
+        let prefix = INDENT.repeat(indent);
+        let (header_comments, dangling) = self.attach(&mut items, body.0, body.1);
+        if items.is_empty() && dangling.is_empty() && header_comments.is_empty() {
+            lines.push(Line::Text(with_trailing(
+                format!("{}{} {{}}", prefix, header),
+                trailing,
+            )));
+            return;
+        }
+        lines.push(Line::Text(with_trailing(
+            format!("{}{} {{", prefix, header),
+            &header_comments,
+        )));
+        self.write_items(lines, indent + 1, items, dangling);
+        lines.push(Line::Text(with_trailing(format!("{}}}", prefix), trailing)));
+    }
+
+    fn write_element(
+        &self,
+        lines: &mut Vec<Line>,
+        indent: usize,
+        element: &'a NamedElement,
+        trailing: &[&Comment],
+    ) {
+        let body = (element.metadata.offset(), element.end.offset());
+        let (header, items) = match &element.type_t {
+            ElementType::Message(message) => {
+                let mut items: Vec<Item> = options(&message.options).collect();
+                items.extend(message.named_elements.iter().map(Item::element));
+                items.extend(reserved(&message.reserved, MAX_FIELD_NUMBER));
+                items.extend(extensions(&message.extension_ranges));
+                (format!("message {}", element.name), items)
+            }
+            ElementType::OneOf(oneof) => {
+                let mut items: Vec<Item> = options(&oneof.options).collect();
+                items.extend(oneof.fields.iter().map(Item::element));
+                (format!("oneof {}", element.name), items)
+            }
+            ElementType::Enum(enum_payload) => {
+                let mut items: Vec<Item> = options(&enum_payload.options).collect();
+                items.extend(enum_payload.values.iter().map(Item::element));
+                items.extend(reserved(&enum_payload.reserved, MAX_ENUM_VALUE));
+                (format!("enum {}", element.name), items)
+            }
+            ElementType::Extension(extension) => (
+                format!("extend {}", element.name),
+                extension.fields.iter().map(Item::element).collect(),
+            ),
+            ElementType::Service(service) => {
+                let mut items: Vec<Item> = options(&service.options).collect();
+                items.extend(service.methods.iter().map(|method| {
+                    Item::new(Node::Method(method), &method.name.metadata, &method.end)
+                }));
+                (format!("service {}", element.name), items)
+            }
+            ElementType::Field(_) | ElementType::EnumValue(_) => {
+                unreachable!("Fields and enum values have no body")
+            }
+        };
+        self.write_block(lines, indent, header, body, items, trailing);
+    }
+
+    fn write_method(
+        &self,
+        lines: &mut Vec<Line>,
+        indent: usize,
+        method: &'a Method,
+        trailing: &[&Comment],
+    ) {
+        let stream = |streaming| if streaming { "stream " } else { "" };
+        let header = format!(
+            "rpc {}({}{}) returns ({}{})",
+            method.name.value,
+            stream(method.client_streaming),
+            method.input_type.value,
+            stream(method.server_streaming),
+            method.output_type.value
+        );
+        if method.options.is_empty() {
+            lines.push(Line::Text(with_trailing(
+                format!("{}{};", INDENT.repeat(indent), header),
+                trailing,
+            )));
+            return;
+        }
+        let body = (method.name.metadata.offset(), method.end.offset());
+        let items = options(&method.options).collect();
+        self.write_block(lines, indent, header, body, items, trailing);
+    }
+}
+
+/// `text` followed by the comments trailing it, block comments keep their line breaks
+fn with_trailing(mut text: String, trailing: &[&Comment]) -> String {
+    for comment in trailing {
+        text.push(' ');
+        text.push_str(&comment.text);
+    }
+    text
+}
+
+/// The part of a field before "=" and the part starting with it
+fn field_parts(name: &str, field: &FieldPayload) -> (String, String) {
+    let label = match field.label {
+        FieldLabel::None => "",
+        FieldLabel::Optional => "optional ",
+        FieldLabel::Repeated => "repeated ",
+        FieldLabel::Required => "required ",
+    };
+    (
+        format!("{}{} {}", label, field_type(&field.field_type.value), name),
+        format!(
+            "= {}{};",
+            field.number.value,
+            compact_options(&field.options)
+        ),
+    )
+}
+
+fn field_type(field_type: &FieldType) -> String {
+    match field_type {
+        FieldType::Scalar(scalar_type) => scalar_type.as_str().to_string(),
+        FieldType::Named(name) => name.clone(),
+        FieldType::Map(key, value) => format!(
+            "map<{}, {}>",
+            self::field_type(&key.value),
+            self::field_type(&value.value)
+        ),
+    }
+}
+
+fn number_range(range: &NumberRange, max: i64) -> String {
+    if range.start == range.end {
+        range.start.to_string()
+    } else if range.end == max {
+        format!("{} to max", range.start)
+    } else {
+        format!("{} to {}", range.start, range.end)
+    }
+}
+
+fn compact_options(options: &[OptionStatement]) -> String {
+    if options.is_empty() {
+        return String::new();
+    }
+    let options: Vec<String> = options.iter().map(option_body).collect();
+    format!(" [{}]", options.join(", "))
+}
+
+fn option_body(option: &OptionStatement) -> String {
+    let name: Vec<String> = option
+        .name
+        .value
+        .iter()
+        .map(|part| match part {
+            OptionNamePart::Simple(name) => name.clone(),
+            OptionNamePart::Extension(name) => format!("({})", name),
+        })
+        .collect();
+    format!("{} = {}", name.join("."), constant(&option.value.value))
+}
+
+fn constant(value: &Constant) -> String {
+    match value {
+        Constant::Identifier(identifier) => identifier.clone(),
+        Constant::Integer(value) => value.to_string(),
+        Constant::Float(value) if value.is_nan() => "nan".to_string(),
+        // Debug keeps the fractional part of whole numbers, and writes "inf" and "-inf"
+        Constant::Float(value) => format!("{:?}", value),
+        Constant::String(value) => string_literal(value.as_bytes()),
+        Constant::Bytes(value) => string_literal(value),
+        Constant::Bool(value) => value.to_string(),
+        Constant::Message(fields) if fields.is_empty() => "{}".to_string(),
+        Constant::Message(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|field| {
+                    let name = match &field.name.value {
+                        OptionNamePart::Simple(name) => name.clone(),
+                        OptionNamePart::Extension(name) => format!("[{}]", name),
+                    };
+                    match &field.value.value {
+                        Constant::Message(_) => {
+                            format!("{} {}", name, constant(&field.value.value))
+                        }
+                        value => format!("{}: {}", name, constant(value)),
+                    }
+                })
+                .collect();
+            format!("{{ {} }}", fields.join(" "))
+        }
+        Constant::List(values) => {
+            let values: Vec<String> = values.iter().map(|value| constant(&value.value)).collect();
+            format!("[{}]", values.join(", "))
+        }
+    }
+}
+
+/// Quote `value`, escaping control characters and the bytes that are not valid UTF-8
+pub(crate) fn string_literal(value: &[u8]) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for chunk in value.utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            match ch {
+                '"' => literal.push_str("\\\""),
+                '\\' => literal.push_str("\\\\"),
+                '\n' => literal.push_str("\\n"),
+                '\r' => literal.push_str("\\r"),
+                '\t' => literal.push_str("\\t"),
+                // "\x" escapes a single byte, not a code point
+                ch if ch.is_ascii_control() => literal.push_str(&format!("\\x{:02x}", ch as u32)),
+                ch if ch.is_control() => literal.push_str(&format!("\\u{:04x}", ch as u32)),
+                ch => literal.push(ch),
+            }
+        }
+        for byte in chunk.invalid() {
+            literal.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_file;
+    use crate::parser::Parser;
+
+    fn format(source: &str) -> String {
+        format_file(&Parser::new(source).parse().unwrap())
+    }
+
+    #[test]
+    fn formatter_test() {
+        let source = r#"// License
+
+syntax = "proto3";
+import "b.proto";
+import "a.proto"; // First
+package demo;
+message Outer { // Outer
+  int32 id=1;   // The id
+  repeated   Inner inner_values = 2 [deprecated=true];
+  option (custom) = "x";
+
+  // About Inner
+  message Inner {}
+  reserved 5, 6 to max;
+}
+enum Kind { KIND_UNSPECIFIED = 0; KIND_OTHER = -1; }
+service Api { rpc Get(stream Outer) returns (Outer) {} }
+// End
+"#;
+        let expected = r#"// License
+
+syntax = "proto3";
+
+package demo;
+
+import "a.proto"; // First
+import "b.proto";
+
+message Outer { // Outer
+  option (custom) = "x";
+
+  int32 id                    = 1; // The id
+  repeated Inner inner_values = 2 [deprecated = true];
+
+  // About Inner
+  message Inner {}
+  reserved 5, 6 to max;
+}
+
+enum Kind {
+  KIND_UNSPECIFIED = 0;
+  KIND_OTHER       = -1;
+}
+
+service Api {
+  rpc Get(stream Outer) returns (Outer);
+}
+// End
+"#;
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn formatter_comment_test() {
+        let source = r#"syntax = "proto3";
+message A {
+  /* Block
+        * indented
+        */
+  int32 a = 1;
+
+  // Detached
+
+  // Leading
+  int32 b = 2 /* Inside */;
+  // Dangling
+}
+"#;
+        let expected = r#"syntax = "proto3";
+
+message A {
+  /* Block
+        * indented
+        */
+  int32 a = 1;
+
+  // Detached
+
+  // Leading
+  int32 b = 2; /* Inside */
+  // Dangling
+}
+"#;
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn formatter_block_comment_test() {
+        // Only the first line of a comment moves, the others are kept as written
+        let source = "syntax = \"proto3\";
+message A {
+      /*
+       *   Indented
+       *     further
+       */
+      int32 a = 1; /* Trailing
+                      on two lines */
+  int32 b = 2; /* After */ /* Another
+    one */
+}
+";
+        let expected = "syntax = \"proto3\";
+
+message A {
+  /*
+       *   Indented
+       *     further
+       */
+  int32 a = 1; /* Trailing
+                      on two lines */
+  int32 b = 2; /* After */ /* Another
+    one */
+}
+";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+}
@@ -0,0 +1,576 @@
+use std::ops::Range;
+
+use crate::{
+    ast_elements::{
+        Comments, Constant, ElementType, FieldType, Import, MessageLiteralField, NamedElement,
+        OptionStatement, Package, ProtoFile, Reserved, Spanned,
+    },
+    diagnostic::Diagnostic,
+    error::RsProtocError,
+    lexer::{Comment, LexerPosition, TokenMetadata},
+    parser::{set_package, PackageStatement, Parser, Statement},
+};
+
+// Editors parse a file again on every keystroke. A file is a sequence of top-level statements
+// and a statement only depends on the text up to the first token after it, so after an edit
+// lexing and parsing resume where the statement before the edit ended. They stop at the first
+// old statement boundary past the edit, once a line break separates the two, and the
+// statements after it are reused with their metadata moved by the size of the edit.
+
+/// A change to a text: the bytes in `range` are replaced with `text`
+#[derive(Clone, PartialEq, Debug)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// What a top-level statement added to the file
+#[derive(Debug)]
+struct Chunk {
+    /// Lexer position right after the last token of the statement
+    end: LexerPosition,
+    imports: usize,
+    options: usize,
+    elements: usize,
+    /// Comments after the end of the statement before, up to the end of this one
+    comments: usize,
+    package: Option<PackageStatement>,
+    errors: Vec<RsProtocError>,
+    /// Set for the package declarations after the first one
+    package_error: Option<RsProtocError>,
+}
+
+/// A file kept parsed across edits
+pub struct IncrementalParser {
+    text: String,
+    file: ProtoFile,
+    chunks: Vec<Chunk>,
+    /// Errors found after the last statement, the missing syntax declaration of an empty file
+    tail_errors: Vec<RsProtocError>,
+}
+
+impl IncrementalParser {
+    pub fn new(text: String) -> Self {
+        let mut parser = IncrementalParser {
+            text,
+            file: ProtoFile {
+                syntax: None,
+                syntax_span: None,
+                imports: Vec::new(),
+                options: Vec::new(),
+                package: Package {
+                    name: None,
+                    span: None,
+                    comments: Comments::default(),
+                    named_elements: Vec::new(),
+                },
+                comments: Vec::new(),
+            },
+            chunks: Vec::new(),
+            tail_errors: Vec::new(),
+        };
+        parser.reparse(0, 0, 0, &Shift::default());
+        parser
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The file as `Parser::parse` would produce it, statements that failed to parse are left out
+    pub fn file(&self) -> &ProtoFile {
+        &self.file
+    }
+
+    /// Errors of the file in source order, the file is valid when there are none
+    pub fn errors(&self) -> impl Iterator<Item = &RsProtocError> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.errors.iter().chain(&chunk.package_error))
+            .chain(&self.tail_errors)
+    }
+
+    /// Number of top-level statements, including empty ones and ones that failed to parse
+    pub fn statement_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Apply `edit` and parse the statements around it again, returns the indices of the
+    /// statements that were parsed again. Panics when the range of the edit is out of bounds
+    /// or not on character boundaries, like `String::replace_range`.
+    pub fn edit(&mut self, edit: &TextEdit) -> Range<usize> {
+        let removed = &self.text[edit.range.clone()];
+        let shift = Shift {
+            bytes: edit.text.len() as isize - removed.len() as isize,
+            lines: edit.text.matches('\n').count() as isize
+                - removed.matches('\n').count() as isize,
+        };
+        // The statement before the one holding the edit depends on the tokens after it too
+        let first = self
+            .chunks
+            .partition_point(|chunk| chunk.end.byte_offset < edit.range.start)
+            .saturating_sub(1);
+        self.text.replace_range(edit.range.clone(), &edit.text);
+        let count = self.reparse(
+            first,
+            edit.range.start + edit.text.len(),
+            edit.range.end,
+            &shift,
+        );
+        first..first + count
+    }
+
+    /// Parse the statements from the one at index `first`, until the end of an old statement
+    /// past `edit_end`, which is `old_edit_end` in the text before the edit. Returns the number
+    /// of statements parsed.
+    fn reparse(
+        &mut self,
+        first: usize,
+        edit_end: usize,
+        old_edit_end: usize,
+        shift: &Shift,
+    ) -> usize {
+        let start = match first {
+            0 => LexerPosition::default(),
+            _ => self.chunks[first - 1].end.clone(),
+        };
+        let mut parser = Parser::resume(&self.text, &start);
+        parser.begin();
+        let mut statements = Vec::new();
+        let mut new_chunks: Vec<Chunk> = Vec::new();
+        let mut reused = self.chunks.len();
+        while let Some(statement) = parser.parse_statement(first == 0 && statements.is_empty()) {
+            let end = parser.token_end().clone();
+            let mut chunk = Chunk {
+                end,
+                imports: 0,
+                options: 0,
+                elements: 0,
+                comments: 0,
+                package: None,
+                errors: parser.take_errors(),
+                package_error: None,
+            };
+            match &statement {
+                Statement::Import(_) => chunk.imports = 1,
+                Statement::Option(_) => chunk.options = 1,
+                Statement::Element(_) => chunk.elements = 1,
+                _ => {}
+            }
+            let end = chunk.end.byte_offset;
+            statements.push(statement);
+            new_chunks.push(chunk);
+            if end < edit_end || !line_break_between(&self.text, edit_end, end) {
+                continue;
+            }
+            let old_end = end.wrapping_add_signed(-shift.bytes);
+            if let Ok(index) = self
+                .chunks
+                .binary_search_by_key(&old_end, |chunk| chunk.end.byte_offset)
+            {
+                if old_end >= old_edit_end {
+                    reused = index + 1;
+                    break;
+                }
+            }
+        }
+        let mut comments = parser.take_comments();
+        let reaches_end = reused == self.chunks.len();
+        if reaches_end {
+            if first == 0 && statements.is_empty() {
+                parser.missing_syntax();
+            }
+            self.tail_errors = parser.take_errors();
+        } else if let Some(last) = new_chunks.last() {
+            // The comments after the last statement belong to the next one, which is reused
+            let end = last.end.byte_offset;
+            comments.retain(|comment| comment.metadata.offset() < end);
+        }
+        let mut comment_index = 0;
+        for chunk in &mut new_chunks {
+            let end = chunk.end.byte_offset;
+            let start = comment_index;
+            while comment_index < comments.len() && comments[comment_index].metadata.offset() < end
+            {
+                comment_index += 1;
+            }
+            chunk.comments = comment_index - start;
+        }
+
+        // Move everything after the statements parsed again past the edit
+        let count = |range: Range<usize>, field: fn(&Chunk) -> usize| -> usize {
+            self.chunks[range].iter().map(field).sum()
+        };
+        let old_range = first..reused;
+        let spliced = [
+            (
+                count(0..first, |chunk| chunk.imports),
+                count(old_range.clone(), |chunk| chunk.imports),
+            ),
+            (
+                count(0..first, |chunk| chunk.options),
+                count(old_range.clone(), |chunk| chunk.options),
+            ),
+            (
+                count(0..first, |chunk| chunk.elements),
+                count(old_range.clone(), |chunk| chunk.elements),
+            ),
+            (
+                count(0..first, |chunk| chunk.comments),
+                count(old_range.clone(), |chunk| chunk.comments),
+            ),
+        ];
+        let after = |(start, len): (usize, usize)| start + len..;
+        let file = &mut self.file;
+        file.imports[after(spliced[0])]
+            .iter_mut()
+            .for_each(|import| import.move_by(shift));
+        file.options[after(spliced[1])]
+            .iter_mut()
+            .for_each(|option| option.move_by(shift));
+        file.package.named_elements[after(spliced[2])]
+            .iter_mut()
+            .for_each(|element| element.move_by(shift));
+        let comments_end = match reaches_end {
+            true => file.comments.len(),
+            false => spliced[3].0 + spliced[3].1,
+        };
+        file.comments[comments_end..]
+            .iter_mut()
+            .for_each(|comment| comment.move_by(shift));
+        for chunk in &mut self.chunks[reused..] {
+            chunk.end.shift(shift.bytes, shift.lines);
+            chunk
+                .errors
+                .iter_mut()
+                .for_each(|error| error.move_by(shift));
+            if let Some(package) = &mut chunk.package {
+                package.keyword.move_by(shift);
+                package.name.move_by(shift);
+                package.span.move_by(shift);
+            }
+        }
+        if !reaches_end {
+            self.tail_errors
+                .iter_mut()
+                .for_each(|error| error.move_by(shift));
+        }
+
+        // Splice in the statements parsed again
+        if first == 0 {
+            file.syntax = None;
+            file.syntax_span = None;
+        }
+        let mut imports = Vec::new();
+        let mut options = Vec::new();
+        let mut elements = Vec::new();
+        for (statement, chunk) in statements.into_iter().zip(&mut new_chunks) {
+            match statement {
+                Statement::Syntax(syntax, span) => {
+                    file.syntax = Some(syntax);
+                    file.syntax_span = Some(span);
+                }
+                Statement::Package(package) => chunk.package = Some(package),
+                Statement::Import(import) => imports.push(import),
+                Statement::Option(option) => options.push(option),
+                Statement::Element(element) => elements.push(element),
+                Statement::Empty => {}
+            }
+        }
+        let range = |(start, len): (usize, usize)| start..start + len;
+        file.imports.splice(range(spliced[0]), imports);
+        file.options.splice(range(spliced[1]), options);
+        file.package
+            .named_elements
+            .splice(range(spliced[2]), elements);
+        file.comments.splice(spliced[3].0..comments_end, comments);
+        let parsed = new_chunks.len();
+        self.chunks.splice(old_range, new_chunks);
+
+        // Only the first package declaration counts, whichever statements were parsed again
+        file.package.name = None;
+        file.package.span = None;
+        file.package.comments = Comments::default();
+        for chunk in &mut self.chunks {
+            chunk.package_error = chunk
+                .package
+                .as_ref()
+                .and_then(|package| set_package(&mut file.package, package.clone()));
+        }
+        parsed
+    }
+}
+
+/// Whether the text from `edit_end` to `end`, or the rest of the line at `end`, has a line break,
+/// so that the columns of everything after `end` are not changed by the edit
+fn line_break_between(text: &str, edit_end: usize, end: usize) -> bool {
+    text[edit_end..end].contains('\n')
+        || text[end..]
+            .find(|c: char| c == '\n' || !c.is_whitespace())
+            .is_none_or(|index| text[end + index..].starts_with('\n'))
+}
+
+/// Size of an edit, by which the metadata after it moves
+#[derive(Default)]
+struct Shift {
+    bytes: isize,
+    lines: isize,
+}
+
+trait ShiftMetadata {
+    fn move_by(&mut self, shift: &Shift);
+}
+
+impl ShiftMetadata for TokenMetadata {
+    fn move_by(&mut self, shift: &Shift) {
+        self.shift(shift.bytes, shift.lines);
+    }
+}
+
+impl<T> ShiftMetadata for Spanned<T> {
+    fn move_by(&mut self, shift: &Shift) {
+        self.metadata.move_by(shift);
+    }
+}
+
+impl ShiftMetadata for Comment {
+    fn move_by(&mut self, shift: &Shift) {
+        self.metadata.move_by(shift);
+    }
+}
+
+impl ShiftMetadata for Import {
+    fn move_by(&mut self, shift: &Shift) {
+        self.path.move_by(shift);
+        self.span.move_by(shift);
+    }
+}
+
+impl ShiftMetadata for OptionStatement {
+    fn move_by(&mut self, shift: &Shift) {
+        self.name.move_by(shift);
+        move_constant(&mut self.value, shift);
+        self.span.move_by(shift);
+    }
+}
+
+fn move_constant(constant: &mut Spanned<Constant>, shift: &Shift) {
+    constant.move_by(shift);
+    match &mut constant.value {
+        Constant::Message(fields) => {
+            for MessageLiteralField { name, value } in fields {
+                name.move_by(shift);
+                move_constant(value, shift);
+            }
+        }
+        Constant::List(values) => values
+            .iter_mut()
+            .for_each(|value| move_constant(value, shift)),
+        _ => {}
+    }
+}
+
+impl ShiftMetadata for NamedElement {
+    fn move_by(&mut self, shift: &Shift) {
+        self.metadata.move_by(shift);
+        self.start.move_by(shift);
+        self.end.move_by(shift);
+        let (elements, options) = match &mut self.type_t {
+            ElementType::Message(message) => {
+                for reserved in &mut message.reserved {
+                    reserved.move_by(shift);
+                }
+                for range in &mut message.extension_ranges {
+                    range.move_by(shift);
+                }
+                (&mut message.named_elements, &mut message.options)
+            }
+            ElementType::Field(field) => {
+                field.field_type.move_by(shift);
+                if let FieldType::Map(key_type, value_type) = &mut field.field_type.value {
+                    key_type.move_by(shift);
+                    value_type.move_by(shift);
+                }
+                field.number.move_by(shift);
+                if let Some(span) = &mut field.options_span {
+                    span.move_by(shift);
+                }
+                field
+                    .options
+                    .iter_mut()
+                    .for_each(|option| option.move_by(shift));
+                return;
+            }
+            ElementType::OneOf(oneof) => (&mut oneof.fields, &mut oneof.options),
+            ElementType::Enum(enum_payload) => {
+                for reserved in &mut enum_payload.reserved {
+                    reserved.move_by(shift);
+                }
+                (&mut enum_payload.values, &mut enum_payload.options)
+            }
+            ElementType::EnumValue(value) => {
+                value.number.move_by(shift);
+                if let Some(span) = &mut value.options_span {
+                    span.move_by(shift);
+                }
+                value
+                    .options
+                    .iter_mut()
+                    .for_each(|option| option.move_by(shift));
+                return;
+            }
+            ElementType::Extension(extension) => {
+                extension
+                    .fields
+                    .iter_mut()
+                    .for_each(|field| field.move_by(shift));
+                return;
+            }
+            ElementType::Service(service) => {
+                for method in &mut service.methods {
+                    method.start.move_by(shift);
+                    method.name.move_by(shift);
+                    method.input_type.move_by(shift);
+                    method.output_type.move_by(shift);
+                    method.end.move_by(shift);
+                    method
+                        .options
+                        .iter_mut()
+                        .for_each(|option| option.move_by(shift));
+                }
+                service
+                    .options
+                    .iter_mut()
+                    .for_each(|option| option.move_by(shift));
+                return;
+            }
+        };
+        elements
+            .iter_mut()
+            .for_each(|element| element.move_by(shift));
+        options.iter_mut().for_each(|option| option.move_by(shift));
+    }
+}
+
+impl ShiftMetadata for Reserved {
+    fn move_by(&mut self, shift: &Shift) {
+        match self {
+            Reserved::Range(range) => range.move_by(shift),
+            Reserved::Name(name) => name.move_by(shift),
+        }
+    }
+}
+
+impl ShiftMetadata for Diagnostic {
+    fn move_by(&mut self, shift: &Shift) {
+        self.labels
+            .iter_mut()
+            .for_each(|label| label.metadata.move_by(shift));
+    }
+}
+
+impl ShiftMetadata for RsProtocError {
+    fn move_by(&mut self, shift: &Shift) {
+        match self {
+            RsProtocError::LexError(diagnostic) | RsProtocError::ParseError(diagnostic) => {
+                diagnostic.move_by(shift)
+            }
+            RsProtocError::ValidationError(_, metadata) => metadata.move_by(shift),
+            RsProtocError::FilesystemError(_) | RsProtocError::EncodingError(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply `edits` one after the other and check the result against parsing from scratch
+    fn check_edits(text: &str, edits: &[(&str, &str)]) -> Vec<Range<usize>> {
+        let mut parser = IncrementalParser::new(text.to_string());
+        let mut reparsed = Vec::new();
+        for (old, new) in edits {
+            let start = parser.text().find(old).unwrap();
+            reparsed.push(parser.edit(&TextEdit {
+                range: start..start + old.len(),
+                text: new.to_string(),
+            }));
+            let mut scratch = Parser::new(parser.text());
+            match scratch.parse() {
+                Ok(file) => {
+                    assert_eq!(parser.errors().count(), 0);
+                    assert_eq!(format!("{:?}", parser.file()), format!("{:?}", file));
+                }
+                Err(errors) => {
+                    let incremental: Vec<String> =
+                        parser.errors().map(|error| error.to_string()).collect();
+                    let scratch: Vec<String> =
+                        errors.iter().map(|error| error.to_string()).collect();
+                    assert_eq!(incremental, scratch);
+                }
+            }
+        }
+        reparsed
+    }
+
+    const SOURCE: &str = r#"syntax = "proto3";
+package foo;
+
+// Leading A
+message A {
+  int32 a = 1; // Trailing a
+}
+
+message B { string b = 1; }
+
+/* Block */
+enum C { C_ZERO = 0; }
+
+service S {
+  rpc Get(A) returns (B);
+}
+// Last
+"#;
+
+    #[test]
+    fn incremental_reuse_test() {
+        let reparsed = check_edits(
+            SOURCE,
+            &[
+                ("int32 a = 1", "int64 renamed = 12"),
+                ("string b", "// Inserted\n  string b"),
+                ("C_ZERO", "C_NONE"),
+                ("// Last", "// Changed"),
+                ("rpc Get", "rpc Fetch"),
+            ],
+        );
+        assert_eq!(reparsed[0], 1..3);
+        assert_eq!(reparsed[1], 2..4);
+        assert_eq!(reparsed[2], 3..5);
+        assert_eq!(reparsed[3], 5..6);
+        assert_eq!(reparsed[4], 4..6);
+    }
+
+    #[test]
+    fn incremental_structure_test() {
+        check_edits(
+            SOURCE,
+            &[
+                // Merge two statements, then split them again
+                ("}\n\nmessage B {", ""),
+                (" string b = 1; }", "}\nmessage B { string b = 1; }"),
+                // Break a statement and fix it
+                ("message B", "mesage B"),
+                ("mesage B", "message B"),
+                // A second package declaration
+                ("/* Block */", "package bar;"),
+                ("package foo;", ""),
+                // Multi-byte characters and a change of line count
+                ("Leading A", "Leading Ä\n// ünïcode"),
+                ("message B", "message B { string ∂ = 1; }\nmessage B2"),
+                ("syntax = \"proto3\";\n", ""),
+            ],
+        );
+        check_edits("", &[("", "syntax = \"proto3\";"), ("syntax", "")]);
+    }
+}
@@ -1,6 +1,7 @@
-use crate::error::{Result, RsProtocError};
+use crate::diagnostic::Diagnostic;
+use crate::source_map::{ColumnUnit, SourceMap};
+use crate::source_text::{FileId, SourceBuffer};
 
-use std::str::Chars;
 use std::str::FromStr;
 
 use byteyarn::YarnBox;
@@ -8,9 +9,9 @@ use byteyarn::YarnBox;
 #[derive(Clone, PartialEq, Debug)]
 pub enum TokenKind<'storage> {
     Identifier(YarnBox<'storage, str>),
-    IntegerLiteral(u64),
-    FloatLiteral(f64),
-    StringLiteral(YarnBox<'storage, str>),
+    IntegerLiteral(IntegerLiteral<'storage>),
+    FloatLiteral(FloatLiteral<'storage>),
+    StringLiteral(StringValue<'storage>),
     Semicolon,
     Colon,
     LParen,
@@ -66,13 +67,280 @@ pub enum TokenKind<'storage> {
     Bytes,
     Group,
     Returns,
-    Error(String), /*TODO Add more information here for better diagnostics */
+    /// Whitespace within a line, only produced by `LosslessLexer`
+    Whitespace,
+    /// A line break, `\n` or `\r\n`, only produced by `LosslessLexer`
+    Newline,
+    /// A line or block comment, only produced by `LosslessLexer`
+    Comment,
+    Error(Box<Diagnostic>),
 }
 
-#[derive(Clone, Debug)]
+impl TokenKind<'_> {
+    /// True for whitespace, line breaks and comments
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Whitespace | TokenKind::Newline | TokenKind::Comment
+        )
+    }
+
+    /// The text of a keyword token, keywords are still valid names in message literals
+    pub fn keyword_text(&self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(_, kind)| kind == self)
+            .map(|(text, _)| *text)
+    }
+}
+
+impl std::fmt::Display for TokenKind<'_> {
+    /// Describe the token the way it should appear in a diagnostic
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let punctuation = match self {
+            TokenKind::Identifier(identifier) => return write!(f, "identifier \"{}\"", identifier),
+            TokenKind::IntegerLiteral(literal) => return write!(f, "integer {}", literal.text()),
+            TokenKind::FloatLiteral(literal) => return write!(f, "float {}", literal.text()),
+            TokenKind::StringLiteral(value) => return write!(f, "string {:?}", value),
+            TokenKind::Error(_) => return write!(f, "invalid token"),
+            TokenKind::Whitespace => return write!(f, "whitespace"),
+            TokenKind::Newline => return write!(f, "line break"),
+            TokenKind::Comment => return write!(f, "comment"),
+            TokenKind::Semicolon => ";",
+            TokenKind::Colon => ":",
+            TokenKind::LParen => "(",
+            TokenKind::LBracket => "[",
+            TokenKind::Comma => ",",
+            TokenKind::Equals => "=",
+            TokenKind::RParen => ")",
+            TokenKind::RBracket => "]",
+            TokenKind::Dot => ".",
+            TokenKind::Minus => "-",
+            TokenKind::LBrace => "{",
+            TokenKind::LAngle => "<",
+            TokenKind::Slash => "/",
+            TokenKind::Plus => "+",
+            TokenKind::RBrace => "}",
+            TokenKind::RAngle => ">",
+            keyword => keyword.keyword_text().unwrap_or("keyword"),
+        };
+        write!(f, "\"{}\"", punctuation)
+    }
+}
+
+/// An integer literal as written, it may be too large for any integer type
+#[derive(Clone, PartialEq, Debug)]
+pub struct IntegerLiteral<'storage> {
+    text: YarnBox<'storage, str>,
+    radix: Radix,
+}
+
+impl<'storage> IntegerLiteral<'storage> {
+    pub fn new(text: &'storage str, radix: Radix) -> Self {
+        IntegerLiteral {
+            text: YarnBox::new(text),
+            radix,
+        }
+    }
+
+    /// The literal as written, including the "0x" or "0" prefix of its radix
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    pub fn radix(&self) -> Radix {
+        self.radix
+    }
+
+    fn digits(&self) -> &str {
+        match self.radix {
+            Radix::Hexadecimal => &self.text[2..],
+            _ => &self.text,
+        }
+    }
+
+    /// The value, None if it does not fit in 128 bits
+    pub fn value(&self) -> Option<u128> {
+        u128::from_str_radix(self.digits(), self.radix.into()).ok()
+    }
+
+    /// The value rounded to the nearest double, which never overflows
+    pub fn to_f64(&self) -> f64 {
+        if let (Radix::Decimal, Ok(value)) = (self.radix, f64::from_str(&self.text)) {
+            return value;
+        }
+        let radix = u32::from(self.radix);
+        self.digits().chars().fold(0.0, |value, digit| {
+            value * radix as f64 + digit.to_digit(radix).unwrap_or(0) as f64
+        })
+    }
+}
+
+impl PartialEq<u128> for IntegerLiteral<'_> {
+    fn eq(&self, other: &u128) -> bool {
+        self.value() == Some(*other)
+    }
+}
+
+/// A floating point literal as written, along with its value
+#[derive(Clone, PartialEq, Debug)]
+pub struct FloatLiteral<'storage> {
+    text: YarnBox<'storage, str>,
+    value: f64,
+}
+
+impl<'storage> FloatLiteral<'storage> {
+    /// None if `text` is not a decimal floating point number
+    pub fn new(text: &'storage str) -> Option<Self> {
+        let value = f64::from_str(text).ok()?;
+        Some(FloatLiteral {
+            text: YarnBox::new(text),
+            value,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    /// The value rounded to the nearest double, infinite if it is too large
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// The value of a string literal. Escapes can produce any byte, so it is a byte sequence which
+/// is only text when it happens to be valid UTF-8.
+#[derive(Clone, PartialEq)]
+pub struct StringValue<'storage> {
+    bytes: YarnBox<'storage, [u8]>,
+}
+
+impl<'storage> StringValue<'storage> {
+    pub fn new(bytes: YarnBox<'storage, [u8]>) -> Self {
+        StringValue { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The value as text, or the error locating the first byte that is not valid UTF-8
+    pub fn to_str(&self) -> std::result::Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.bytes)
+    }
+}
+
+impl PartialEq<str> for StringValue<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for StringValue<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl std::fmt::Display for StringValue<'_> {
+    /// The text of the value, bytes that are not valid UTF-8 are replaced
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.bytes))
+    }
+}
+
+impl std::fmt::Debug for StringValue<'_> {
+    /// The value quoted, with bytes that are not valid UTF-8 escaped
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.to_str() {
+            Ok(text) => write!(f, "{:?}", text),
+            Err(_) => write!(f, "\"{}\"", self.bytes.escape_ascii()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub struct TokenMetadata {
     span: Span,
     line_info: LineInfo,
+    file: FileId,
+}
+
+impl TokenMetadata {
+    /// Zero based line number of the token
+    pub fn line_number(&self) -> usize {
+        self.line_info.line_number
+    }
+
+    /// One based column number of the first character of the token, counted in bytes like
+    /// protoc does. `SourceMap` gives columns in other units.
+    pub fn column_number(&self) -> usize {
+        self.span.start - self.line_info.line_start + 1
+    }
+
+    /// Number of bytes spanned by the token
+    pub fn span_len(&self) -> usize {
+        self.span.len()
+    }
+
+    /// Offset of the first byte of the token into the source text
+    pub fn offset(&self) -> usize {
+        self.span.start
+    }
+
+    /// Offset of the byte right after the token
+    pub fn end_offset(&self) -> usize {
+        self.span.end
+    }
+
+    /// The file the token was lexed from
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    /// Metadata of the bytes from `start` to `end` of the text of `file`, for locations that
+    /// were not lexed
+    pub(crate) fn from_offsets<T: AsRef<str>>(
+        file: FileId,
+        source_map: &SourceMap<T>,
+        start: usize,
+        end: usize,
+    ) -> TokenMetadata {
+        let line_number = source_map.line(start);
+        TokenMetadata {
+            span: Span { start, end },
+            line_info: LineInfo {
+                line_start: source_map.line_start(line_number),
+                line_number,
+            },
+            file,
+        }
+    }
+
+    /// Extend the span of the token to the end of the later token `end`
+    pub(crate) fn extend_to(&mut self, end: &TokenMetadata) {
+        self.span.end = end.span.end;
+    }
+
+    /// Move the token past an edit made before it, see `LexerPosition::shift`
+    pub(crate) fn shift(&mut self, bytes: isize, lines: isize) {
+        self.span.start = self.span.start.wrapping_add_signed(bytes);
+        self.span.end = self.span.end.wrapping_add_signed(bytes);
+        self.line_info.line_start = self.line_info.line_start.wrapping_add_signed(bytes);
+        self.line_info.line_number = self.line_info.line_number.wrapping_add_signed(lines);
+    }
+}
+
+impl std::fmt::Display for TokenMetadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.line_info.line_number + 1,
+            self.column_number()
+        )
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -81,70 +349,361 @@ pub struct Token<'storage> {
     pub metadata: TokenMetadata,
 }
 
+impl Token<'_> {
+    /// The name held by an identifier, or by a keyword since keywords are only reserved where
+    /// the grammar expects them. Names are spelled as in `source_text`, the text the token was
+    /// lexed from.
+    pub fn name<'source>(&self, source_text: &'source str) -> Option<&'source str> {
+        match &self.kind {
+            TokenKind::Identifier(_) => {}
+            kind if kind.keyword_text().is_some() => {}
+            _ => return None,
+        }
+        Some(&source_text[self.metadata.offset()..self.metadata.end_offset()])
+    }
+}
+
+/// A comment, kept by the lexer as trivia next to the tokens it produces
+#[derive(Clone, PartialEq, Debug)]
+pub struct Comment {
+    /// The comment as written, including the `//` or `/* */` delimiters but not the line break
+    pub text: String,
+    /// Metadata of the comment, its line is the line the comment starts on
+    pub metadata: TokenMetadata,
+    /// True when the comment follows a token on the same line
+    pub trailing: bool,
+}
+
+impl Comment {
+    /// Zero based line number of the last line of the comment
+    pub fn end_line_number(&self) -> usize {
+        self.metadata.line_number() + self.text.matches('\n').count()
+    }
+
+    fn is_line_comment(&self) -> bool {
+        self.text.starts_with("//")
+    }
+
+    /// The text of the comment the way protoc records it in SourceCodeInfo: without the
+    /// delimiters, a line comment keeps its line break and every line of a block comment after
+    /// the first loses its indentation and leading asterisk
+    pub fn content(&self) -> String {
+        if self.is_line_comment() {
+            return format!("{}\n", &self.text[2..]);
+        }
+        let text = self.text[2..].strip_suffix("*/").unwrap_or(&self.text[2..]);
+        let mut lines = text.split('\n');
+        let mut content = lines.next().unwrap_or_default().to_string();
+        for line in lines {
+            let line = line.trim_start_matches(|ch: char| {
+                ch != '\n' && ch.is_ascii() && is_whitespace(ch as u8)
+            });
+            content.push('\n');
+            content.push_str(line.strip_prefix('*').unwrap_or(line));
+        }
+        content
+    }
+}
+
+/// The comments found between two tokens, split the way protoc attaches comments to the
+/// declarations around them
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct TokenComments {
+    /// Comment attached to the previous token
+    pub trailing: Option<String>,
+    /// Comment blocks separated from both tokens by blank lines
+    pub detached: Vec<String>,
+    /// Comment block attached to the next token
+    pub leading: Option<String>,
+}
+
+/// Groups consecutive comments into blocks, protoc's `CommentCollector`
+struct CommentCollector {
+    comments: TokenComments,
+    /// The block being collected and whether it is made of line comments
+    block: Option<(String, bool)>,
+    can_attach_to_previous: bool,
+    /// Number of blocks flushed so far
+    count: usize,
+}
+
+impl CommentCollector {
+    fn add(&mut self, comment: &Comment) {
+        let is_line_comment = comment.is_line_comment();
+        match &mut self.block {
+            // Consecutive line comments form a single block
+            Some((block, true)) if is_line_comment => block.push_str(&comment.content()),
+            _ => {
+                self.flush();
+                self.block = Some((comment.content(), is_line_comment));
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some((block, _)) = self.block.take() {
+            if self.can_attach_to_previous {
+                self.comments.trailing = Some(block);
+                self.can_attach_to_previous = false;
+            } else {
+                self.comments.detached.push(block);
+            }
+            self.count += 1;
+        }
+    }
+
+    fn detach_from_previous(&mut self) {
+        self.flush();
+        self.can_attach_to_previous = false;
+    }
+}
+
+/// Split the comments between the token on `previous_line` (`None` at the start of the file)
+/// and the next token, given by its line and whether it closes a scope (`None` at the end of
+/// the file). Follows `Tokenizer::NextWithComments` of protoc:
+/// - A comment on the line of the previous token trails it. So does the first block on the
+///   lines after it, if a blank line separates that block from the next token.
+/// - The block right before the next token leads it, unless the token closes a scope.
+/// - Every other block is detached.
+fn split_comments(
+    comments: &[Comment],
+    previous_line: Option<usize>,
+    next: Option<(usize, bool)>,
+) -> TokenComments {
+    let mut collector = CommentCollector {
+        comments: TokenComments::default(),
+        block: None,
+        can_attach_to_previous: previous_line.is_some(),
+        count: 0,
+    };
+    let mut last_line = previous_line;
+    let mut trailing_end_line = None;
+    let mut rest = comments;
+    if let [first, others @ ..] = comments {
+        if first.trailing {
+            let following_line = others
+                .first()
+                .map(|comment| comment.metadata.line_number())
+                .or(next.map(|(line, _)| line));
+            if !first.is_line_comment() && following_line == Some(first.end_line_number()) {
+                // Something follows the block comment on its line, protoc cannot tell which
+                // token it belongs to and drops every comment up to the next token
+                return TokenComments::default();
+            }
+            collector.add(first);
+            collector.flush();
+            last_line = Some(first.end_line_number());
+            trailing_end_line = last_line;
+            rest = others;
+        }
+    }
+    for comment in rest {
+        if last_line.is_some_and(|last_line| comment.metadata.line_number() > last_line + 1) {
+            collector.detach_from_previous();
+        }
+        collector.add(comment);
+        last_line = Some(comment.end_line_number());
+    }
+    match next {
+        Some((line, closes_scope)) => {
+            if last_line.is_some_and(|last_line| line > last_line + 1) {
+                collector.detach_from_previous();
+            }
+            if closes_scope {
+                collector.flush();
+            }
+            let on_previous_line = previous_line == Some(line) || trailing_end_line == Some(line);
+            let count = collector.count + usize::from(collector.block.is_some());
+            if on_previous_line && count == 1 {
+                // Whether the only comment belongs to the token before or after it is unclear
+                if let Some(trailing) = collector.comments.trailing.take() {
+                    collector.comments.detached.insert(0, trailing);
+                }
+                collector.detach_from_previous();
+            }
+        }
+        None => collector.flush(),
+    }
+    collector.comments.leading = collector.block.take().map(|(block, _)| block);
+    collector.comments
+}
+
+/// A token along with the exact source text it was lexed from
+#[derive(Clone, Debug)]
+pub struct LosslessToken<'storage> {
+    pub token: Token<'storage>,
+    pub text: &'storage str,
+}
+
+/// Lexer mode that also produces whitespace, line breaks and comments as tokens, so that the
+/// texts of the tokens put together give back the source text exactly
+#[derive(Clone)]
+pub struct LosslessLexer<'storage> {
+    lexer: Lexer<'storage>,
+}
+
+impl<'storage> LosslessLexer<'storage> {
+    pub fn new(source_text: &'storage str) -> Self {
+        LosslessLexer {
+            lexer: Lexer::new(source_text),
+        }
+    }
+}
+
+impl<'storage> Iterator for LosslessLexer<'storage> {
+    type Item = LosslessToken<'storage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lexer = &mut self.lexer;
+        let start = lexer.cursor.get_current_index();
+        let token = match lexer.next_trivia() {
+            Some(trivia) => trivia,
+            None => lexer.next_token()?,
+        };
+        let source_text = lexer.source_text;
+        Some(LosslessToken {
+            token,
+            text: &source_text[start..lexer.cursor.get_current_index()],
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct Lexer<'storage> {
     source_text: &'storage str,
+    file: FileId,
     cursor: Cursor<'storage>,
-    current_line_column: usize,
     current_line_number: usize,
-    current_line_start_char_offset: usize,
+    /// Byte offset of the start of the current line
+    current_line_start: usize,
+    /// Line of the token being lexed, which may end on a later line
+    token_line_info: LineInfo,
     seen_error: bool,
+    /// Line of the last token returned, comments on the same line trail it
+    last_token_line_number: Option<usize>,
+    comments: Vec<Comment>,
+    /// Index of the first comment after the last token returned
+    pending_comments: usize,
+    /// Comments before the last token returned, or before the end of the file once reached
+    token_comments: TokenComments,
+    end_reached: bool,
+    token_end: LexerPosition,
+    /// Whether `#` starts a comment, as it does in the text format
+    hash_comments: bool,
+}
+
+/// A place in the source text between two tokens, along with the line tracking state of the
+/// lexer there
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct LexerPosition {
+    /// Offset into the source text in bytes
+    pub byte_offset: usize,
+    line_number: usize,
+    line_start: usize,
+    /// Line of the token before the position, comments on that line trail it
+    last_token_line_number: Option<usize>,
+}
+
+impl LexerPosition {
+    /// Move the position past an edit made before it that changed the length of the text
+    /// by `bytes` and its number of lines by `lines`. The edit must end on an earlier line, so
+    /// that the column stays the same.
+    pub(crate) fn shift(&mut self, bytes: isize, lines: isize) {
+        self.byte_offset = self.byte_offset.wrapping_add_signed(bytes);
+        self.line_number = self.line_number.wrapping_add_signed(lines);
+        self.line_start = self.line_start.wrapping_add_signed(bytes);
+        if let Some(line) = &mut self.last_token_line_number {
+            *line = line.wrapping_add_signed(lines);
+        }
+    }
 }
 
 impl<'storage> Lexer<'storage> {
     /// Create a lexer to generate tokens for the provided source text
     pub fn new(source_text: &'storage str) -> Self {
+        Self::resume(source_text, &LexerPosition::default())
+    }
+
+    /// Create a lexer for a registered file, its tokens carry the id of the file
+    pub fn for_buffer(source: &'storage SourceBuffer) -> Self {
+        let mut lexer = Self::new(source.text());
+        lexer.file = source.id();
+        lexer
+    }
+
+    /// Create a lexer that starts at `position` of the source text, as if it had lexed
+    /// everything before it
+    pub fn resume(source_text: &'storage str, position: &LexerPosition) -> Self {
         Lexer {
             source_text,
-            cursor: Cursor::new(source_text),
-            current_line_column: 0,
-            current_line_number: 0,
-            current_line_start_char_offset: 0,
+            file: FileId::default(),
+            cursor: Cursor::new_at(source_text, position.byte_offset),
+            current_line_number: position.line_number,
+            current_line_start: position.line_start,
+            token_line_info: LineInfo {
+                line_start: position.line_start,
+                line_number: position.line_number,
+            },
             seen_error: false,
+            last_token_line_number: position.last_token_line_number,
+            comments: Vec::new(),
+            pending_comments: 0,
+            token_comments: TokenComments::default(),
+            end_reached: false,
+            token_end: position.clone(),
+            hash_comments: false,
         }
     }
 
-    /// Print the token in the context of the line it's part of in the source text
-    pub fn print_token_in_line(&self, metadata: &TokenMetadata) {
-        println!(
-            "Line {}:{}",
-            metadata.line_info.line_number, metadata.line_info.column_number
-        );
-        println!("{}", self.get_token_line(metadata));
-        for _ in 0..metadata.line_info.column_number - 2 {
-            print!(" ");
-        }
-        print!("↑");
-        if metadata.span.len() > 1 {
-            for _ in metadata.line_info.column_number
-                ..(metadata.line_info.column_number - 2 + metadata.span.len())
-            {
-                print!(" ");
-            }
-            println!("↑");
-        } else {
-            println!("");
-        }
+    /// Create a lexer for a document in the protobuf text format, where `#` starts a comment
+    /// that runs to the end of the line
+    pub fn for_text_format(source_text: &'storage str) -> Self {
+        let mut lexer = Self::new(source_text);
+        lexer.hash_comments = true;
+        lexer
     }
 
-    fn get_token_line(&self, metadata: &TokenMetadata) -> String {
-        let line_start = metadata.line_info.line_start_offset_into_source;
-        let offset = self.source_text[line_start + 1..].find('\n');
-        if let Some(offset) = offset {
-            format!("{}", &self.source_text[line_start..line_start + offset + 1])
-        } else {
-            format!("{}", &self.source_text[line_start..])
-        }
+    /// Position right after the last token returned
+    pub fn token_end(&self) -> &LexerPosition {
+        &self.token_end
+    }
+
+    /// Comments found so far, in source order
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    /// Take the comments found so far, leaving none behind
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        self.pending_comments = 0;
+        std::mem::take(&mut self.comments)
+    }
+
+    /// Take the comments between the last token returned and the one before it
+    pub fn take_token_comments(&mut self) -> TokenComments {
+        std::mem::take(&mut self.token_comments)
+    }
+
+    /// Print the token in the context of the line it's part of in the source text
+    pub fn print_token_in_line(&self, metadata: &TokenMetadata) {
+        let source_map = SourceMap::new(self.source_text);
+        let start = source_map.line_column(metadata.offset(), ColumnUnit::Grapheme);
+        let line_text = source_map.line_text(start.line);
+        // Tokens spanning several lines are only underlined up to the end of the first one
+        let end = metadata
+            .end_offset()
+            .min(source_map.line_start(start.line) + line_text.len());
+        let width = source_map.line_column(end, ColumnUnit::Grapheme).column - start.column;
+        println!("Line {}:{}", start.line + 1, start.column + 1);
+        println!("{}", line_text);
+        println!("{}{}", " ".repeat(start.column), "^".repeat(width.max(1)));
     }
 
     fn get_token_metadata(&self, span: Span) -> TokenMetadata {
         TokenMetadata {
-            span: span.clone(),
-            line_info: LineInfo {
-                line_start_offset_into_source: self.current_line_start_char_offset,
-                line_number: self.current_line_number,
-                column_number: self.current_line_column - span.len() + 1,
-            },
+            span,
+            line_info: self.token_line_info,
+            file: self.file,
         }
     }
 
@@ -152,72 +711,34 @@ impl<'storage> Lexer<'storage> {
         debug_assert!(header.is_alphabetic() || header == '_');
         let start = self.cursor.get_current_index() - 1;
         loop {
-            if let Some(ch) = self.cursor.peek() {
-                if ch.is_alphanumeric() || ch == '_' {
-                    _ = self.next_char();
-                } else {
-                    break;
-                }
-            } else {
-                break;
+            self.cursor
+                .skip_while(|byte| byte.is_ascii_alphanumeric() || byte == b'_');
+            // Letters and digits outside of ASCII are rare, only decode them when found
+            match self.cursor.peek() {
+                Some(ch) if !ch.is_ascii() && ch.is_alphanumeric() => _ = self.cursor.next_char(),
+                _ => break,
             }
         }
         let end = self.cursor.get_current_index();
-        let idententifier_or_keyword = &self.source_text[start..end];
-        if let Some(keyword) = get_keyword_token_kind(idententifier_or_keyword) {
-            return Some(Token {
-                kind: keyword,
-                metadata: self.get_token_metadata(Span { start, end }),
-            });
-        }
-        return Some(Token {
-            kind: TokenKind::Identifier(YarnBox::from(idententifier_or_keyword)),
+        let text = &self.source_text[start..end];
+        let kind = get_keyword_token_kind(text)
+            .unwrap_or_else(|| TokenKind::Identifier(YarnBox::from(text)));
+        Some(Token {
+            kind,
             metadata: self.get_token_metadata(Span { start, end }),
-        });
+        })
     }
 
     fn consume_decimal_digits(&mut self) {
-        loop {
-            if let Some(ch) = self.cursor.peek() {
-                if ch.is_ascii_digit() {
-                    _ = self.next_char();
-                } else {
-                    break;
-                }
-            } else {
-                break;
-            }
-        }
+        self.cursor.skip_while(|byte| byte.is_ascii_digit());
     }
+
     fn consume_hex_digits(&mut self) {
-        loop {
-            if let Some(ch) = self.cursor.peek() {
-                if ch.is_ascii_hexdigit() {
-                    _ = self.next_char();
-                } else {
-                    break;
-                }
-            } else {
-                break;
-            }
-        }
+        self.cursor.skip_while(|byte| byte.is_ascii_hexdigit());
     }
 
     fn consume_octal_digits(&mut self) {
-        loop {
-            if let Some(ch) = self.cursor.peek() {
-                match ch {
-                    '0'..='7' => {
-                        _ = self.next_char();
-                    }
-                    _ => {
-                        break;
-                    }
-                }
-            } else {
-                break;
-            }
-        }
+        self.cursor.skip_while(|byte| matches!(byte, b'0'..=b'7'));
     }
 
     fn determine_radix(&mut self, header: char) -> Radix {
@@ -225,17 +746,19 @@ impl<'storage> Lexer<'storage> {
         let mut radix = Radix::Decimal; // Default to a decimal radix for the integral part
         if header == '0' {
             radix = Radix::Octal;
-            if let Some(ch) = self.cursor.peek() {
-                if ch == 'X' || ch == 'x' {
-                    radix = Radix::Hexadecimal;
-                    _ = self.next_char();
-                }
+            if let Some(b'X' | b'x') = self.cursor.peek_byte() {
+                radix = Radix::Hexadecimal;
+                _ = self.next_char();
             }
         }
-        return radix;
+        radix
     }
 
-    fn extract_integral_part(&mut self, header: char, radix: Radix) -> Result<Span> {
+    fn extract_integral_part(
+        &mut self,
+        header: char,
+        radix: Radix,
+    ) -> std::result::Result<Span, &'static str> {
         debug_assert!(header.is_numeric() || header == '.');
         if header == '.' {
             // Example case: ".123" Integral part = ""
@@ -253,9 +776,7 @@ impl<'storage> Lexer<'storage> {
                     let cached_index = self.cursor.get_current_index();
                     self.consume_hex_digits();
                     if cached_index == self.cursor.get_current_index() {
-                        return Err(RsProtocError::LexError(
-                            "Expected hexadecimal digits after the \"0x\"/\"0X\"".to_string(),
-                        ));
+                        return Err("Expected hexadecimal digits after the \"0x\"/\"0X\"");
                     }
                 }
                 Radix::Octal => self.consume_octal_digits(),
@@ -286,52 +807,37 @@ impl<'storage> Lexer<'storage> {
             // Example case: "123" Fractional part = ""
             let mut start = self.cursor.get_current_index();
             let mut end = self.cursor.get_current_index();
-            if let Some(ch) = self.cursor.peek() {
-                if ch == '.' {
-                    // Example case: "123.666" Fractional part = .666
-                    start = self.cursor.get_current_index();
-                    _ = self.next_char();
-                    self.consume_decimal_digits();
-                    end = self.cursor.get_current_index();
-                }
+            if self.cursor.peek_byte() == Some(b'.') {
+                // Example case: "123.666" Fractional part = .666
+                start = self.cursor.get_current_index();
+                _ = self.next_char();
+                self.consume_decimal_digits();
+                end = self.cursor.get_current_index();
             }
             Span { start, end }
         }
     }
 
-    fn extract_exponent(&mut self) -> Result<Span> {
+    fn extract_exponent(&mut self) -> std::result::Result<Span, &'static str> {
         // exponent  = ( "e" | "E" ) [ "+" | "-" ] <EXPONENT_PART>
         // Default to empty span
         let mut span = Span {
             start: self.cursor.get_current_index(),
             end: self.cursor.get_current_index(),
         };
-        if let Some(ch) = self.cursor.peek() {
-            match ch {
-                'e' | 'E' => {
-                    _ = self.next_char();
-                    span.start += 1;
-                    if let Some(ch) = self.cursor.peek() {
-                        match ch {
-                            '+' | '-' => {
-                                // Consume optional '+'/'-' after the 'e'/'E'
-                                _ = self.next_char();
-                            }
-                            _ => {}
-                        }
-                    }
-                    let cached_index = self.cursor.get_current_index();
-                    self.consume_decimal_digits();
-                    if cached_index == self.cursor.get_current_index() {
-                        return Err(RsProtocError::LexError(
-                            "Expected decimal digits in exponent part of numeric literal"
-                                .to_string(),
-                        ));
-                    }
-                    span.end = self.cursor.get_current_index();
-                }
-                _ => {}
+        if let Some(b'e' | b'E') = self.cursor.peek_byte() {
+            _ = self.next_char();
+            span.start += 1;
+            if let Some(b'+' | b'-') = self.cursor.peek_byte() {
+                // Consume optional '+'/'-' after the 'e'/'E'
+                _ = self.next_char();
+            }
+            let cached_index = self.cursor.get_current_index();
+            self.consume_decimal_digits();
+            if cached_index == self.cursor.get_current_index() {
+                return Err("Expected decimal digits in exponent part of numeric literal");
             }
+            span.end = self.cursor.get_current_index();
         }
         Ok(span)
     }
@@ -346,613 +852,409 @@ impl<'storage> Lexer<'storage> {
         let integral_part = match self.extract_integral_part(header, radix) {
             Ok(integral_part) => integral_part,
             Err(err) => {
-                return Some(Token {
-                    kind: self.get_error_token(err.to_string().as_str()),
-                    metadata: self.get_token_metadata(Span {
+                return Some(self.error_token(
+                    err,
+                    Span {
                         start,
                         end: self.cursor.get_current_index(),
-                    }),
-                });
+                    },
+                ));
             }
         };
         let fractional_part: Span = self.extract_fractional_part(&integral_part, header);
         let exponent_part = match self.extract_exponent() {
             Ok(exponent_part) => exponent_part,
             Err(err) => {
-                return Some(Token {
-                    kind: self.get_error_token(err.to_string().as_str()),
-                    metadata: self.get_token_metadata(Span {
+                return Some(self.error_token(
+                    err,
+                    Span {
                         start,
                         end: self.cursor.get_current_index(),
-                    }),
-                });
+                    },
+                ));
             }
         };
-        let integral_value = {
-            if !integral_part.is_empty() {
-                match u64::from_str_radix(
-                    integral_part.extract_from_source(self.source_text),
-                    u32::from(radix),
-                ) {
-                    Ok(value) => value,
-                    Err(err) => {
-                        return Some(Token {
-                            kind: self.get_error_token(err.to_string().as_str()),
-                            metadata: self.get_token_metadata(Span {
-                                start,
-                                end: self.cursor.get_current_index(),
-                            }),
-                        });
-                    }
-                }
-            } else {
-                0u64
-            }
-        };
-        if fractional_part.is_empty() && exponent_part.is_empty() && !integral_part.is_empty() {
-            return Some(Token {
-                kind: TokenKind::IntegerLiteral(integral_value),
-                metadata: self.get_token_metadata(Span {
-                    start,
-                    end: self.cursor.get_current_index(),
-                }),
-            });
-        }
-        let mut floating_point_number = integral_value as f64;
-        let fractional_value = {
-            if fractional_part.is_empty() || fractional_part.len() == 1 {
-                0f64
-            } else {
-                match f64::from_str(fractional_part.extract_from_source(self.source_text)) {
-                    Ok(value) => value,
-                    Err(err) => {
-                        return Some(Token {
-                            kind: self.get_error_token(err.to_string().as_str()),
-                            metadata: self.get_token_metadata(Span {
-                                start,
-                                end: self.cursor.get_current_index(),
-                            }),
-                        });
-                    }
-                }
-            }
+        let span = Span {
+            start,
+            end: self.cursor.get_current_index(),
         };
-        floating_point_number += fractional_value;
-        if exponent_part.is_empty() {
-            return Some(Token {
-                kind: TokenKind::FloatLiteral(floating_point_number),
-                metadata: self.get_token_metadata(Span {
-                    start,
-                    end: self.cursor.get_current_index(),
-                }),
-            });
-        }
-
-        let exponent_value: i32 = {
-            match i32::from_str_radix(exponent_part.extract_from_source(self.source_text), 10) {
-                Ok(value) => value,
-                Err(err) => {
-                    return Some(Token {
-                        kind: self.get_error_token(err.to_string().as_str()),
-                        metadata: self.get_token_metadata(Span {
-                            start,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    });
-                }
+        let text = &self.source_text[span.start..span.end];
+        // Values are only computed from the text when needed, an integer literal may not fit in
+        // any integer type and still be valid for a floating point field
+        let kind = if fractional_part.is_empty() && exponent_part.is_empty() {
+            TokenKind::IntegerLiteral(IntegerLiteral::new(text, radix))
+        } else {
+            match FloatLiteral::new(text) {
+                Some(literal) => TokenKind::FloatLiteral(literal),
+                None => return Some(self.error_token("Invalid numeric literal", span)),
             }
         };
-
-        floating_point_number = floating_point_number * 10f64.powi(exponent_value);
-
-        return Some(Token {
-            kind: TokenKind::FloatLiteral(floating_point_number),
-            metadata: self.get_token_metadata(Span {
-                start,
-                end: self.cursor.get_current_index(),
-            }),
-        });
+        Some(Token {
+            kind,
+            metadata: self.get_token_metadata(span),
+        })
     }
 
-    fn string_literal(&mut self, string_literal_header: char) -> Option<Token<'storage>> {
+    /// https://protobuf.com/docs/language-spec#string-literals
+    fn string_literal(&mut self, quote: char) -> Option<Token<'storage>> {
         // We've already consumed the quote
-        debug_assert!(string_literal_header == '\'' || string_literal_header == '\"');
-        let string_literal_start_index = self.cursor.get_current_index();
-        let mut escaped_sequence = String::new();
+        debug_assert!(quote == '\'' || quote == '\"');
+        let source_text = self.source_text;
+        let start = self.cursor.get_current_index() - 1;
+        let content_start = start + 1;
+        // Only allocated once an escape sequence is found, until then the value is the source
+        let mut escaped: Option<Vec<u8>> = None;
+        let mut invalid_escapes = Vec::new();
         loop {
-            if let Some(ch) = self.next_char() {
-                match ch {
-                    '\n' => {
-                        return Some(Token {
-                            kind: self.get_error_token("Unterminated string literal"),
-                            metadata: self.get_token_metadata(Span {
-                                start: string_literal_start_index,
-                                end: self.cursor.get_current_index(),
-                            }),
-                        });
-                    }
-                    '\x00' => {
-                        return Some(Token {
-                            kind: self.get_error_token("Unterminated string literal"),
-                            metadata: self.get_token_metadata(Span {
-                                start: string_literal_start_index,
-                                end: self.cursor.get_current_index(),
-                            }),
-                        });
-                    }
-                    '\\' => {
-                        // Start of escape sequence
-                        if escaped_sequence.is_empty() {
-                            // Trigger a dynamic allocation and capture all the characters until the start of the escape sequence
-                            escaped_sequence.push_str(
-                                &self.source_text[string_literal_start_index
-                                    ..self.cursor.get_current_index() - 1],
-                            );
-                        }
-                        if !self.consume_escape_sequence(&mut escaped_sequence) {
-                            return Some(Token {
-                                kind: self
-                                    .get_error_token("Invalid escape sequence in string literal"),
-                                metadata: self.get_token_metadata(Span {
-                                    start: string_literal_start_index,
-                                    end: self.cursor.get_current_index(),
-                                }),
-                            });
-                        }
-                    }
-                    ch if ch == string_literal_header => {
-                        // '\'' OR '\"'
-                        if escaped_sequence.len() > 0 {
-                            return Some(Token {
-                                kind: TokenKind::StringLiteral(YarnBox::from_string(
-                                    escaped_sequence,
-                                )),
-                                metadata: self.get_token_metadata(Span {
-                                    start: string_literal_start_index,
-                                    end: self.cursor.get_current_index(),
-                                }),
-                            });
-                        } else {
-                            return Some(Token {
-                                kind: TokenKind::StringLiteral(YarnBox::new(
-                                    &self.source_text[string_literal_start_index
-                                        ..self.cursor.get_current_index() - 1],
-                                )),
-                                metadata: self.get_token_metadata(Span {
-                                    start: string_literal_start_index,
-                                    end: self.cursor.get_current_index(),
-                                }),
-                            });
-                        }
-                    }
-                    ch => {
-                        if escaped_sequence.len() > 0 {
-                            // We've already triggered an allocation previously when we came across an escape sequence
-                            escaped_sequence.push(ch);
-                        }
-                    }
-                }
-            } else {
-                return Some(Token {
-                    kind: self.get_error_token("Unterminated string literal"),
-                    metadata: self.get_token_metadata(Span {
-                        start: string_literal_start_index,
-                        end: self.cursor.get_current_index(),
-                    }),
-                });
+            // Copy runs of plain characters at once
+            let run = self.cursor.get_current_index();
+            self.cursor.skip_while(|byte| {
+                byte.is_ascii() && !matches!(byte, b'\n' | b'\x00' | b'\\' | b'\'' | b'"')
+            });
+            if let Some(value) = &mut escaped {
+                value.extend_from_slice(
+                    &source_text.as_bytes()[run..self.cursor.get_current_index()],
+                );
             }
-        }
-    }
-
-    fn next_token(&mut self) -> Option<Token<'storage>> {
-        self.consume_whitespace_and_comments();
-        while let Some(ch) = self.next_char() {
-            match ch {
-                ';' => {
-                    return Some(Token {
-                        kind: TokenKind::Semicolon,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                ':' => {
-                    return Some(Token {
-                        kind: TokenKind::Colon,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                '(' => {
-                    return Some(Token {
-                        kind: TokenKind::LParen,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                '[' => {
-                    return Some(Token {
-                        kind: TokenKind::LBracket,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                ',' => {
-                    return Some(Token {
-                        kind: TokenKind::Comma,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                '=' => {
-                    return Some(Token {
-                        kind: TokenKind::Equals,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                ')' => {
-                    return Some(Token {
-                        kind: TokenKind::RParen,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                ']' => {
-                    return Some(Token {
-                        kind: TokenKind::RBracket,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                '.' => {
-                    if let Some(next_ch) = self.cursor.peek() {
-                        if next_ch.is_numeric() {
-                            return self.numeric_literal(ch);
-                        }
-                    }
-                    return Some(Token {
-                        kind: TokenKind::Dot,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    });
-                }
-                '-' => {
-                    return Some(Token {
-                        kind: TokenKind::Minus,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                '{' => {
-                    return Some(Token {
-                        kind: TokenKind::LBrace,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
+            match self.cursor.peek() {
+                None | Some('\n') => {
+                    return Some(self.error_token(
+                        "Unterminated string literal",
+                        Span {
+                            start,
                             end: self.cursor.get_current_index(),
-                        }),
-                    })
+                        },
+                    ));
                 }
-                '<' => {
-                    return Some(Token {
-                        kind: TokenKind::LAngle,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
+                Some(_) => {}
+            }
+            let ch = self.next_char()?;
+            match ch {
+                '\x00' => {
+                    return Some(self.error_token(
+                        "Unterminated string literal",
+                        Span {
+                            start,
                             end: self.cursor.get_current_index(),
-                        }),
-                    })
+                        },
+                    ));
                 }
-                '/' => {
-                    return Some(Token {
-                        kind: TokenKind::Slash,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
+                '\\' => {
+                    let escape_start = self.cursor.get_current_index() - 1;
+                    let value = escaped.get_or_insert_with(|| {
+                        source_text.as_bytes()[content_start..escape_start].to_vec()
                     });
-                }
-                '+' => {
-                    return Some(Token {
-                        kind: TokenKind::Plus,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                '}' => {
-                    return Some(Token {
-                        kind: TokenKind::RBrace,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
-                }
-                '>' => {
-                    return Some(Token {
-                        kind: TokenKind::RAngle,
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
+                    if let Err(message) = self.consume_escape_sequence(value) {
+                        let span = Span {
+                            start: escape_start,
                             end: self.cursor.get_current_index(),
-                        }),
-                    })
+                        };
+                        invalid_escapes.push((span, message));
+                    }
                 }
-                '\'' | '"' => return self.string_literal(ch),
-                '0'..='9' => return self.numeric_literal(ch),
-                'a'..='z' | 'A'..='Z' | '_' => return self.identifier_or_keyword(ch),
-                _ => {
-                    return Some(Token {
-                        kind: self.get_error_token("Unknown character"),
-                        metadata: self.get_token_metadata(Span {
-                            start: self.cursor.get_current_index() - 1,
-                            end: self.cursor.get_current_index(),
-                        }),
-                    })
+                ch if ch == quote => break,
+                ch => {
+                    if let Some(value) = &mut escaped {
+                        value.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
                 }
             }
         }
-        None
+        let end = self.cursor.get_current_index();
+        let metadata = self.get_token_metadata(Span { start, end });
+        if !invalid_escapes.is_empty() {
+            self.seen_error = true;
+            let mut diagnostic = Diagnostic::error("Invalid escape sequence in string literal");
+            for (span, message) in invalid_escapes {
+                diagnostic = diagnostic.with_primary_label(&self.get_token_metadata(span), message);
+            }
+            return Some(Token {
+                kind: TokenKind::Error(Box::new(diagnostic)),
+                metadata,
+            });
+        }
+        let value = match escaped {
+            Some(value) => YarnBox::from_vec(value),
+            None => YarnBox::new(&source_text.as_bytes()[content_start..end - 1]),
+        };
+        Some(Token {
+            kind: TokenKind::StringLiteral(StringValue::new(value)),
+            metadata,
+        })
     }
 
-    fn get_error_token(&mut self, message: &str) -> TokenKind<'storage> {
-        self.seen_error = true;
-        let mut error_message = format!("Lexer error {}\n", message);
-        error_message += format!("{}", self.current_line_number).as_str();
+    fn next_token(&mut self) -> Option<Token<'storage>> {
+        self.consume_whitespace_and_comments();
+        self.token_line_info = self.line_info();
+        let ch = self.next_char()?;
+        let kind = match ch {
+            ';' => TokenKind::Semicolon,
+            ':' => TokenKind::Colon,
+            '(' => TokenKind::LParen,
+            '[' => TokenKind::LBracket,
+            ',' => TokenKind::Comma,
+            '=' => TokenKind::Equals,
+            ')' => TokenKind::RParen,
+            ']' => TokenKind::RBracket,
+            '.' if self
+                .cursor
+                .peek_byte()
+                .is_some_and(|byte| byte.is_ascii_digit()) =>
+            {
+                return self.numeric_literal(ch)
+            }
+            '.' => TokenKind::Dot,
+            '-' => TokenKind::Minus,
+            '{' => TokenKind::LBrace,
+            '<' => TokenKind::LAngle,
+            '/' => TokenKind::Slash,
+            '+' => TokenKind::Plus,
+            '}' => TokenKind::RBrace,
+            '>' => TokenKind::RAngle,
+            '\'' | '"' => return self.string_literal(ch),
+            '0'..='9' => return self.numeric_literal(ch),
+            'a'..='z' | 'A'..='Z' | '_' => return self.identifier_or_keyword(ch),
+            _ => {
+                return Some(self.error_token(
+                    &format!("Unknown character {:?}", ch),
+                    Span {
+                        start: self.cursor.get_current_index() - ch.len_utf8(),
+                        end: self.cursor.get_current_index(),
+                    },
+                ))
+            }
+        };
+        // Punctuation is a single ASCII character
+        Some(Token {
+            kind,
+            metadata: self.get_token_metadata(Span {
+                start: self.cursor.get_current_index() - 1,
+                end: self.cursor.get_current_index(),
+            }),
+        })
+    }
 
-        TokenKind::Error(error_message)
+    fn error_token(&mut self, message: &str, span: Span) -> Token<'storage> {
+        self.seen_error = true;
+        let metadata = self.get_token_metadata(span);
+        let diagnostic = Diagnostic::error(message).with_primary_label(&metadata, "");
+        Token {
+            kind: TokenKind::Error(Box::new(diagnostic)),
+            metadata,
+        }
     }
 
     /// https://protobuf.com/docs/language-spec#whitespace-and-comments
     fn consume_whitespace_and_comments(&mut self) {
-        let is_start_of_single_line_comment = |cursor: &mut Cursor| -> bool {
-            if let Some(char_0) = cursor.peek() {
-                if char_0 == '/' {
-                    if let Some(char_1) = cursor.peek_next() {
-                        if char_1 == '/' {
-                            return true;
-                        }
-                    }
-                }
+        loop {
+            match (self.cursor.peek_byte(), self.cursor.peek_next_byte()) {
+                (Some(b'/'), Some(b'*')) => self.record_comment(Self::consume_block_comment),
+                (Some(b'/'), Some(b'/')) => self.record_comment(Self::consume_single_line_comment),
+                (Some(b'#'), _) if self.hash_comments => {
+                    let rest = self.cursor.rest();
+                    let len = rest.iter().position(|byte| *byte == b'\n');
+                    self.skip_bytes(len.unwrap_or(rest.len()));
+                }
+                (Some(b'\n'), _) => _ = self.next_char(),
+                (Some(byte), _) if is_whitespace(byte) => self
+                    .cursor
+                    .skip_while(|byte| byte != b'\n' && is_whitespace(byte)),
+                // At the first non-whitespace/non-comment character
+                _ => break,
             }
-            false
+        }
+    }
+
+    /// Consume a run of whitespace, a line break or a comment as a single trivia token
+    fn next_trivia(&mut self) -> Option<Token<'storage>> {
+        let start = self.cursor.get_current_index();
+        let line_info = self.line_info();
+        let rest = self.cursor.rest();
+        let at_line_break = |index: usize| {
+            let rest = &rest[index..];
+            rest.starts_with(b"\n") || rest.starts_with(b"\r\n")
         };
-        let is_start_of_block_comment = |cursor: &mut Cursor| -> bool {
-            if let Some(char_0) = cursor.peek() {
-                if char_0 == '/' {
-                    if let Some(char_1) = cursor.peek_next() {
-                        if char_1 == '*' {
-                            return true;
-                        }
-                    }
-                }
+        let kind = match (self.cursor.peek_byte()?, self.cursor.peek_next_byte()) {
+            (b'\n', _) => {
+                self.skip_bytes(1);
+                TokenKind::Newline
             }
-            false
-        };
-        loop {
-            if is_start_of_block_comment(&mut self.cursor) {
-                self.consume_block_comment();
-                continue;
+            (b'\r', Some(b'\n')) => {
+                self.skip_bytes(2);
+                TokenKind::Newline
             }
-            if is_start_of_single_line_comment(&mut self.cursor) {
-                self.consume_single_line_comment();
-                continue;
+            (b'/', Some(b'/')) => {
+                // Unlike in `consume_single_line_comment`, the line break is a token of its own
+                let len = (2..rest.len()).find(|index| at_line_break(*index));
+                self.skip_bytes(len.unwrap_or(rest.len()));
+                TokenKind::Comment
             }
-            if let Some(ch) = self.cursor.peek() {
-                if is_whitespace(ch) {
-                    _ = self.next_char(); // Consume the whitespace and move ahead
-                    continue;
-                }
+            (b'/', Some(b'*')) => {
+                self.consume_block_comment();
+                TokenKind::Comment
+            }
+            (byte, _) if is_whitespace(byte) => {
+                let len = (0..rest.len())
+                    .find(|index| !is_whitespace(rest[*index]) || at_line_break(*index));
+                self.skip_bytes(len.unwrap_or(rest.len()));
+                TokenKind::Whitespace
             }
-            // At the first non-whitespace/non-comment character
-            break;
+            _ => return None,
+        };
+        Some(Token {
+            kind,
+            metadata: TokenMetadata {
+                span: Span {
+                    start,
+                    end: self.cursor.get_current_index(),
+                },
+                line_info,
+                file: self.file,
+            },
+        })
+    }
+
+    /// Consume a comment with `consume` and keep it as trivia
+    fn record_comment(&mut self, consume: fn(&mut Self)) {
+        let start = self.cursor.get_current_index();
+        let line_info = self.line_info();
+        consume(self);
+        let span = Span {
+            start,
+            end: self.cursor.get_current_index(),
+        };
+        let text = &self.source_text[span.start..span.end];
+        // A line comment owns the line break that ends it, the break is not part of the text
+        let text = text.strip_suffix('\n').unwrap_or(text).to_string();
+        self.comments.push(Comment {
+            trailing: self.last_token_line_number == Some(line_info.line_number),
+            text,
+            metadata: TokenMetadata {
+                span,
+                line_info,
+                file: self.file,
+            },
+        });
+    }
+
+    fn line_info(&self) -> LineInfo {
+        LineInfo {
+            line_start: self.current_line_start,
+            line_number: self.current_line_number,
         }
     }
 
     fn next_char(&mut self) -> Option<char> {
-        match self.cursor.next_with_index() {
-            Some((_, ch)) => {
-                if ch == '\n' {
-                    self.current_line_number += 1;
-                    self.current_line_column = 1;
-                    self.current_line_start_char_offset = self.cursor.get_current_index();
-                } else if ch == '\t' {
-                    self.current_line_column += 4;
-                } else {
-                    self.current_line_column += 1;
-                }
-                return Some(ch);
-            }
-            None => return None,
+        let ch = self.cursor.next_char()?;
+        if ch == '\n' {
+            self.current_line_number += 1;
+            self.current_line_start = self.cursor.get_current_index();
+        }
+        Some(ch)
+    }
+
+    /// Move `count` bytes ahead, which must end on a character boundary, keeping track of the
+    /// line breaks skipped
+    fn skip_bytes(&mut self, count: usize) {
+        let skipped = &self.cursor.rest()[..count];
+        if let Some(last_break) = skipped.iter().rposition(|byte| *byte == b'\n') {
+            self.current_line_number += skipped.iter().filter(|byte| **byte == b'\n').count();
+            self.current_line_start = self.cursor.get_current_index() + last_break + 1;
         }
+        self.cursor.index += count;
     }
 
     /// https://protobuf.com/docs/language-spec#whitespace-and-comments
     fn consume_single_line_comment(&mut self) {
-        debug_assert!(self.cursor.peek().is_some());
-        debug_assert!(self.cursor.peek().unwrap() == '/');
-        _ = self.next_char(); // Consume the "/"
-        debug_assert!(self.cursor.peek().is_some());
-        debug_assert!(self.cursor.peek().unwrap() == '/');
-        _ = self.next_char(); // Consume the "/"
-        while let Some(ch) = self.next_char() {
-            if ch == '\n' || ch == '\x00' {
-                break;
-            }
-        }
+        let rest = self.cursor.rest();
+        debug_assert!(rest.starts_with(b"//"));
+        // The comment ends with the first line break or null character, which it owns
+        let len = rest[2..]
+            .iter()
+            .position(|byte| *byte == b'\n' || *byte == b'\x00')
+            .map_or(rest.len(), |end| end + 3);
+        self.skip_bytes(len);
     }
+
     /// https://protobuf.com/docs/language-spec#whitespace-and-comments
     fn consume_block_comment(&mut self) {
-        debug_assert!(self.cursor.peek().is_some());
-        debug_assert!(self.cursor.peek().unwrap() == '/');
-        _ = self.next_char(); // Consume the "/"
-        debug_assert!(self.cursor.peek().is_some());
-        debug_assert!(self.cursor.peek().unwrap() == '*');
-        _ = self.next_char(); // Consume the "*"
-        while let Some(ch) = self.next_char() {
-            if ch == '*' {
-                if let Some(next_ch) = self.next_char() {
-                    if next_ch == '/' {
-                        break;
-                    }
-                }
-            }
-        }
+        let rest = self.cursor.rest();
+        debug_assert!(rest.starts_with(b"/*"));
+        let len = rest[2..]
+            .windows(2)
+            .position(|pair| pair == b"*/")
+            .map_or(rest.len(), |end| end + 4);
+        self.skip_bytes(len);
     }
 
-    fn consume_hex_escape_sequence(&mut self, escaped_string: &mut String) -> bool {
-        let mut decoded_char: u32;
-        if let Some(first_required_char) = self.next_char() {
-            if first_required_char.is_ascii_hexdigit() {
-                let digit = first_required_char.to_digit(16).unwrap(); // SAFETY: We  just checked above that the character is a valid hex digit.
-                decoded_char = digit;
-                if let Some(second_optional_character) = self.cursor.peek() {
-                    if second_optional_character.is_ascii_hexdigit() {
-                        _ = self.next_char(); // Consume the second hex digit
-                        let lower_nibble = second_optional_character.to_digit(16).unwrap(); // SAFETY: We  just checked above that the character is a valid hex digit.
-                        let upper_nibble = decoded_char << 4;
-                        decoded_char = upper_nibble | lower_nibble;
-                    }
-                }
-                escaped_string.push(std::char::from_u32(decoded_char).unwrap()/*Unwrap here as we've validated above that we are combing two valid nibbles*/);
-                return true;
-            }
+    /// Consume up to `max_digits` digits in `radix`, returns their value and how many there were
+    fn consume_escape_digits(&mut self, radix: u32, max_digits: usize) -> (u32, usize) {
+        let mut value = 0;
+        let mut count = 0;
+        while count < max_digits {
+            let Some(digit) = self.cursor.peek().and_then(|ch| ch.to_digit(radix)) else {
+                break;
+            };
+            _ = self.next_char();
+            value = value * radix + digit;
+            count += 1;
         }
-        return false;
+        (value, count)
     }
 
-    fn consume_octal_escape_sequence(
+    /// Decode the escape sequence after a backslash into `value`. Characters that cannot be
+    /// part of the sequence are left alone, so that an invalid escape cannot swallow the
+    /// closing quote.
+    fn consume_escape_sequence(
         &mut self,
-        first_octal_digit: char,
-        escaped_string: &mut String,
-    ) {
-        let is_octal_digit = |ch: char| match ch {
-            '0'..='7' => true,
-            _ => false,
+        value: &mut Vec<u8>,
+    ) -> std::result::Result<(), &'static str> {
+        let Some(ch) = self.cursor.peek().filter(|ch| *ch != '\n') else {
+            return Err("incomplete escape sequence");
         };
-        assert!(is_octal_digit(first_octal_digit));
-        let mut decoded_byte: u32 = first_octal_digit.to_digit(8).unwrap(); // SAFETY: We  just checked above that the character is a valid hex digit.;
-        for _ in 1..=2 {
-            if let Some(optional_digit) = self.cursor.peek() {
-                if is_octal_digit(optional_digit) {
-                    let optional_digit = optional_digit.to_digit(8).unwrap();
-                    decoded_byte = decoded_byte << 3 | optional_digit;
-                    _ = self.next_char(); // Consume the digit
-                }
-            }
-        }
-        escaped_string.push(std::char::from_u32(decoded_byte).unwrap());
-    }
-
-    fn consume_unicode_escape_sequence(
-        &mut self,
-        escaped_string: &mut String,
-        header: char,
-    ) -> bool {
-        debug_assert!(header == 'u' || header == 'U');
-        let mut consume_n_hex_digits = |n: usize| -> bool {
-            let mut decoded_value: u32 = 0;
-            for _ in 0..n {
-                match self.next_char() {
-                    Some(ch) => {
-                        if ch.is_ascii_hexdigit() {
-                            let nibble = ch.to_digit(16).unwrap();
-                            decoded_value = (decoded_value << 4) | nibble; // SAFETY: We  just checked above that the character is a valid hex digit.
-                        } else {
-                            // Found non hex digit
-                            return false;
-                        }
-                    }
-                    None => return false, // Ran out of digits
-                }
+        _ = self.next_char();
+        let byte = match ch {
+            'a' => b'\x07', // Alert bell
+            'b' => b'\x08', // Back space
+            'f' => b'\x0c', // Form feed
+            'n' => b'\n',   // New line
+            'r' => b'\r',   // Carriage return
+            't' => b'\t',   // Horizontal tab
+            'v' => b'\x0b', // Vertical tab
+            '\\' | '\'' | '\"' | '?' => ch as u8,
+            // One or two hex digits make a single byte
+            'x' | 'X' => match self.consume_escape_digits(16, 2) {
+                (_, 0) => return Err("expected a hex digit"),
+                (byte, _) => byte as u8,
+            },
+            // Up to three octal digits make a single byte
+            '0'..='7' => {
+                let first_digit = ch.to_digit(8).unwrap(); // SAFETY: Matched an octal digit above
+                let (rest, count) = self.consume_escape_digits(8, 2);
+                let byte = first_digit << (3 * count) | rest;
+                if byte > 0xff {
+                    return Err("octal escape is larger than \\377");
+                }
+                byte as u8
             }
-            let decode_result = std::char::from_u32(decoded_value);
-            if decode_result.is_none() {
-                return false;
+            // A code point in exactly four or eight hex digits, encoded in UTF-8
+            'u' | 'U' => {
+                let digits = if ch == 'u' { 4 } else { 8 };
+                let code_point = match self.consume_escape_digits(16, digits) {
+                    (code_point, count) if count == digits => code_point,
+                    _ if digits == 4 => return Err("expected 4 hex digits"),
+                    _ => return Err("expected 8 hex digits"),
+                };
+                let Some(ch) = char::from_u32(code_point) else {
+                    return Err("not a Unicode scalar value");
+                };
+                value.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                return Ok(());
             }
-            escaped_string.push(decode_result.unwrap());
-            return true;
+            _ => return Err("unknown escape sequence"),
         };
-
-        match header {
-            'u' => consume_n_hex_digits(4),
-            'U' => consume_n_hex_digits(8),
-            _ => unreachable!(),
-        }
-    }
-
-    fn consume_escape_sequence(&mut self, escaped_string: &mut String) -> bool {
-        match self.next_char() {
-            Some(ch) => match ch {
-                'a' => {
-                    escaped_string.push('\x07'); // Alert bell
-                    return true;
-                }
-                'b' => {
-                    escaped_string.push('\x08'); // Back space
-                    return true;
-                }
-                'f' => {
-                    escaped_string.push('\x0c'); // Form feed
-                    return true;
-                }
-                'n' => {
-                    escaped_string.push('\n'); // New line
-                    return true;
-                }
-                'r' => {
-                    escaped_string.push('\x0d'); // Carriage return
-                    return true;
-                }
-                't' => {
-                    escaped_string.push('\t'); // Horizontal tab
-                    return true;
-                }
-                'v' => {
-                    escaped_string.push('\x0b'); // Vertical tab
-                    return true;
-                }
-                '\"' => {
-                    escaped_string.push('\"');
-                    return true;
-                }
-                '\'' => {
-                    escaped_string.push('\'');
-                    return true;
-                }
-                '?' => {
-                    escaped_string.push('?');
-                    return true;
-                }
-                'x' | 'X' => self.consume_hex_escape_sequence(escaped_string),
-                '0'..='7' => {
-                    self.consume_octal_escape_sequence(ch, escaped_string);
-                    return true;
-                }
-                'u' | 'U' => self.consume_unicode_escape_sequence(escaped_string, ch),
-                _ => false,
-            },
-            None => false,
-        }
+        value.push(byte);
+        Ok(())
     }
 }
 
@@ -960,73 +1262,142 @@ impl<'storage> Iterator for Lexer<'storage> {
     type Item = Token<'storage>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.next_token()
+        if self.end_reached {
+            return None;
+        }
+        let token = self.next_token();
+        let next = token.as_ref().map(|token| {
+            let closes_scope = matches!(
+                token.kind,
+                TokenKind::RBrace | TokenKind::RBracket | TokenKind::RParen
+            );
+            (token.metadata.line_number(), closes_scope)
+        });
+        let pending = &self.comments[self.pending_comments..];
+        // Most tokens have no comments before them, and then nothing to split
+        self.token_comments = if pending.is_empty() {
+            TokenComments::default()
+        } else {
+            split_comments(pending, self.last_token_line_number, next)
+        };
+        self.pending_comments = self.comments.len();
+        match &token {
+            Some(token) => {
+                self.last_token_line_number = Some(token.metadata.line_number());
+                self.token_end = LexerPosition {
+                    byte_offset: self.cursor.get_current_index(),
+                    line_number: self.current_line_number,
+                    line_start: self.current_line_start,
+                    last_token_line_number: self.last_token_line_number,
+                };
+            }
+            None => self.end_reached = true,
+        }
+        token
     }
 }
 
-fn is_whitespace(ch: char) -> bool {
+fn is_whitespace(byte: u8) -> bool {
     // https://protobuf.com/docs/language-spec#whitespace-and-comments
-    match ch {
-        ' ' | '\n' | '\r' | '\t' => true,
-        '\x0c' => true, // Form-feed
-        '\x0b' => true, // Vertical-tab
+    match byte {
+        b' ' | b'\n' | b'\r' | b'\t' => true,
+        b'\x0c' => true, // Form-feed
+        b'\x0b' => true, // Vertical-tab
         _ => false,
     }
 }
 
+/// Keywords and their spelling, the first spelling of a keyword is the canonical one
+static KEYWORDS: [(&str, TokenKind<'static>); 40] = [
+    ("import", TokenKind::Import),
+    ("syntax", TokenKind::Syntax),
+    ("bool", TokenKind::Bool),
+    ("to", TokenKind::To),
+    ("oneof", TokenKind::OneOf),
+    ("oneOf", TokenKind::OneOf),
+    ("float", TokenKind::Float),
+    ("double", TokenKind::Double),
+    ("map", TokenKind::Map),
+    ("weak", TokenKind::Weak),
+    ("int32", TokenKind::Int32),
+    ("extensions", TokenKind::Extensions),
+    ("public", TokenKind::Public),
+    ("int64", TokenKind::Int64),
+    ("package", TokenKind::Package),
+    ("uint32", TokenKind::Uint32),
+    ("max", TokenKind::Max),
+    ("option", TokenKind::Option),
+    ("uint64", TokenKind::Uint64),
+    ("reserved", TokenKind::Reserved),
+    ("inf", TokenKind::Inf),
+    ("sint32", TokenKind::Sint32),
+    ("enum", TokenKind::Enum),
+    ("repeated", TokenKind::Repeated),
+    ("sint64", TokenKind::Sint64),
+    ("message", TokenKind::Message),
+    ("optional", TokenKind::Optional),
+    ("fixed32", TokenKind::Fixed32),
+    ("extend", TokenKind::Extend),
+    ("required", TokenKind::Required),
+    ("fixed64", TokenKind::Fixed64),
+    ("service", TokenKind::Service),
+    ("sfixed32", TokenKind::SFixed32),
+    ("rpc", TokenKind::Rpc),
+    ("string", TokenKind::String),
+    ("sfixed64", TokenKind::SFixed64),
+    ("stream", TokenKind::Stream),
+    ("bytes", TokenKind::Bytes),
+    ("group", TokenKind::Group),
+    ("returns", TokenKind::Returns),
+];
+
+/// Picked so that no two keywords share a slot of `KEYWORD_TABLE`, which is checked when
+/// building it
+const KEYWORD_HASH_MULTIPLIER: u64 = 0x10b4427078e5f7c5;
+
+/// One plus the index in `KEYWORDS` of the keyword hashing to each slot, zero for empty slots
+static KEYWORD_TABLE: [u8; 64] = keyword_table();
+
+/// Multiplicative hash of the length and of the first, middle and last two bytes of a text
+/// of at least two bytes, keeping the top 6 bits as the slot. It only looks at five bytes
+/// however long the text is.
+const fn keyword_hash(text: &[u8]) -> usize {
+    let len = text.len();
+    let key = text[0] as u64
+        | (text[len / 2] as u64) << 8
+        | (text[len - 2] as u64) << 16
+        | (text[len - 1] as u64) << 24
+        | (len as u64) << 32;
+    (key.wrapping_mul(KEYWORD_HASH_MULTIPLIER) >> 58) as usize
+}
+
+const fn keyword_table() -> [u8; 64] {
+    let mut table = [0; 64];
+    let mut i = 0;
+    while i < KEYWORDS.len() {
+        let slot = keyword_hash(KEYWORDS[i].0.as_bytes());
+        assert!(table[slot] == 0, "Two keywords hash to the same slot");
+        table[slot] = i as u8 + 1;
+        i += 1;
+    }
+    table
+}
+
+/// Look the text up in a perfect hash of the keywords, any other text lands in an empty slot
+/// or in the slot of a different keyword
 fn get_keyword_token_kind<'a>(text: &'a str) -> Option<TokenKind<'a>> {
-    const TABLE: [(&str, TokenKind); 39] = [
-        ("import", TokenKind::Import),
-        ("syntax", TokenKind::Syntax),
-        ("bool", TokenKind::Bool),
-        ("to", TokenKind::To),
-        ("oneOf", TokenKind::OneOf),
-        ("float", TokenKind::Float),
-        ("double", TokenKind::Double),
-        ("map", TokenKind::Map),
-        ("weak", TokenKind::Weak),
-        ("int32", TokenKind::Int32),
-        ("extensions", TokenKind::Extensions),
-        ("public", TokenKind::Public),
-        ("int64", TokenKind::Int64),
-        ("package", TokenKind::Package),
-        ("uint32", TokenKind::Uint32),
-        ("max", TokenKind::Max),
-        ("option", TokenKind::Option),
-        ("uint64", TokenKind::Uint64),
-        ("reserved", TokenKind::Reserved),
-        ("inf", TokenKind::Inf),
-        ("sint32", TokenKind::Sint32),
-        ("enum", TokenKind::Enum),
-        ("repeated", TokenKind::Repeated),
-        ("sint64", TokenKind::Sint64),
-        ("message", TokenKind::Message),
-        ("optional", TokenKind::Optional),
-        ("fixed32", TokenKind::Fixed32),
-        ("extend", TokenKind::Extend),
-        ("required", TokenKind::Required),
-        ("fixed64", TokenKind::Fixed64),
-        ("service", TokenKind::Service),
-        ("sfixed32", TokenKind::SFixed32),
-        ("rpc", TokenKind::Rpc),
-        ("string", TokenKind::String),
-        ("sfixed64", TokenKind::SFixed64),
-        ("stream", TokenKind::Stream),
-        ("bytes", TokenKind::Bytes),
-        ("group", TokenKind::Group),
-        ("returns", TokenKind::Returns),
-    ];
-    match TABLE
-        .into_iter()
-        .find(|(keyword_string, _)| *keyword_string == text)
-    {
-        Some((_, kind)) => Some(kind),
-        None => None,
+    // Every keyword is at least two bytes long
+    if text.len() < 2 {
+        return None;
     }
+    let index = KEYWORD_TABLE[keyword_hash(text.as_bytes())].checked_sub(1)?;
+    let (keyword, kind) = &KEYWORDS[index as usize];
+    (*keyword == text).then(|| kind.clone())
 }
 
-#[derive(Clone, Copy, PartialEq)]
-enum Radix {
+/// Base of an integer literal, octal ones start with "0" and hexadecimal ones with "0x"
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Radix {
     Decimal,
     Hexadecimal,
     Octal,
@@ -1044,12 +1415,12 @@ impl From<Radix> for u32 {
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 struct LineInfo {
-    line_start_offset_into_source: usize,
+    /// Byte offset of the start of the line
+    line_start: usize,
     line_number: usize,
-    column_number: usize,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 struct Span {
     start: usize,
     end: usize,
@@ -1064,61 +1435,74 @@ impl Span {
         debug_assert!(self.end >= self.start);
         self.end == self.start
     }
-    fn extract_from_source<'a>(&self, source: &'a str) -> &'a str {
-        debug_assert!(self.end >= self.start);
-        if self.is_empty() {
-            ""
-        } else {
-            &source[self.start..self.end]
-        }
-    }
 }
 
+/// Scans the source text a byte at a time, characters outside of ASCII are only decoded when
+/// the lexer needs them
 #[derive(Clone)]
 struct Cursor<'source> {
-    iter: Chars<'source>,
-    number_of_chars_consumed: usize,
+    source_text: &'source str,
+    /// Byte offset of the next character
+    index: usize,
 }
 
 impl<'source> Cursor<'source> {
-    fn new(source_text: &'source str) -> Self {
+    fn new_at(source_text: &'source str, byte_offset: usize) -> Self {
         Self {
-            iter: source_text.chars(),
-            number_of_chars_consumed: 0,
+            source_text,
+            index: byte_offset,
         }
     }
 
-    fn next_with_index(&mut self) -> Option<(usize, char)> {
-        if let Some(ch) = self.iter.next() {
-            let index = self.number_of_chars_consumed;
-            self.number_of_chars_consumed += 1;
-            Some((index, ch))
-        } else {
-            None
-        }
+    fn next_char(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.index += ch.len_utf8();
+        Some(ch)
     }
 
+    /// Byte offset of the next character
     fn get_current_index(&self) -> usize {
-        self.number_of_chars_consumed
+        self.index
+    }
+
+    /// The bytes left to scan
+    fn rest(&self) -> &'source [u8] {
+        &self.source_text.as_bytes()[self.index..]
+    }
+
+    fn peek_byte(&self) -> Option<u8> {
+        self.rest().first().copied()
+    }
+
+    fn peek_next_byte(&self) -> Option<u8> {
+        self.rest().get(1).copied()
     }
 
     fn peek(&self) -> Option<char> {
-        self.iter.clone().next()
+        char_at(self.source_text, self.index)
     }
 
-    fn peek_next(&self) -> Option<char> {
-        let mut iter = self.iter.clone();
-        match iter.next() {
-            Some(_) => match iter.next() {
-                Some(ch) => Some(ch),
-                None => None,
-            },
-            None => None,
-        }
+    /// Move past the bytes for which `predicate` holds, which must all be ASCII
+    fn skip_while(&mut self, predicate: impl Fn(u8) -> bool) {
+        let count = self
+            .rest()
+            .iter()
+            .position(|byte| !predicate(*byte))
+            .unwrap_or(self.rest().len());
+        debug_assert!(self.rest()[..count].is_ascii());
+        self.index += count;
+    }
+}
+
+fn char_at(source_text: &str, index: usize) -> Option<char> {
+    match source_text.as_bytes().get(index)? {
+        byte if byte.is_ascii() => Some(*byte as char),
+        _ => source_text[index..].chars().next(),
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
     use super::*;
 
@@ -1149,6 +1533,98 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_comment_trivia() {
+        let source_text = "// Leading\nmessage A { // Trailing\n  /* Block\n   */ }";
+        let mut lexer = Lexer::new(source_text);
+        let tokens: Vec<Token> = lexer.by_ref().collect();
+        assert_eq!(tokens.len(), 4);
+        let comments = lexer.comments();
+        assert_eq!(comments.len(), 3);
+        assert_eq!(comments[0].text, "// Leading");
+        assert!(!comments[0].trailing);
+        assert_eq!(comments[1].text, "// Trailing");
+        assert!(comments[1].trailing);
+        assert_eq!(comments[1].metadata.line_number(), 1);
+        assert_eq!(comments[2].text, "/* Block\n   */");
+        assert!(!comments[2].trailing);
+        assert_eq!(comments[2].metadata.offset(), 37);
+        assert_eq!(comments[2].end_line_number(), 3);
+    }
+
+    #[test]
+    fn test_byte_scanner() {
+        for (text, kind) in &KEYWORDS {
+            assert_eq!(get_keyword_token_kind(text).as_ref(), Some(kind));
+        }
+        for text in [
+            "Message", "int", "int3", "sfixed", "importx", "returns_", "é",
+        ] {
+            assert_eq!(get_keyword_token_kind(text), None);
+        }
+        // Letters outside of ASCII go on with an identifier, "**/" closes a block comment
+        let source_text = "naïve /* a\n **/ x // é\r\n y\t\"a\\tb é\"";
+        let tokens: Vec<Token> = Lexer::new(source_text).collect();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[0].kind, TokenKind::Identifier(YarnBox::new("naïve")));
+        assert_eq!(tokens[0].metadata.span_len(), 6);
+        assert_eq!(tokens[1].kind, TokenKind::Identifier(YarnBox::new("x")));
+        assert_eq!(tokens[1].metadata.line_number(), 1);
+        assert_eq!(tokens[1].metadata.column_number(), 6);
+        assert_eq!(tokens[2].metadata.line_number(), 2);
+        assert_eq!(tokens[2].metadata.column_number(), 2);
+        match &tokens[3].kind {
+            TokenKind::StringLiteral(value) => assert_eq!(value, "a\tb é"),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_lossless() {
+        let source_text =
+            "syntax = \"proto3\";\r\n// Doc\t\nmessage A {\n\t/* Block\n */ string s = 1; } \"open";
+        let tokens: Vec<LosslessToken> = LosslessLexer::new(source_text).collect();
+        let text: String = tokens.iter().map(|token| token.text).collect();
+        assert_eq!(text, source_text);
+        let kinds: Vec<String> = tokens[..8]
+            .iter()
+            .map(|token| token.token.kind.to_string())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "\"syntax\"",
+                "whitespace",
+                "\"=\"",
+                "whitespace",
+                "string \"proto3\"",
+                "\";\"",
+                "line break",
+                "comment"
+            ]
+        );
+        assert_eq!(tokens[6].text, "\r\n");
+        assert_eq!(tokens[7].text, "// Doc\t");
+        assert_eq!(tokens[8].text, "\n");
+        let block = tokens
+            .iter()
+            .find(|token| token.text.starts_with("/*"))
+            .unwrap();
+        assert_eq!(block.text, "/* Block\n */");
+        assert_eq!(block.token.metadata.line_number(), 3);
+        assert!(matches!(
+            tokens.last().unwrap().token.kind,
+            TokenKind::Error(_)
+        ));
+        assert_eq!(
+            tokens
+                .iter()
+                .filter(|token| !token.token.kind.is_trivia())
+                .count(),
+            Lexer::new(source_text).count()
+        );
+    }
+
     #[test]
     fn test_string_literal() {
         let mut lexer = Lexer::new("\"StringLiteral\"");
@@ -1218,6 +1694,70 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_string_literal_bytes() {
+        // Hex and octal escapes make single bytes, unicode escapes UTF-8
+        let token = Lexer::new(r#""\xff\377\u00e9\x7" x"#).next().unwrap();
+        let TokenKind::StringLiteral(value) = &token.kind else {
+            panic!("{:?}", token.kind);
+        };
+        assert_eq!(value.as_bytes(), [0xff, 0xff, 0xc3, 0xa9, 0x07]);
+        assert!(value.to_str().is_err());
+        assert_eq!(format!("{:?}", value), r#""\xff\xff\xc3\xa9\x07""#);
+        assert_eq!(
+            (token.metadata.offset(), token.metadata.span_len()),
+            (0, 19)
+        );
+    }
+
+    #[test]
+    fn test_string_literal_invalid_escapes() {
+        // Every invalid escape is labelled, and none swallows the closing quote
+        let source = r#"'a\q \400 \ud800 \u12' x"#;
+        let tokens: Vec<Token> = Lexer::new(source).collect();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].kind, TokenKind::Identifier(YarnBox::new("x")));
+        let TokenKind::Error(diagnostic) = &tokens[0].kind else {
+            panic!("{:?}", tokens[0].kind);
+        };
+        assert_eq!(tokens[0].metadata.span_len(), 22);
+        let labels: Vec<(&str, &str)> = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                let metadata = &label.metadata;
+                let text = &source[metadata.offset()..metadata.end_offset()];
+                (text, label.message.as_str())
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (r"\q", "unknown escape sequence"),
+                (r"\400", r"octal escape is larger than \377"),
+                (r"\ud800", "not a Unicode scalar value"),
+                (r"\u12", "expected 4 hex digits"),
+            ]
+        );
+
+        let tokens: Vec<Token> = Lexer::new("\"\\x\" \"ab\n").collect();
+        let messages: Vec<String> = tokens
+            .iter()
+            .map(|token| match &token.kind {
+                TokenKind::Error(diagnostic) => diagnostic.message.clone(),
+                kind => kind.to_string(),
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "Invalid escape sequence in string literal",
+                "Unterminated string literal"
+            ]
+        );
+        assert_eq!(tokens[1].metadata.span_len(), 3);
+    }
+
     #[test]
     fn test_string_literal_unicode() {
         {
@@ -1264,7 +1804,7 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 12.56e-12;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
@@ -1276,7 +1816,7 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 0.5;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
@@ -1289,7 +1829,7 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 1e3;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
@@ -1302,7 +1842,7 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 1.0;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
@@ -1315,7 +1855,7 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 0.0;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
@@ -1328,7 +1868,7 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 0.123;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
@@ -1341,7 +1881,7 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 555.555;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
@@ -1354,7 +1894,7 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 1.234e-12;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
@@ -1367,7 +1907,7 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 0.953e20;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
@@ -1380,13 +1920,36 @@ mod tests {
             match token.kind {
                 TokenKind::FloatLiteral(float_value) => {
                     const GROUND_TRUTH: f64 = 5E+40;
-                    assert!((GROUND_TRUTH - float_value).abs() < 2.0f64 * &f64::EPSILON);
+                    assert!((GROUND_TRUTH - float_value.value()).abs() < 2.0f64 * &f64::EPSILON);
                 }
                 _ => assert!(false),
             }
         }
     }
 
+    #[test]
+    fn test_numerical_literal_text() {
+        let tokens: Vec<Token> =
+            Lexer::new("0x1F 017 0 1.50e2 99999999999999999999999999999999999999999").collect();
+        let integer = |index: usize| match &tokens[index].kind {
+            TokenKind::IntegerLiteral(literal) => literal.clone(),
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(integer(0).text(), "0x1F");
+        assert_eq!(integer(0).radix(), Radix::Hexadecimal);
+        assert_eq!(integer(0).value(), Some(31));
+        assert_eq!(integer(1).radix(), Radix::Octal);
+        assert_eq!(integer(1).value(), Some(15));
+        assert_eq!(integer(2).value(), Some(0));
+        let TokenKind::FloatLiteral(float) = &tokens[3].kind else {
+            panic!("{:?}", tokens[3].kind);
+        };
+        assert_eq!((float.text(), float.value()), ("1.50e2", 150.0));
+        // Too large for 128 bits, but still a number
+        assert_eq!(integer(4).value(), None);
+        assert_eq!(integer(4).to_f64(), 1e41);
+    }
+
     #[test]
     fn test_numerical_literal_integers1() {
         let mut lexer = Lexer::new("184467440737095516151 123 0123 0x123");
@@ -1395,7 +1958,10 @@ mod tests {
             assert!(result.is_some());
             let token = result.unwrap();
             match token.kind {
-                TokenKind::Error(_) => { /*We expect an error here as  184467440737095516151 is > u64::MAX*/
+                TokenKind::IntegerLiteral(value) => {
+                    // Larger than u64::MAX, whether that fits depends on where it is used
+                    assert!(value == 184467440737095516151);
+                    assert!(value.text() == "184467440737095516151");
                 }
                 _ => assert!(false),
             }
@@ -1571,13 +2137,10 @@ mod tests {
                 ("group", TokenKind::Group),
                 ("returns", TokenKind::Returns),
             ];
-            match TABLE
+            TABLE
                 .into_iter()
                 .find(|(keyword_string, _)| *keyword_string == text)
-            {
-                Some((_, kind)) => Some(kind),
-                None => None,
-            }
+                .map(|(_, kind)| kind)
         }
         let lexeme_text_vector: Vec<&str> = source_text.split_ascii_whitespace().collect();
         let mut lexer = Lexer::new(source_text);
@@ -1607,28 +2170,60 @@ mod tests {
             TokenKind::String,
             TokenKind::Identifier(YarnBox::new("name")),
             TokenKind::Equals,
-            TokenKind::IntegerLiteral(1),
+            TokenKind::IntegerLiteral(IntegerLiteral::new("1", Radix::Decimal)),
             TokenKind::Semicolon,
             TokenKind::Optional,
             TokenKind::Int32,
             TokenKind::Identifier(YarnBox::new("id")),
             TokenKind::Equals,
-            TokenKind::IntegerLiteral(2),
+            TokenKind::IntegerLiteral(IntegerLiteral::new("2", Radix::Decimal)),
             TokenKind::Semicolon,
             TokenKind::Optional,
             TokenKind::String,
             TokenKind::Identifier(YarnBox::new("email")),
             TokenKind::Equals,
-            TokenKind::IntegerLiteral(3),
+            TokenKind::IntegerLiteral(IntegerLiteral::new("3", Radix::Decimal)),
             TokenKind::Semicolon,
             TokenKind::RBrace,
         ];
-        let actual_token_kinds: Vec<TokenKind> = Lexer::new(source_text)
-            .into_iter()
-            .map(|token| token.kind)
-            .collect();
+        let actual_token_kinds: Vec<TokenKind> =
+            Lexer::new(source_text).map(|token| token.kind).collect();
         assert!(expected_token_kinds == actual_token_kinds);
     }
+    #[test]
+    fn test_token_offsets() {
+        // Offsets and columns count bytes, on every line
+        let source_text = "a \"é\" b\n\tc\n\"x\n∂ d";
+        let tokens: Vec<Token> = Lexer::new(source_text).collect();
+        let located: Vec<(usize, usize, usize, usize)> = tokens
+            .iter()
+            .map(|token| {
+                let metadata = &token.metadata;
+                (
+                    metadata.offset(),
+                    metadata.span_len(),
+                    metadata.line_number(),
+                    metadata.column_number(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            located,
+            [
+                (0, 1, 0, 1),
+                (2, 4, 0, 3),
+                (7, 1, 0, 8),
+                (10, 1, 1, 2),
+                (12, 2, 2, 1),
+                (15, 3, 3, 1),
+                (19, 1, 3, 5)
+            ]
+        );
+        assert!(matches!(tokens[4].kind, TokenKind::Error(_)));
+        assert!(matches!(tokens[5].kind, TokenKind::Error(_)));
+        assert_eq!(tokens[6].metadata.to_string(), "4:5");
+    }
+
     #[test]
     fn test_token_metadata() {
         let source_text = r#"
@@ -0,0 +1,26 @@
+pub mod ast_elements;
+pub mod breaking;
+pub mod builtin;
+pub mod codegen;
+pub mod config;
+pub mod descriptor;
+pub mod diagnostic;
+pub mod docgen;
+pub mod dynamic;
+pub mod error;
+pub mod formatter;
+pub mod incremental;
+pub mod json;
+pub mod lexer;
+pub mod linter;
+pub mod lsp;
+pub mod parser;
+pub mod protojson;
+pub mod resolver;
+pub mod schema;
+pub mod source_map;
+pub mod source_text;
+pub mod textproto;
+pub mod validator;
+
+pub use config::{compile_protos, Config};
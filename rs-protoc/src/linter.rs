@@ -0,0 +1,531 @@
+use std::{collections::HashSet, path::Path};
+
+use crate::{
+    ast_elements::{Comments, ElementType, FieldLabel, NamedElement, ProtoFile},
+    codegen::{to_snake_case, to_upper_camel_case},
+    diagnostic::Diagnostic,
+    json::JsonValue,
+    lexer::TokenMetadata,
+};
+
+// Style checks on parsed files. Every rule can be turned off in a configuration file, a single
+// element can opt out of rules with a comment attached to it:
+//
+//     // rs-protoc:lint:ignore field-no-required
+//     required int32 id = 1;
+//
+// Lints only look at one file at a time and do not need its imports to be resolved.
+
+/// Marker of the comments suppressing rules for the element they are attached to
+const IGNORE_MARKER: &str = "rs-protoc:lint:ignore";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Rule {
+    MessagePascalCase,
+    FieldLowerSnakeCase,
+    OneofLowerSnakeCase,
+    EnumPascalCase,
+    EnumValueUpperSnakeCase,
+    EnumValuePrefix,
+    EnumZeroValueSuffix,
+    ServicePascalCase,
+    RpcPascalCase,
+    PackageDirectoryMatch,
+    FieldNoRequired,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 11] = [
+        Rule::MessagePascalCase,
+        Rule::FieldLowerSnakeCase,
+        Rule::OneofLowerSnakeCase,
+        Rule::EnumPascalCase,
+        Rule::EnumValueUpperSnakeCase,
+        Rule::EnumValuePrefix,
+        Rule::EnumZeroValueSuffix,
+        Rule::ServicePascalCase,
+        Rule::RpcPascalCase,
+        Rule::PackageDirectoryMatch,
+        Rule::FieldNoRequired,
+    ];
+
+    /// Name of the rule in configuration files and suppression comments, also the code of its
+    /// diagnostics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::MessagePascalCase => "message-pascal-case",
+            Rule::FieldLowerSnakeCase => "field-lower-snake-case",
+            Rule::OneofLowerSnakeCase => "oneof-lower-snake-case",
+            Rule::EnumPascalCase => "enum-pascal-case",
+            Rule::EnumValueUpperSnakeCase => "enum-value-upper-snake-case",
+            Rule::EnumValuePrefix => "enum-value-prefix",
+            Rule::EnumZeroValueSuffix => "enum-zero-value-suffix",
+            Rule::ServicePascalCase => "service-pascal-case",
+            Rule::RpcPascalCase => "rpc-pascal-case",
+            Rule::PackageDirectoryMatch => "package-directory-match",
+            Rule::FieldNoRequired => "field-no-required",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+/// The rules to check, every rule is enabled by default
+#[derive(Clone, PartialEq, Debug)]
+pub struct LintConfig {
+    enabled: HashSet<Rule>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            enabled: Rule::ALL.into_iter().collect(),
+        }
+    }
+}
+
+impl LintConfig {
+    /// Parse a configuration file, a JSON object whose "rules" member maps rule names to
+    /// whether they are enabled:
+    ///
+    /// ```json
+    /// { "rules": { "field-no-required": false } }
+    /// ```
+    ///
+    /// Rules it does not name keep their default.
+    pub fn parse(text: &str) -> Result<LintConfig, String> {
+        let value = JsonValue::parse(text).map_err(|error| error.to_string())?;
+        let mut config = LintConfig::default();
+        match value.get("rules") {
+            None => {}
+            Some(JsonValue::Object(members)) => {
+                for (name, enabled) in members {
+                    let rule = Rule::from_name(name)
+                        .ok_or_else(|| format!("Unknown lint rule \"{}\"", name))?;
+                    let enabled = enabled.as_bool().ok_or_else(|| {
+                        format!("Expected true or false for lint rule \"{}\"", name)
+                    })?;
+                    config.set_enabled(rule, enabled);
+                }
+            }
+            Some(_) => return Err("Expected \"rules\" to be an object".to_string()),
+        }
+        Ok(config)
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        self.enabled.contains(&rule)
+    }
+
+    pub fn set_enabled(&mut self, rule: Rule, enabled: bool) {
+        if enabled {
+            self.enabled.insert(rule);
+        } else {
+            self.enabled.remove(&rule);
+        }
+    }
+}
+
+/// Check `file` against the enabled rules, `file_name` is the path of the file as given to the
+/// compiler. Every problem is reported as a warning whose code is the name of the rule.
+pub fn lint_file(file: &ProtoFile, file_name: &str, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        config,
+        diagnostics: Vec::new(),
+    };
+    linter.check_package(file, file_name);
+    for element in &file.package.named_elements {
+        linter.check_element(element);
+    }
+    linter.diagnostics
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(
+        &mut self,
+        rule: Rule,
+        comments: &Comments,
+        metadata: &TokenMetadata,
+        message: String,
+        help: Option<String>,
+    ) {
+        if !self.config.is_enabled(rule) || is_suppressed(comments, rule) {
+            return;
+        }
+        let mut diagnostic = Diagnostic::warning(message)
+            .with_code(rule.name())
+            .with_primary_label(metadata, "");
+        if let Some(help) = help {
+            diagnostic = diagnostic.with_help(help);
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn check_package(&mut self, file: &ProtoFile, file_name: &str) {
+        let Some(package) = &file.package.name else {
+            return;
+        };
+        let directories: Vec<String> = Path::new(file_name)
+            .parent()
+            .map(|parent| {
+                parent
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let expected: Vec<String> = package.value.split('.').map(str::to_string).collect();
+        if !directories.ends_with(&expected) {
+            self.report(
+                Rule::PackageDirectoryMatch,
+                &file.package.comments,
+                &package.metadata,
+                format!(
+                    "Files of package \"{}\" should be in a directory \"{}\"",
+                    package.value,
+                    expected.join("/")
+                ),
+                Some(format!(
+                    "move \"{}\" to a \"{}\" directory",
+                    file_name,
+                    expected.join("/")
+                )),
+            );
+        }
+    }
+
+    fn check_element(&mut self, element: &NamedElement) {
+        match &element.type_t {
+            ElementType::Message(payload) => {
+                self.check_case(Rule::MessagePascalCase, "Message", element, Case::Pascal);
+                for nested in &payload.named_elements {
+                    self.check_element(nested);
+                }
+            }
+            ElementType::Field(payload) => {
+                self.check_case(
+                    Rule::FieldLowerSnakeCase,
+                    "Field",
+                    element,
+                    Case::LowerSnake,
+                );
+                if payload.label == FieldLabel::Required {
+                    self.report(
+                        Rule::FieldNoRequired,
+                        &element.comments,
+                        &element.metadata,
+                        format!("Field \"{}\" is required", element.name),
+                        Some("required fields can never be removed, make it optional".to_string()),
+                    );
+                }
+            }
+            ElementType::OneOf(payload) => {
+                self.check_case(
+                    Rule::OneofLowerSnakeCase,
+                    "Oneof",
+                    element,
+                    Case::LowerSnake,
+                );
+                for field in &payload.fields {
+                    self.check_element(field);
+                }
+            }
+            ElementType::Enum(payload) => {
+                self.check_case(Rule::EnumPascalCase, "Enum", element, Case::Pascal);
+                let prefix = format!("{}_", to_snake_case(&element.name).to_uppercase());
+                for value in &payload.values {
+                    self.check_enum_value(value, &prefix);
+                }
+            }
+            ElementType::EnumValue(_) => {}
+            ElementType::Extension(payload) => {
+                for field in &payload.fields {
+                    self.check_element(field);
+                }
+            }
+            ElementType::Service(payload) => {
+                self.check_case(Rule::ServicePascalCase, "Service", element, Case::Pascal);
+                for method in &payload.methods {
+                    let name = &method.name.value;
+                    if !Case::Pascal.matches(name) {
+                        self.report(
+                            Rule::RpcPascalCase,
+                            &method.comments,
+                            &method.name.metadata,
+                            format!("RPC name \"{}\" should be {}", name, Case::Pascal),
+                            Case::Pascal.suggest(name),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// `prefix` is the name of the enum in upper snake case followed by an underscore
+    fn check_enum_value(&mut self, value: &NamedElement, prefix: &str) {
+        self.check_case(
+            Rule::EnumValueUpperSnakeCase,
+            "Enum value",
+            value,
+            Case::UpperSnake,
+        );
+        if !value.name.starts_with(prefix) {
+            self.report(
+                Rule::EnumValuePrefix,
+                &value.comments,
+                &value.metadata,
+                format!(
+                    "Enum value \"{}\" should be prefixed with \"{}\"",
+                    value.name, prefix
+                ),
+                Some(format!("rename it to \"{}{}\"", prefix, value.name)),
+            );
+        }
+        let ElementType::EnumValue(payload) = &value.type_t else {
+            return;
+        };
+        if payload.number.value == 0 && !value.name.ends_with("_UNSPECIFIED") {
+            self.report(
+                Rule::EnumZeroValueSuffix,
+                &value.comments,
+                &value.metadata,
+                format!(
+                    "Enum zero value \"{}\" should end with \"_UNSPECIFIED\"",
+                    value.name
+                ),
+                Some(format!("rename it to \"{}UNSPECIFIED\"", prefix)),
+            );
+        }
+    }
+
+    fn check_case(&mut self, rule: Rule, kind: &str, element: &NamedElement, case: Case) {
+        if !case.matches(&element.name) {
+            self.report(
+                rule,
+                &element.comments,
+                &element.metadata,
+                format!("{} name \"{}\" should be {}", kind, element.name, case),
+                case.suggest(&element.name),
+            );
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+    /// `FooBar`
+    Pascal,
+    /// `foo_bar`
+    LowerSnake,
+    /// `FOO_BAR`
+    UpperSnake,
+}
+
+impl Case {
+    fn matches(&self, name: &str) -> bool {
+        let Some(first) = name.chars().next() else {
+            return false;
+        };
+        match self {
+            Case::Pascal => {
+                first.is_ascii_uppercase() && name.chars().all(|ch| ch.is_ascii_alphanumeric())
+            }
+            Case::LowerSnake => {
+                first.is_ascii_lowercase()
+                    && name
+                        .chars()
+                        .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_')
+            }
+            Case::UpperSnake => {
+                first.is_ascii_uppercase()
+                    && name
+                        .chars()
+                        .all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_')
+            }
+        }
+    }
+
+    /// Help renaming `name`, if it can be converted automatically
+    fn suggest(&self, name: &str) -> Option<String> {
+        let converted = match self {
+            Case::Pascal => to_upper_camel_case(&to_snake_case(name)),
+            Case::LowerSnake => to_snake_case(name).to_lowercase(),
+            Case::UpperSnake => to_snake_case(name).to_uppercase(),
+        };
+        (self.matches(&converted) && converted != name)
+            .then(|| format!("rename it to \"{}\"", converted))
+    }
+}
+
+impl std::fmt::Display for Case {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Case::Pascal => write!(f, "PascalCase"),
+            Case::LowerSnake => write!(f, "lower_snake_case"),
+            Case::UpperSnake => write!(f, "UPPER_SNAKE_CASE"),
+        }
+    }
+}
+
+/// True if a leading or trailing comment of the element names `rule` after `IGNORE_MARKER`
+fn is_suppressed(comments: &Comments, rule: Rule) -> bool {
+    [&comments.leading, &comments.trailing]
+        .into_iter()
+        .flatten()
+        .flat_map(|comment| comment.lines())
+        .filter_map(|line| line.split_once(IGNORE_MARKER))
+        .any(|(_, rules)| rules.split_whitespace().any(|name| name == rule.name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn lint(source: &str, file_name: &str, config: &LintConfig) -> Vec<(&'static str, String)> {
+        let file = Parser::new(source).parse().unwrap();
+        lint_file(&file, file_name, config)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.code.unwrap(), diagnostic.message))
+            .collect()
+    }
+
+    const SOURCE: &str = r#"syntax = "proto3";
+package foo.bar;
+
+message search_request {
+  string Query = 1;
+  required int32 page_number = 2;
+  oneof Choice { int32 a = 3; }
+  enum color { RED = 0; COLOR_BLUE = 1; color_green = 2; }
+}
+
+service searchService {
+  rpc do_search(search_request) returns (search_request);
+}
+"#;
+
+    #[test]
+    fn lint_rules_test() {
+        assert_eq!(
+            lint(SOURCE, "foo/bar/search.proto", &LintConfig::default()),
+            vec![
+                (
+                    "message-pascal-case",
+                    "Message name \"search_request\" should be PascalCase".to_string()
+                ),
+                (
+                    "field-lower-snake-case",
+                    "Field name \"Query\" should be lower_snake_case".to_string()
+                ),
+                (
+                    "field-no-required",
+                    "Field \"page_number\" is required".to_string()
+                ),
+                (
+                    "oneof-lower-snake-case",
+                    "Oneof name \"Choice\" should be lower_snake_case".to_string()
+                ),
+                (
+                    "enum-pascal-case",
+                    "Enum name \"color\" should be PascalCase".to_string()
+                ),
+                (
+                    "enum-value-prefix",
+                    "Enum value \"RED\" should be prefixed with \"COLOR_\"".to_string()
+                ),
+                (
+                    "enum-zero-value-suffix",
+                    "Enum zero value \"RED\" should end with \"_UNSPECIFIED\"".to_string()
+                ),
+                (
+                    "enum-value-upper-snake-case",
+                    "Enum value name \"color_green\" should be UPPER_SNAKE_CASE".to_string()
+                ),
+                (
+                    "enum-value-prefix",
+                    "Enum value \"color_green\" should be prefixed with \"COLOR_\"".to_string()
+                ),
+                (
+                    "service-pascal-case",
+                    "Service name \"searchService\" should be PascalCase".to_string()
+                ),
+                (
+                    "rpc-pascal-case",
+                    "RPC name \"do_search\" should be PascalCase".to_string()
+                ),
+            ]
+        );
+
+        let file = Parser::new(SOURCE).parse().unwrap();
+        let diagnostics = lint_file(&file, "foo/bar/search.proto", &LintConfig::default());
+        assert_eq!(
+            diagnostics[0].help.as_deref(),
+            Some("rename it to \"SearchRequest\"")
+        );
+        assert_eq!(
+            diagnostics[1].help.as_deref(),
+            Some("rename it to \"query\"")
+        );
+
+        let directory_mismatch = lint(
+            "syntax = \"proto3\"; package foo.bar;",
+            "bar/search.proto",
+            &LintConfig::default(),
+        );
+        assert_eq!(
+            directory_mismatch,
+            vec![(
+                "package-directory-match",
+                "Files of package \"foo.bar\" should be in a directory \"foo/bar\"".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn lint_config_test() {
+        let config = LintConfig::parse(
+            r#"{ "rules": { "enum-value-prefix": false, "field-no-required": false } }"#,
+        )
+        .unwrap();
+        assert!(!config.is_enabled(Rule::EnumValuePrefix));
+        assert!(config.is_enabled(Rule::MessagePascalCase));
+        let codes: Vec<_> = lint(SOURCE, "foo/bar/search.proto", &config)
+            .into_iter()
+            .map(|(code, _)| code)
+            .collect();
+        assert!(!codes.contains(&"enum-value-prefix"));
+        assert!(!codes.contains(&"field-no-required"));
+        assert_eq!(codes.len(), 8);
+
+        assert!(LintConfig::parse(r#"{ "rules": { "no-such-rule": false } }"#).is_err());
+        assert!(LintConfig::parse(r#"{ "rules": { "enum-value-prefix": 0 } }"#).is_err());
+    }
+
+    #[test]
+    fn lint_suppression_test() {
+        let source = r#"syntax = "proto3";
+package foo;
+
+// rs-protoc:lint:ignore message-pascal-case
+message legacy_message {
+  required int32 id = 1; // rs-protoc:lint:ignore field-no-required
+  required int32 other = 2;
+}
+"#;
+        assert_eq!(
+            lint(source, "foo/a.proto", &LintConfig::default()),
+            vec![(
+                "field-no-required",
+                "Field \"other\" is required".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,1331 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast_elements::{ElementType, FieldLabel, FieldType, Method, NamedElement, ProtoFile, Spanned},
+    diagnostic::Severity,
+    incremental::{IncrementalParser, TextEdit},
+    json::JsonValue,
+    lexer::{Lexer, TokenKind, TokenMetadata},
+    resolver::{self, qualified_name, LoadedFile, Loader, SymbolKind, SymbolTable},
+    source_map::{ColumnUnit, LineColumn, SourceMap},
+};
+
+// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_REQUEST: i64 = -32600;
+const PARSE_ERROR: i64 = -32700;
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+/// LSP `SymbolKind` values
+mod symbol_kind {
+    pub const NAMESPACE: u64 = 3;
+    pub const METHOD: u64 = 6;
+    pub const FIELD: u64 = 8;
+    pub const ENUM: u64 = 10;
+    pub const INTERFACE: u64 = 11;
+    pub const OBJECT: u64 = 19;
+    pub const ENUM_MEMBER: u64 = 22;
+    pub const STRUCT: u64 = 23;
+}
+
+/// LSP `CompletionItemKind` values
+mod completion_kind {
+    pub const ENUM: u64 = 13;
+    pub const STRUCT: u64 = 22;
+}
+
+/// A language server for .proto files speaking LSP over a pair of byte streams, usually
+/// stdin and stdout
+pub struct LanguageServer {
+    include_paths: Vec<PathBuf>,
+    documents: HashMap<String, Document>,
+    initialized: bool,
+    exit_requested: bool,
+}
+
+struct Document {
+    /// The text of the document, kept parsed across edits
+    parser: IncrementalParser,
+    /// Result of the last analysis that managed to parse the document. It is kept while the
+    /// document does not parse so that navigation keeps working during edits
+    analysis: Option<Analysis>,
+}
+
+struct Analysis {
+    /// Name of the document relative to the include path it was found in
+    name: String,
+    /// The document along with everything it imports
+    files: Vec<LoadedFile>,
+    symbols: SymbolTable,
+    /// Lines of the text `files` were parsed from, to convert between byte offsets and LSP
+    /// positions
+    source_map: SourceMap<String>,
+}
+
+impl Analysis {
+    fn file(&self, name: &str) -> Option<&LoadedFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+}
+
+impl LanguageServer {
+    /// Imports are searched for in `include_paths`, then in the workspace folders and
+    /// finally in the directory of the file being edited
+    pub fn new(include_paths: Vec<PathBuf>) -> Self {
+        LanguageServer {
+            include_paths,
+            documents: HashMap::new(),
+            initialized: false,
+            exit_requested: false,
+        }
+    }
+
+    /// Serve requests until the client sends the exit notification or closes `input`
+    pub fn run(
+        &mut self,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> std::io::Result<()> {
+        while let Some(content) = read_message(input)? {
+            let outgoing = match JsonValue::parse(&content) {
+                Ok(message) => self.handle_message(&message),
+                Err(error) => vec![error_response(
+                    JsonValue::Null,
+                    PARSE_ERROR,
+                    &error.to_string(),
+                )],
+            };
+            for message in outgoing {
+                write_message(output, &message)?;
+            }
+            if self.exit_requested {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a request or a notification, returning the messages to send back
+    pub fn handle_message(&mut self, message: &JsonValue) -> Vec<JsonValue> {
+        if !matches!(message, JsonValue::Object(_)) {
+            return vec![error_response(
+                JsonValue::Null,
+                INVALID_REQUEST,
+                "Messages have to be objects",
+            )];
+        }
+        let Some(method) = message.get("method").and_then(JsonValue::as_str) else {
+            // Responses to requests we never send
+            return Vec::new();
+        };
+        let params = message.get("params").unwrap_or(&JsonValue::Null);
+        let Some(id) = message.get("id").cloned() else {
+            return self.handle_notification(method, params);
+        };
+        if !self.initialized && method != "initialize" {
+            return vec![error_response(
+                id,
+                SERVER_NOT_INITIALIZED,
+                "The server has not been initialized",
+            )];
+        }
+        let result = match method {
+            "initialize" => self.initialize(params),
+            "shutdown" => JsonValue::Null,
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/completion" => self.completion(params),
+            _ => {
+                return vec![error_response(
+                    id,
+                    METHOD_NOT_FOUND,
+                    &format!("Unsupported method: {}", method),
+                )]
+            }
+        };
+        vec![JsonValue::object([
+            ("jsonrpc", JsonValue::from("2.0")),
+            ("id", id),
+            ("result", result),
+        ])]
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &JsonValue) -> Vec<JsonValue> {
+        let text_document = params.get("textDocument");
+        let uri = text_document
+            .and_then(|document| document.get("uri"))
+            .and_then(JsonValue::as_str)
+            .map(str::to_string);
+        match (method, uri) {
+            ("exit", _) => {
+                self.exit_requested = true;
+                Vec::new()
+            }
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = text_document
+                    .and_then(|document| document.get("text"))
+                    .and_then(JsonValue::as_str)
+                    .unwrap_or_default();
+                self.documents.insert(
+                    uri.clone(),
+                    Document {
+                        parser: IncrementalParser::new(text.to_string()),
+                        analysis: None,
+                    },
+                );
+                vec![self.analyze(&uri)]
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                let Some(document) = self.documents.get_mut(&uri) else {
+                    return Vec::new();
+                };
+                let changes = params
+                    .get("contentChanges")
+                    .and_then(JsonValue::as_array)
+                    .unwrap_or_default();
+                for change in changes {
+                    apply_change(&mut document.parser, change);
+                }
+                vec![self.analyze(&uri)]
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(&uri);
+                vec![publish_diagnostics(&uri, Vec::new())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn initialize(&mut self, params: &JsonValue) -> JsonValue {
+        self.initialized = true;
+        let workspace_folders = params
+            .get("workspaceFolders")
+            .and_then(JsonValue::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|folder| folder.get("uri"));
+        for uri in workspace_folders.chain(params.get("rootUri")) {
+            if let Some(path) = uri.as_str().and_then(uri_to_path) {
+                if !self.include_paths.contains(&path) {
+                    self.include_paths.push(path);
+                }
+            }
+        }
+        JsonValue::object([
+            (
+                "capabilities",
+                JsonValue::object([
+                    // Incremental document synchronization
+                    ("textDocumentSync", JsonValue::from(2u64)),
+                    ("hoverProvider", JsonValue::from(true)),
+                    ("definitionProvider", JsonValue::from(true)),
+                    ("documentSymbolProvider", JsonValue::from(true)),
+                    (
+                        "completionProvider",
+                        JsonValue::object([(
+                            "triggerCharacters",
+                            JsonValue::from(vec![JsonValue::from(".")]),
+                        )]),
+                    ),
+                ]),
+            ),
+            (
+                "serverInfo",
+                JsonValue::object([
+                    ("name", JsonValue::from("rs-protoc")),
+                    ("version", JsonValue::from(env!("CARGO_PKG_VERSION"))),
+                ]),
+            ),
+        ])
+    }
+
+    /// Load the document along with its imports, preferring the text of open documents over
+    /// the files on disk, and return the `publishDiagnostics` notification for it
+    fn analyze(&mut self, uri: &str) -> JsonValue {
+        let path = uri_to_path(uri).unwrap_or_else(|| PathBuf::from(uri));
+        let mut include_paths = self.include_paths.clone();
+        if let Some(parent) = path.parent() {
+            include_paths.push(parent.to_path_buf());
+        }
+        let mut loader = Loader::new(include_paths);
+        for (open_uri, document) in &self.documents {
+            let open_path = uri_to_path(open_uri).unwrap_or_else(|| PathBuf::from(open_uri));
+            loader.add_parsed_overlay(open_path, &document.parser);
+        }
+        let name = loader
+            .virtual_name(&path.to_string_lossy())
+            .unwrap_or_else(|| path.to_string_lossy().to_string());
+        loader.load(&name);
+        let (files, mut errors) = loader.finish_partial();
+        if errors.is_empty() {
+            if let Err(resolve_errors) = resolver::resolve(&files) {
+                errors = resolve_errors;
+            }
+        }
+
+        let document = self.documents.get_mut(uri).unwrap(); // SAFETY: Only open documents are analyzed
+        let source_map = SourceMap::new(document.parser.text().to_string());
+        let diagnostics = errors
+            .iter()
+            .filter(|error| error.file_name == name)
+            .map(|error| {
+                let diagnostic = error.error.to_diagnostic();
+                let range = match diagnostic.primary_label() {
+                    Some(label) => range_json(&source_map, &label.metadata),
+                    None => range_json_between(&source_map, 0, 0),
+                };
+                let mut message = diagnostic.message.clone();
+                for note in &diagnostic.notes {
+                    message.push_str(&format!("\nnote: {}", note));
+                }
+                if let Some(help) = &diagnostic.help {
+                    message.push_str(&format!("\nhelp: {}", help));
+                }
+                let severity: u64 = match diagnostic.severity {
+                    Severity::Error => 1,
+                    Severity::Warning => 2,
+                    Severity::Note => 3,
+                };
+                JsonValue::object([
+                    ("range", range),
+                    ("severity", JsonValue::from(severity)),
+                    ("code", JsonValue::from(diagnostic.code)),
+                    ("source", JsonValue::from("rs-protoc")),
+                    ("message", JsonValue::from(message)),
+                ])
+            })
+            .collect();
+        if files.iter().any(|file| file.name == name) {
+            let (symbols, _) = SymbolTable::build(&files);
+            document.analysis = Some(Analysis {
+                name,
+                files,
+                symbols,
+                source_map,
+            });
+        }
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    /// The analysis of the document named in the request along with the byte offset of the
+    /// requested position
+    fn document_at(&self, params: &JsonValue) -> Option<(&Analysis, &ProtoFile, usize)> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let analysis = self.documents.get(uri)?.analysis.as_ref()?;
+        let ast = &analysis.file(&analysis.name)?.ast;
+        let offset = match params.get("position") {
+            Some(position) => offset_of(
+                &analysis.source_map,
+                position.get("line")?.as_u64()? as usize,
+                position.get("character")?.as_u64()? as usize,
+            ),
+            None => 0,
+        };
+        Some((analysis, ast, offset))
+    }
+
+    fn hover(&self, params: &JsonValue) -> JsonValue {
+        let Some((analysis, ast, offset)) = self.document_at(params) else {
+            return JsonValue::Null;
+        };
+        let Some(occurrence) = occurrences(ast)
+            .into_iter()
+            .find(|occurrence| occurrence.contains(offset))
+        else {
+            return JsonValue::Null;
+        };
+        let type_name = |scope: &str, name: &str| match analysis.symbols.lookup(scope, name) {
+            Some((full_name, _)) => format!(".{}", full_name),
+            None => name.to_string(),
+        };
+        let contents = match &occurrence.target {
+            Target::TypeReference { name, scope } => match analysis.symbols.lookup(scope, name) {
+                Some((full_name, kind)) => format!("{} .{}", symbol_kind_keyword(kind), full_name),
+                None => return JsonValue::Null,
+            },
+            Target::Element {
+                element,
+                full_name,
+                scope,
+            } => match &element.type_t {
+                ElementType::Field(field) => {
+                    let label = match field.label {
+                        FieldLabel::None => "",
+                        FieldLabel::Optional => "optional ",
+                        FieldLabel::Repeated => "repeated ",
+                        FieldLabel::Required => "required ",
+                    };
+                    format!(
+                        "{}{} {} = {}; // .{}",
+                        label,
+                        describe_field_type(&field.field_type.value, &|name| type_name(
+                            scope, name
+                        )),
+                        element.name,
+                        field.number.value,
+                        full_name
+                    )
+                }
+                ElementType::EnumValue(value) => {
+                    format!(
+                        "{} = {}; // .{}",
+                        element.name, value.number.value, full_name
+                    )
+                }
+                ElementType::Message(_) => format!("message .{}", full_name),
+                ElementType::Enum(_) => format!("enum .{}", full_name),
+                ElementType::OneOf(_) => format!("oneof .{}", full_name),
+                ElementType::Service(_) => format!("service .{}", full_name),
+                ElementType::Extension(_) => return JsonValue::Null,
+            },
+            Target::Method {
+                method,
+                full_name,
+                scope,
+            } => format!(
+                "rpc {}({}{}) returns ({}{}); // .{}",
+                method.name.value,
+                if method.client_streaming {
+                    "stream "
+                } else {
+                    ""
+                },
+                type_name(scope, &method.input_type.value),
+                if method.server_streaming {
+                    "stream "
+                } else {
+                    ""
+                },
+                type_name(scope, &method.output_type.value),
+                full_name
+            ),
+        };
+        JsonValue::object([
+            (
+                "contents",
+                JsonValue::object([
+                    ("kind", JsonValue::from("markdown")),
+                    (
+                        "value",
+                        JsonValue::from(format!("```proto\n{}\n```", contents)),
+                    ),
+                ]),
+            ),
+            (
+                "range",
+                range_json_between(&analysis.source_map, occurrence.start, occurrence.end),
+            ),
+        ])
+    }
+
+    fn definition(&self, params: &JsonValue) -> JsonValue {
+        let Some((analysis, ast, offset)) = self.document_at(params) else {
+            return JsonValue::Null;
+        };
+        let Some(occurrence) = occurrences(ast)
+            .into_iter()
+            .find(|occurrence| occurrence.contains(offset))
+        else {
+            return JsonValue::Null;
+        };
+        let (file_name, metadata) = match &occurrence.target {
+            Target::TypeReference { name, scope } => {
+                let Some(symbol) = analysis
+                    .symbols
+                    .lookup(scope, name)
+                    .and_then(|(full_name, _)| analysis.symbols.get(&full_name))
+                else {
+                    return JsonValue::Null;
+                };
+                (symbol.file_name.as_str(), &symbol.metadata)
+            }
+            Target::Element { element, .. } => (analysis.name.as_str(), &element.metadata),
+            Target::Method { method, .. } => (analysis.name.as_str(), &method.name.metadata),
+        };
+        let Some(file) = analysis.file(file_name) else {
+            return JsonValue::Null;
+        };
+        JsonValue::object([
+            ("uri", JsonValue::from(path_to_uri(&file.path))),
+            (
+                "range",
+                range_json(&SourceMap::new(file.source.text()), metadata),
+            ),
+        ])
+    }
+
+    fn document_symbols(&self, params: &JsonValue) -> JsonValue {
+        let Some((analysis, ast, _)) = self.document_at(params) else {
+            return JsonValue::Null;
+        };
+        JsonValue::from(
+            ast.package
+                .named_elements
+                .iter()
+                .map(|element| element_symbol(&analysis.source_map, element))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn completion(&self, params: &JsonValue) -> JsonValue {
+        let Some((analysis, _, offset)) = self.document_at(params) else {
+            return JsonValue::Null;
+        };
+        let scope = scope_at(analysis.source_map.text(), offset);
+        let mut items: Vec<(String, String, SymbolKind)> = analysis
+            .symbols
+            .iter()
+            .filter(|(_, symbol)| matches!(symbol.kind, SymbolKind::Message | SymbolKind::Enum))
+            .map(|(full_name, symbol)| {
+                (
+                    shortest_name(&analysis.symbols, &scope, full_name),
+                    full_name.clone(),
+                    symbol.kind,
+                )
+            })
+            .collect();
+        items.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        JsonValue::from(
+            items
+                .into_iter()
+                .map(|(label, full_name, kind)| {
+                    let kind = match kind {
+                        SymbolKind::Enum => completion_kind::ENUM,
+                        _ => completion_kind::STRUCT,
+                    };
+                    JsonValue::object([
+                        ("label", JsonValue::from(label)),
+                        ("kind", JsonValue::from(kind)),
+                        ("detail", JsonValue::from(format!(".{}", full_name))),
+                    ])
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+/// Something in the document that hover and go-to-definition know about
+struct Occurrence<'a> {
+    /// Byte offsets, the end is exclusive
+    start: usize,
+    end: usize,
+    target: Target<'a>,
+}
+
+enum Target<'a> {
+    /// A possibly relative type name, looked up from `scope`
+    TypeReference { name: &'a str, scope: String },
+    /// The name of an element where it is declared, `scope` is the one it is declared in
+    Element {
+        element: &'a NamedElement,
+        full_name: String,
+        scope: String,
+    },
+    Method {
+        method: &'a Method,
+        full_name: String,
+        scope: String,
+    },
+}
+
+impl Occurrence<'_> {
+    fn contains(&self, offset: usize) -> bool {
+        // The position right after a name still counts, that is where the cursor is when typing
+        self.start <= offset && offset <= self.end
+    }
+}
+
+fn occurrences(file: &ProtoFile) -> Vec<Occurrence<'_>> {
+    let package = file
+        .package
+        .name
+        .as_ref()
+        .map(|name| name.value.clone())
+        .unwrap_or_default();
+    let mut occurrences = Vec::new();
+    collect_occurrences(&package, &file.package.named_elements, &mut occurrences);
+    occurrences
+}
+
+fn type_reference<'a>(name: &'a str, metadata: &TokenMetadata, scope: &str) -> Occurrence<'a> {
+    Occurrence {
+        start: metadata.offset(),
+        end: metadata.offset() + name.len(),
+        target: Target::TypeReference {
+            name,
+            scope: scope.to_string(),
+        },
+    }
+}
+
+fn collect_field_type_references<'a>(
+    field_type: &'a Spanned<FieldType>,
+    scope: &str,
+    occurrences: &mut Vec<Occurrence<'a>>,
+) {
+    match &field_type.value {
+        FieldType::Scalar(_) => {}
+        FieldType::Named(name) => {
+            occurrences.push(type_reference(name, &field_type.metadata, scope))
+        }
+        FieldType::Map(key, value) => {
+            collect_field_type_references(key, scope, occurrences);
+            collect_field_type_references(value, scope, occurrences);
+        }
+    }
+}
+
+fn collect_occurrences<'a>(
+    scope: &str,
+    elements: &'a [NamedElement],
+    occurrences: &mut Vec<Occurrence<'a>>,
+) {
+    for element in elements {
+        let full_name = qualified_name(scope, &element.name);
+        if let ElementType::Extension(extension) = &element.type_t {
+            // The element name is the extendee, and the fields live in the enclosing scope
+            occurrences.push(type_reference(&element.name, &element.metadata, scope));
+            collect_occurrences(scope, &extension.fields, occurrences);
+            continue;
+        }
+        occurrences.push(Occurrence {
+            start: element.metadata.offset(),
+            end: element.metadata.offset() + element.metadata.span_len(),
+            target: Target::Element {
+                element,
+                full_name: full_name.clone(),
+                scope: scope.to_string(),
+            },
+        });
+        match &element.type_t {
+            ElementType::Message(message) => {
+                collect_occurrences(&full_name, &message.named_elements, occurrences)
+            }
+            ElementType::Field(field) => {
+                collect_field_type_references(&field.field_type, scope, occurrences)
+            }
+            // Oneof fields and enum values are part of the enclosing scope
+            ElementType::OneOf(oneof) => collect_occurrences(scope, &oneof.fields, occurrences),
+            ElementType::Enum(enum_payload) => {
+                collect_occurrences(scope, &enum_payload.values, occurrences)
+            }
+            ElementType::Service(service) => {
+                for method in &service.methods {
+                    occurrences.push(Occurrence {
+                        start: method.name.metadata.offset(),
+                        end: method.name.metadata.offset() + method.name.metadata.span_len(),
+                        target: Target::Method {
+                            method,
+                            full_name: qualified_name(&full_name, &method.name.value),
+                            scope: scope.to_string(),
+                        },
+                    });
+                    for type_name in [&method.input_type, &method.output_type] {
+                        occurrences.push(type_reference(
+                            &type_name.value,
+                            &type_name.metadata,
+                            scope,
+                        ));
+                    }
+                }
+            }
+            ElementType::EnumValue(_) | ElementType::Extension(_) => {}
+        }
+    }
+}
+
+fn describe_field_type(field_type: &FieldType, type_name: &dyn Fn(&str) -> String) -> String {
+    match field_type {
+        FieldType::Scalar(scalar_type) => scalar_type.as_str().to_string(),
+        FieldType::Named(name) => type_name(name),
+        FieldType::Map(key, value) => format!(
+            "map<{}, {}>",
+            describe_field_type(&key.value, type_name),
+            describe_field_type(&value.value, type_name)
+        ),
+    }
+}
+
+fn symbol_kind_keyword(kind: SymbolKind) -> &'static str {
+    match kind {
+        SymbolKind::Package => "package",
+        SymbolKind::Message => "message",
+        SymbolKind::Enum => "enum",
+        SymbolKind::Service => "service",
+    }
+}
+
+fn element_symbol(source_map: &SourceMap<String>, element: &NamedElement) -> JsonValue {
+    let range = range_json_between(source_map, element.start.offset(), element.end.end_offset());
+    let (kind, detail, children): (u64, String, Vec<JsonValue>) = match &element.type_t {
+        ElementType::Message(message) => (
+            symbol_kind::STRUCT,
+            String::new(),
+            message
+                .named_elements
+                .iter()
+                .map(|child| element_symbol(source_map, child))
+                .collect(),
+        ),
+        ElementType::Field(field) => (
+            symbol_kind::FIELD,
+            format!(
+                "{} = {}",
+                describe_field_type(&field.field_type.value, &|name| name.to_string()),
+                field.number.value
+            ),
+            Vec::new(),
+        ),
+        ElementType::OneOf(oneof) => (
+            symbol_kind::OBJECT,
+            String::new(),
+            oneof
+                .fields
+                .iter()
+                .map(|child| element_symbol(source_map, child))
+                .collect(),
+        ),
+        ElementType::Enum(enum_payload) => (
+            symbol_kind::ENUM,
+            String::new(),
+            enum_payload
+                .values
+                .iter()
+                .map(|child| element_symbol(source_map, child))
+                .collect(),
+        ),
+        ElementType::EnumValue(value) => (
+            symbol_kind::ENUM_MEMBER,
+            value.number.value.to_string(),
+            Vec::new(),
+        ),
+        ElementType::Extension(extension) => (
+            symbol_kind::NAMESPACE,
+            "extend".to_string(),
+            extension
+                .fields
+                .iter()
+                .map(|child| element_symbol(source_map, child))
+                .collect(),
+        ),
+        ElementType::Service(service) => (
+            symbol_kind::INTERFACE,
+            String::new(),
+            service
+                .methods
+                .iter()
+                .map(|method| {
+                    JsonValue::object([
+                        ("name", JsonValue::from(method.name.value.as_str())),
+                        ("kind", JsonValue::from(symbol_kind::METHOD)),
+                        (
+                            "range",
+                            range_json_between(
+                                source_map,
+                                method.start.offset(),
+                                method.end.end_offset(),
+                            ),
+                        ),
+                        (
+                            "selectionRange",
+                            range_json(source_map, &method.name.metadata),
+                        ),
+                    ])
+                })
+                .collect(),
+        ),
+    };
+    JsonValue::object([
+        ("name", JsonValue::from(element.name.as_str())),
+        ("detail", JsonValue::from(detail)),
+        ("kind", JsonValue::from(kind)),
+        ("range", range),
+        ("selectionRange", range_json(source_map, &element.metadata)),
+        ("children", JsonValue::from(children)),
+    ])
+}
+
+/// Scope of the message the byte `offset` is in, found from the tokens before it so that
+/// it works while the document does not parse
+fn scope_at(text: &str, offset: usize) -> String {
+    let tokens: Vec<_> = Lexer::new(text)
+        .take_while(|token| token.metadata.offset() < offset)
+        .collect();
+    let mut package = String::new();
+    // One entry per open brace, with the message name for message bodies
+    let mut blocks: Vec<Option<String>> = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        match &token.kind {
+            TokenKind::Package => {
+                package = tokens[index + 1..]
+                    .iter()
+                    .map_while(|token| match &token.kind {
+                        TokenKind::Dot => Some("."),
+                        _ => token.name(text),
+                    })
+                    .collect();
+            }
+            TokenKind::LBrace => {
+                let message_name = match index.checked_sub(2).map(|start| &tokens[start..index]) {
+                    Some([keyword, name]) if keyword.kind == TokenKind::Message => {
+                        name.name(text).map(str::to_string)
+                    }
+                    _ => None,
+                };
+                blocks.push(message_name);
+            }
+            TokenKind::RBrace => {
+                blocks.pop();
+            }
+            _ => {}
+        }
+    }
+    blocks
+        .into_iter()
+        .flatten()
+        .fold(package, |scope, name| qualified_name(&scope, &name))
+}
+
+/// The shortest suffix of `full_name` that still refers to it from within `scope`
+fn shortest_name(symbols: &SymbolTable, scope: &str, full_name: &str) -> String {
+    let parts: Vec<&str> = full_name.split('.').collect();
+    for start in (0..parts.len()).rev() {
+        let candidate = parts[start..].join(".");
+        if let Some((resolved, _)) = symbols.lookup(scope, &candidate) {
+            if resolved == full_name {
+                return candidate;
+            }
+        }
+    }
+    format!(".{}", full_name)
+}
+
+/// Apply one of the `contentChanges` of a `didChange` notification. Changes with a range
+/// replace that range of the text, the others the whole text.
+fn apply_change(parser: &mut IncrementalParser, change: &JsonValue) {
+    let Some(text) = change.get("text").and_then(JsonValue::as_str) else {
+        return;
+    };
+    let Some(range) = change.get("range") else {
+        *parser = IncrementalParser::new(text.to_string());
+        return;
+    };
+    let source_map = SourceMap::new(parser.text());
+    let offset = |position: Option<&JsonValue>| {
+        let position = position?;
+        Some(offset_of(
+            &source_map,
+            position.get("line")?.as_u64()? as usize,
+            position.get("character")?.as_u64()? as usize,
+        ))
+    };
+    let (Some(start), Some(end)) = (offset(range.get("start")), offset(range.get("end"))) else {
+        return;
+    };
+    let edit = TextEdit {
+        range: start.min(end)..end,
+        text: text.to_string(),
+    };
+    parser.edit(&edit);
+}
+
+/// LSP positions count UTF-16 code units, token offsets count bytes
+fn position_json(source_map: &SourceMap<impl AsRef<str>>, offset: usize) -> JsonValue {
+    let position = source_map.line_column(offset, ColumnUnit::Utf16);
+    JsonValue::object([
+        ("line", JsonValue::from(position.line)),
+        ("character", JsonValue::from(position.column)),
+    ])
+}
+
+/// Byte offset of an LSP position, clamped to the end of its line
+fn offset_of(source_map: &SourceMap<impl AsRef<str>>, line: usize, character: usize) -> usize {
+    let position = LineColumn {
+        line,
+        column: character,
+    };
+    source_map.offset(position, ColumnUnit::Utf16)
+}
+
+fn range_json_between(
+    source_map: &SourceMap<impl AsRef<str>>,
+    start: usize,
+    end: usize,
+) -> JsonValue {
+    JsonValue::object([
+        ("start", position_json(source_map, start)),
+        ("end", position_json(source_map, end)),
+    ])
+}
+
+fn range_json(source_map: &SourceMap<impl AsRef<str>>, metadata: &TokenMetadata) -> JsonValue {
+    range_json_between(
+        source_map,
+        metadata.offset(),
+        metadata.offset() + metadata.span_len(),
+    )
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<JsonValue>) -> JsonValue {
+    JsonValue::object([
+        ("jsonrpc", JsonValue::from("2.0")),
+        ("method", JsonValue::from("textDocument/publishDiagnostics")),
+        (
+            "params",
+            JsonValue::object([
+                ("uri", JsonValue::from(uri)),
+                ("diagnostics", JsonValue::from(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn error_response(id: JsonValue, code: i64, message: &str) -> JsonValue {
+    JsonValue::object([
+        ("jsonrpc", JsonValue::from("2.0")),
+        ("id", id),
+        (
+            "error",
+            JsonValue::object([
+                ("code", JsonValue::from(code)),
+                ("message", JsonValue::from(message)),
+            ]),
+        ),
+    ])
+}
+
+/// Read the content of the next message, `None` once the input is closed
+fn read_message(input: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut content_length = None;
+    let mut content = loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            match content_length {
+                Some(content_length) => break vec![0; content_length],
+                None => continue,
+            }
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    };
+    input.read_exact(&mut content)?;
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+}
+
+fn write_message(output: &mut impl Write, message: &JsonValue) -> std::io::Result<()> {
+    let content = message.to_string();
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    output.flush()
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| path.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: u64, method: &str, params: JsonValue) -> JsonValue {
+        JsonValue::object([
+            ("jsonrpc", JsonValue::from("2.0")),
+            ("id", JsonValue::from(id)),
+            ("method", JsonValue::from(method)),
+            ("params", params),
+        ])
+    }
+
+    fn notification(method: &str, params: JsonValue) -> JsonValue {
+        JsonValue::object([
+            ("jsonrpc", JsonValue::from("2.0")),
+            ("method", JsonValue::from(method)),
+            ("params", params),
+        ])
+    }
+
+    fn position(uri: &str, line: u64, character: u64) -> JsonValue {
+        JsonValue::object([
+            (
+                "textDocument",
+                JsonValue::object([("uri", JsonValue::from(uri))]),
+            ),
+            (
+                "position",
+                JsonValue::object([
+                    ("line", JsonValue::from(line)),
+                    ("character", JsonValue::from(character)),
+                ]),
+            ),
+        ])
+    }
+
+    fn result(server: &mut LanguageServer, message: JsonValue) -> JsonValue {
+        let mut responses = server.handle_message(&message);
+        assert_eq!(responses.len(), 1);
+        responses.remove(0).get("result").unwrap().clone()
+    }
+
+    const SOURCE: &str = "syntax = \"proto3\";
+package demo;
+message Outer {
+  message Inner { int32 value = 1; }
+  Inner inner = 1;
+  repeated Kind kinds = 2;
+}
+enum Kind { KIND_UNSPECIFIED = 0; }
+service Api { rpc Get(Outer) returns (Outer.Inner); }
+";
+
+    fn open(server: &mut LanguageServer, uri: &str, text: &str) -> Vec<JsonValue> {
+        server.handle_message(&notification(
+            "textDocument/didOpen",
+            JsonValue::object([(
+                "textDocument",
+                JsonValue::object([
+                    ("uri", JsonValue::from(uri)),
+                    ("text", JsonValue::from(text)),
+                ]),
+            )]),
+        ))
+    }
+
+    #[test]
+    fn lsp_navigation_test() {
+        let directory = std::env::temp_dir().join("rs_protoc_lsp_navigation_test");
+        let uri = path_to_uri(&directory.join("demo.proto"));
+        let mut server = LanguageServer::new(Vec::new());
+        let capabilities = result(&mut server, request(1, "initialize", JsonValue::object([])));
+        assert_eq!(
+            capabilities
+                .get("capabilities")
+                .unwrap()
+                .get("hoverProvider"),
+            Some(&JsonValue::Bool(true))
+        );
+
+        let published = open(&mut server, &uri, SOURCE);
+        let diagnostics = published[0]
+            .get("params")
+            .unwrap()
+            .get("diagnostics")
+            .unwrap();
+        assert_eq!(diagnostics.as_array().unwrap().len(), 0, "{}", diagnostics);
+
+        // Hovering the type of `inner` shows the resolved name
+        let hover = result(
+            &mut server,
+            request(2, "textDocument/hover", position(&uri, 4, 3)),
+        );
+        let contents = hover.get("contents").unwrap().get("value").unwrap();
+        assert_eq!(
+            contents.as_str(),
+            Some("```proto\nmessage .demo.Outer.Inner\n```")
+        );
+
+        // Hovering a field name shows its resolved type and number
+        let hover = result(
+            &mut server,
+            request(3, "textDocument/hover", position(&uri, 5, 17)),
+        );
+        let contents = hover.get("contents").unwrap().get("value").unwrap();
+        assert_eq!(
+            contents.as_str(),
+            Some("```proto\nrepeated .demo.Kind kinds = 2; // .demo.Outer.kinds\n```")
+        );
+
+        // Go to the definition of `Outer.Inner` from the rpc
+        let definition = result(
+            &mut server,
+            request(4, "textDocument/definition", position(&uri, 8, 42)),
+        );
+        assert_eq!(definition.get("uri").unwrap().as_str(), Some(uri.as_str()));
+        let start = definition.get("range").unwrap().get("start").unwrap();
+        assert_eq!(start.get("line").unwrap().as_u64(), Some(3));
+        assert_eq!(start.get("character").unwrap().as_u64(), Some(10));
+
+        let symbols = result(
+            &mut server,
+            request(5, "textDocument/documentSymbol", position(&uri, 0, 0)),
+        );
+        let names: Vec<_> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|symbol| symbol.get("name").unwrap().as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Outer", "Kind", "Api"]);
+        let outer_children = symbols.as_array().unwrap()[0].get("children").unwrap();
+        assert_eq!(outer_children.as_array().unwrap().len(), 3);
+        // The range covers the whole declaration, the selection only its name
+        let lines_and_characters = |range: &JsonValue| {
+            ["start", "end"].map(|end| {
+                let position = range.get(end).unwrap();
+                (
+                    position.get("line").unwrap().as_u64().unwrap(),
+                    position.get("character").unwrap().as_u64().unwrap(),
+                )
+            })
+        };
+        let outer = &symbols.as_array().unwrap()[0];
+        assert_eq!(
+            lines_and_characters(outer.get("range").unwrap()),
+            [(2, 0), (6, 1)]
+        );
+        assert_eq!(
+            lines_and_characters(outer.get("selectionRange").unwrap()),
+            [(2, 8), (2, 13)]
+        );
+
+        // Inside `Outer`, `Inner` does not need to be qualified
+        let completion = result(
+            &mut server,
+            request(6, "textDocument/completion", position(&uri, 5, 2)),
+        );
+        let labels: Vec<_> = completion
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item.get("label").unwrap().as_str().unwrap())
+            .collect();
+        assert_eq!(labels, ["Inner", "Kind", "Outer"]);
+
+        // Navigation keeps working from the last good analysis while the document is broken
+        let published = server.handle_message(&notification(
+            "textDocument/didChange",
+            JsonValue::object([
+                (
+                    "textDocument",
+                    JsonValue::object([("uri", JsonValue::from(uri.as_str()))]),
+                ),
+                (
+                    "contentChanges",
+                    JsonValue::from(vec![JsonValue::object([(
+                        "text",
+                        JsonValue::from(SOURCE.replace("int32 value = 1;", "int32 value = ;")),
+                    )])]),
+                ),
+            ]),
+        ));
+        let diagnostics = published[0]
+            .get("params")
+            .unwrap()
+            .get("diagnostics")
+            .unwrap();
+        let diagnostics = diagnostics.as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].get("range").unwrap().get("start").unwrap(),
+            &JsonValue::object([
+                ("line", JsonValue::from(3u64)),
+                ("character", JsonValue::from(32u64)),
+            ])
+        );
+        let hover = result(
+            &mut server,
+            request(7, "textDocument/hover", position(&uri, 4, 3)),
+        );
+        assert!(!hover.is_null());
+
+        // Changes with a range only replace that range, one after the other
+        let range = |line: u64, start: u64, end: u64| {
+            let position = |character: u64| {
+                JsonValue::object([
+                    ("line", JsonValue::from(line)),
+                    ("character", JsonValue::from(character)),
+                ])
+            };
+            JsonValue::object([("start", position(start)), ("end", position(end))])
+        };
+        let published = server.handle_message(&notification(
+            "textDocument/didChange",
+            JsonValue::object([
+                (
+                    "textDocument",
+                    JsonValue::object([("uri", JsonValue::from(uri.as_str()))]),
+                ),
+                (
+                    "contentChanges",
+                    JsonValue::from(vec![
+                        JsonValue::object([
+                            ("range", range(3, 32, 32)),
+                            ("text", JsonValue::from("1")),
+                        ]),
+                        JsonValue::object([
+                            ("range", range(4, 8, 13)),
+                            ("text", JsonValue::from("first")),
+                        ]),
+                    ]),
+                ),
+            ]),
+        ));
+        let diagnostics = published[0]
+            .get("params")
+            .unwrap()
+            .get("diagnostics")
+            .unwrap();
+        assert_eq!(diagnostics.as_array().unwrap().len(), 0, "{}", diagnostics);
+        let hover = result(
+            &mut server,
+            request(8, "textDocument/hover", position(&uri, 4, 10)),
+        );
+        let contents = hover.get("contents").unwrap().get("value").unwrap();
+        assert_eq!(
+            contents.as_str(),
+            Some("```proto\n.demo.Outer.Inner first = 1; // .demo.Outer.first\n```")
+        );
+
+        // Positions in the last good analysis stay those of the text it was parsed from
+        server.handle_message(&notification(
+            "textDocument/didChange",
+            JsonValue::object([
+                (
+                    "textDocument",
+                    JsonValue::object([("uri", JsonValue::from(uri.as_str()))]),
+                ),
+                (
+                    "contentChanges",
+                    JsonValue::from(vec![JsonValue::object([(
+                        "text",
+                        JsonValue::from(format!("// Broken\n{}", SOURCE).replace("= 1;", "= ;")),
+                    )])]),
+                ),
+            ]),
+        ));
+        let definition = result(
+            &mut server,
+            request(9, "textDocument/definition", position(&uri, 8, 42)),
+        );
+        let start = definition.get("range").unwrap().get("start").unwrap();
+        assert_eq!(start.get("line").unwrap().as_u64(), Some(3));
+        assert_eq!(start.get("character").unwrap().as_u64(), Some(10));
+    }
+
+    #[test]
+    fn lsp_protocol_test() {
+        let mut server = LanguageServer::new(Vec::new());
+        let responses = server.handle_message(&request(1, "textDocument/hover", JsonValue::Null));
+        assert_eq!(
+            responses[0]
+                .get("error")
+                .unwrap()
+                .get("code")
+                .unwrap()
+                .as_i64(),
+            Some(SERVER_NOT_INITIALIZED)
+        );
+
+        let messages = [
+            request(1, "initialize", JsonValue::object([])),
+            request(2, "workspace/symbol", JsonValue::object([])),
+            request(3, "shutdown", JsonValue::Null),
+            notification("exit", JsonValue::Null),
+            // Never read, the server stops at exit
+            request(4, "shutdown", JsonValue::Null),
+        ];
+        let mut input = Vec::new();
+        for message in &messages {
+            let content = message.to_string();
+            write!(
+                input,
+                "Content-Length: {}\r\n\r\n{}",
+                content.len(),
+                content
+            )
+            .unwrap();
+        }
+        let mut output = Vec::new();
+        server
+            .run(&mut std::io::Cursor::new(input), &mut output)
+            .unwrap();
+        let mut output = std::io::Cursor::new(output);
+        let mut responses = Vec::new();
+        while let Some(content) = read_message(&mut output).unwrap() {
+            responses.push(JsonValue::parse(&content).unwrap());
+        }
+        assert_eq!(responses.len(), 3);
+        assert_eq!(
+            responses[1]
+                .get("error")
+                .unwrap()
+                .get("code")
+                .unwrap()
+                .as_i64(),
+            Some(METHOD_NOT_FOUND)
+        );
+        assert!(responses[2].get("result").unwrap().is_null());
+    }
+
+    #[test]
+    fn lsp_uri_test() {
+        let path = PathBuf::from("/tmp/my protos/ä.proto");
+        let uri = path_to_uri(&path);
+        assert_eq!(uri, "file:///tmp/my%20protos/%C3%A4.proto");
+        assert_eq!(uri_to_path(&uri), Some(path));
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+
+        let source_map = SourceMap::new("a😀b\ncd");
+        assert_eq!(offset_of(&source_map, 0, 3), 5);
+        assert_eq!(offset_of(&source_map, 1, 1), 8);
+        assert_eq!(offset_of(&source_map, 0, 100), 6);
+        assert_eq!(
+            position_json(&source_map, 8),
+            JsonValue::object([
+                ("line", JsonValue::from(1u64)),
+                ("character", JsonValue::from(1u64)),
+            ])
+        );
+    }
+}
@@ -1,8 +1,636 @@
-mod error;
-mod lexer;
-mod parser;
-mod source_text;
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
+
+use rs_protoc::{
+    breaking::{self, CompatibilityLevel},
+    codegen, descriptor,
+    diagnostic::{Diagnostic, DiagnosticFormat},
+    docgen::{self, DocFormat},
+    error::{FileError, RsProtocError},
+    formatter,
+    linter::{self, LintConfig},
+    lsp::LanguageServer,
+    parser::Parser,
+    resolver::{self, Loader},
+    schema::Schema,
+    source_text::SourceRegistry,
+};
+
+const USAGE: &str = "Usage: rs-protoc [OPTION] PROTO_FILES
+       rs-protoc lsp [-IPATH]...
+       rs-protoc format [--check | --in_place] [--error_format=FORMAT] PROTO_FILES
+       rs-protoc lint [--config=FILE] [--error_format=FORMAT] PROTO_FILES
+       rs-protoc breaking --against=OLD [--level=LEVEL] [-IPATH]... PROTO_FILES
+  lsp                         Run a language server speaking the Language
+                              Server Protocol over stdin and stdout. Imports
+                              are searched for in the given paths, the
+                              workspace folders and the directory of each
+                              open file.
+  format                      Print PROTO_FILES in the canonical layout.
+                              Comments are kept.
+  --check                     With format, list the files that are not in
+                              the canonical layout instead of printing them
+                              and fail if there is any.
+  --in_place                  With format, rewrite the files that are not
+                              in the canonical layout.
+  lint                        Check PROTO_FILES against the style rules and
+                              fail if any of them is broken. An element
+                              opts out of rules with a comment attached to
+                              it: rs-protoc:lint:ignore RULE...
+  --config=FILE               With lint, read the rules to check from FILE,
+                              a JSON object like {\"rules\": {\"RULE\": false}}.
+                              Defaults to rs-protoc-lint.json when it
+                              exists.
+  breaking                    Compare PROTO_FILES with an older version of
+                              the schema and fail on incompatible changes.
+  --against=OLD               With breaking, the older version: a directory
+                              holding the same files or a FileDescriptorSet.
+  --level=LEVEL               With breaking, the compatibility to keep: 'wire'
+                              (the binary encoding), 'json' (also the JSON
+                              mapping) or 'source' (the default, also the
+                              generated code).
+  -IPATH, --proto_path=PATH   Specify the directory in which to search for
+                              imports. May be specified multiple times.
+                              Defaults to the current working directory.
+  --descriptor_set_in=FILE    Read the input files from a FileDescriptorSet
+                              instead of parsing .proto sources. PROTO_FILES
+                              are names of files in the set.
+  --descriptor_set_out=FILE   Write a FileDescriptorSet (a protocol buffer,
+                              defined in descriptor.proto) containing all of
+                              the input files to FILE.
+  --rust_out=DIR              Generate Rust code for the input files into DIR,
+                              one file per package along with mod.rs which
+                              includes them into a module tree.
+  --doc_out=DIR               Generate reference documentation for the
+                              packages of the input files into DIR, one page
+                              per package along with an index page.
+  --doc_format=FORMAT         With --doc_out, the format of the pages:
+                              'markdown' (the default) or 'html'.
+  --include_imports           When using --descriptor_set_out, also include
+                              all dependencies of the input files in the set.
+  --include_source_info       When using --descriptor_set_out, do not strip
+                              SourceCodeInfo from the FileDescriptorProto.
+  --error_format=FORMAT       Set the format in which to print errors.
+                              FORMAT may be 'pretty' (the default, shows the
+                              offending source lines), 'gcc' or 'msvs' (the
+                              same text as protoc) or 'json' (one object per
+                              line with file, range, severity, code and
+                              message).";
+
+#[derive(Default)]
+struct Arguments {
+    include_paths: Vec<PathBuf>,
+    descriptor_set_in: Option<String>,
+    descriptor_set_out: Option<String>,
+    rust_out: Option<String>,
+    doc_out: Option<String>,
+    doc_format: DocFormat,
+    include_imports: bool,
+    include_source_info: bool,
+    error_format: DiagnosticFormat,
+    input_files: Vec<String>,
+}
+
+fn parse_arguments(mut args: impl Iterator<Item = String>) -> Result<Arguments, String> {
+    let mut arguments = Arguments::default();
+    while let Some(arg) = args.next() {
+        if [
+            "-I",
+            "--proto_path",
+            "--descriptor_set_in",
+            "--descriptor_set_out",
+            "--rust_out",
+            "--doc_out",
+            "--doc_format",
+            "--error_format",
+        ]
+        .contains(&arg.as_str())
+        {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for flag: {}", arg))?;
+            match arg.as_str() {
+                "--descriptor_set_in" => arguments.descriptor_set_in = Some(value),
+                "--descriptor_set_out" => arguments.descriptor_set_out = Some(value),
+                "--rust_out" => arguments.rust_out = Some(value),
+                "--doc_out" => arguments.doc_out = Some(value),
+                "--doc_format" => arguments.doc_format = value.parse()?,
+                "--error_format" => arguments.error_format = value.parse()?,
+                _ => arguments.include_paths.push(PathBuf::from(value)),
+            }
+        } else if let Some(path) = arg
+            .strip_prefix("--proto_path=")
+            .or_else(|| arg.strip_prefix("-I"))
+        {
+            arguments.include_paths.push(PathBuf::from(path));
+        } else if let Some(path) = arg.strip_prefix("--descriptor_set_in=") {
+            arguments.descriptor_set_in = Some(path.to_string());
+        } else if let Some(path) = arg.strip_prefix("--descriptor_set_out=") {
+            arguments.descriptor_set_out = Some(path.to_string());
+        } else if let Some(path) = arg.strip_prefix("--rust_out=") {
+            arguments.rust_out = Some(path.to_string());
+        } else if let Some(path) = arg.strip_prefix("--doc_out=") {
+            arguments.doc_out = Some(path.to_string());
+        } else if let Some(format) = arg.strip_prefix("--doc_format=") {
+            arguments.doc_format = format.parse()?;
+        } else if let Some(format) = arg.strip_prefix("--error_format=") {
+            arguments.error_format = format.parse()?;
+        } else if arg == "--include_imports" {
+            arguments.include_imports = true;
+        } else if arg == "--include_source_info" {
+            arguments.include_source_info = true;
+        } else if arg.starts_with('-') {
+            return Err(format!("Unknown flag: {}", arg));
+        } else {
+            arguments.input_files.push(arg);
+        }
+    }
+    if arguments.input_files.is_empty() {
+        return Err("Missing input file.".to_string());
+    }
+    if arguments.include_paths.is_empty() {
+        arguments.include_paths.push(PathBuf::from("."));
+    }
+    Ok(arguments)
+}
+
+/// Include paths of the language server, the only flags it accepts
+fn parse_lsp_arguments(mut args: impl Iterator<Item = String>) -> Result<Vec<PathBuf>, String> {
+    let mut include_paths = Vec::new();
+    while let Some(arg) = args.next() {
+        let path = if arg == "-I" || arg == "--proto_path" {
+            args.next()
+                .ok_or_else(|| format!("Missing value for flag: {}", arg))?
+        } else if let Some(path) = arg
+            .strip_prefix("--proto_path=")
+            .or_else(|| arg.strip_prefix("-I"))
+        {
+            path.to_string()
+        } else {
+            return Err(format!("Unknown argument for lsp: {}", arg));
+        };
+        // Documents are addressed by absolute URIs
+        include_paths.push(std::path::absolute(&path).map_err(|error| error.to_string())?);
+    }
+    Ok(include_paths)
+}
+
+#[derive(Default)]
+struct FormatArguments {
+    check: bool,
+    in_place: bool,
+    error_format: DiagnosticFormat,
+    input_files: Vec<String>,
+}
+
+fn parse_format_arguments(args: impl Iterator<Item = String>) -> Result<FormatArguments, String> {
+    let mut arguments = FormatArguments::default();
+    for arg in args {
+        if let Some(format) = arg.strip_prefix("--error_format=") {
+            arguments.error_format = format.parse()?;
+            continue;
+        }
+        match arg.as_str() {
+            "--check" => arguments.check = true,
+            "--in_place" => arguments.in_place = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown flag for format: {}", arg)),
+            _ => arguments.input_files.push(arg),
+        }
+    }
+    if arguments.input_files.is_empty() {
+        return Err("Missing input file.".to_string());
+    }
+    if arguments.check && arguments.in_place {
+        return Err("--check and --in_place cannot be used together.".to_string());
+    }
+    Ok(arguments)
+}
+
+/// Format every input file, returns false if a file could not be parsed or, with `--check`,
+/// is not formatted
+fn run_format(arguments: &FormatArguments) -> bool {
+    let mut sources = SourceRegistry::default();
+    let mut success = true;
+    for input_file in &arguments.input_files {
+        let to_file_errors = |errors: Vec<RsProtocError>| -> Vec<FileError> {
+            errors
+                .into_iter()
+                .map(|error| FileError {
+                    file_name: input_file.clone(),
+                    error,
+                })
+                .collect()
+        };
+        let source = match std::fs::read_to_string(input_file) {
+            Ok(source) => source,
+            Err(error) => {
+                let errors =
+                    to_file_errors(vec![RsProtocError::FilesystemError(error.to_string())]);
+                print_errors(&errors, arguments.error_format, &sources);
+                success = false;
+                continue;
+            }
+        };
+        let buffer = sources.add(input_file, Path::new(input_file), source);
+        let file = match Parser::for_buffer(&buffer).parse() {
+            Ok(file) => file,
+            Err(errors) => {
+                print_errors(&to_file_errors(errors), arguments.error_format, &sources);
+                success = false;
+                continue;
+            }
+        };
+        let formatted = formatter::format_file(&file);
+        if arguments.check {
+            if formatted != buffer.text() {
+                println!("{}", input_file);
+                success = false;
+            }
+        } else if arguments.in_place {
+            if formatted != buffer.text() {
+                if let Err(error) = std::fs::write(input_file, formatted) {
+                    let errors =
+                        to_file_errors(vec![RsProtocError::FilesystemError(error.to_string())]);
+                    print_errors(&errors, arguments.error_format, &sources);
+                    success = false;
+                }
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    success
+}
+
+/// Configuration file of lint read when no --config is given
+const DEFAULT_LINT_CONFIG: &str = "rs-protoc-lint.json";
+
+#[derive(Default)]
+struct LintArguments {
+    config: Option<String>,
+    error_format: DiagnosticFormat,
+    input_files: Vec<String>,
+}
+
+fn parse_lint_arguments(args: impl Iterator<Item = String>) -> Result<LintArguments, String> {
+    let mut arguments = LintArguments::default();
+    for arg in args {
+        if let Some(path) = arg.strip_prefix("--config=") {
+            arguments.config = Some(path.to_string());
+        } else if let Some(format) = arg.strip_prefix("--error_format=") {
+            arguments.error_format = format.parse()?;
+        } else if arg.starts_with('-') {
+            return Err(format!("Unknown flag for lint: {}", arg));
+        } else {
+            arguments.input_files.push(arg);
+        }
+    }
+    if arguments.input_files.is_empty() {
+        return Err("Missing input file.".to_string());
+    }
+    Ok(arguments)
+}
+
+fn load_lint_config(arguments: &LintArguments) -> Result<LintConfig, String> {
+    let path = match &arguments.config {
+        Some(path) => path.as_str(),
+        None if std::path::Path::new(DEFAULT_LINT_CONFIG).exists() => DEFAULT_LINT_CONFIG,
+        None => return Ok(LintConfig::default()),
+    };
+    let text = std::fs::read_to_string(path)
+        .map_err(|error| format!("Failed to read \"{}\": {}", path, error))?;
+    LintConfig::parse(&text).map_err(|error| format!("{}: {}", path, error))
+}
+
+/// Lint every input file, returns false if a file could not be parsed or breaks a rule
+fn run_lint(arguments: &LintArguments) -> bool {
+    let config = match load_lint_config(arguments) {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{}", message);
+            return false;
+        }
+    };
+    let mut sources = SourceRegistry::default();
+    let mut success = true;
+    for input_file in &arguments.input_files {
+        let source = match std::fs::read_to_string(input_file) {
+            Ok(source) => source,
+            Err(error) => {
+                let error = FileError {
+                    file_name: input_file.clone(),
+                    error: RsProtocError::FilesystemError(error.to_string()),
+                };
+                print_errors(&[error], arguments.error_format, &sources);
+                success = false;
+                continue;
+            }
+        };
+        let buffer = sources.add(input_file, Path::new(input_file), source);
+        let diagnostics = match Parser::for_buffer(&buffer).parse() {
+            Ok(file) => linter::lint_file(&file, input_file, &config),
+            Err(errors) => errors.iter().map(RsProtocError::to_diagnostic).collect(),
+        };
+        success &= diagnostics.is_empty();
+        print_diagnostics(
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (input_file.as_str(), diagnostic)),
+            arguments.error_format,
+            &sources,
+        );
+    }
+    success
+}
+
+#[derive(Default)]
+struct BreakingArguments {
+    against: String,
+    level: CompatibilityLevel,
+    include_paths: Vec<PathBuf>,
+    error_format: DiagnosticFormat,
+    input_files: Vec<String>,
+}
+
+fn parse_breaking_arguments(
+    mut args: impl Iterator<Item = String>,
+) -> Result<BreakingArguments, String> {
+    let mut arguments = BreakingArguments::default();
+    while let Some(arg) = args.next() {
+        if arg == "-I" || arg == "--proto_path" {
+            let path = args
+                .next()
+                .ok_or_else(|| format!("Missing value for flag: {}", arg))?;
+            arguments.include_paths.push(PathBuf::from(path));
+        } else if let Some(path) = arg
+            .strip_prefix("--proto_path=")
+            .or_else(|| arg.strip_prefix("-I"))
+        {
+            arguments.include_paths.push(PathBuf::from(path));
+        } else if let Some(against) = arg.strip_prefix("--against=") {
+            arguments.against = against.to_string();
+        } else if let Some(level) = arg.strip_prefix("--level=") {
+            arguments.level = level.parse()?;
+        } else if let Some(format) = arg.strip_prefix("--error_format=") {
+            arguments.error_format = format.parse()?;
+        } else if arg.starts_with('-') {
+            return Err(format!("Unknown flag for breaking: {}", arg));
+        } else {
+            arguments.input_files.push(arg);
+        }
+    }
+    if arguments.input_files.is_empty() {
+        return Err("Missing input file.".to_string());
+    }
+    if arguments.against.is_empty() {
+        return Err("Missing --against.".to_string());
+    }
+    if arguments.include_paths.is_empty() {
+        arguments.include_paths.push(PathBuf::from("."));
+    }
+    Ok(arguments)
+}
+
+/// Load the older version of the schema, the files named `input_names` from a directory or a
+/// whole descriptor set
+fn load_against(
+    against: &str,
+    input_names: &[String],
+    sources: &mut SourceRegistry,
+) -> Result<Schema, Vec<FileError>> {
+    if std::path::Path::new(against).is_dir() {
+        let mut loader =
+            Loader::with_sources(vec![PathBuf::from(against)], std::mem::take(sources));
+        for name in input_names {
+            loader.load(name);
+        }
+        let (files, errors, loaded_sources) = loader.into_parts();
+        *sources = loaded_sources;
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        return resolver::resolve(&files);
+    }
+    let to_file_error = |error| {
+        vec![FileError {
+            file_name: against.to_string(),
+            error,
+        }]
+    };
+    let bytes = std::fs::read(against)
+        .map_err(|error| to_file_error(RsProtocError::FilesystemError(error.to_string())))?;
+    descriptor::decode_file_descriptor_set(&bytes).map_err(to_file_error)
+}
+
+/// Compare the input files with the older version, returns false if either version could not
+/// be loaded or a change breaks compatibility
+fn run_breaking(arguments: &BreakingArguments) -> bool {
+    // Both versions share the registry, so that every file id is unique
+    let mut sources = SourceRegistry::default();
+    let new = match resolver::load_schema(
+        &arguments.input_files,
+        &arguments.include_paths,
+        &mut sources,
+    ) {
+        Ok(new) => new,
+        Err(errors) => {
+            print_errors(&errors, arguments.error_format, &sources);
+            return false;
+        }
+    };
+    let old = match load_against(&arguments.against, &new.input_names, &mut sources) {
+        Ok(old) => old,
+        Err(errors) => {
+            print_errors(&errors, arguments.error_format, &sources);
+            return false;
+        }
+    };
+    let changes = breaking::find_breaking_changes(&old, &new.schema, arguments.level);
+    print_diagnostics(
+        changes
+            .iter()
+            .map(|change| (change.file_name.as_str(), change.to_diagnostic(&sources))),
+        arguments.error_format,
+        &sources,
+    );
+    changes.is_empty()
+}
+
+/// Decode the schema from a descriptor set, the input files have to be part of it
+fn load_descriptor_set(
+    descriptor_set_in: &str,
+    arguments: &Arguments,
+) -> Result<(Schema, Vec<String>), Vec<FileError>> {
+    let to_file_error = |error| {
+        vec![FileError {
+            file_name: descriptor_set_in.to_string(),
+            error,
+        }]
+    };
+    let bytes = std::fs::read(descriptor_set_in).map_err(|error| {
+        to_file_error(RsProtocError::FilesystemError(format!(
+            "Failed to read \"{}\": {}",
+            descriptor_set_in, error
+        )))
+    })?;
+    let schema = descriptor::decode_file_descriptor_set(&bytes).map_err(to_file_error)?;
+    let errors: Vec<FileError> = arguments
+        .input_files
+        .iter()
+        .filter(|input_file| schema.file(input_file).is_none())
+        .map(|input_file| FileError {
+            file_name: input_file.clone(),
+            error: RsProtocError::FilesystemError(format!(
+                "File not found in {}",
+                descriptor_set_in
+            )),
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok((schema, arguments.input_files.clone()))
+}
+
+/// Parse the input files and their imports and resolve them into a schema
+fn load_proto_files(
+    arguments: &Arguments,
+    sources: &mut SourceRegistry,
+) -> Result<(Schema, Vec<String>), Vec<FileError>> {
+    let loaded = resolver::load_schema(&arguments.input_files, &arguments.include_paths, sources)?;
+    Ok((loaded.schema, loaded.input_names))
+}
+
+/// Compile the input files, the files read are registered in `sources`
+fn run(arguments: &Arguments, sources: &mut SourceRegistry) -> Result<(), Vec<FileError>> {
+    let (schema, input_names) = match &arguments.descriptor_set_in {
+        Some(descriptor_set_in) => load_descriptor_set(descriptor_set_in, arguments)?,
+        None => load_proto_files(arguments, sources)?,
+    };
+
+    if let Some(descriptor_set_out) = &arguments.descriptor_set_out {
+        let selected_files: Vec<_> = schema
+            .files
+            .iter()
+            .filter(|file| arguments.include_imports || input_names.contains(&file.name))
+            .collect();
+        let to_file_error = |error| FileError {
+            file_name: descriptor_set_out.clone(),
+            error,
+        };
+        let bytes =
+            descriptor::encode_file_descriptor_set(&selected_files, arguments.include_source_info)
+                .map_err(|error| vec![to_file_error(error)])?;
+        std::fs::write(descriptor_set_out, bytes).map_err(|error| {
+            vec![to_file_error(RsProtocError::FilesystemError(
+                error.to_string(),
+            ))]
+        })?;
+    }
+
+    let mut outputs = Vec::new();
+    if let Some(rust_out) = &arguments.rust_out {
+        outputs.push((rust_out, codegen::generate(&schema, &input_names)));
+    }
+    if let Some(doc_out) = &arguments.doc_out {
+        outputs.push((
+            doc_out,
+            docgen::generate_docs(&schema, &input_names, arguments.doc_format),
+        ));
+    }
+    for (out_dir, generated_files) in outputs {
+        for generated_file in generated_files {
+            let path = std::path::Path::new(out_dir).join(&generated_file.name);
+            std::fs::write(&path, generated_file.content).map_err(|error| {
+                vec![FileError {
+                    file_name: path.to_string_lossy().to_string(),
+                    error: RsProtocError::FilesystemError(error.to_string()),
+                }]
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Print every diagnostic after the name of its file, with the offending lines of the files in
+/// `sources`
+fn print_diagnostics<'a>(
+    diagnostics: impl IntoIterator<Item = (&'a str, Diagnostic)>,
+    format: DiagnosticFormat,
+    sources: &SourceRegistry,
+) {
+    let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    for (file_name, diagnostic) in diagnostics {
+        let text = diagnostic.format_in(format, file_name, sources, color);
+        match format {
+            // Keep a blank line between the multi-line diagnostics
+            DiagnosticFormat::Pretty => eprintln!("{}", text),
+            _ => eprint!("{}", text),
+        }
+    }
+}
+
+/// Print every error, with the offending lines of the files read
+fn print_errors(errors: &[FileError], format: DiagnosticFormat, sources: &SourceRegistry) {
+    let diagnostics = errors
+        .iter()
+        .map(|error| (error.file_name.as_str(), error.error.to_diagnostic()));
+    print_diagnostics(diagnostics, format, sources);
+}
 
 fn main() {
-    println!("Hello, world!");
+    if std::env::args().nth(1).as_deref() == Some("lsp") {
+        let include_paths = match parse_lsp_arguments(std::env::args().skip(2)) {
+            Ok(include_paths) => include_paths,
+            Err(message) => {
+                eprintln!("{}\n{}", message, USAGE);
+                std::process::exit(1);
+            }
+        };
+        let mut server = LanguageServer::new(include_paths);
+        if let Err(error) = server.run(&mut std::io::stdin().lock(), &mut std::io::stdout().lock())
+        {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("format") {
+        match parse_format_arguments(std::env::args().skip(2)) {
+            Ok(arguments) if run_format(&arguments) => return,
+            Ok(_) => {}
+            Err(message) => eprintln!("{}\n{}", message, USAGE),
+        }
+        std::process::exit(1);
+    }
+    if std::env::args().nth(1).as_deref() == Some("lint") {
+        match parse_lint_arguments(std::env::args().skip(2)) {
+            Ok(arguments) if run_lint(&arguments) => return,
+            Ok(_) => {}
+            Err(message) => eprintln!("{}\n{}", message, USAGE),
+        }
+        std::process::exit(1);
+    }
+    if std::env::args().nth(1).as_deref() == Some("breaking") {
+        match parse_breaking_arguments(std::env::args().skip(2)) {
+            Ok(arguments) if run_breaking(&arguments) => return,
+            Ok(_) => {}
+            Err(message) => eprintln!("{}\n{}", message, USAGE),
+        }
+        std::process::exit(1);
+    }
+    let arguments = match parse_arguments(std::env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            std::process::exit(1);
+        }
+    };
+    let mut sources = SourceRegistry::default();
+    if let Err(errors) = run(&arguments, &mut sources) {
+        print_errors(&errors, arguments.error_format, &sources);
+        std::process::exit(1);
+    }
 }
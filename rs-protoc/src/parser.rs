@@ -1,8 +1,15 @@
 use crate::{
+    ast_elements::{
+        Comments, Constant, ElementType, EnumPayload, EnumValuePayload, ExtensionPayload,
+        FieldLabel, FieldPayload, FieldType, Import, ImportKind, MessageLiteralField,
+        MessagePayload, Method, NamedElement, NumberRange, OneOfPayload, OptionNamePart,
+        OptionStatement, Package, ProtoFile, Reserved, ScalarType, ServicePayload, Spanned,
+    },
+    diagnostic::Diagnostic,
     error::{Result, RsProtocError},
-    lexer::{self, TokenKind},
+    lexer::{self, Comment, IntegerLiteral, LexerPosition, Token, TokenKind, TokenMetadata},
+    source_text::SourceBuffer,
 };
-use std::collections::HashMap;
 
 // Package hierarchy
 
@@ -25,101 +32,1372 @@ use std::collections::HashMap;
 //    └─ Services
 //        └─ Methods
 
-enum ElementType {
-    Message(Vec<NamedElement>),
-    Field(FieldPayload),
-    OneOf(Vec<NamedElement>),
-    Enum,
-    EnumValue,
-    Extension,
-    Service(Vec<Method>),
-    Method,
-}
+/// Upper bound used for `max` in message reserved and extension ranges
+pub const MAX_FIELD_NUMBER: i64 = (1 << 29) - 1;
+/// Upper bound used for `max` in enum reserved ranges
+pub const MAX_ENUM_VALUE: i64 = i32::MAX as i64;
 
-struct NamedElement {
-    name: String,
-    type_t: ElementType,
+pub struct Parser<'a> {
+    /// Text being parsed, keywords used as names are spelled as written in it
+    source_text: &'a str,
+    lexer: lexer::Lexer<'a>,
+    /// Token peeked at or put back after it failed an expectation, recovery resynchronizes
+    /// starting from it
+    rewound: Option<Token<'a>>,
+    /// Metadata of the last token consumed, errors at the end of input point right after it
+    previous: Option<TokenMetadata>,
+    /// Leading and detached comments of the next declaration
+    upcoming: Comments,
+    /// Position right after the last token consumed
+    token_end: LexerPosition,
+    /// Value of `token_end` before the last token was consumed, restored when it is put back
+    previous_end: LexerPosition,
+    /// Number of message literal braces currently open, skipped past when the literal fails to parse
+    literal_depth: usize,
+    /// Whether the text is a document in the text format rather than a .proto file
+    text_format: bool,
+    errors: Vec<RsProtocError>,
 }
 
-pub struct Package {
-    named_elements: Vec<NamedElement>,
-}
+impl<'a> Parser<'a> {
+    pub fn new(source_text: &'a str) -> Parser<'a> {
+        Self::resume(source_text, &LexerPosition::default())
+    }
 
-struct FieldPayload {}
+    /// Create a parser for a registered file, the metadata of the AST carries the id of the file
+    pub fn for_buffer(source: &'a SourceBuffer) -> Parser<'a> {
+        let mut parser = Self::new(source.text());
+        parser.lexer = lexer::Lexer::for_buffer(source);
+        parser
+    }
 
-struct Method {}
+    fn peek_token(&mut self) -> Option<&Token<'a>> {
+        if self.rewound.is_none() {
+            self.rewound = self.lexer.next();
+        }
+        self.rewound.as_ref()
+    }
 
-pub type PackageMap = HashMap<String, Package>;
+    fn advance(&mut self) -> Option<Token<'a>> {
+        let token = self.rewound.take().or_else(|| self.lexer.next());
+        if let Some(token) = &token {
+            self.previous = Some(token.metadata.clone());
+            // The lexer has not moved on since producing the token, even when it was put back
+            self.previous_end =
+                std::mem::replace(&mut self.token_end, self.lexer.token_end().clone());
+        }
+        token
+    }
 
-pub struct Parser<'a> {
-    token_iterator: std::iter::Peekable<lexer::Lexer<'a>>,
-}
+    /// Put back a token that does not fit where it was found, so that recovery can resynchronize on it
+    fn reject(&mut self, token: Token<'a>, expected: &str) -> RsProtocError {
+        let error = unexpected_token(&token, expected);
+        self.put_back(token);
+        error
+    }
 
-impl<'a> Parser<'a> {
-    pub fn new(source_text: &str) -> Parser {
-        Parser {
-            token_iterator: lexer::Lexer::new(source_text).peekable(),
-        }
+    fn put_back(&mut self, token: Token<'a>) {
+        self.rewound = Some(token);
+        self.token_end = self.previous_end.clone();
     }
 
     fn consume(&mut self, expected_token_kind: &TokenKind) -> bool {
-        if let Some(token) = self.token_iterator.peek() {
+        if let Some(token) = self.peek_token() {
             if token.kind == *expected_token_kind {
-                _ = self.token_iterator.next();
+                _ = self.advance();
                 return true;
             }
         }
-        return false;
+        false
+    }
+
+    fn peek_kind(&mut self) -> Option<&TokenKind<'a>> {
+        self.peek_token().map(|token| &token.kind)
+    }
+
+    /// Metadata spanning from `start` to the end of the last token consumed
+    fn span_from(&self, start: &TokenMetadata) -> TokenMetadata {
+        let mut span = start.clone();
+        if let Some(previous) = &self.previous {
+            span.extend_to(previous);
+        }
+        span
+    }
+
+    /// Consume the token ending a declaration, its ";" or the "{" opening its body, and return
+    /// the comments of the declaration. Like in protoc, the comments up to the next token are
+    /// split between the trailing comment of this declaration and the comments of the next one.
+    fn end_declaration(
+        &mut self,
+        expected_token_kind: &TokenKind,
+        expected: &str,
+    ) -> Result<(Token<'a>, Comments)> {
+        let token = self.expect(expected_token_kind, expected)?;
+        Ok((token, self.collect_comments()))
+    }
+
+    fn collect_comments(&mut self) -> Comments {
+        _ = self.peek_token();
+        let next = self.lexer.take_token_comments();
+        let upcoming = std::mem::replace(
+            &mut self.upcoming,
+            Comments {
+                leading: next.leading,
+                trailing: None,
+                detached: next.detached,
+            },
+        );
+        Comments {
+            leading: upcoming.leading,
+            trailing: next.trailing,
+            detached: upcoming.detached,
+        }
+    }
+
+    /// Keep the comments after a token no declaration ends with, a "}" or an empty statement,
+    /// for the next declaration
+    fn carry_comments(&mut self, closes_scope: bool) {
+        _ = self.peek_token();
+        let next = self.lexer.take_token_comments();
+        self.upcoming.leading = next.leading;
+        if closes_scope {
+            self.upcoming.detached = next.detached;
+        } else {
+            self.upcoming.detached.extend(next.detached);
+        }
+    }
+
+    /// Consume the "}" closing a block
+    fn end_scope(&mut self) -> Result<TokenMetadata> {
+        let token = self.next_token("\"}\"")?;
+        self.carry_comments(true);
+        Ok(token.metadata)
+    }
+
+    fn empty_statement(&mut self) {
+        _ = self.advance();
+        self.carry_comments(false);
+    }
+
+    /// Return the next token, surfacing lexer errors and the end of input as errors
+    fn next_token(&mut self, expected: &str) -> Result<Token<'a>> {
+        match self.advance() {
+            Some(Token {
+                kind: TokenKind::Error(diagnostic),
+                ..
+            }) => Err(RsProtocError::LexError(diagnostic)),
+            Some(token) => Ok(token),
+            None => Err(self.end_of_input(expected)),
+        }
+    }
+
+    fn end_of_input(&self, expected: &str) -> RsProtocError {
+        let mut diagnostic = Diagnostic::error(format!(
+            "Expected {} but reached the end of input",
+            expected
+        ));
+        if let Some(previous) = &self.previous {
+            diagnostic = diagnostic
+                .with_primary_label(previous, format!("expected {} after this", expected));
+        }
+        RsProtocError::ParseError(Box::new(diagnostic))
+    }
+
+    /// Error for a block that is still open at the end of input
+    fn unclosed(&self, kind: &str, name: &Spanned<String>) -> RsProtocError {
+        let RsProtocError::ParseError(diagnostic) = self.end_of_input("\"}\"") else {
+            unreachable!()
+        };
+        RsProtocError::ParseError(Box::new((*diagnostic).with_secondary_label(
+            &name.metadata,
+            format!("{} \"{}\" starts here", kind, name.value),
+        )))
+    }
+
+    /// Record an error and skip ahead to where parsing can resume: past the end of the current
+    /// statement or nested block, or right before the "}" closing the enclosing block
+    fn recover(&mut self, error: RsProtocError) {
+        self.errors.push(error);
+        let mut depth = 0usize;
+        while let Some(kind) = self.peek_kind() {
+            match kind {
+                TokenKind::Semicolon if depth == 0 => {
+                    _ = self.advance();
+                    return;
+                }
+                TokenKind::RBrace if depth == 0 => return,
+                TokenKind::RBrace => {
+                    _ = self.advance();
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                    continue;
+                }
+                TokenKind::LBrace => depth += 1,
+                _ => {}
+            }
+            _ = self.advance();
+        }
+    }
+
+    fn expect(&mut self, expected_token_kind: &TokenKind, expected: &str) -> Result<Token<'a>> {
+        let token = self.next_token(expected)?;
+        if token.kind == *expected_token_kind {
+            Ok(token)
+        } else {
+            Err(self.reject(token, expected))
+        }
+    }
+
+    /// An identifier, or a keyword used as one
+    fn expect_identifier(&mut self, expected: &str) -> Result<Spanned<String>> {
+        let token = self.next_token(expected)?;
+        match token.name(self.source_text) {
+            Some(name) => Ok(Spanned {
+                value: name.to_string(),
+                metadata: token.metadata,
+            }),
+            None => Err(self.reject(token, expected)),
+        }
+    }
+
+    /// strLit = strLitSingle { strLitSingle }, adjacent string literals are concatenated
+    fn parse_string(&mut self, expected: &str) -> Result<Spanned<Vec<u8>>> {
+        let token = self.next_token(expected)?;
+        let TokenKind::StringLiteral(value) = &token.kind else {
+            return Err(self.reject(token, expected));
+        };
+        let mut string = Spanned {
+            value: value.as_bytes().to_vec(),
+            metadata: token.metadata,
+        };
+        while let Some(TokenKind::StringLiteral(_)) = self.peek_kind() {
+            let token = self.next_token(expected)?;
+            if let TokenKind::StringLiteral(value) = &token.kind {
+                string.value.extend_from_slice(value.as_bytes());
+                string.metadata.extend_to(&token.metadata);
+            }
+        }
+        Ok(string)
+    }
+
+    /// A string literal holding text, its escapes have to produce valid UTF-8
+    fn parse_text(&mut self, expected: &str) -> Result<Spanned<String>> {
+        let string = self.parse_string(expected)?;
+        match String::from_utf8(string.value) {
+            Ok(value) => Ok(Spanned {
+                value,
+                metadata: string.metadata,
+            }),
+            Err(_) => Err(invalid_utf8(&string.metadata)),
+        }
+    }
+
+    /// fullIdent = ident { "." ident }
+    fn parse_full_identifier(&mut self, expected: &str) -> Result<Spanned<String>> {
+        let first = self.expect_identifier(expected)?;
+        self.continue_full_identifier(first, expected)
+    }
+
+    /// The rest of a full identifier starting with `full_identifier`
+    fn continue_full_identifier(
+        &mut self,
+        mut full_identifier: Spanned<String>,
+        expected: &str,
+    ) -> Result<Spanned<String>> {
+        while self.consume(&TokenKind::Dot) {
+            let part = self.expect_identifier(expected)?;
+            full_identifier.value.push('.');
+            full_identifier.value.push_str(&part.value);
+        }
+        Ok(full_identifier)
+    }
+
+    /// typeName = [ "." ] fullIdent, spanning every part of the name
+    fn parse_type_name(&mut self) -> Result<Spanned<String>> {
+        if let Some(Token {
+            kind: TokenKind::Dot,
+            metadata,
+        }) = self.peek_token()
+        {
+            let metadata = metadata.clone();
+            _ = self.advance();
+            let full_identifier = self.parse_full_identifier("type name")?;
+            return Ok(Spanned {
+                value: format!(".{}", full_identifier.value),
+                metadata: self.span_from(&metadata),
+            });
+        }
+        let full_identifier = self.parse_full_identifier("type name")?;
+        Ok(Spanned {
+            metadata: self.span_from(&full_identifier.metadata),
+            value: full_identifier.value,
+        })
+    }
+
+    /// syntax = "syntax" "=" ( "'proto2'" | '"proto2"' | "'proto3'" | '"proto3"' ) ";", returns
+    /// the syntax and the span of the declaration
+    fn parse_syntax_declaration(&mut self) -> Result<(Spanned<String>, TokenMetadata)> {
+        let keyword = self.expect(&TokenKind::Syntax, "\"syntax\"")?;
+        self.expect(&TokenKind::Equals, "\"=\"")?;
+        let syntax = self.parse_text("\"proto3\"")?;
+        if syntax.value != "proto2" && syntax.value != "proto3" {
+            return Err(RsProtocError::ParseError(Box::new(
+                Diagnostic::error(format!("Unsupported syntax \"{}\"", syntax.value))
+                    .with_primary_label(&syntax.metadata, "")
+                    .with_note("only proto2 and proto3 files are supported"),
+            )));
+        }
+        self.end_declaration(&TokenKind::Semicolon, "\";\"")?;
+        Ok((syntax, self.span_from(&keyword.metadata)))
+    }
+
+    /// Parse the whole file, reporting every error found rather than stopping at the first one
+    pub fn parse(&mut self) -> std::result::Result<ProtoFile, Vec<RsProtocError>> {
+        let mut file = ProtoFile {
+            syntax: None,
+            syntax_span: None,
+            imports: Vec::new(),
+            options: Vec::new(),
+            package: Package {
+                name: None,
+                span: None,
+                comments: Comments::default(),
+                named_elements: Vec::new(),
+            },
+            comments: Vec::new(),
+        };
+        self.begin();
+        let mut first = true;
+        while let Some(statement) = self.parse_statement(first) {
+            first = false;
+            match statement {
+                Statement::Syntax(syntax, span) => {
+                    file.syntax = Some(syntax);
+                    file.syntax_span = Some(span);
+                }
+                Statement::Package(package) => {
+                    if let Some(error) = set_package(&mut file.package, package) {
+                        self.errors.push(error);
+                    }
+                }
+                Statement::Import(import) => file.imports.push(import),
+                Statement::Option(option) => file.options.push(option),
+                Statement::Element(element) => file.package.named_elements.push(element),
+                Statement::Empty => {}
+            }
+        }
+        if first {
+            self.missing_syntax();
+        }
+        file.comments = self.lexer.take_comments();
+        if self.errors.is_empty() {
+            Ok(file)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Create a parser that starts at `position` of the source text, between two top-level
+    /// statements
+    pub(crate) fn resume(source_text: &'a str, position: &LexerPosition) -> Parser<'a> {
+        Parser {
+            source_text,
+            lexer: lexer::Lexer::resume(source_text, position),
+            rewound: None,
+            previous: None,
+            upcoming: Comments::default(),
+            token_end: position.clone(),
+            previous_end: position.clone(),
+            literal_depth: 0,
+            text_format: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Create a parser for a document in the protobuf text format
+    pub(crate) fn for_text_format(source_text: &'a str) -> Parser<'a> {
+        let mut parser = Self::new(source_text);
+        parser.lexer = lexer::Lexer::for_text_format(source_text);
+        parser.text_format = true;
+        parser
+    }
+
+    /// Parse a text format document, the fields of a message up to the end of input. Parsing
+    /// stops at the first error, the rest of the document cannot be trusted to line up with the
+    /// schema after it.
+    pub(crate) fn parse_text_format(
+        &mut self,
+    ) -> std::result::Result<Vec<MessageLiteralField>, Vec<RsProtocError>> {
+        let mut fields = Vec::new();
+        while self.peek_token().is_some() {
+            match self.parse_message_literal_field() {
+                Ok(field) => fields.push(field),
+                Err(error) => {
+                    self.errors.push(error);
+                    break;
+                }
+            }
+            if !self.consume(&TokenKind::Comma) {
+                _ = self.consume(&TokenKind::Semicolon);
+            }
+        }
+        if self.errors.is_empty() {
+            Ok(fields)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    /// Comments before the first token lead the first declaration
+    pub(crate) fn begin(&mut self) {
+        _ = self.peek_token();
+        let first = self.lexer.take_token_comments();
+        self.upcoming = Comments {
+            leading: first.leading,
+            trailing: None,
+            detached: first.detached,
+        };
+    }
+
+    /// Parse the next top-level statement, `None` at the end of input. Errors are recorded
+    /// for `take_errors`, statements that fail to parse are skipped and come out as empty.
+    pub(crate) fn parse_statement(&mut self, first: bool) -> Option<Statement> {
+        let kind = self.peek_kind()?;
+        if first && *kind != TokenKind::Syntax {
+            self.missing_syntax();
+        }
+        let result = match self.peek_kind()? {
+            TokenKind::Syntax if first => self
+                .parse_syntax_declaration()
+                .map(|(syntax, span)| Statement::Syntax(syntax, span)),
+            TokenKind::Package => self.parse_package(),
+            TokenKind::Import => self.parse_import().map(Statement::Import),
+            TokenKind::Option => self.parse_option_statement().map(Statement::Option),
+            TokenKind::Message => self.parse_message().map(Statement::Element),
+            TokenKind::Enum => self.parse_enum().map(Statement::Element),
+            TokenKind::Service => self.parse_service().map(Statement::Element),
+            TokenKind::Extend => self.parse_extend().map(Statement::Element),
+            TokenKind::Semicolon => {
+                self.empty_statement();
+                Ok(Statement::Empty)
+            }
+            _ => self
+                .next_token("top-level declaration")
+                .and_then(|token| Err(unexpected_token(&token, "top-level declaration"))),
+        };
+        Some(result.unwrap_or_else(|error| {
+            self.recover(error);
+            Statement::Empty
+        }))
+    }
+
+    /// Position right after the last token consumed
+    pub(crate) fn token_end(&self) -> &LexerPosition {
+        &self.token_end
+    }
+
+    pub(crate) fn take_errors(&mut self) -> Vec<RsProtocError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    pub(crate) fn take_comments(&mut self) -> Vec<Comment> {
+        self.lexer.take_comments()
+    }
+
+    pub(crate) fn missing_syntax(&mut self) {
+        let mut diagnostic = Diagnostic::error("Missing syntax declaration")
+            .with_help("start the file with `syntax = \"proto3\";`");
+        if let Some(token) = self.peek_token() {
+            diagnostic = diagnostic.with_primary_label(&token.metadata, "");
+        }
+        self.errors
+            .push(RsProtocError::ParseError(Box::new(diagnostic)));
+    }
+
+    /// package = "package" fullIdent ";"
+    fn parse_package(&mut self) -> Result<Statement> {
+        let token = self.expect(&TokenKind::Package, "\"package\"")?;
+        let name = self.parse_full_identifier("package name")?;
+        let (_, comments) = self.end_declaration(&TokenKind::Semicolon, "\";\"")?;
+        Ok(Statement::Package(PackageStatement {
+            span: self.span_from(&token.metadata),
+            keyword: token.metadata,
+            name,
+            comments,
+        }))
+    }
+
+    /// import = "import" [ "weak" | "public" ] strLit ";"
+    fn parse_import(&mut self) -> Result<Import> {
+        let keyword = self.expect(&TokenKind::Import, "\"import\"")?;
+        let kind = if self.consume(&TokenKind::Weak) {
+            ImportKind::Weak
+        } else if self.consume(&TokenKind::Public) {
+            ImportKind::Public
+        } else {
+            ImportKind::Default
+        };
+        let path = self.parse_text("import path")?;
+        let (_, comments) = self.end_declaration(&TokenKind::Semicolon, "\";\"")?;
+        Ok(Import {
+            kind,
+            path,
+            span: self.span_from(&keyword.metadata),
+            comments,
+        })
+    }
+
+    /// option = "option" optionName "=" constant ";"
+    fn parse_option_statement(&mut self) -> Result<OptionStatement> {
+        let keyword = self.expect(&TokenKind::Option, "\"option\"")?;
+        let mut option = self.parse_option_body()?;
+        self.end_declaration(&TokenKind::Semicolon, "\";\"")?;
+        option.span = self.span_from(&keyword.metadata);
+        Ok(option)
+    }
+
+    /// optionName "=" constant
+    fn parse_option_body(&mut self) -> Result<OptionStatement> {
+        let name = self.parse_option_name()?;
+        self.expect(&TokenKind::Equals, "\"=\"")?;
+        let value = self.parse_constant()?;
+        let span = self.span_from(&name.metadata);
+        Ok(OptionStatement { name, value, span })
+    }
+
+    /// optionName = ( ident | "(" typeName ")" ) { "." ( ident | "(" typeName ")" ) }
+    fn parse_option_name(&mut self) -> Result<Spanned<Vec<OptionNamePart>>> {
+        let mut parts = Vec::new();
+        let mut metadata = None;
+        loop {
+            let token = self.next_token("option name")?;
+            if metadata.is_none() {
+                metadata = Some(token.metadata.clone());
+            }
+            if let Some(name) = token.name(self.source_text) {
+                parts.push(OptionNamePart::Simple(name.to_string()));
+            } else if token.kind == TokenKind::LParen {
+                let type_name = self.parse_type_name()?;
+                self.expect(&TokenKind::RParen, "\")\"")?;
+                parts.push(OptionNamePart::Extension(type_name.value));
+            } else {
+                return Err(self.reject(token, "option name"));
+            }
+            if !self.consume(&TokenKind::Dot) {
+                break;
+            }
+        }
+        Ok(Spanned {
+            value: parts,
+            metadata: metadata.unwrap(), // SAFETY: The loop above runs at least once
+        })
+    }
+
+    /// constant = fullIdent | ( [ "-" | "+" ] intLit ) | ( [ "-" | "+" ] floatLit ) | strLit | boolLit
+    ///     | messageValue
+    fn parse_constant(&mut self) -> Result<Spanned<Constant>> {
+        let token = self.next_token("constant")?;
+        let mut metadata = token.metadata.clone();
+        let value = match token.kind {
+            TokenKind::Minus | TokenKind::Plus => {
+                let negate = token.kind == TokenKind::Minus;
+                let number_token = self.next_token("numeric literal")?;
+                let value = match &number_token.kind {
+                    TokenKind::IntegerLiteral(literal) => integer_constant(literal, negate),
+                    TokenKind::FloatLiteral(literal) if negate => Constant::Float(-literal.value()),
+                    TokenKind::FloatLiteral(literal) => Constant::Float(literal.value()),
+                    TokenKind::Inf if negate => Constant::Float(f64::NEG_INFINITY),
+                    TokenKind::Inf => Constant::Float(f64::INFINITY),
+                    TokenKind::Identifier(identifier) if identifier == "nan" => {
+                        Constant::Float(f64::NAN)
+                    }
+                    // The text format spells these in any case, and "infinity" in full
+                    TokenKind::Identifier(identifier) if self.text_format => {
+                        match identifier.to_ascii_lowercase().as_str() {
+                            "inf" | "infinity" if negate => Constant::Float(f64::NEG_INFINITY),
+                            "inf" | "infinity" => Constant::Float(f64::INFINITY),
+                            "nan" => Constant::Float(f64::NAN),
+                            _ => return Err(self.reject(number_token, "numeric literal")),
+                        }
+                    }
+                    _ => return Err(self.reject(number_token, "numeric literal")),
+                };
+                metadata.extend_to(&number_token.metadata);
+                value
+            }
+            TokenKind::IntegerLiteral(literal) => integer_constant(&literal, false),
+            TokenKind::FloatLiteral(literal) => Constant::Float(literal.value()),
+            TokenKind::Inf => Constant::Float(f64::INFINITY),
+            TokenKind::StringLiteral(_) => {
+                self.put_back(token);
+                let string = self.parse_string("constant")?;
+                let value = match String::from_utf8(string.value) {
+                    Ok(text) => Constant::String(text),
+                    Err(error) => Constant::Bytes(error.into_bytes()),
+                };
+                return Ok(Spanned {
+                    value,
+                    metadata: string.metadata,
+                });
+            }
+            TokenKind::Identifier(ref identifier) if identifier == "true" => Constant::Bool(true),
+            TokenKind::Identifier(ref identifier) if identifier == "false" => Constant::Bool(false),
+            TokenKind::LBrace => {
+                self.put_back(token);
+                return match self.parse_message_value() {
+                    Ok(value) => Ok(value),
+                    Err(error) => {
+                        self.skip_message_literal();
+                        Err(error)
+                    }
+                };
+            }
+            _ => match token.name(self.source_text) {
+                Some(name) => {
+                    let first = Spanned {
+                        value: name.to_string(),
+                        metadata: token.metadata,
+                    };
+                    let full_identifier = self.continue_full_identifier(first, "identifier")?;
+                    Constant::Identifier(full_identifier.value)
+                }
+                None => return Err(self.reject(token, "constant")),
+            },
+        };
+        Ok(Spanned { value, metadata })
+    }
+
+    /// messageValue = ( "{" messageLiteral "}" ) | ( "<" messageLiteral ">" )
+    fn parse_message_value(&mut self) -> Result<Spanned<Constant>> {
+        let token = self.next_token("\"{\"")?;
+        let close = match token.kind {
+            TokenKind::LBrace => TokenKind::RBrace,
+            TokenKind::LAngle => TokenKind::RAngle,
+            _ => return Err(self.reject(token, "\"{\"")),
+        };
+        self.literal_depth += 1;
+        let mut fields = Vec::new();
+        let expected = format!("field name or \"{}\"", close);
+        loop {
+            let token = self.next_token(&expected)?;
+            if token.kind == close {
+                break;
+            }
+            self.put_back(token);
+            fields.push(self.parse_message_literal_field()?);
+            if !self.consume(&TokenKind::Comma) {
+                _ = self.consume(&TokenKind::Semicolon);
+            }
+        }
+        self.literal_depth -= 1;
+        Ok(Spanned {
+            value: Constant::Message(fields),
+            metadata: token.metadata,
+        })
+    }
+
+    /// messageLiteralField = fieldName ( ( ":" value ) | ( [ ":" ] messageValue ) )
+    /// fieldName = ident | "[" typeName "]" | "[" typeName "/" typeName "]"
+    fn parse_message_literal_field(&mut self) -> Result<MessageLiteralField> {
+        let token = self.next_token("field name")?;
+        let name = if let Some(name) = token.name(self.source_text) {
+            Spanned {
+                value: OptionNamePart::Simple(name.to_string()),
+                metadata: token.metadata,
+            }
+        } else if token.kind == TokenKind::LBracket {
+            let mut type_name = self.parse_type_name()?;
+            // The type URL of an expanded `Any`, such as `type.googleapis.com/pkg.Message`
+            if self.consume(&TokenKind::Slash) {
+                let message_name = self.parse_type_name()?;
+                type_name.value = format!("{}/{}", type_name.value, message_name.value);
+            }
+            self.expect(&TokenKind::RBracket, "\"]\"")?;
+            Spanned {
+                value: OptionNamePart::Extension(type_name.value),
+                metadata: token.metadata,
+            }
+        } else {
+            return Err(self.reject(token, "field name"));
+        };
+        let has_colon = self.consume(&TokenKind::Colon);
+        let value = match self.peek_kind() {
+            Some(TokenKind::LBrace | TokenKind::LAngle) => self.parse_message_value()?,
+            Some(TokenKind::LBracket) if has_colon => self.parse_list_value()?,
+            _ if has_colon => self.parse_constant()?,
+            _ => {
+                let token = self.next_token("\":\"")?;
+                return Err(self.reject(token, "\":\""));
+            }
+        };
+        Ok(MessageLiteralField { name, value })
+    }
+
+    /// list = "[" [ listValue { "," listValue } ] "]"
+    /// listValue = constant | messageValue
+    fn parse_list_value(&mut self) -> Result<Spanned<Constant>> {
+        let token = self.expect(&TokenKind::LBracket, "\"[\"")?;
+        let mut values = Vec::new();
+        if !self.consume(&TokenKind::RBracket) {
+            loop {
+                values.push(match self.peek_kind() {
+                    Some(TokenKind::LBrace | TokenKind::LAngle) => self.parse_message_value()?,
+                    _ => self.parse_constant()?,
+                });
+                if !self.consume(&TokenKind::Comma) {
+                    break;
+                }
+            }
+            self.expect(&TokenKind::RBracket, "\"]\"")?;
+        }
+        Ok(Spanned {
+            value: Constant::List(values),
+            metadata: token.metadata,
+        })
     }
 
-    fn consume_multiple(&mut self, expected_tokens: &[TokenKind]) -> bool {
-        for token in expected_tokens {
-            if !self.consume(token) {
-                return false;
+    /// Skip the rest of a message literal that failed to parse, so that recovery resumes after it
+    fn skip_message_literal(&mut self) {
+        let mut depth = std::mem::take(&mut self.literal_depth);
+        while depth > 0 {
+            let Some(token) = self.advance() else {
+                return;
+            };
+            match token.kind {
+                TokenKind::LBrace | TokenKind::LAngle => depth += 1,
+                TokenKind::RBrace | TokenKind::RAngle => depth -= 1,
+                _ => {}
             }
         }
-        return true;
     }
 
-    fn consume_syntax_declaration(&mut self) -> Result<()> {
-        // "Should be: "syntax = "proto3""
-        if !self.consume_multiple(&[TokenKind::Syntax, TokenKind::Equals]) {
-            return Err(crate::error::RsProtocError::ParseError(
-                "Expected syntax declaration of the form: \"syntax = proto3\"".to_string(),
-            ));
+    /// fieldOptions = "[" fieldOption { ","  fieldOption } "]", returns the options along with
+    /// the span of the brackets
+    fn parse_compact_options(&mut self) -> Result<(Vec<OptionStatement>, Option<TokenMetadata>)> {
+        let mut options = Vec::new();
+        let Some(Token {
+            kind: TokenKind::LBracket,
+            metadata,
+        }) = self.peek_token()
+        else {
+            return Ok((options, None));
+        };
+        let open = metadata.clone();
+        _ = self.advance();
+        loop {
+            options.push(self.parse_option_body()?);
+            if !self.consume(&TokenKind::Comma) {
+                break;
+            }
         }
-        if let Some(token) = self.token_iterator.peek() {
-            if let TokenKind::StringLiteral(string_literal) = &token.kind {
-                if string_literal != "proto3" {
-                    return Err(crate::error::RsProtocError::ParseError(
-                        "Expected syntax declaration of the form: \"syntax = proto3\"".to_string(),
-                    ));
+        self.expect(&TokenKind::RBracket, "\"]\"")?;
+        Ok((options, Some(self.span_from(&open))))
+    }
+
+    /// message = "message" messageName messageBody
+    fn parse_message(&mut self) -> Result<NamedElement> {
+        let keyword = self.expect(&TokenKind::Message, "\"message\"")?;
+        let name = self.expect_identifier("message name")?;
+        let (_, comments) = self.end_declaration(&TokenKind::LBrace, "\"{\"")?;
+        let mut message = MessagePayload {
+            named_elements: Vec::new(),
+            reserved: Vec::new(),
+            extension_ranges: Vec::new(),
+            options: Vec::new(),
+        };
+        let end = loop {
+            let result = match self.peek_kind() {
+                Some(TokenKind::RBrace) => break self.end_scope()?,
+                Some(TokenKind::Semicolon) => {
+                    self.empty_statement();
+                    Ok(())
                 }
+                Some(TokenKind::Message) => self
+                    .parse_message()
+                    .map(|element| message.named_elements.push(element)),
+                Some(TokenKind::Enum) => self
+                    .parse_enum()
+                    .map(|element| message.named_elements.push(element)),
+                Some(TokenKind::Extend) => self
+                    .parse_extend()
+                    .map(|element| message.named_elements.push(element)),
+                Some(TokenKind::OneOf) => self
+                    .parse_oneof()
+                    .map(|element| message.named_elements.push(element)),
+                Some(TokenKind::Option) => self
+                    .parse_option_statement()
+                    .map(|option| message.options.push(option)),
+                Some(TokenKind::Reserved) => self
+                    .parse_reserved(MAX_FIELD_NUMBER)
+                    .map(|mut reserved| message.reserved.append(&mut reserved)),
+                Some(TokenKind::Extensions) => self
+                    .parse_extensions()
+                    .map(|mut ranges| message.extension_ranges.append(&mut ranges)),
+                Some(_) => self
+                    .parse_field()
+                    .map(|element| message.named_elements.push(element)),
+                None => return Err(self.unclosed("message", &name)),
+            };
+            if let Err(error) = result {
+                self.recover(error);
             }
-            _ = self.token_iterator.next();
+        };
+        Ok(NamedElement {
+            name: name.value,
+            metadata: name.metadata,
+            start: keyword.metadata,
+            end,
+            comments,
+            type_t: ElementType::Message(message),
+        })
+    }
+
+    /// extensions = "extensions" ranges [ "[" fieldOptions "]" ] ";"
+    fn parse_extensions(&mut self) -> Result<Vec<Spanned<NumberRange>>> {
+        self.expect(&TokenKind::Extensions, "\"extensions\"")?;
+        let ranges = self.parse_ranges(MAX_FIELD_NUMBER)?;
+        _ = self.parse_compact_options()?;
+        self.end_declaration(&TokenKind::Semicolon, "\";\"")?;
+        Ok(ranges)
+    }
+
+    /// field = [ "repeated" | "optional" | "required" ] type fieldName "=" fieldNumber [ "[" fieldOptions "]" ] ";"
+    /// mapField = "map" "<" keyType "," type ">" mapName "=" fieldNumber [ "[" fieldOptions "]" ] ";"
+    fn parse_field(&mut self) -> Result<NamedElement> {
+        let label = match self.peek_kind() {
+            Some(TokenKind::Optional) => FieldLabel::Optional,
+            Some(TokenKind::Repeated) => FieldLabel::Repeated,
+            Some(TokenKind::Required) => FieldLabel::Required,
+            _ => FieldLabel::None,
+        };
+        let label_token = if label != FieldLabel::None {
+            self.advance()
         } else {
-            return Err(crate::error::RsProtocError::ParseError(
-                "Expected syntax declaration of the form: \"syntax = proto3\"".to_string(),
-            ));
+            None
+        };
+        let map_token = match self.peek_kind() {
+            Some(TokenKind::Map) => self.advance(),
+            _ => None,
+        };
+        let start = label_token
+            .as_ref()
+            .or(map_token.as_ref())
+            .map(|token| token.metadata.clone());
+        let field_type = if let Some(map_token) = map_token {
+            if self.peek_kind() != Some(&TokenKind::LAngle) {
+                // Not a map field but a message named "map"
+                let first = Spanned {
+                    value: "map".to_string(),
+                    metadata: map_token.metadata,
+                };
+                let type_name = self.continue_full_identifier(first, "type name")?;
+                Spanned {
+                    value: FieldType::Named(type_name.value),
+                    metadata: type_name.metadata,
+                }
+            } else if let Some(label_token) = label_token {
+                return Err(RsProtocError::ParseError(Box::new(
+                    Diagnostic::error("Map fields cannot have a label")
+                        .with_primary_label(&label_token.metadata, "")
+                        .with_help("remove the label, map fields are implicitly repeated"),
+                )));
+            } else {
+                self.expect(&TokenKind::LAngle, "\"<\"")?;
+                let key_type = self.parse_field_type()?;
+                self.expect(&TokenKind::Comma, "\",\"")?;
+                let value_type = self.parse_field_type()?;
+                self.expect(&TokenKind::RAngle, "\">\"")?;
+                Spanned {
+                    value: FieldType::Map(Box::new(key_type), Box::new(value_type)),
+                    metadata: self.span_from(&map_token.metadata),
+                }
+            }
+        } else {
+            self.parse_field_type()?
+        };
+        let name = self.expect_identifier("field name")?;
+        self.expect(&TokenKind::Equals, "\"=\"")?;
+        let number_token = self.next_token("field number")?;
+        let number = match &number_token.kind {
+            TokenKind::IntegerLiteral(literal) => {
+                match literal.value().and_then(|value| u64::try_from(value).ok()) {
+                    Some(value) => Spanned {
+                        value,
+                        metadata: number_token.metadata,
+                    },
+                    None => return Err(out_of_range(literal.text(), &number_token.metadata)),
+                }
+            }
+            _ => return Err(self.reject(number_token, "field number")),
+        };
+        let (options, options_span) = self.parse_compact_options()?;
+        let (end, comments) = self.end_declaration(&TokenKind::Semicolon, "\";\"")?;
+        Ok(NamedElement {
+            name: name.value,
+            metadata: name.metadata,
+            start: start.unwrap_or_else(|| field_type.metadata.clone()),
+            end: end.metadata,
+            comments,
+            type_t: ElementType::Field(FieldPayload {
+                label,
+                field_type,
+                number,
+                options,
+                options_span,
+            }),
+        })
+    }
+
+    fn parse_field_type(&mut self) -> Result<Spanned<FieldType>> {
+        let scalar_type = match self.peek_kind() {
+            Some(TokenKind::Double) => Some(ScalarType::Double),
+            Some(TokenKind::Float) => Some(ScalarType::Float),
+            Some(TokenKind::Int32) => Some(ScalarType::Int32),
+            Some(TokenKind::Int64) => Some(ScalarType::Int64),
+            Some(TokenKind::Uint32) => Some(ScalarType::Uint32),
+            Some(TokenKind::Uint64) => Some(ScalarType::Uint64),
+            Some(TokenKind::Sint32) => Some(ScalarType::Sint32),
+            Some(TokenKind::Sint64) => Some(ScalarType::Sint64),
+            Some(TokenKind::Fixed32) => Some(ScalarType::Fixed32),
+            Some(TokenKind::Fixed64) => Some(ScalarType::Fixed64),
+            Some(TokenKind::SFixed32) => Some(ScalarType::SFixed32),
+            Some(TokenKind::SFixed64) => Some(ScalarType::SFixed64),
+            Some(TokenKind::Bool) => Some(ScalarType::Bool),
+            Some(TokenKind::String) => Some(ScalarType::String),
+            Some(TokenKind::Bytes) => Some(ScalarType::Bytes),
+            _ => None,
+        };
+        if let Some(scalar_type) = scalar_type {
+            let token = self.next_token("field type")?;
+            return Ok(Spanned {
+                value: FieldType::Scalar(scalar_type),
+                metadata: token.metadata,
+            });
+        }
+        let type_name = self.parse_type_name()?;
+        Ok(Spanned {
+            value: FieldType::Named(type_name.value),
+            metadata: type_name.metadata,
+        })
+    }
+
+    /// oneof = "oneof" oneofName "{" { option | oneofField } "}"
+    fn parse_oneof(&mut self) -> Result<NamedElement> {
+        let keyword = self.expect(&TokenKind::OneOf, "\"oneof\"")?;
+        let name = self.expect_identifier("oneof name")?;
+        let (_, comments) = self.end_declaration(&TokenKind::LBrace, "\"{\"")?;
+        let mut oneof = OneOfPayload {
+            fields: Vec::new(),
+            options: Vec::new(),
+        };
+        let end = loop {
+            let result = match self.peek_kind() {
+                Some(TokenKind::RBrace) => break self.end_scope()?,
+                Some(TokenKind::Semicolon) => {
+                    self.empty_statement();
+                    Ok(())
+                }
+                Some(TokenKind::Option) => self
+                    .parse_option_statement()
+                    .map(|option| oneof.options.push(option)),
+                Some(TokenKind::Optional | TokenKind::Repeated | TokenKind::Required) => {
+                    self.next_token("oneof field").and_then(|token| {
+                        Err(RsProtocError::ParseError(Box::new(
+                            Diagnostic::error("Fields in oneofs must not have labels")
+                                .with_primary_label(&token.metadata, "")
+                                .with_secondary_label(&name.metadata, "in this oneof")
+                                .with_help("remove the label"),
+                        )))
+                    })
+                }
+                Some(_) => self.parse_field().map(|field| oneof.fields.push(field)),
+                None => return Err(self.unclosed("oneof", &name)),
+            };
+            if let Err(error) = result {
+                self.recover(error);
+            }
+        };
+        Ok(NamedElement {
+            name: name.value,
+            metadata: name.metadata,
+            start: keyword.metadata,
+            end,
+            comments,
+            type_t: ElementType::OneOf(oneof),
+        })
+    }
+
+    /// enum = "enum" enumName "{" { option | enumField | reserved } "}"
+    fn parse_enum(&mut self) -> Result<NamedElement> {
+        let keyword = self.expect(&TokenKind::Enum, "\"enum\"")?;
+        let name = self.expect_identifier("enum name")?;
+        let (_, comments) = self.end_declaration(&TokenKind::LBrace, "\"{\"")?;
+        let mut enum_payload = EnumPayload {
+            values: Vec::new(),
+            reserved: Vec::new(),
+            options: Vec::new(),
+        };
+        let end = loop {
+            let result = match self.peek_kind() {
+                Some(TokenKind::RBrace) => break self.end_scope()?,
+                Some(TokenKind::Semicolon) => {
+                    self.empty_statement();
+                    Ok(())
+                }
+                Some(TokenKind::Option) => self
+                    .parse_option_statement()
+                    .map(|option| enum_payload.options.push(option)),
+                Some(TokenKind::Reserved) => self
+                    .parse_reserved(MAX_ENUM_VALUE)
+                    .map(|mut reserved| enum_payload.reserved.append(&mut reserved)),
+                Some(_) => self
+                    .parse_enum_value()
+                    .map(|value| enum_payload.values.push(value)),
+                None => return Err(self.unclosed("enum", &name)),
+            };
+            if let Err(error) = result {
+                self.recover(error);
+            }
+        };
+        Ok(NamedElement {
+            name: name.value,
+            metadata: name.metadata,
+            start: keyword.metadata,
+            end,
+            comments,
+            type_t: ElementType::Enum(enum_payload),
+        })
+    }
+
+    /// enumField = ident "=" [ "-" ] intLit [ "[" enumValueOption { ","  enumValueOption } "]" ]";"
+    fn parse_enum_value(&mut self) -> Result<NamedElement> {
+        let name = self.expect_identifier("enum value name")?;
+        self.expect(&TokenKind::Equals, "\"=\"")?;
+        let number = self.parse_signed_integer("enum value number")?;
+        let (options, options_span) = self.parse_compact_options()?;
+        let (end, comments) = self.end_declaration(&TokenKind::Semicolon, "\";\"")?;
+        Ok(NamedElement {
+            name: name.value,
+            start: name.metadata.clone(),
+            metadata: name.metadata,
+            end: end.metadata,
+            comments,
+            type_t: ElementType::EnumValue(EnumValuePayload {
+                number,
+                options,
+                options_span,
+            }),
+        })
+    }
+
+    fn parse_signed_integer(&mut self, expected: &str) -> Result<Spanned<i64>> {
+        let token = self.next_token(expected)?;
+        let mut metadata = token.metadata.clone();
+        let (negate, token) = match token.kind {
+            TokenKind::Minus => (true, self.next_token(expected)?),
+            _ => (false, token),
+        };
+        let TokenKind::IntegerLiteral(literal) = &token.kind else {
+            return Err(self.reject(token, expected));
+        };
+        metadata.extend_to(&token.metadata);
+        let value = literal
+            .value()
+            .and_then(|value| i128::try_from(value).ok())
+            .map(|value| if negate { -value } else { value })
+            .and_then(|value| i64::try_from(value).ok());
+        match value {
+            Some(value) => Ok(Spanned { value, metadata }),
+            None if negate => Err(out_of_range(&format!("-{}", literal.text()), &metadata)),
+            None => Err(out_of_range(literal.text(), &metadata)),
+        }
+    }
+
+    /// ranges = range { "," range }
+    /// range =  intLit [ "to" ( intLit | "max" ) ]
+    fn parse_ranges(&mut self, max: i64) -> Result<Vec<Spanned<NumberRange>>> {
+        let mut ranges = Vec::new();
+        loop {
+            let start = self.parse_signed_integer("range start")?;
+            let end = if self.consume(&TokenKind::To) {
+                if self.consume(&TokenKind::Max) {
+                    max
+                } else {
+                    self.parse_signed_integer("range end")?.value
+                }
+            } else {
+                start.value
+            };
+            if end < start.value {
+                // The statement is still well formed, keep parsing it
+                self.errors.push(RsProtocError::ParseError(Box::new(
+                    Diagnostic::error(format!(
+                        "Range end {} is smaller than the range start {}",
+                        end, start.value
+                    ))
+                    .with_primary_label(&start.metadata, ""),
+                )));
+            } else {
+                ranges.push(Spanned {
+                    value: NumberRange {
+                        start: start.value,
+                        end,
+                    },
+                    metadata: start.metadata,
+                });
+            }
+            if !self.consume(&TokenKind::Comma) {
+                break;
+            }
         }
-        if !self.consume(&TokenKind::Semicolon) {
-            return Err(crate::error::RsProtocError::ParseError(
-                "Expected syntax declaration of the form: \"syntax = proto3\"".to_string(),
-            ));
+        Ok(ranges)
+    }
+
+    /// reserved = "reserved" ( ranges | strFieldNames ) ";"
+    fn parse_reserved(&mut self, max: i64) -> Result<Vec<Reserved>> {
+        self.expect(&TokenKind::Reserved, "\"reserved\"")?;
+        let reserved = if let Some(TokenKind::StringLiteral(_)) = self.peek_kind() {
+            let mut names = Vec::new();
+            loop {
+                names.push(Reserved::Name(self.parse_text("reserved name")?));
+                if !self.consume(&TokenKind::Comma) {
+                    break;
+                }
+            }
+            names
+        } else {
+            self.parse_ranges(max)?
+                .into_iter()
+                .map(Reserved::Range)
+                .collect()
+        };
+        self.end_declaration(&TokenKind::Semicolon, "\";\"")?;
+        Ok(reserved)
+    }
+
+    /// extend = "extend" messageType "{" {field | ";"} "}"
+    fn parse_extend(&mut self) -> Result<NamedElement> {
+        let keyword = self.expect(&TokenKind::Extend, "\"extend\"")?;
+        let extendee = self.parse_type_name()?;
+        let (_, comments) = self.end_declaration(&TokenKind::LBrace, "\"{\"")?;
+        let mut fields = Vec::new();
+        let end = loop {
+            let result = match self.peek_kind() {
+                Some(TokenKind::RBrace) => break self.end_scope()?,
+                Some(TokenKind::Semicolon) => {
+                    self.empty_statement();
+                    Ok(())
+                }
+                Some(_) => self.parse_field().map(|field| fields.push(field)),
+                None => return Err(self.unclosed("extend", &extendee)),
+            };
+            if let Err(error) = result {
+                self.recover(error);
+            }
+        };
+        Ok(NamedElement {
+            name: extendee.value,
+            metadata: extendee.metadata,
+            start: keyword.metadata,
+            end,
+            comments,
+            type_t: ElementType::Extension(ExtensionPayload { fields }),
+        })
+    }
+
+    /// service = "service" serviceName "{" { option | rpc | ";" } "}"
+    fn parse_service(&mut self) -> Result<NamedElement> {
+        let keyword = self.expect(&TokenKind::Service, "\"service\"")?;
+        let name = self.expect_identifier("service name")?;
+        let (_, comments) = self.end_declaration(&TokenKind::LBrace, "\"{\"")?;
+        let mut service = ServicePayload {
+            methods: Vec::new(),
+            options: Vec::new(),
+        };
+        let end = loop {
+            let result = match self.peek_kind() {
+                Some(TokenKind::RBrace) => break self.end_scope()?,
+                Some(TokenKind::Semicolon) => {
+                    self.empty_statement();
+                    Ok(())
+                }
+                Some(TokenKind::Option) => self
+                    .parse_option_statement()
+                    .map(|option| service.options.push(option)),
+                Some(TokenKind::Rpc) => self.parse_rpc().map(|method| service.methods.push(method)),
+                Some(_) => self
+                    .next_token("\"rpc\"")
+                    .and_then(|token| Err(unexpected_token(&token, "\"rpc\" or \"option\""))),
+                None => return Err(self.unclosed("service", &name)),
+            };
+            if let Err(error) = result {
+                self.recover(error);
+            }
+        };
+        Ok(NamedElement {
+            name: name.value,
+            metadata: name.metadata,
+            start: keyword.metadata,
+            end,
+            comments,
+            type_t: ElementType::Service(service),
+        })
+    }
+
+    /// "(" [ "stream" ] messageType ")", returns whether the type is streamed
+    fn parse_method_type(&mut self) -> Result<(bool, Spanned<String>)> {
+        self.expect(&TokenKind::LParen, "\"(\"")?;
+        let stream_token = match self.peek_kind() {
+            Some(TokenKind::Stream) => self.advance(),
+            _ => None,
+        };
+        let method_type = match stream_token {
+            // A message named "stream", "stream.Foo" streams ".Foo" like in protoc
+            Some(token) if self.peek_kind() == Some(&TokenKind::RParen) => {
+                let name = Spanned {
+                    value: "stream".to_string(),
+                    metadata: token.metadata,
+                };
+                (false, name)
+            }
+            stream_token => (stream_token.is_some(), self.parse_type_name()?),
+        };
+        self.expect(&TokenKind::RParen, "\")\"")?;
+        Ok(method_type)
+    }
+
+    /// rpc = "rpc" rpcName "(" [ "stream" ] messageType ")" "returns" "(" [ "stream" ]
+    ///       messageType ")" (( "{" {option | ";" } "}" ) | ";")
+    fn parse_rpc(&mut self) -> Result<Method> {
+        let keyword = self.expect(&TokenKind::Rpc, "\"rpc\"")?;
+        let name = self.expect_identifier("rpc name")?;
+        let (client_streaming, input_type) = self.parse_method_type()?;
+        self.expect(&TokenKind::Returns, "\"returns\"")?;
+        let (server_streaming, output_type) = self.parse_method_type()?;
+        let mut options = Vec::new();
+        let comments;
+        let end = if self.consume(&TokenKind::LBrace) {
+            comments = self.collect_comments();
+            loop {
+                let result = match self.peek_kind() {
+                    Some(TokenKind::RBrace) => break self.end_scope()?,
+                    Some(TokenKind::Semicolon) => {
+                        self.empty_statement();
+                        Ok(())
+                    }
+                    Some(_) => self
+                        .parse_option_statement()
+                        .map(|option| options.push(option)),
+                    None => return Err(self.unclosed("rpc", &name)),
+                };
+                if let Err(error) = result {
+                    self.recover(error);
+                }
+            }
+        } else {
+            let (end, end_comments) = self.end_declaration(&TokenKind::Semicolon, "\";\"")?;
+            comments = end_comments;
+            end.metadata
+        };
+        Ok(Method {
+            start: keyword.metadata,
+            name,
+            input_type,
+            output_type,
+            client_streaming,
+            server_streaming,
+            options,
+            end,
+            comments,
+        })
+    }
+}
+
+/// A top-level statement of a file
+#[derive(Debug)]
+pub(crate) enum Statement {
+    /// The syntax and the span of its declaration
+    Syntax(Spanned<String>, TokenMetadata),
+    Package(PackageStatement),
+    Import(Import),
+    Option(OptionStatement),
+    Element(NamedElement),
+    /// An empty statement, or one that failed to parse
+    Empty,
+}
+
+/// A `package` declaration
+#[derive(Clone, Debug)]
+pub(crate) struct PackageStatement {
+    /// The `package` keyword
+    pub keyword: TokenMetadata,
+    pub name: Spanned<String>,
+    /// The whole declaration
+    pub span: TokenMetadata,
+    pub comments: Comments,
+}
+
+/// Record a package declaration, only the first one of a file counts
+pub(crate) fn set_package(
+    package: &mut Package,
+    statement: PackageStatement,
+) -> Option<RsProtocError> {
+    match &package.name {
+        Some(first) => Some(RsProtocError::ParseError(Box::new(
+            Diagnostic::error("Multiple package declarations")
+                .with_primary_label(&statement.keyword, "")
+                .with_secondary_label(&first.metadata, "package first declared here"),
+        ))),
+        None => {
+            package.name = Some(statement.name);
+            package.span = Some(statement.span);
+            package.comments = statement.comments;
+            None
         }
-        return Ok(());
     }
+}
 
-    fn parse(&mut self) -> Result<PackageMap> {
-        self.consume_syntax_declaration()?;
-        /// TODO: Handle import statements
-        Ok(PackageMap::new())
+fn unexpected_token(token: &Token, expected: &str) -> RsProtocError {
+    RsProtocError::ParseError(Box::new(
+        Diagnostic::error(format!("Expected {} but found {}", expected, token.kind))
+            .with_primary_label(&token.metadata, format!("expected {}", expected)),
+    ))
+}
+
+/// The constant for an integer literal, one too large for any integer type can still be the value
+/// of a floating point field
+fn integer_constant(literal: &IntegerLiteral, negate: bool) -> Constant {
+    match literal.value().and_then(|value| i128::try_from(value).ok()) {
+        Some(value) if negate => Constant::Integer(-value),
+        Some(value) => Constant::Integer(value),
+        None if negate => Constant::Float(-literal.to_f64()),
+        None => Constant::Float(literal.to_f64()),
     }
 }
 
+fn out_of_range(text: &str, metadata: &TokenMetadata) -> RsProtocError {
+    RsProtocError::ParseError(Box::new(
+        Diagnostic::error(format!("Integer {} is out of range", text))
+            .with_primary_label(metadata, ""),
+    ))
+}
+
+fn invalid_utf8(metadata: &TokenMetadata) -> RsProtocError {
+    RsProtocError::ParseError(Box::new(
+        Diagnostic::error("String is not valid UTF-8")
+            .with_primary_label(metadata, "")
+            .with_note("escapes can only produce arbitrary bytes in bytes values"),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::Parser;
+    use crate::ast_elements::{
+        Constant, ElementType, FieldLabel, FieldType, OptionNamePart, Reserved, ScalarType,
+    };
     fn add_header(source: &str) -> String {
         let header = "syntax = \"proto3\";\
         package tests.test_package;";
@@ -139,19 +1417,488 @@ mod tests {
             "#,
             );
             let mut parser = Parser::new(&source);
-            assert_eq!(true, parser.parse().is_ok());
+            assert!(parser.parse().is_ok());
         }
 
         {
             let source = "syntax = \"proto2\";";
-            let mut parser = Parser::new(&source);
-            assert_eq!(true, parser.parse().is_err());
+            let mut parser = Parser::new(source);
+            assert!(parser.parse().is_ok());
+        }
+
+        {
+            let source = "syntax = \"proto4\";";
+            let mut parser = Parser::new(source);
+            assert!(parser.parse().is_err());
         }
 
         {
             let source = "syntax = \"proto3\"";
-            let mut parser = Parser::new(&source);
-            assert_eq!(true, parser.parse().is_err());
+            let mut parser = Parser::new(source);
+            assert!(parser.parse().is_err());
+        }
+
+        {
+            // Adjacent string literals are concatenated
+            let source = "syntax = \"pro\" 'to3'; import \"a/\"\n  \"b.proto\";";
+            let file = Parser::new(source).parse().unwrap();
+            let syntax = file.syntax.unwrap();
+            assert_eq!(syntax.value, "proto3");
+            assert_eq!(
+                &source[syntax.metadata.offset()..syntax.metadata.end_offset()],
+                "\"pro\" 'to3'"
+            );
+            assert_eq!(file.imports[0].path.value, "a/b.proto");
+            assert_eq!(file.imports[0].path.metadata.span_len(), 16);
         }
     }
+
+    #[test]
+    fn parser_string_bytes_test() {
+        let source = add_header(
+            r#"
+            option (text) = "caf\303\251" '\u00e9';
+            option (data) = "\xff" "\x00";
+            "#,
+        );
+        let file = Parser::new(&source).parse().unwrap();
+        assert_eq!(
+            file.options[0].value.value,
+            Constant::String("café\u{e9}".to_string())
+        );
+        assert_eq!(file.options[1].value.value, Constant::Bytes(vec![0xff, 0]));
+
+        // Text has to be valid UTF-8
+        let errors = Parser::new(&add_header(r#"import "\xff.proto";"#))
+            .parse()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("String is not valid UTF-8"));
+    }
+
+    #[test]
+    fn parser_message_test() {
+        let source = add_header(
+            r#"
+            import public "other.proto";
+            option java_package = "com.example";
+            message Outer {
+                message Inner {
+                    repeated string names = 1 [deprecated = true];
+                }
+                enum Kind {
+                    option allow_alias = true;
+                    KIND_UNSPECIFIED = 0;
+                    KIND_NEGATIVE = -1;
+                }
+                optional .tests.test_package.Outer.Inner inner = 1;
+                map<string, Inner> inner_map = 2;
+                oneof choice {
+                    int64 number = 3;
+                    Kind kind = 4 [(custom.opt).value = 5];
+                }
+                reserved 10, 20 to max;
+                reserved "old_field";
+            }
+            "#,
+        );
+        let file = Parser::new(&source).parse().unwrap();
+        assert_eq!(
+            file.package.name.as_ref().unwrap().value,
+            "tests.test_package"
+        );
+        assert_eq!(file.imports.len(), 1);
+        assert_eq!(
+            file.options[0].value.value,
+            Constant::String("com.example".to_string())
+        );
+        let outer = match &file.package.named_elements[0].type_t {
+            ElementType::Message(message) => message,
+            _ => panic!("Expected a message"),
+        };
+        assert_eq!(outer.named_elements.len(), 5);
+        match &outer.named_elements[2].type_t {
+            ElementType::Field(field) => {
+                assert_eq!(field.label, FieldLabel::Optional);
+                assert!(
+                    matches!(&field.field_type.value, FieldType::Named(name) if name == ".tests.test_package.Outer.Inner")
+                );
+                assert_eq!(field.number.value, 1);
+            }
+            _ => panic!("Expected a field"),
+        }
+        match &outer.named_elements[3].type_t {
+            ElementType::Field(field) => match &field.field_type.value {
+                FieldType::Map(key, _) => {
+                    assert!(matches!(key.value, FieldType::Scalar(ScalarType::String)))
+                }
+                _ => panic!("Expected a map field"),
+            },
+            _ => panic!("Expected a field"),
+        }
+        match &outer.named_elements[4].type_t {
+            ElementType::OneOf(oneof) => assert_eq!(oneof.fields.len(), 2),
+            _ => panic!("Expected a oneof"),
+        }
+        assert_eq!(outer.reserved.len(), 3);
+        assert!(
+            matches!(&outer.reserved[1], Reserved::Range(range) if range.value.end == super::MAX_FIELD_NUMBER)
+        );
+    }
+
+    #[test]
+    fn parser_service_test() {
+        let source = add_header(
+            r#"
+            message Request {}
+            message Response {}
+            service Greeter {
+                rpc Unary(Request) returns (Response);
+                rpc Bidi(stream Request) returns (stream .tests.test_package.Response) {
+                    option deprecated = true;
+                }
+            }
+            "#,
+        );
+        let file = Parser::new(&source).parse().unwrap();
+        match &file.package.named_elements[2].type_t {
+            ElementType::Service(service) => {
+                assert_eq!(service.methods.len(), 2);
+                assert!(!service.methods[0].client_streaming);
+                assert!(service.methods[1].client_streaming);
+                assert!(service.methods[1].server_streaming);
+                assert_eq!(service.methods[1].options.len(), 1);
+            }
+            _ => panic!("Expected a service"),
+        }
+    }
+
+    #[test]
+    fn parser_numeric_constant_test() {
+        let source = add_header(
+            r#"
+            option (a) = -0x10;
+            option (b) = - inf;
+            option (c) = -nan;
+            option (d) = 99999999999999999999999999999999999999999;
+            option (e) = +1.5;
+            enum E { ZERO = 0; NEGATIVE = -2; }
+            "#,
+        );
+        let file = Parser::new(&source).parse().unwrap();
+        let values: Vec<&Constant> = file
+            .options
+            .iter()
+            .map(|option| &option.value.value)
+            .collect();
+        assert_eq!(values[0], &Constant::Integer(-16));
+        assert_eq!(values[1], &Constant::Float(f64::NEG_INFINITY));
+        assert!(matches!(values[2], Constant::Float(value) if value.is_nan()));
+        assert_eq!(values[3], &Constant::Float(1e41));
+        assert_eq!(values[4], &Constant::Float(1.5));
+        // The sign is part of the span
+        let metadata = &file.options[1].value.metadata;
+        assert_eq!(&source[metadata.offset()..metadata.end_offset()], "- inf");
+        let ElementType::Enum(enum_payload) = &file.package.named_elements[0].type_t else {
+            panic!();
+        };
+        let ElementType::EnumValue(value) = &enum_payload.values[1].type_t else {
+            panic!();
+        };
+        let metadata = &value.number.metadata;
+        assert_eq!(&source[metadata.offset()..metadata.end_offset()], "-2");
+
+        let errors = Parser::new(&add_header(
+            "message M { int32 a = 18446744073709551616; } enum E { A = -9223372036854775809; }",
+        ))
+        .parse()
+        .unwrap_err();
+        let errors: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+        assert!(errors[0].contains("Integer 18446744073709551616 is out of range"));
+        assert!(errors[1].contains("Integer -9223372036854775809 is out of range"));
+    }
+
+    #[test]
+    fn parser_message_literal_test() {
+        let source = add_header(
+            r#"
+            option (rules) = { min: 1, max: -2; tags: ["a", "b"] nested < flag: true > [pkg.ext] {} };
+            message M { int32 a = 1 [deprecated = true, (field_rules).limits = { max: 3 }]; }
+            "#,
+        );
+        let file = Parser::new(&source).parse().unwrap();
+        let Constant::Message(fields) = &file.options[0].value.value else {
+            panic!("Expected a message literal");
+        };
+        let names: Vec<&OptionNamePart> = fields.iter().map(|field| &field.name.value).collect();
+        assert_eq!(
+            names,
+            [
+                &OptionNamePart::Simple("min".to_string()),
+                &OptionNamePart::Simple("max".to_string()),
+                &OptionNamePart::Simple("tags".to_string()),
+                &OptionNamePart::Simple("nested".to_string()),
+                &OptionNamePart::Extension("pkg.ext".to_string()),
+            ]
+        );
+        assert_eq!(fields[1].value.value, Constant::Integer(-2));
+        let Constant::List(tags) = &fields[2].value.value else {
+            panic!("Expected a list");
+        };
+        assert_eq!(tags[1].value, Constant::String("b".to_string()));
+        assert!(matches!(&fields[3].value.value, Constant::Message(nested) if nested.len() == 1));
+        assert_eq!(fields[4].value.value, Constant::Message(Vec::new()));
+
+        let ElementType::Message(message) = &file.package.named_elements[0].type_t else {
+            panic!("Expected a message");
+        };
+        let ElementType::Field(field) = &message.named_elements[0].type_t else {
+            panic!("Expected a field");
+        };
+        assert_eq!(field.options.len(), 2);
+        assert_eq!(
+            field.options[1].name.value,
+            [
+                OptionNamePart::Extension("field_rules".to_string()),
+                OptionNamePart::Simple("limits".to_string()),
+            ]
+        );
+
+        // A broken literal is skipped as a whole
+        let source = add_header("option (rules) = { a: { b 1 } c: 2 }; message B {}");
+        let errors = Parser::new(&source).parse().unwrap_err();
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+        assert!(errors[0]
+            .to_string()
+            .contains("Expected \":\" but found integer 1"));
+    }
+
+    #[test]
+    fn parser_contextual_keyword_test() {
+        let source = add_header(
+            r#"
+            option (message).oneOf = { stream: 1 oneOf: max };
+            message map {}
+            message stream {}
+            message Msg {
+                string message = 1;
+                map map = 2;
+                map<string, map.Entry> to = 3;
+                repeated .message.stream max = 4 [default = inf, (option).enum = max];
+                oneof oneof { int32 syntax = 5; }
+            }
+            enum enum { max = 0; oneOf = 1; }
+            service service { rpc rpc(stream) returns (stream stream); }
+            "#,
+        );
+        let file = Parser::new(&source).parse().unwrap();
+        assert_eq!(
+            file.options[0].name.value,
+            [
+                OptionNamePart::Extension("message".to_string()),
+                OptionNamePart::Simple("oneOf".to_string()),
+            ]
+        );
+        let Constant::Message(fields) = &file.options[0].value.value else {
+            panic!("Expected a message literal");
+        };
+        assert_eq!(
+            fields[0].name.value,
+            OptionNamePart::Simple("stream".to_string())
+        );
+        assert_eq!(
+            fields[1].name.value,
+            OptionNamePart::Simple("oneOf".to_string())
+        );
+        assert_eq!(
+            fields[1].value.value,
+            Constant::Identifier("max".to_string())
+        );
+
+        let names: Vec<&str> = file
+            .package
+            .named_elements
+            .iter()
+            .map(|element| element.name.as_str())
+            .collect();
+        assert_eq!(names, ["map", "stream", "Msg", "enum", "service"]);
+        let ElementType::Message(message) = &file.package.named_elements[2].type_t else {
+            panic!("Expected a message");
+        };
+        let field_types: Vec<(&str, &FieldType)> = message
+            .named_elements
+            .iter()
+            .filter_map(|element| match &element.type_t {
+                ElementType::Field(field) => Some((element.name.as_str(), &field.field_type.value)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(field_types.len(), 4);
+        assert!(matches!(
+            field_types[0],
+            ("message", FieldType::Scalar(ScalarType::String))
+        ));
+        assert!(matches!(field_types[1], ("map", FieldType::Named(name)) if name == "map"));
+        assert!(matches!(
+            field_types[2],
+            ("to", FieldType::Map(_, value)) if matches!(&value.value, FieldType::Named(name) if name == "map.Entry")
+        ));
+        assert!(
+            matches!(field_types[3], ("max", FieldType::Named(name)) if name == ".message.stream")
+        );
+        let ElementType::OneOf(oneof) = &message.named_elements[4].type_t else {
+            panic!("Expected a oneof");
+        };
+        assert_eq!(message.named_elements[4].name, "oneof");
+        assert_eq!(oneof.fields[0].name, "syntax");
+
+        let ElementType::Enum(enum_payload) = &file.package.named_elements[3].type_t else {
+            panic!("Expected an enum");
+        };
+        assert_eq!(enum_payload.values[0].name, "max");
+        assert_eq!(enum_payload.values[1].name, "oneOf");
+        let ElementType::Service(service) = &file.package.named_elements[4].type_t else {
+            panic!("Expected a service");
+        };
+        let method = &service.methods[0];
+        assert_eq!(method.name.value, "rpc");
+        assert_eq!(method.input_type.value, "stream");
+        assert!(!method.client_streaming);
+        assert_eq!(method.output_type.value, "stream");
+        assert!(method.server_streaming);
+    }
+
+    #[test]
+    fn parser_error_test() {
+        for source in [
+            "message Missing { int32 a = 1; ",
+            "message Label { repeated map<string, string> a = 1; }",
+            "message NoNumber { int32 a = ; }",
+            "enum E { A = 0 }",
+            "service S { message M {} }",
+        ] {
+            assert!(Parser::new(&add_header(source)).parse().is_err());
+        }
+    }
+
+    #[test]
+    fn parser_recovery_test() {
+        let source = add_header(
+            r#"
+            message A {
+                int32 a = 1
+                string b = 2;
+                message Nested { int32 = 3; }
+                bool c = 4;
+            }
+            enum E { E_ZERO = 0; E_ONE = ; }
+            service S { message M {} }
+            message B { int32 ok = 1; }
+            "#,
+        );
+        let errors: Vec<String> = match Parser::new(&source).parse() {
+            Ok(_) => panic!("Expected errors"),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        };
+        assert_eq!(errors.len(), 4, "{:#?}", errors);
+        assert!(errors[0].contains("Expected \";\" but found \"string\""));
+        assert!(errors[1].contains("Expected field name but found \"=\""));
+        assert!(errors[2].contains("Expected enum value number but found \";\""));
+        assert!(errors[3].contains("Expected \"rpc\" or \"option\" but found \"message\""));
+
+        let errors = Parser::new("message M { int32 a = 1;").parse().unwrap_err();
+        assert_eq!(errors.len(), 2, "{:#?}", errors);
+        let diagnostic = errors[1].to_diagnostic();
+        assert_eq!(diagnostic.code, Some("parse-error"));
+        assert_eq!(diagnostic.labels.len(), 2);
+        assert!(diagnostic.message.contains("reached the end of input"));
+    }
+
+    #[test]
+    fn parser_comments_test() {
+        let source = r#"syntax = "proto3";
+// Leading comment of the package
+package tests;
+
+message Foo {
+  int32 foo = 1;  // Comment attached to foo.
+  // Comment attached to bar.
+  int32 bar = 2;
+
+  string baz = 3;
+  // Comment attached to baz.
+  // Another line attached to baz.
+
+  // Comment attached to moo.
+  //
+  // Another line attached to moo.
+  double moo = 4;
+
+  // Detached comment for corge. This is not leading or trailing comments
+  // to moo or corge because there are blank lines separating it from
+  // both.
+
+  // Detached comment for corge paragraph 2.
+
+  string corge = 5;
+  /* Block comment attached
+   * to corge.  Leading asterisks
+   * will be removed. */
+  /* Block comment attached to
+   * grault. */
+  int32 grault = 6;
+
+  // ignored detached comments.
+}
+"#;
+        let file = Parser::new(source).parse().unwrap();
+        let message = &file.package.named_elements[0];
+        let ElementType::Message(payload) = &message.type_t else {
+            panic!("Expected a message");
+        };
+        let comments: Vec<_> = payload
+            .named_elements
+            .iter()
+            .map(|element| (element.name.as_str(), &element.comments))
+            .collect();
+        assert_eq!(comments[0].0, "foo");
+        assert_eq!(
+            comments[0].1.trailing.as_deref(),
+            Some(" Comment attached to foo.\n")
+        );
+        assert_eq!(
+            comments[1].1.leading.as_deref(),
+            Some(" Comment attached to bar.\n")
+        );
+        assert_eq!(
+            comments[2].1.trailing.as_deref(),
+            Some(" Comment attached to baz.\n Another line attached to baz.\n")
+        );
+        assert_eq!(
+            comments[3].1.leading.as_deref(),
+            Some(" Comment attached to moo.\n\n Another line attached to moo.\n")
+        );
+        assert_eq!(comments[3].1.trailing, None);
+        assert_eq!(
+            comments[4].1.detached,
+            vec![
+                " Detached comment for corge. This is not leading or trailing comments\n to moo or corge because there are blank lines separating it from\n both.\n".to_string(),
+                " Detached comment for corge paragraph 2.\n".to_string(),
+            ]
+        );
+        assert_eq!(comments[4].1.leading, None);
+        assert_eq!(
+            comments[4].1.trailing.as_deref(),
+            Some(" Block comment attached\n to corge.  Leading asterisks\n will be removed. ")
+        );
+        assert_eq!(
+            comments[5].1.leading.as_deref(),
+            Some(" Block comment attached to\n grault. ")
+        );
+        assert_eq!(message.comments, Default::default());
+        assert_eq!(
+            file.package.comments.leading.as_deref(),
+            Some(" Leading comment of the package\n")
+        );
+    }
 }
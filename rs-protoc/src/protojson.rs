@@ -0,0 +1,1136 @@
+use crate::{
+    ast_elements::{Constant, ScalarType},
+    dynamic::{DynamicMessage, Value},
+    error::{Result, RsProtocError},
+    json::JsonValue,
+    resolver::check_scalar,
+    schema::{FieldDescriptor, FieldType, Label, MessageDescriptor, Schema},
+    validator::json_name,
+};
+
+// The canonical proto3 JSON mapping between dynamic messages and `JsonValue`s. Fields are keyed
+// by their json_name, both it and the field name are accepted when parsing, and extensions by
+// their full name in brackets. 64-bit integers are strings, bytes are base64 and enums are the
+// names of their values. The well-known types that have a JSON representation of their own are
+// recognized by name, they have to be part of the schema like any other message.
+// https://protobuf.dev/programming-guides/json/
+
+const ANY: &str = "google.protobuf.Any";
+const TIMESTAMP: &str = "google.protobuf.Timestamp";
+const DURATION: &str = "google.protobuf.Duration";
+const FIELD_MASK: &str = "google.protobuf.FieldMask";
+const STRUCT: &str = "google.protobuf.Struct";
+const VALUE: &str = "google.protobuf.Value";
+const LIST_VALUE: &str = "google.protobuf.ListValue";
+const EMPTY: &str = "google.protobuf.Empty";
+const NULL_VALUE: &str = "google.protobuf.NullValue";
+const WRAPPERS: [&str; 9] = [
+    "google.protobuf.DoubleValue",
+    "google.protobuf.FloatValue",
+    "google.protobuf.Int64Value",
+    "google.protobuf.UInt64Value",
+    "google.protobuf.Int32Value",
+    "google.protobuf.UInt32Value",
+    "google.protobuf.BoolValue",
+    "google.protobuf.StringValue",
+    "google.protobuf.BytesValue",
+];
+
+/// Seconds from 0001-01-01T00:00:00Z to 9999-12-31T23:59:59Z, the range of `Timestamp`
+const TIMESTAMP_SECONDS: std::ops::RangeInclusive<i64> = -62_135_596_800..=253_402_300_799;
+/// Roughly 10,000 years, the range of `Duration`
+const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+/// Decode a message of the type `message_name` from the binary encoding and print it as JSON
+pub fn binary_to_json(schema: &Schema, message_name: &str, bytes: &[u8]) -> Result<String> {
+    let message = DynamicMessage::decode(schema, message_name, bytes)?;
+    Ok(to_json(schema, &message)?.to_string())
+}
+
+/// Parse a message of the type `message_name` from JSON and encode it to the binary encoding
+pub fn json_to_binary(schema: &Schema, message_name: &str, text: &str) -> Result<Vec<u8>> {
+    from_json(schema, message_name, &JsonValue::parse(text)?)?.encode(schema)
+}
+
+/// The JSON representation of `message`. Fields without presence are left out when they hold
+/// their default value.
+pub fn to_json(schema: &Schema, message: &DynamicMessage) -> Result<JsonValue> {
+    let descriptor = find_message(schema, &message.type_name)?;
+    match descriptor.full_name.as_str() {
+        ANY => return any_to_json(schema, message),
+        TIMESTAMP => {
+            let (seconds, nanos) = (integer(message, 1), integer(message, 2));
+            return timestamp_to_json(seconds, nanos).map(JsonValue::String);
+        }
+        DURATION => {
+            let (seconds, nanos) = (integer(message, 1), integer(message, 2));
+            return duration_to_json(seconds, nanos).map(JsonValue::String);
+        }
+        FIELD_MASK => {
+            let paths: Vec<String> = message
+                .get(1)
+                .iter()
+                .filter_map(|path| match path {
+                    Value::String(path) => Some(json_name(path)),
+                    _ => None,
+                })
+                .collect();
+            return Ok(JsonValue::String(paths.join(",")));
+        }
+        STRUCT | LIST_VALUE => {
+            let field = find_field(descriptor, |field| field.number == 1)?;
+            return field_to_json(schema, field, message.get(field.number));
+        }
+        VALUE => {
+            let (number, value) = message
+                .fields
+                .iter()
+                .find_map(|(number, values)| Some((*number, values.last()?)))
+                .ok_or_else(|| error("A google.protobuf.Value must have one of its fields set"))?;
+            let field = find_field(descriptor, |field| field.number == number)?;
+            return value_to_json(schema, &field.field_type, value);
+        }
+        name if WRAPPERS.contains(&name) => {
+            let field = find_field(descriptor, |field| field.number == 1)?;
+            return match message.get(field.number).last() {
+                Some(value) => value_to_json(schema, &field.field_type, value),
+                None => value_to_json(schema, &field.field_type, &default_value(field)),
+            };
+        }
+        _ => {}
+    }
+    let mut members = Vec::new();
+    for (number, values) in &message.fields {
+        let Some((key, field)) = field_key(schema, descriptor, *number) else {
+            return Err(error(format!(
+                "Message \"{}\" has no field number {}",
+                message.type_name, number
+            )));
+        };
+        if !has_presence(field) && values.iter().all(is_default) {
+            continue;
+        }
+        members.push((key, field_to_json(schema, field, values)?));
+    }
+    Ok(JsonValue::Object(members))
+}
+
+/// Parse a message of the type `message_name` from its JSON representation
+pub fn from_json(schema: &Schema, message_name: &str, json: &JsonValue) -> Result<DynamicMessage> {
+    let descriptor = find_message(schema, message_name)?;
+    let mut message = DynamicMessage::new(&descriptor.full_name);
+    match (descriptor.full_name.as_str(), json) {
+        (ANY, JsonValue::Object(_)) => {
+            any_from_json(schema, json, &mut message)?;
+            return Ok(message);
+        }
+        (TIMESTAMP, JsonValue::String(text)) => {
+            let (seconds, nanos) = parse_timestamp(text)?;
+            set_seconds_and_nanos(&mut message, seconds, nanos);
+            return Ok(message);
+        }
+        (DURATION, JsonValue::String(text)) => {
+            let (seconds, nanos) = parse_duration(text)?;
+            set_seconds_and_nanos(&mut message, seconds, nanos);
+            return Ok(message);
+        }
+        (FIELD_MASK, JsonValue::String(text)) => {
+            for path in text.split(',').filter(|path| !path.is_empty()) {
+                message.push(1, Value::String(snake_case(path)));
+            }
+            return Ok(message);
+        }
+        (STRUCT, JsonValue::Object(_)) | (LIST_VALUE, JsonValue::Array(_)) => {
+            let field = find_field(descriptor, |field| field.number == 1)?;
+            field_from_json(schema, field, json, &mut message)?;
+            return Ok(message);
+        }
+        (VALUE, json) => {
+            let (number, value) = match json {
+                JsonValue::Null => (1, Value::Enum(0)),
+                JsonValue::Number(_) => (
+                    2,
+                    scalar_from_json(ScalarType::Double, json, "number_value")?,
+                ),
+                JsonValue::String(value) => (3, Value::String(value.clone())),
+                JsonValue::Bool(value) => (4, Value::Bool(*value)),
+                JsonValue::Object(_) => (5, Value::Message(from_json(schema, STRUCT, json)?)),
+                JsonValue::Array(_) => (6, Value::Message(from_json(schema, LIST_VALUE, json)?)),
+            };
+            message.set(number, value);
+            return Ok(message);
+        }
+        (name, json) if WRAPPERS.contains(&name) => {
+            let field = find_field(descriptor, |field| field.number == 1)?;
+            message.set(field.number, value_from_json(schema, field, json)?);
+            return Ok(message);
+        }
+        (TIMESTAMP | DURATION | FIELD_MASK | STRUCT | LIST_VALUE | ANY, _) => {
+            return Err(unexpected_json(descriptor, json));
+        }
+        _ => {}
+    }
+    let JsonValue::Object(members) = json else {
+        return Err(unexpected_json(descriptor, json));
+    };
+    // Names of the singular fields set so far, with the oneof they belong to
+    let mut set_fields: Vec<(i32, Option<usize>, &str)> = Vec::new();
+    for (key, value) in members {
+        let field = match key.strip_prefix('[').and_then(|key| key.strip_suffix(']')) {
+            Some(name) => schema
+                .extensions_of(&descriptor.full_name)
+                .into_iter()
+                .find(|(full_name, _)| full_name == name)
+                .map(|(_, extension)| extension),
+            None => descriptor
+                .fields
+                .iter()
+                .find(|field| field.json_name == *key || field.name == *key),
+        };
+        let Some(field) = field else {
+            return Err(error(format!(
+                "Message \"{}\" has no field \"{}\"",
+                descriptor.full_name, key
+            )));
+        };
+        // Null is the default value, except for the types that represent null
+        if value.is_null() && !holds_null(&field.field_type) {
+            continue;
+        }
+        if field.label != Label::Repeated {
+            let oneof = field.oneof_index.filter(|_| !field.proto3_optional);
+            let conflict = set_fields.iter().find(|(number, other_oneof, _)| {
+                *number == field.number || (oneof.is_some() && *other_oneof == oneof)
+            });
+            match conflict {
+                Some((number, _, _)) if *number == field.number => {
+                    return Err(error(format!("Field \"{}\" was already set", key)));
+                }
+                Some((_, _, other_key)) => {
+                    return Err(error(format!(
+                        "Field \"{}\" and field \"{}\" are members of the same oneof",
+                        key, other_key
+                    )));
+                }
+                None => set_fields.push((field.number, oneof, key)),
+            }
+        }
+        field_from_json(schema, field, value, &mut message)?;
+    }
+    Ok(message)
+}
+
+fn error(message: impl Into<String>) -> RsProtocError {
+    RsProtocError::EncodingError(message.into())
+}
+
+fn unexpected_json(descriptor: &MessageDescriptor, json: &JsonValue) -> RsProtocError {
+    error(format!(
+        "Expected the JSON representation of \"{}\", found {}",
+        descriptor.full_name, json
+    ))
+}
+
+fn find_message<'s>(schema: &'s Schema, type_name: &str) -> Result<&'s MessageDescriptor> {
+    schema
+        .find_message(type_name)
+        .ok_or_else(|| error(format!("Unknown message type \"{}\"", type_name)))
+}
+
+/// Look up a field of a well-known type, whose declaration is not under our control
+fn find_field(
+    descriptor: &MessageDescriptor,
+    predicate: impl Fn(&FieldDescriptor) -> bool,
+) -> Result<&FieldDescriptor> {
+    descriptor
+        .fields
+        .iter()
+        .find(|field| predicate(field))
+        .ok_or_else(|| {
+            error(format!(
+                "Unexpected definition of \"{}\"",
+                descriptor.full_name
+            ))
+        })
+}
+
+/// The JSON key of the field `number` of `descriptor`, its full name in brackets for extensions
+fn field_key<'s>(
+    schema: &'s Schema,
+    descriptor: &'s MessageDescriptor,
+    number: i32,
+) -> Option<(String, &'s FieldDescriptor)> {
+    if let Some(field) = descriptor
+        .fields
+        .iter()
+        .find(|field| field.number == number)
+    {
+        return Some((field.json_name.clone(), field));
+    }
+    schema
+        .extensions_of(&descriptor.full_name)
+        .into_iter()
+        .find(|(_, extension)| extension.number == number)
+        .map(|(full_name, extension)| (format!("[{}]", full_name), extension))
+}
+
+/// Whether the field is serialized when it holds its default value, proto3 scalars outside of
+/// oneofs are not
+fn has_presence(field: &FieldDescriptor) -> bool {
+    field.label != Label::Repeated
+        && (field.oneof_index.is_some()
+            || field.extendee.is_some()
+            || matches!(field.field_type, FieldType::Message(_)))
+}
+
+fn is_default(value: &Value) -> bool {
+    match value {
+        Value::Bool(value) => !value,
+        Value::I32(value) | Value::Enum(value) => *value == 0,
+        Value::I64(value) => *value == 0,
+        Value::U32(value) => *value == 0,
+        Value::U64(value) => *value == 0,
+        // Compare bits so that -0.0 is still serialized
+        Value::F32(value) => value.to_bits() == 0,
+        Value::F64(value) => value.to_bits() == 0,
+        Value::String(value) => value.is_empty(),
+        Value::Bytes(value) => value.is_empty(),
+        Value::Message(_) => false,
+    }
+}
+
+fn default_value(field: &FieldDescriptor) -> Value {
+    match &field.field_type {
+        FieldType::Scalar(scalar_type) => match scalar_type {
+            ScalarType::Double => Value::F64(0.0),
+            ScalarType::Float => Value::F32(0.0),
+            ScalarType::Int32 | ScalarType::Sint32 | ScalarType::SFixed32 => Value::I32(0),
+            ScalarType::Int64 | ScalarType::Sint64 | ScalarType::SFixed64 => Value::I64(0),
+            ScalarType::Uint32 | ScalarType::Fixed32 => Value::U32(0),
+            ScalarType::Uint64 | ScalarType::Fixed64 => Value::U64(0),
+            ScalarType::Bool => Value::Bool(false),
+            ScalarType::String => Value::String(String::new()),
+            ScalarType::Bytes => Value::Bytes(Vec::new()),
+        },
+        FieldType::Enum(_) => Value::Enum(0),
+        FieldType::Message(type_name) => Value::Message(DynamicMessage::new(type_name)),
+    }
+}
+
+fn holds_null(field_type: &FieldType) -> bool {
+    match field_type {
+        FieldType::Message(type_name) => type_name == VALUE,
+        FieldType::Enum(type_name) => type_name == NULL_VALUE,
+        FieldType::Scalar(_) => false,
+    }
+}
+
+/// The entry message of a map field
+fn map_entry<'s>(schema: &'s Schema, field: &FieldDescriptor) -> Option<&'s MessageDescriptor> {
+    match &field.field_type {
+        FieldType::Message(type_name) if field.label == Label::Repeated => schema
+            .find_message(type_name)
+            .filter(|descriptor| descriptor.is_map_entry()),
+        _ => None,
+    }
+}
+
+/// An integer field of a message, zero when it is not set
+fn integer(message: &DynamicMessage, number: i32) -> i64 {
+    match message.get(number).last() {
+        Some(Value::I32(value)) => *value as i64,
+        Some(Value::I64(value)) => *value,
+        _ => 0,
+    }
+}
+
+fn set_seconds_and_nanos(message: &mut DynamicMessage, seconds: i64, nanos: i32) {
+    if seconds != 0 {
+        message.set(1, Value::I64(seconds));
+    }
+    if nanos != 0 {
+        message.set(2, Value::I32(nanos));
+    }
+}
+
+fn field_to_json(schema: &Schema, field: &FieldDescriptor, values: &[Value]) -> Result<JsonValue> {
+    if let Some(entry) = map_entry(schema, field) {
+        let key_field = find_field(entry, |field| field.number == 1)?;
+        let value_field = find_field(entry, |field| field.number == 2)?;
+        let mut members = Vec::new();
+        for value in values {
+            let Value::Message(entry) = value else {
+                continue;
+            };
+            let key = match entry.get(1).last() {
+                Some(key) => key,
+                None => &default_value(key_field),
+            };
+            let key = match value_to_json(schema, &key_field.field_type, key)? {
+                JsonValue::Number(key) | JsonValue::String(key) => key,
+                key => key.to_string(),
+            };
+            let value = match entry.get(2).last() {
+                Some(value) => value_to_json(schema, &value_field.field_type, value)?,
+                None => {
+                    value_to_json(schema, &value_field.field_type, &default_value(value_field))?
+                }
+            };
+            members.push((key, value));
+        }
+        return Ok(JsonValue::Object(members));
+    }
+    if field.label == Label::Repeated {
+        let values = values
+            .iter()
+            .map(|value| value_to_json(schema, &field.field_type, value))
+            .collect::<Result<Vec<JsonValue>>>()?;
+        return Ok(JsonValue::Array(values));
+    }
+    match values.last() {
+        Some(value) => value_to_json(schema, &field.field_type, value),
+        None => Ok(JsonValue::Null),
+    }
+}
+
+fn value_to_json(schema: &Schema, field_type: &FieldType, value: &Value) -> Result<JsonValue> {
+    let json = match value {
+        Value::Bool(value) => JsonValue::Bool(*value),
+        Value::I32(value) => JsonValue::Number(value.to_string()),
+        Value::U32(value) => JsonValue::Number(value.to_string()),
+        Value::I64(value) => JsonValue::String(value.to_string()),
+        Value::U64(value) => JsonValue::String(value.to_string()),
+        Value::F32(value) => float_to_json(*value as f64, value.to_string()),
+        Value::F64(value) => float_to_json(*value, value.to_string()),
+        Value::String(value) => JsonValue::String(value.clone()),
+        Value::Bytes(value) => JsonValue::String(base64_encode(value)),
+        Value::Enum(number) => match field_type {
+            FieldType::Enum(enum_name) if enum_name == NULL_VALUE => JsonValue::Null,
+            FieldType::Enum(enum_name) => schema
+                .find_enum(enum_name)
+                .and_then(|descriptor| {
+                    descriptor
+                        .values
+                        .iter()
+                        .find(|enum_value| enum_value.number == *number)
+                })
+                .map_or_else(
+                    || JsonValue::Number(number.to_string()),
+                    |enum_value| JsonValue::String(enum_value.name.clone()),
+                ),
+            _ => JsonValue::Number(number.to_string()),
+        },
+        Value::Message(message) => to_json(schema, message)?,
+    };
+    Ok(json)
+}
+
+/// `digits` is the shortest text that reads back as the value
+fn float_to_json(value: f64, digits: String) -> JsonValue {
+    if value.is_nan() {
+        JsonValue::from("NaN")
+    } else if value.is_infinite() {
+        JsonValue::from(if value > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        JsonValue::Number(digits)
+    }
+}
+
+fn field_from_json(
+    schema: &Schema,
+    field: &FieldDescriptor,
+    json: &JsonValue,
+    message: &mut DynamicMessage,
+) -> Result<()> {
+    if let Some(entry) = map_entry(schema, field) {
+        let JsonValue::Object(members) = json else {
+            return Err(error(format!(
+                "Value must be an object for field \"{}\"",
+                field.name
+            )));
+        };
+        let key_field = find_field(entry, |field| field.number == 1)?;
+        let value_field = find_field(entry, |field| field.number == 2)?;
+        for (key, value) in members {
+            let mut entry_message = DynamicMessage::new(&entry.full_name);
+            let key = match key_field.field_type {
+                FieldType::Scalar(ScalarType::Bool) => match key.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    _ => {
+                        return Err(error(format!(
+                            "Key \"{}\" of field \"{}\" must be \"true\" or \"false\"",
+                            key, field.name
+                        )))
+                    }
+                },
+                _ => value_from_json(schema, key_field, &JsonValue::String(key.clone()))?,
+            };
+            entry_message.set(1, key);
+            entry_message.set(2, value_from_json(schema, value_field, value)?);
+            message.push(field.number, Value::Message(entry_message));
+        }
+        return Ok(());
+    }
+    if field.label == Label::Repeated {
+        let JsonValue::Array(elements) = json else {
+            return Err(error(format!(
+                "Value must be an array for field \"{}\"",
+                field.name
+            )));
+        };
+        for element in elements {
+            message.push(field.number, value_from_json(schema, field, element)?);
+        }
+        return Ok(());
+    }
+    message.set(field.number, value_from_json(schema, field, json)?);
+    Ok(())
+}
+
+fn value_from_json(schema: &Schema, field: &FieldDescriptor, json: &JsonValue) -> Result<Value> {
+    match &field.field_type {
+        FieldType::Scalar(scalar_type) => scalar_from_json(*scalar_type, json, &field.name),
+        FieldType::Message(type_name) => Ok(Value::Message(from_json(schema, type_name, json)?)),
+        FieldType::Enum(enum_name) => {
+            let descriptor = schema
+                .find_enum(enum_name)
+                .ok_or_else(|| error(format!("Unknown enum type \"{}\"", enum_name)))?;
+            let number = match json {
+                JsonValue::Null if enum_name == NULL_VALUE => Some(0),
+                JsonValue::String(name) => descriptor
+                    .values
+                    .iter()
+                    .find(|enum_value| enum_value.name == *name)
+                    .map(|enum_value| enum_value.number),
+                // Numbers that are not declared are kept, proto3 enums are open
+                JsonValue::Number(number) => number.parse().ok(),
+                _ => None,
+            };
+            number.map(Value::Enum).ok_or_else(|| {
+                let names: Vec<&str> = descriptor
+                    .values
+                    .iter()
+                    .map(|enum_value| enum_value.name.as_str())
+                    .collect();
+                error(format!(
+                    "Value must be a number or one of {} for field \"{}\"",
+                    names.join(", "),
+                    field.name
+                ))
+            })
+        }
+    }
+}
+
+/// Numbers may also be written as strings, and floating point values as "NaN", "Infinity" or
+/// "-Infinity"
+fn scalar_from_json(scalar_type: ScalarType, json: &JsonValue, name: &str) -> Result<Value> {
+    let constant = match (scalar_type, json) {
+        (ScalarType::Bool, JsonValue::Bool(value)) => Some(Constant::Bool(*value)),
+        (ScalarType::String, JsonValue::String(value)) => Some(Constant::String(value.clone())),
+        (ScalarType::Bytes, JsonValue::String(value)) => base64_decode(value).map(Constant::Bytes),
+        (ScalarType::Bool | ScalarType::String | ScalarType::Bytes, _) => None,
+        (_, JsonValue::Number(number)) => number_constant(scalar_type, number),
+        (ScalarType::Float | ScalarType::Double, JsonValue::String(text)) => match text.as_str() {
+            "NaN" => Some(Constant::Float(f64::NAN)),
+            "Infinity" => Some(Constant::Float(f64::INFINITY)),
+            "-Infinity" => Some(Constant::Float(f64::NEG_INFINITY)),
+            text => quoted_number(scalar_type, text),
+        },
+        (_, JsonValue::String(text)) => quoted_number(scalar_type, text),
+        _ => None,
+    };
+    let expected = match constant.map(|constant| check_scalar(scalar_type, &constant)) {
+        Some(Ok(constant)) => return Ok(Value::from_constant(scalar_type, constant)),
+        Some(Err(expected)) => expected,
+        None => match scalar_type {
+            ScalarType::Bool => "true or false".to_string(),
+            ScalarType::String => "a string".to_string(),
+            ScalarType::Bytes => "a base64 string".to_string(),
+            _ => "a number".to_string(),
+        },
+    };
+    Err(error(format!(
+        "Value must be {} for field \"{}\", found {}",
+        expected, name, json
+    )))
+}
+
+fn quoted_number(scalar_type: ScalarType, text: &str) -> Option<Constant> {
+    match JsonValue::parse(text) {
+        Ok(JsonValue::Number(number)) if !text.starts_with(char::is_whitespace) => {
+            number_constant(scalar_type, &number)
+        }
+        _ => None,
+    }
+}
+
+/// Integers may be written with a fraction or an exponent, as long as their value is integral
+fn number_constant(scalar_type: ScalarType, number: &str) -> Option<Constant> {
+    if let Ok(integer) = number.parse::<i128>() {
+        return Some(Constant::Integer(integer));
+    }
+    let value: f64 = number.parse().ok()?;
+    match scalar_type {
+        ScalarType::Float | ScalarType::Double => Some(Constant::Float(value)),
+        // Within the range of i128, so that values out of range for the type are reported
+        _ if value.fract() == 0.0 && value.abs() < 1e38 => Some(Constant::Integer(value as i128)),
+        _ => None,
+    }
+}
+
+fn any_to_json(schema: &Schema, message: &DynamicMessage) -> Result<JsonValue> {
+    let type_url = match message.get(1).last() {
+        Some(Value::String(type_url)) => type_url,
+        _ if message.fields.is_empty() => return Ok(JsonValue::Object(Vec::new())),
+        _ => return Err(error("A google.protobuf.Any must have a type URL")),
+    };
+    let bytes = match message.get(2).last() {
+        Some(Value::Bytes(bytes)) => bytes.as_slice(),
+        _ => &[],
+    };
+    let message_name = message_name(type_url)?;
+    let value = to_json(
+        schema,
+        &DynamicMessage::decode(schema, message_name, bytes)?,
+    )?;
+    let mut members = vec![("@type".to_string(), JsonValue::String(type_url.clone()))];
+    match value {
+        JsonValue::Object(mut fields) if !has_custom_json(message_name) => {
+            members.append(&mut fields)
+        }
+        value => members.push(("value".to_string(), value)),
+    }
+    Ok(JsonValue::Object(members))
+}
+
+fn any_from_json(schema: &Schema, json: &JsonValue, message: &mut DynamicMessage) -> Result<()> {
+    let JsonValue::Object(members) = json else {
+        return Err(error("A google.protobuf.Any must be an object"));
+    };
+    if members.is_empty() {
+        return Ok(());
+    }
+    let type_url = json
+        .get("@type")
+        .and_then(JsonValue::as_str)
+        .ok_or_else(|| error("A google.protobuf.Any must have an \"@type\" member"))?;
+    let message_name = message_name(type_url)?;
+    let value = if has_custom_json(message_name) {
+        let value = json.get("value").ok_or_else(|| {
+            error(format!(
+                "A google.protobuf.Any holding \"{}\" must have a \"value\" member",
+                message_name
+            ))
+        })?;
+        from_json(schema, message_name, value)?
+    } else {
+        let fields = members
+            .iter()
+            .filter(|(key, _)| key != "@type")
+            .cloned()
+            .collect();
+        from_json(schema, message_name, &JsonValue::Object(fields))?
+    };
+    message.set(1, Value::String(type_url.to_string()));
+    message.set(2, Value::Bytes(value.encode(schema)?));
+    Ok(())
+}
+
+/// The message name follows the last "/" of a type URL
+fn message_name(type_url: &str) -> Result<&str> {
+    type_url
+        .rsplit_once('/')
+        .map(|(_, message_name)| message_name)
+        .ok_or_else(|| error(format!("Invalid type URL \"{}\"", type_url)))
+}
+
+/// Well-known types whose JSON is not an object of their fields, or which do not follow the
+/// usual rules. They are held in the "value" member of an `Any`.
+fn has_custom_json(message_name: &str) -> bool {
+    [
+        ANY, TIMESTAMP, DURATION, FIELD_MASK, STRUCT, VALUE, LIST_VALUE, EMPTY,
+    ]
+    .contains(&message_name)
+        || WRAPPERS.contains(&message_name)
+}
+
+/// The inverse of `json_name`, "fooBar" becomes "foo_bar"
+fn snake_case(name: &str) -> String {
+    let mut output = String::with_capacity(name.len() + 4);
+    for ch in name.chars() {
+        if ch.is_ascii_uppercase() {
+            output.push('_');
+            output.push(ch.to_ascii_lowercase());
+        } else {
+            output.push(ch);
+        }
+    }
+    output
+}
+
+/// RFC 3339 in UTC, with 0, 3, 6 or 9 fractional digits
+fn timestamp_to_json(seconds: i64, nanos: i64) -> Result<String> {
+    if !TIMESTAMP_SECONDS.contains(&seconds) || !(0..NANOS_PER_SECOND).contains(&nanos) {
+        return Err(error(format!(
+            "Timestamp {}s {}ns is out of range",
+            seconds, nanos
+        )));
+    }
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+    Ok(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        fraction(nanos)
+    ))
+}
+
+/// Accepts any offset from UTC, the value is normalized to UTC
+fn parse_timestamp(text: &str) -> Result<(i64, i32)> {
+    let invalid = || error(format!("Invalid timestamp \"{}\"", text));
+    let bytes = text.as_bytes();
+    let number = |range: std::ops::Range<usize>| -> Result<i64> {
+        let digits = bytes.get(range).ok_or_else(invalid)?;
+        digits.iter().try_fold(0, |value: i64, digit| match digit {
+            b'0'..=b'9' => Ok(value * 10 + i64::from(digit - b'0')),
+            _ => Err(invalid()),
+        })
+    };
+    let separators = [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':')];
+    if bytes.len() < 20
+        || separators
+            .iter()
+            .any(|(index, separator)| !bytes[*index].eq_ignore_ascii_case(separator))
+    {
+        return Err(invalid());
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    let days = days_from_civil(year, month, day);
+    if year == 0
+        || civil_from_days(days) != (year, month, day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+    let mut rest = &text[19..];
+    let mut nanos = 0;
+    if let Some(after_dot) = rest.strip_prefix('.') {
+        let digits = after_dot.bytes().take_while(u8::is_ascii_digit).count();
+        nanos = parse_fraction(&after_dot[..digits]).ok_or_else(invalid)?;
+        rest = &after_dot[digits..];
+    }
+    let offset = match rest.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let start = text.len() - 5;
+            let (hours, minutes) = (number(start..start + 2)?, number(start + 3..start + 5)?);
+            if hours > 23 || minutes > 59 {
+                return Err(invalid());
+            }
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return Err(invalid()),
+    };
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    if !TIMESTAMP_SECONDS.contains(&seconds) {
+        return Err(invalid());
+    }
+    Ok((seconds, nanos))
+}
+
+/// Seconds with 0, 3, 6 or 9 fractional digits followed by "s"
+fn duration_to_json(seconds: i64, nanos: i64) -> Result<String> {
+    if seconds.abs() > MAX_DURATION_SECONDS
+        || nanos.abs() >= NANOS_PER_SECOND
+        || (seconds != 0 && nanos != 0 && (seconds < 0) != (nanos < 0))
+    {
+        return Err(error(format!(
+            "Duration {}s {}ns is out of range",
+            seconds, nanos
+        )));
+    }
+    let sign = if seconds < 0 || nanos < 0 { "-" } else { "" };
+    Ok(format!(
+        "{}{}{}s",
+        sign,
+        seconds.unsigned_abs(),
+        fraction(nanos.abs())
+    ))
+}
+
+fn parse_duration(text: &str) -> Result<(i64, i32)> {
+    let invalid = || error(format!("Invalid duration \"{}\"", text));
+    let value = text.strip_suffix('s').ok_or_else(invalid)?;
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if seconds.is_empty() || !seconds.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let seconds: i64 = seconds.parse().map_err(|_| invalid())?;
+    let nanos = match fraction {
+        "" if !value.contains('.') => 0,
+        fraction => parse_fraction(fraction).ok_or_else(invalid)?,
+    };
+    if seconds > MAX_DURATION_SECONDS {
+        return Err(invalid());
+    }
+    Ok(match negative {
+        true => (-seconds, -nanos),
+        false => (seconds, nanos),
+    })
+}
+
+fn fraction(nanos: i64) -> String {
+    match nanos {
+        0 => String::new(),
+        nanos if nanos % 1_000_000 == 0 => format!(".{:03}", nanos / 1_000_000),
+        nanos if nanos % 1_000 == 0 => format!(".{:06}", nanos / 1_000),
+        nanos => format!(".{:09}", nanos),
+    }
+}
+
+/// Nanoseconds from 1 to 9 fractional digits
+fn parse_fraction(digits: &str) -> Option<i32> {
+    if digits.is_empty() || digits.len() > 9 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let value: i32 = digits.parse().ok()?;
+    Some(value * 10_i32.pow(9 - digits.len() as u32))
+}
+
+// Conversions between days since 1970-01-01 and proleptic Gregorian dates, from
+// https://howardhinnant.github.io/date_algorithms.html
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let shifted_month = (month + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 with padding
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| {
+            group | (*byte as u32) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let sextet = (group >> (18 - 6 * index)) & 0x3f;
+                output.push(BASE64_ALPHABET[sextet as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+/// Standard or URL-safe base64, with or without padding
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut output = Vec::with_capacity(text.len() * 3 / 4);
+    let mut group = 0u32;
+    let mut bits = 0;
+    for byte in text.bytes() {
+        let sextet = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        group = group << 6 | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((group >> bits) as u8);
+        }
+    }
+    // A single character left over cannot hold a whole byte
+    (bits < 6).then_some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::{
+        resolver::{resolve, Loader},
+        source_text::{SourceRegistry, VirtualFileSystem},
+    };
+
+    /// The well-known types are the ones the loader provides
+    fn schema() -> Schema {
+        let mut file_system = VirtualFileSystem::default();
+        file_system.insert(
+            "protos/test.proto",
+            r#"syntax = "proto3";
+            package p;
+            import "google/protobuf/any.proto";
+            import "google/protobuf/duration.proto";
+            import "google/protobuf/field_mask.proto";
+            import "google/protobuf/struct.proto";
+            import "google/protobuf/timestamp.proto";
+            import "google/protobuf/wrappers.proto";
+            enum Kind {
+                KIND_UNSPECIFIED = 0;
+                KIND_FIRST = 1;
+            }
+            message M {
+                int32 small_number = 1;
+                int64 big_number = 2;
+                uint64 unsigned = 3 [json_name = "u"];
+                repeated double values = 4;
+                bytes raw = 5;
+                Kind kind = 6;
+                map<int32, string> names = 7;
+                map<bool, M> children = 8;
+                optional string label = 9;
+                float ratio = 10;
+                google.protobuf.Timestamp time = 11;
+                google.protobuf.Duration timeout = 12;
+                google.protobuf.FieldMask mask = 13;
+                google.protobuf.Struct details = 14;
+                google.protobuf.Int64Value wrapped = 15;
+                google.protobuf.Any any = 16;
+                google.protobuf.Value dynamic = 17;
+                oneof choice {
+                    string first = 18;
+                    string second = 19;
+                }
+                extensions 100 to 200;
+            }
+            extend M {
+                string ext = 100;
+            }"#,
+        );
+        let mut loader = Loader::with_sources(
+            vec![PathBuf::from("protos")],
+            SourceRegistry::new(file_system),
+        );
+        loader.load("test.proto");
+        resolve(&loader.finish().unwrap()).unwrap()
+    }
+
+    fn round_trip(schema: &Schema, json: &str) -> String {
+        let bytes = json_to_binary(schema, "p.M", json).unwrap();
+        binary_to_json(schema, "p.M", &bytes).unwrap()
+    }
+
+    #[test]
+    fn scalars_test() {
+        let schema = schema();
+        assert_eq!(
+            round_trip(
+                &schema,
+                r#"{
+                    "small_number": -5,
+                    "bigNumber": "9007199254740993",
+                    "u": 18446744073709551615,
+                    "values": [1.5, "NaN", "-Infinity", "2e3"],
+                    "raw": "AP9h",
+                    "kind": "KIND_FIRST",
+                    "names": {"1": "one", "-2": "minus two"},
+                    "children": {"true": {"ratio": 0.1}},
+                    "label": "",
+                    "ratio": 0,
+                    "second": "b",
+                    "[p.ext]": "extended"
+                }"#
+            ),
+            concat!(
+                r#"{"smallNumber":-5,"bigNumber":"9007199254740993","u":"18446744073709551615","#,
+                r#""values":[1.5,"NaN","-Infinity",2000],"raw":"AP9h","kind":"KIND_FIRST","#,
+                r#""names":{"1":"one","-2":"minus two"},"children":{"true":{"ratio":0.1}},"#,
+                r#""label":"","second":"b","[p.ext]":"extended"}"#
+            )
+        );
+        // Numbers in strings and integral floating point numbers, unknown enum values and
+        // URL-safe base64 without padding
+        assert_eq!(
+            round_trip(
+                &schema,
+                r#"{"smallNumber": "7", "bigNumber": 1e3, "kind": 9, "raw": "-_8", "label": null}"#
+            ),
+            r#"{"smallNumber":7,"bigNumber":"1000","raw":"+/8=","kind":9}"#
+        );
+    }
+
+    #[test]
+    fn well_known_types_test() {
+        let schema = schema();
+        assert_eq!(
+            round_trip(
+                &schema,
+                r#"{
+                    "time": "1972-01-01T10:00:20.021+01:00",
+                    "timeout": "-1.5s",
+                    "mask": "fooBar,baz.quxQuux",
+                    "details": {"a": [1, "x", true, null, {"b": {}}]},
+                    "wrapped": "42",
+                    "any": {"@type": "type.googleapis.com/google.protobuf.Duration", "value": "3s"},
+                    "dynamic": null
+                }"#
+            ),
+            concat!(
+                r#"{"time":"1972-01-01T09:00:20.021Z","timeout":"-1.500s","#,
+                r#""mask":"fooBar,baz.quxQuux","details":{"a":[1,"x",true,null,{"b":{}}]},"#,
+                r#""wrapped":"42","any":{"@type":"type.googleapis.com/google.protobuf.Duration","value":"3s"},"#,
+                r#""dynamic":null}"#
+            )
+        );
+        let message = from_json(
+            &schema,
+            "google.protobuf.FieldMask",
+            &JsonValue::from("fooBar"),
+        )
+        .unwrap();
+        assert_eq!(message.get(1), [Value::String("foo_bar".to_string())]);
+        // An Any holding a message with fields merges them into its own object
+        assert_eq!(
+            round_trip(
+                &schema,
+                r#"{"any": {"@type": "example.com/p.M", "kind": "KIND_FIRST", "time": "0001-01-01T00:00:00Z"}}"#
+            ),
+            r#"{"any":{"@type":"example.com/p.M","kind":"KIND_FIRST","time":"0001-01-01T00:00:00Z"}}"#
+        );
+        assert_eq!(timestamp_to_json(0, 0).unwrap(), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            timestamp_to_json(253_402_300_799, 1).unwrap(),
+            "9999-12-31T23:59:59.000000001Z"
+        );
+        assert_eq!(
+            parse_timestamp("2000-02-29T00:00:00Z").unwrap(),
+            (951_782_400, 0)
+        );
+        assert_eq!(duration_to_json(0, -1_000).unwrap(), "-0.000001s");
+        assert_eq!(parse_duration("0.25s").unwrap(), (0, 250_000_000));
+    }
+
+    #[test]
+    fn errors_test() {
+        let schema = schema();
+        let error = |json: &str| {
+            json_to_binary(&schema, "p.M", json)
+                .unwrap_err()
+                .to_string()
+        };
+        for (json, message) in [
+            (r#"{"other": 1}"#, r#"Message "p.M" has no field "other""#),
+            (
+                r#"{"smallNumber": 1, "small_number": 2}"#,
+                r#"Field "small_number" was already set"#,
+            ),
+            (
+                r#"{"first": "a", "second": "b"}"#,
+                r#"Field "second" and field "first" are members of the same oneof"#,
+            ),
+            (
+                r#"{"smallNumber": 3000000000}"#,
+                r#"Value must be an integer between -2147483648 and 2147483647 for field "small_number", found 3000000000"#,
+            ),
+            (
+                r#"{"smallNumber": 1.5}"#,
+                r#"Value must be a number for field "small_number", found 1.5"#,
+            ),
+            (
+                r#"{"raw": "A"}"#,
+                r#"Value must be a base64 string for field "raw", found "A""#,
+            ),
+            (
+                r#"{"kind": "KIND_THIRD"}"#,
+                r#"Value must be a number or one of KIND_UNSPECIFIED, KIND_FIRST for field "kind""#,
+            ),
+            (
+                r#"{"values": 1}"#,
+                r#"Value must be an array for field "values""#,
+            ),
+            (
+                r#"{"children": {"yes": {}}}"#,
+                r#"Key "yes" of field "children" must be "true" or "false""#,
+            ),
+            (
+                r#"{"time": "1972-13-01T00:00:00Z"}"#,
+                r#"Invalid timestamp "1972-13-01T00:00:00Z""#,
+            ),
+            (r#"{"timeout": "1.5"}"#, r#"Invalid duration "1.5""#),
+            (
+                r#"{"any": {"value": "3s"}}"#,
+                r#"A google.protobuf.Any must have an "@type" member"#,
+            ),
+            (
+                r#"[]"#,
+                r#"Expected the JSON representation of "p.M", found []"#,
+            ),
+        ] {
+            assert_eq!(
+                error(json),
+                format!("RsProtocError::EncodingError[{}]", message)
+            );
+        }
+    }
+
+    #[test]
+    fn base64_test() {
+        for (bytes, text) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+        ] {
+            assert_eq!(base64_encode(bytes), text);
+            assert_eq!(base64_decode(text).unwrap(), bytes);
+        }
+        assert_eq!(base64_decode("Zm9vYg").unwrap(), b"foob");
+        assert!(base64_decode("Z").is_none());
+        assert!(base64_decode("Zm9v!").is_none());
+    }
+}
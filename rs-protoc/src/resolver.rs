@@ -0,0 +1,2554 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    ast_elements::{
+        find_option, Comments, Constant, ElementType, EnumPayload, FieldLabel, FieldPayload,
+        ImportKind, MessageLiteralField, MessagePayload, NamedElement, OptionNamePart,
+        OptionStatement, ProtoFile, Reserved, ScalarType, ServicePayload, Spanned,
+    },
+    builtin::builtin_file,
+    descriptor::{
+        descriptor_proto, enum_descriptor_proto, enum_value_descriptor_proto,
+        field_descriptor_proto, file_descriptor_proto, find_builtin_option,
+        method_descriptor_proto, oneof_descriptor_proto, service_descriptor_proto,
+        BuiltinOptionType, OptionsKind,
+    },
+    diagnostic::Diagnostic,
+    error::{FileError, RsProtocError},
+    incremental::IncrementalParser,
+    lexer::TokenMetadata,
+    parser::Parser,
+    schema::{
+        is_packed, EnumDescriptor, EnumValueDescriptor, FieldDescriptor, FieldType, FileDescriptor,
+        Label, MessageDescriptor, MethodDescriptor, OneofDescriptor, OptionValue, Schema,
+        SchemaOption, ServiceDescriptor, SourceLocation, Syntax,
+    },
+    source_map::SourceMap,
+    source_text::{SourceBuffer, SourceRegistry},
+    validator,
+};
+
+/// A parsed and validated .proto file
+pub struct LoadedFile {
+    /// Path of the file relative to the include path it was found in
+    pub name: String,
+    /// Where the file was read from
+    pub path: PathBuf,
+    pub source: Arc<SourceBuffer>,
+    pub ast: ProtoFile,
+}
+
+impl LoadedFile {
+    /// Parse a file that does not live on disk, imports are not loaded
+    pub fn from_source(name: &str, text: String) -> Result<Self, Vec<RsProtocError>> {
+        let source = SourceBuffer::new(text).map_err(|error| vec![error])?;
+        let ast = Parser::new(source.text()).parse()?;
+        Ok(LoadedFile {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            source: Arc::new(source),
+            ast,
+        })
+    }
+}
+
+/// Loads .proto files along with everything they import, searching a list of include paths
+pub struct Loader {
+    include_paths: Vec<PathBuf>,
+    sources: SourceRegistry,
+    /// Files in dependency order
+    files: Vec<LoadedFile>,
+    /// Chain of files currently being loaded, used to detect import cycles
+    in_progress: Vec<String>,
+    errors: Vec<FileError>,
+    /// Text to use instead of the contents of files on disk
+    overlays: HashMap<PathBuf, Overlay>,
+}
+
+struct Overlay {
+    text: String,
+    /// The result of parsing `text` when it is known already
+    parsed: Option<Result<ProtoFile, Vec<RsProtocError>>>,
+}
+
+impl Loader {
+    pub fn new(include_paths: Vec<PathBuf>) -> Self {
+        Loader::with_sources(include_paths, SourceRegistry::default())
+    }
+
+    /// Register the files read in `sources`, which also decides where they are read from
+    pub fn with_sources(include_paths: Vec<PathBuf>, sources: SourceRegistry) -> Self {
+        Loader {
+            include_paths,
+            sources,
+            files: Vec::new(),
+            in_progress: Vec::new(),
+            errors: Vec::new(),
+            overlays: HashMap::new(),
+        }
+    }
+
+    /// Read `text` instead of the file at `path`, which does not have to exist, e.g. for
+    /// unsaved editor buffers
+    pub fn add_overlay(&mut self, path: PathBuf, text: String) {
+        self.overlays.insert(path, Overlay { text, parsed: None });
+    }
+
+    /// Like `add_overlay`, with the file the way `parser` has it parsed already
+    pub fn add_parsed_overlay(&mut self, path: PathBuf, parser: &IncrementalParser) {
+        let errors: Vec<RsProtocError> = parser.errors().cloned().collect();
+        let parsed = if errors.is_empty() {
+            Ok(parser.file().clone())
+        } else {
+            Err(errors)
+        };
+        let overlay = Overlay {
+            text: parser.text().to_string(),
+            parsed: Some(parsed),
+        };
+        self.overlays.insert(path, overlay);
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.overlays.contains_key(path) || self.sources.is_file(path)
+    }
+
+    /// Every file read so far, including the ones that failed to parse
+    pub fn sources(&self) -> &SourceRegistry {
+        &self.sources
+    }
+
+    /// Map a path on disk to the name of the file relative to the first include path containing it
+    pub fn virtual_name(&self, path: &str) -> Option<String> {
+        let path = Path::new(path);
+        for include_path in &self.include_paths {
+            let relative_path = if include_path == Path::new(".") {
+                path.strip_prefix(".").unwrap_or(path)
+            } else {
+                match path.strip_prefix(include_path) {
+                    Ok(relative_path) => relative_path,
+                    Err(_) => continue,
+                }
+            };
+            if self.exists(&include_path.join(relative_path)) {
+                return Some(
+                    relative_path
+                        .components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                );
+            }
+        }
+        None
+    }
+
+    /// Load the file called `name` and its imports, errors are collected until `finish`
+    pub fn load(&mut self, name: &str) {
+        if !self.load_internal(name) && self.errors.is_empty() {
+            self.errors.push(FileError {
+                file_name: name.to_string(),
+                error: RsProtocError::FilesystemError(format!("File not found: {}", name)),
+            });
+        }
+    }
+
+    /// Returns false if the file or one of its imports could not be loaded
+    fn load_internal(&mut self, name: &str) -> bool {
+        if self.files.iter().any(|file| file.name == name) {
+            return true;
+        }
+        if self.in_progress.iter().any(|file_name| file_name == name) {
+            self.errors.push(FileError {
+                file_name: name.to_string(),
+                error: RsProtocError::ParseError(Box::new(Diagnostic::error(format!(
+                    "File recursively imports itself: {} -> {}",
+                    self.in_progress.join(" -> "),
+                    name
+                )))),
+            });
+            return false;
+        }
+        let path = self
+            .include_paths
+            .iter()
+            .map(|include_path| include_path.join(name))
+            .find(|path| self.exists(path));
+        // Files on the include paths take precedence over the built-in ones
+        let builtin = match path {
+            Some(_) => None,
+            None => builtin_file(name),
+        };
+        let (path, source, parsed) = match (path, builtin) {
+            (Some(path), _) => {
+                let (source, parsed) = match self.overlays.get(&path) {
+                    Some(overlay) => (
+                        Ok(self.sources.add(name, &path, overlay.text.clone())),
+                        overlay.parsed.clone(),
+                    ),
+                    None => (self.sources.load(name, &path), None),
+                };
+                (path, source, parsed)
+            }
+            (None, Some(text)) => {
+                let path = PathBuf::from(name);
+                let source = self.sources.add(name, &path, text.to_string());
+                (path, Ok(source), None)
+            }
+            (None, None) => return false,
+        };
+        let source = match source {
+            Ok(source) => source,
+            Err(error) => {
+                self.errors.push(FileError {
+                    file_name: name.to_string(),
+                    error,
+                });
+                return false;
+            }
+        };
+        let parsed = parsed.unwrap_or_else(|| Parser::for_buffer(&source).parse());
+        let ast = match parsed {
+            Ok(ast) => ast,
+            Err(errors) => {
+                self.errors
+                    .extend(errors.into_iter().map(|error| FileError {
+                        file_name: name.to_string(),
+                        error,
+                    }));
+                return false;
+            }
+        };
+
+        self.in_progress.push(name.to_string());
+        let mut success = true;
+        for import in &ast.imports {
+            if !self.load_internal(&import.path.value) {
+                self.errors.push(FileError {
+                    file_name: name.to_string(),
+                    error: RsProtocError::ValidationError(
+                        format!(
+                            "Import \"{}\" was not found or had errors",
+                            import.path.value
+                        ),
+                        import.path.metadata.clone(),
+                    ),
+                });
+                success = false;
+            }
+        }
+        self.in_progress.pop();
+
+        if let Err(errors) = validator::validate(&ast) {
+            self.errors
+                .extend(errors.into_iter().map(|error| FileError {
+                    file_name: name.to_string(),
+                    error,
+                }));
+            success = false;
+        }
+        self.files.push(LoadedFile {
+            name: name.to_string(),
+            path,
+            source,
+            ast,
+        });
+        success
+    }
+
+    pub fn finish(self) -> std::result::Result<Vec<LoadedFile>, Vec<FileError>> {
+        let (files, errors) = self.finish_partial();
+        if errors.is_empty() {
+            Ok(files)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Every file that could be parsed along with the errors, for tools that keep going
+    /// on broken input
+    pub fn finish_partial(self) -> (Vec<LoadedFile>, Vec<FileError>) {
+        let (files, errors, _) = self.into_parts();
+        (files, errors)
+    }
+
+    /// Like `finish_partial`, along with every file read to report the errors against
+    pub fn into_parts(self) -> (Vec<LoadedFile>, Vec<FileError>, SourceRegistry) {
+        (self.files, self.errors, self.sources)
+    }
+}
+
+/// A schema built from .proto files on disk
+pub struct LoadedSchema {
+    pub schema: Schema,
+    /// Names of the input files within the schema
+    pub input_names: Vec<String>,
+    /// Every file that was read, including the imported ones
+    pub paths: Vec<PathBuf>,
+}
+
+/// Load `input_files` along with everything they import and resolve them into a schema. The
+/// files read are registered in `sources`, errors point into them.
+pub fn load_schema(
+    input_files: &[String],
+    include_paths: &[PathBuf],
+    sources: &mut SourceRegistry,
+) -> std::result::Result<LoadedSchema, Vec<FileError>> {
+    let mut loader = Loader::with_sources(include_paths.to_vec(), std::mem::take(sources));
+    let mut input_names = Vec::new();
+    let mut errors = Vec::new();
+    for input_file in input_files {
+        if !loader.exists(Path::new(input_file)) {
+            errors.push(FileError {
+                file_name: input_file.clone(),
+                error: RsProtocError::FilesystemError("No such file or directory".to_string()),
+            });
+            continue;
+        }
+        match loader.virtual_name(input_file) {
+            Some(name) => input_names.push(name),
+            None => errors.push(FileError {
+                file_name: input_file.clone(),
+                error: RsProtocError::FilesystemError(
+                    "File does not reside within any path specified using --proto_path".to_string(),
+                ),
+            }),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    for name in &input_names {
+        loader.load(name);
+    }
+    let (files, errors, loaded_sources) = loader.into_parts();
+    *sources = loaded_sources;
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let schema = resolve(&files)?;
+    Ok(LoadedSchema {
+        schema,
+        input_names,
+        paths: files.into_iter().map(|file| file.path).collect(),
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SymbolKind {
+    Package,
+    Message,
+    Enum,
+    Service,
+}
+
+/// A package or type along with where it was defined
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    /// Name of the file defining the symbol, the first one for packages
+    pub file_name: String,
+    /// Metadata of the token holding the name
+    pub metadata: TokenMetadata,
+}
+
+/// Every package, message, enum and service defined by a set of files, keyed by full name
+#[derive(Default)]
+pub struct SymbolTable {
+    symbols: HashMap<String, Symbol>,
+}
+
+impl SymbolTable {
+    /// Collect the symbols of `files`, along with errors for names defined more than once
+    pub fn build(files: &[LoadedFile]) -> (SymbolTable, Vec<FileError>) {
+        let mut table = SymbolTable::default();
+        let mut errors = Vec::new();
+        for file in files {
+            table.collect_symbols(&file.name, &file.ast, &mut errors);
+        }
+        (table, errors)
+    }
+
+    pub fn get(&self, full_name: &str) -> Option<&Symbol> {
+        self.symbols.get(full_name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Symbol)> {
+        self.symbols.iter()
+    }
+
+    fn define(
+        &mut self,
+        full_name: String,
+        kind: SymbolKind,
+        file_name: &str,
+        metadata: &TokenMetadata,
+        errors: &mut Vec<FileError>,
+    ) {
+        match self.symbols.get(&full_name) {
+            Some(symbol) if symbol.kind == SymbolKind::Package && kind == SymbolKind::Package => {}
+            Some(_) => errors.push(FileError {
+                file_name: file_name.to_string(),
+                error: RsProtocError::ValidationError(
+                    format!("\"{}\" is already defined", full_name),
+                    metadata.clone(),
+                ),
+            }),
+            None => {
+                self.symbols.insert(
+                    full_name,
+                    Symbol {
+                        kind,
+                        file_name: file_name.to_string(),
+                        metadata: metadata.clone(),
+                    },
+                );
+            }
+        }
+    }
+
+    fn collect_symbols(&mut self, file_name: &str, file: &ProtoFile, errors: &mut Vec<FileError>) {
+        let package = match &file.package.name {
+            Some(name) => {
+                let mut scope = String::new();
+                for part in name.value.split('.') {
+                    scope = qualified_name(&scope, part);
+                    self.define(
+                        scope.clone(),
+                        SymbolKind::Package,
+                        file_name,
+                        &name.metadata,
+                        errors,
+                    );
+                }
+                scope
+            }
+            None => String::new(),
+        };
+        self.collect_element_symbols(file_name, &package, &file.package.named_elements, errors);
+    }
+
+    fn collect_element_symbols(
+        &mut self,
+        file_name: &str,
+        scope: &str,
+        elements: &[NamedElement],
+        errors: &mut Vec<FileError>,
+    ) {
+        for element in elements {
+            let full_name = qualified_name(scope, &element.name);
+            let kind = match &element.type_t {
+                ElementType::Message(_) => SymbolKind::Message,
+                ElementType::Enum(_) => SymbolKind::Enum,
+                ElementType::Service(_) => SymbolKind::Service,
+                _ => continue,
+            };
+            self.define(
+                full_name.clone(),
+                kind,
+                file_name,
+                &element.metadata,
+                errors,
+            );
+            if let ElementType::Message(message) = &element.type_t {
+                self.collect_element_symbols(
+                    file_name,
+                    &full_name,
+                    &message.named_elements,
+                    errors,
+                );
+            }
+        }
+    }
+
+    /// Find the symbol a possibly relative `name` refers to from within `scope`, following
+    /// the C++ like scoping rules of protobuf where inner scopes are searched first
+    pub fn lookup(&self, scope: &str, name: &str) -> Option<(String, SymbolKind)> {
+        if let Some(full_name) = name.strip_prefix('.') {
+            return self
+                .symbols
+                .get(full_name)
+                .map(|symbol| (full_name.to_string(), symbol.kind));
+        }
+        let first_part = name.split('.').next().unwrap_or(name);
+        let mut scope = scope;
+        loop {
+            let candidate = qualified_name(scope, first_part);
+            if let Some(symbol) = self.symbols.get(&candidate) {
+                if first_part.len() == name.len() {
+                    if symbol.kind != SymbolKind::Package {
+                        return Some((candidate, symbol.kind));
+                    }
+                } else if matches!(symbol.kind, SymbolKind::Package | SymbolKind::Message) {
+                    // The first part names a scope, the rest of the name has to be inside it
+                    let full_name = qualified_name(scope, name);
+                    return self
+                        .symbols
+                        .get(&full_name)
+                        .map(|symbol| (full_name, symbol.kind));
+                }
+            }
+            if scope.is_empty() {
+                return None;
+            }
+            scope = parent_scope(scope);
+        }
+    }
+}
+
+pub fn qualified_name(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+fn parent_scope(scope: &str) -> &str {
+    match scope.rfind('.') {
+        Some(index) => &scope[..index],
+        None => "",
+    }
+}
+
+/// A field or extension as seen by custom option values
+#[derive(Clone, Debug)]
+struct OptionField {
+    name: String,
+    number: i32,
+    repeated: bool,
+    field_type: FieldType,
+    /// Full name of the extended message, for extensions
+    extendee: Option<String>,
+}
+
+/// The declarations custom option values are checked against, collected from every file before
+/// lowering since an option can use an extension declared further down in its file
+#[derive(Default)]
+struct OptionTypes {
+    /// Extensions by full name
+    extensions: HashMap<String, OptionField>,
+    /// Fields by the full name of their message, including the fields of map entries
+    messages: HashMap<String, Vec<OptionField>>,
+    /// (name, number) values by the full name of their enum
+    enums: HashMap<String, Vec<(String, i32)>>,
+}
+
+impl OptionTypes {
+    fn build(files: &[LoadedFile], symbols: &SymbolTable) -> OptionTypes {
+        let mut option_types = OptionTypes::default();
+        for file in files {
+            let package = match &file.ast.package.name {
+                Some(name) => name.value.clone(),
+                None => String::new(),
+            };
+            option_types.collect(symbols, &package, &file.ast.package.named_elements);
+        }
+        option_types
+    }
+
+    fn collect(&mut self, symbols: &SymbolTable, scope: &str, elements: &[NamedElement]) {
+        for element in elements {
+            match &element.type_t {
+                ElementType::Message(message) => {
+                    let full_name = qualified_name(scope, &element.name);
+                    let mut fields = Vec::new();
+                    for child in &message.named_elements {
+                        let oneof_fields = match &child.type_t {
+                            ElementType::Field(_) => std::slice::from_ref(child),
+                            ElementType::OneOf(oneof) => oneof.fields.as_slice(),
+                            _ => continue,
+                        };
+                        for field in oneof_fields {
+                            if let ElementType::Field(payload) = &field.type_t {
+                                fields.extend(self.field(symbols, &full_name, field, payload));
+                            }
+                        }
+                    }
+                    self.messages.insert(full_name.clone(), fields);
+                    self.collect(symbols, &full_name, &message.named_elements);
+                }
+                ElementType::Enum(payload) => {
+                    let values = payload
+                        .values
+                        .iter()
+                        .filter_map(|value| match &value.type_t {
+                            ElementType::EnumValue(value_payload) => {
+                                Some((value.name.clone(), value_payload.number.value as i32))
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                    self.enums
+                        .insert(qualified_name(scope, &element.name), values);
+                }
+                ElementType::Extension(extension) => {
+                    let extendee = symbols
+                        .lookup(scope, &element.name)
+                        .map(|(full_name, _)| full_name);
+                    for field in &extension.fields {
+                        if let ElementType::Field(payload) = &field.type_t {
+                            if let Some(mut lowered) = self.field(symbols, scope, field, payload) {
+                                lowered.extendee = extendee.clone();
+                                self.extensions
+                                    .insert(qualified_name(scope, &field.name), lowered);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Describe a field declared in `scope`, unresolved types are left out as the resolver
+    /// reports them when lowering the field
+    fn field(
+        &mut self,
+        symbols: &SymbolTable,
+        scope: &str,
+        element: &NamedElement,
+        payload: &FieldPayload,
+    ) -> Option<OptionField> {
+        let resolve = |field_type: &crate::ast_elements::FieldType| match field_type {
+            crate::ast_elements::FieldType::Scalar(scalar_type) => {
+                Some(FieldType::Scalar(*scalar_type))
+            }
+            crate::ast_elements::FieldType::Named(name) => match symbols.lookup(scope, name) {
+                Some((full_name, SymbolKind::Message)) => Some(FieldType::Message(full_name)),
+                Some((full_name, SymbolKind::Enum)) => Some(FieldType::Enum(full_name)),
+                _ => None,
+            },
+            crate::ast_elements::FieldType::Map(_, _) => None,
+        };
+        let mut repeated = payload.label == FieldLabel::Repeated;
+        let field_type = match &payload.field_type.value {
+            crate::ast_elements::FieldType::Map(key_type, value_type) => {
+                let entry_name = qualified_name(scope, &map_entry_name(&element.name));
+                let entry_field = |name: &str, number: i32, field_type| OptionField {
+                    name: name.to_string(),
+                    number,
+                    repeated: false,
+                    field_type,
+                    extendee: None,
+                };
+                let entry_fields = vec![
+                    entry_field("key", 1, resolve(&key_type.value)?),
+                    entry_field("value", 2, resolve(&value_type.value)?),
+                ];
+                self.messages.insert(entry_name.clone(), entry_fields);
+                repeated = true;
+                FieldType::Message(entry_name)
+            }
+            field_type => resolve(field_type)?,
+        };
+        Some(OptionField {
+            name: element.name.clone(),
+            number: payload.number.value as i32,
+            repeated,
+            field_type,
+            extendee: None,
+        })
+    }
+
+    /// Find the extension a possibly relative `name` refers to from within `scope`
+    fn find_extension(&self, scope: &str, name: &str) -> Option<&OptionField> {
+        if let Some(full_name) = name.strip_prefix('.') {
+            return self.extensions.get(full_name);
+        }
+        let mut scope = scope;
+        loop {
+            if let Some(extension) = self.extensions.get(&qualified_name(scope, name)) {
+                return Some(extension);
+            }
+            if scope.is_empty() {
+                return None;
+            }
+            scope = parent_scope(scope);
+        }
+    }
+
+    /// Find a field of `message` by name, or one of its extensions by `[name]`
+    fn find_field(
+        &self,
+        scope: &str,
+        message: &str,
+        name: &OptionNamePart,
+    ) -> Option<&OptionField> {
+        match name {
+            OptionNamePart::Simple(name) => self
+                .messages
+                .get(message)?
+                .iter()
+                .find(|field| field.name == *name),
+            OptionNamePart::Extension(name) => self
+                .find_extension(scope, name)
+                .filter(|extension| extension.extendee.as_deref() == Some(message)),
+        }
+    }
+}
+
+/// Name of the message synthesized for a map field, `foo_bar` becomes `FooBarEntry`
+pub fn map_entry_name(field_name: &str) -> String {
+    let mut output = String::with_capacity(field_name.len() + 5);
+    let mut capitalize_next = true;
+    for ch in field_name.chars() {
+        if ch == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            output.push(ch.to_ascii_uppercase());
+            capitalize_next = false;
+        } else {
+            output.push(ch);
+        }
+    }
+    output.push_str("Entry");
+    output
+}
+
+/// Resolve type references across the loaded files and build the schema
+pub fn resolve(files: &[LoadedFile]) -> std::result::Result<Schema, Vec<FileError>> {
+    let (symbols, errors) = SymbolTable::build(files);
+    let option_types = OptionTypes::build(files, &symbols);
+    let mut resolver = Resolver {
+        symbols,
+        option_types,
+        errors,
+        current_file: String::new(),
+        current_source: None,
+        syntax: Syntax::Proto3,
+        locations: Vec::new(),
+    };
+    let mut schema = Schema::default();
+    for file in files {
+        resolver.current_file = file.name.clone();
+        schema.files.push(resolver.lower_file(file));
+    }
+    if resolver.errors.is_empty() {
+        Ok(schema)
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+struct Resolver {
+    symbols: SymbolTable,
+    option_types: OptionTypes,
+    errors: Vec<FileError>,
+    current_file: String,
+    /// The file being lowered, default values of float fields are copied from its text
+    current_source: Option<Arc<SourceBuffer>>,
+    syntax: Syntax,
+    /// Source locations of the file being lowered
+    locations: Vec<PendingLocation>,
+}
+
+/// A source location of the file being lowered, before it is put in order and its byte range
+/// turned into lines and columns
+struct PendingLocation {
+    /// Where protoc creates the location, which decides the order of the locations
+    order: usize,
+    path: Vec<i32>,
+    range: Range<usize>,
+    comments: Option<Comments>,
+}
+
+/// What a field is declared in
+enum FieldParent<'a> {
+    /// The message that gets the entry types of map fields
+    Message(&'a mut MessageDescriptor),
+    /// An extend block, along with its extendee
+    Extend(&'a TokenMetadata),
+}
+
+fn token_range(metadata: &TokenMetadata) -> Range<usize> {
+    metadata.offset()..metadata.end_offset()
+}
+
+/// From the start of `start` to the end of `end`
+fn span_range(start: &TokenMetadata, end: &TokenMetadata) -> Range<usize> {
+    start.offset()..end.end_offset()
+}
+
+fn child_path(path: &[i32], field_number: u64) -> Vec<i32> {
+    let mut child_path = path.to_vec();
+    child_path.push(field_number as i32);
+    child_path
+}
+
+impl Resolver {
+    fn report(&mut self, metadata: &TokenMetadata, message: String) {
+        self.errors.push(FileError {
+            file_name: self.current_file.clone(),
+            error: RsProtocError::ValidationError(message, metadata.clone()),
+        });
+    }
+
+    fn add_location(&mut self, path: &[i32], range: Range<usize>, comments: Option<&Comments>) {
+        self.add_location_at(range.start, path, range, comments);
+    }
+
+    /// Add a location protoc creates at `order` rather than at the start of its range
+    fn add_location_at(
+        &mut self,
+        order: usize,
+        path: &[i32],
+        range: Range<usize>,
+        comments: Option<&Comments>,
+    ) {
+        self.locations.push(PendingLocation {
+            order,
+            path: path.to_vec(),
+            range,
+            comments: comments.cloned(),
+        });
+    }
+
+    /// Put the locations in the order protoc creates them, parents before their children, and
+    /// add the location of the whole file
+    fn take_locations(&mut self, source: &str) -> Vec<SourceLocation> {
+        let mut locations = std::mem::take(&mut self.locations);
+        let start = locations.iter().map(|location| location.range.start).min();
+        let end = locations.iter().map(|location| location.range.end).max();
+        if let (Some(start), Some(end)) = (start, end) {
+            locations.push(PendingLocation {
+                order: start,
+                path: Vec::new(),
+                range: start..end,
+                comments: None,
+            });
+        }
+        locations.sort_by_key(|location| (location.order, location.path.len()));
+        let source_map = SourceMap::new(source);
+        locations
+            .into_iter()
+            .map(|location| {
+                let start = source_map.protoc_line_column(location.range.start);
+                let end = source_map.protoc_line_column(location.range.end);
+                let mut span = vec![start.line as i32, start.column as i32];
+                if end.line != start.line {
+                    span.push(end.line as i32);
+                }
+                span.push(end.column as i32);
+                let comments = location.comments.unwrap_or_default();
+                SourceLocation {
+                    path: location.path,
+                    span,
+                    leading_comments: comments.leading,
+                    trailing_comments: comments.trailing,
+                    leading_detached_comments: comments.detached,
+                }
+            })
+            .collect()
+    }
+
+    /// The location of a declaration and the one of its name, at `name_field` in the descriptor
+    fn add_declaration_locations(&mut self, path: &[i32], element: &NamedElement, name_field: u64) {
+        self.add_location(
+            path,
+            span_range(&element.start, &element.end),
+            Some(&element.comments),
+        );
+        self.add_location(
+            &child_path(path, name_field),
+            token_range(&element.metadata),
+            None,
+        );
+    }
+
+    fn resolve_type(
+        &mut self,
+        scope: &str,
+        name: &str,
+        metadata: &TokenMetadata,
+    ) -> Option<FieldType> {
+        match self.symbols.lookup(scope, name) {
+            Some((full_name, SymbolKind::Message)) => Some(FieldType::Message(full_name)),
+            Some((full_name, SymbolKind::Enum)) => Some(FieldType::Enum(full_name)),
+            Some((full_name, _)) => {
+                self.report(metadata, format!("\"{}\" is not a type", full_name));
+                None
+            }
+            None => {
+                self.report(metadata, format!("\"{}\" is not defined", name));
+                None
+            }
+        }
+    }
+
+    fn resolve_message_type(
+        &mut self,
+        scope: &str,
+        name: &str,
+        metadata: &TokenMetadata,
+    ) -> String {
+        match self.resolve_type(scope, name, metadata) {
+            Some(FieldType::Message(full_name)) => full_name,
+            Some(_) => {
+                self.report(metadata, format!("\"{}\" is not a message type", name));
+                name.to_string()
+            }
+            None => name.to_string(),
+        }
+    }
+
+    /// Check options against the built-in options of `kind`, or against their extension
+    /// declarations for custom options, whose names are looked up from `scope`. `path` leads to
+    /// the options message, `brackets` span compact options, each option statement is a location
+    /// of the options message otherwise.
+    fn lower_options(
+        &mut self,
+        scope: &str,
+        options: &[OptionStatement],
+        kind: OptionsKind,
+        path: &[i32],
+        brackets: Option<&TokenMetadata>,
+    ) -> Vec<SchemaOption> {
+        if let Some(brackets) = brackets {
+            self.add_location(path, token_range(brackets), None);
+        }
+        let mut lowered_options: Vec<SchemaOption> = Vec::new();
+        for option in options {
+            if lowered_options
+                .iter()
+                .any(|lowered| lowered.name == option.name.value)
+            {
+                self.report(
+                    &option.name.metadata,
+                    format!(
+                        "Option \"{}\" was already set",
+                        option_name_to_string(&option.name.value)
+                    ),
+                );
+                continue;
+            }
+            let mut custom = None;
+            let mut option_path = path.to_vec();
+            match option.name.value.as_slice() {
+                [OptionNamePart::Simple(name)]
+                    if kind == OptionsKind::Field && (name == "json_name" || name == "default") =>
+                {
+                    continue; // Stored in FieldDescriptorProto rather than the options
+                }
+                [OptionNamePart::Simple(name)] if name == "map_entry" => {
+                    self.report(
+                        &option.name.metadata,
+                        "map_entry should not be set explicitly, use map<KeyType, ValueType> instead"
+                            .to_string(),
+                    );
+                    continue;
+                }
+                [OptionNamePart::Simple(name)] => match find_builtin_option(kind, name) {
+                    Some(builtin) => {
+                        option_path.push(builtin.number as i32);
+                        let is_valid = match (builtin.option_type, &option.value.value) {
+                            (BuiltinOptionType::Bool, Constant::Bool(_)) => true,
+                            (BuiltinOptionType::String, Constant::String(_)) => true,
+                            (BuiltinOptionType::Enum(values), Constant::Identifier(identifier)) => {
+                                values.iter().any(|(name, _)| name == identifier)
+                            }
+                            _ => false,
+                        };
+                        if !is_valid {
+                            let expected = match (builtin.option_type, &option.value.value) {
+                                (BuiltinOptionType::String, Constant::Bytes(_)) => {
+                                    "valid UTF-8".to_string()
+                                }
+                                (BuiltinOptionType::Bool, _) => "\"true\" or \"false\"".to_string(),
+                                (BuiltinOptionType::String, _) => "a string".to_string(),
+                                (BuiltinOptionType::Enum(values), _) => values
+                                    .iter()
+                                    .map(|(name, _)| *name)
+                                    .collect::<Vec<&str>>()
+                                    .join(", "),
+                            };
+                            self.report(
+                                &option.value.metadata,
+                                format!("Value must be {} for option \"{}\"", expected, name),
+                            );
+                            continue;
+                        }
+                    }
+                    None => {
+                        self.report(
+                            &option.name.metadata,
+                            format!("Option \"{}\" unknown", name),
+                        );
+                        continue;
+                    }
+                },
+                [OptionNamePart::Simple(_), ..] => {
+                    self.report(
+                        &option.name.metadata,
+                        format!(
+                            "Option \"{}\" unknown",
+                            option_name_to_string(&option.name.value)
+                        ),
+                    );
+                    continue;
+                }
+                _ => match self.resolve_custom_option(scope, option, kind) {
+                    Some(resolved) => {
+                        let (mut number, mut value) = (resolved.0, &resolved.1);
+                        for _ in 1..option.name.value.len() {
+                            option_path.push(number);
+                            let OptionValue::Message(fields) = value else {
+                                unreachable!("The parts of a name lead through message fields")
+                            };
+                            (number, value) = (fields[0].0, &fields[0].1);
+                        }
+                        option_path.push(number);
+                        custom = Some(resolved);
+                    }
+                    None => continue,
+                },
+            }
+            if brackets.is_none() {
+                self.add_location(path, token_range(&option.span), None);
+            }
+            self.add_location(&option_path, token_range(&option.span), None);
+            lowered_options.push(SchemaOption {
+                name: option.name.value.clone(),
+                value: option.value.value.clone(),
+                custom,
+            });
+        }
+        lowered_options
+    }
+
+    /// Resolve a custom option such as `(my.ext).field = value` to its extension and check
+    /// the value against the type of the last field of the name
+    fn resolve_custom_option(
+        &mut self,
+        scope: &str,
+        option: &OptionStatement,
+        kind: OptionsKind,
+    ) -> Option<(i32, OptionValue)> {
+        let name = &option.name.value;
+        let [OptionNamePart::Extension(extension_name), field_names @ ..] = name.as_slice() else {
+            unreachable!("Options starting with a simple name are built-in options")
+        };
+        let Some(extension) = self
+            .option_types
+            .find_extension(scope, extension_name)
+            .cloned()
+        else {
+            self.report(
+                &option.name.metadata,
+                format!("Option \"({})\" unknown", extension_name),
+            );
+            return None;
+        };
+        // Extensions of types that failed to resolve were already reported
+        let extendee = extension.extendee.as_deref()?;
+        if extendee != kind.message_name() {
+            self.report(
+                &option.name.metadata,
+                format!(
+                    "Option \"({})\" extends \"{}\", it cannot be used where a \"{}\" option is expected",
+                    extension_name,
+                    extendee,
+                    kind.message_name()
+                ),
+            );
+            return None;
+        }
+        let mut fields = vec![extension];
+        for (index, field_name) in field_names.iter().enumerate() {
+            let parent = &fields[fields.len() - 1];
+            let parent_name = option_name_to_string(&name[..=index]);
+            let message = match &parent.field_type {
+                FieldType::Message(message) if !parent.repeated => message.clone(),
+                FieldType::Message(_) => {
+                    self.report(
+                        &option.name.metadata,
+                        format!(
+                            "Option \"{}\" is repeated, set it with a message literal instead",
+                            parent_name
+                        ),
+                    );
+                    return None;
+                }
+                _ => {
+                    self.report(
+                        &option.name.metadata,
+                        format!("Option \"{}\" is not a message", parent_name),
+                    );
+                    return None;
+                }
+            };
+            match self
+                .option_types
+                .find_field(scope, &message, field_name)
+                .cloned()
+            {
+                Some(field) => fields.push(field),
+                None => {
+                    self.report(
+                        &option.name.metadata,
+                        format!(
+                            "Message \"{}\" has no field \"{}\"",
+                            message,
+                            option_name_to_string(std::slice::from_ref(field_name))
+                        ),
+                    );
+                    return None;
+                }
+            }
+        }
+        let last = &fields[fields.len() - 1];
+        let mut value =
+            self.check_option_value(scope, last, &option_name_to_string(name), &option.value)?;
+        for field in fields[1..].iter().rev() {
+            value = OptionValue::Message(vec![(field.number, value)]);
+        }
+        Some((fields[0].number, value))
+    }
+
+    /// Check a single value of `field`, `name` is how the field is referred to in errors
+    fn check_option_value(
+        &mut self,
+        scope: &str,
+        field: &OptionField,
+        name: &str,
+        value: &Spanned<Constant>,
+    ) -> Option<OptionValue> {
+        let expected = match (&field.field_type, &value.value) {
+            (FieldType::Scalar(scalar_type), constant) => {
+                match check_scalar(*scalar_type, constant) {
+                    Ok(constant) => return Some(OptionValue::Scalar(*scalar_type, constant)),
+                    Err(expected) => expected,
+                }
+            }
+            (FieldType::Enum(enum_name), constant) => {
+                let values = self.option_types.enums.get(enum_name)?;
+                if let Constant::Identifier(identifier) = constant {
+                    if let Some((_, number)) = values.iter().find(|(name, _)| name == identifier) {
+                        return Some(OptionValue::Enum(*number));
+                    }
+                }
+                values
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            }
+            (FieldType::Message(message), Constant::Message(fields)) => {
+                let message = message.clone();
+                return self.check_message_literal(scope, &message, fields);
+            }
+            (FieldType::Message(_), _) => "a message literal".to_string(),
+        };
+        self.report(
+            &value.metadata,
+            format!("Value must be {} for option \"{}\"", expected, name),
+        );
+        None
+    }
+
+    fn check_message_literal(
+        &mut self,
+        scope: &str,
+        message: &str,
+        literal_fields: &[MessageLiteralField],
+    ) -> Option<OptionValue> {
+        let mut values = Vec::new();
+        let mut is_valid = true;
+        let mut set_numbers = Vec::new();
+        for literal_field in literal_fields {
+            let field_name = match &literal_field.name.value {
+                OptionNamePart::Simple(name) => name.clone(),
+                OptionNamePart::Extension(name) => format!("[{}]", name),
+            };
+            let Some(field) = self
+                .option_types
+                .find_field(scope, message, &literal_field.name.value)
+                .cloned()
+            else {
+                self.report(
+                    &literal_field.name.metadata,
+                    format!("Message \"{}\" has no field \"{}\"", message, field_name),
+                );
+                is_valid = false;
+                continue;
+            };
+            if !field.repeated {
+                if set_numbers.contains(&field.number) {
+                    self.report(
+                        &literal_field.name.metadata,
+                        format!("Field \"{}\" was already set", field_name),
+                    );
+                    is_valid = false;
+                    continue;
+                }
+                set_numbers.push(field.number);
+            }
+            let elements = match &literal_field.value.value {
+                Constant::List(elements) if field.repeated => elements.iter().collect(),
+                Constant::List(_) => {
+                    self.report(
+                        &literal_field.value.metadata,
+                        format!(
+                            "Field \"{}\" is not repeated, it cannot be a list",
+                            field_name
+                        ),
+                    );
+                    is_valid = false;
+                    continue;
+                }
+                _ => vec![&literal_field.value],
+            };
+            for element in elements {
+                match self.check_option_value(scope, &field, &field_name, element) {
+                    Some(value) => values.push((field.number, value)),
+                    None => is_valid = false,
+                }
+            }
+        }
+        is_valid.then_some(OptionValue::Message(values))
+    }
+
+    fn lower_file(&mut self, file: &LoadedFile) -> FileDescriptor {
+        self.locations = Vec::new();
+        let ast = &file.ast;
+        self.current_source = Some(file.source.clone());
+        self.syntax = match &ast.syntax {
+            Some(syntax) if syntax.value == "proto3" => Syntax::Proto3,
+            _ => Syntax::Proto2,
+        };
+        if let Some(span) = &ast.syntax_span {
+            self.add_location(
+                &[file_descriptor_proto::SYNTAX as i32],
+                token_range(span),
+                None,
+            );
+        }
+        if let Some(span) = &ast.package.span {
+            self.add_location(
+                &[file_descriptor_proto::PACKAGE as i32],
+                token_range(span),
+                Some(&ast.package.comments),
+            );
+        }
+        let package = match &ast.package.name {
+            Some(name) => name.value.clone(),
+            None => String::new(),
+        };
+        let mut descriptor = FileDescriptor {
+            name: file.name.clone(),
+            package: package.clone(),
+            syntax: self.syntax,
+            dependencies: Vec::new(),
+            public_dependencies: Vec::new(),
+            weak_dependencies: Vec::new(),
+            messages: Vec::new(),
+            enums: Vec::new(),
+            services: Vec::new(),
+            extensions: Vec::new(),
+            options: self.lower_options(
+                &package,
+                &ast.options,
+                OptionsKind::File,
+                &[file_descriptor_proto::OPTIONS as i32],
+                None,
+            ),
+            source_code_info: Vec::new(),
+            source_file: file.source.id(),
+        };
+        for (index, import) in ast.imports.iter().enumerate() {
+            self.add_location(
+                &[file_descriptor_proto::DEPENDENCY as i32, index as i32],
+                token_range(&import.span),
+                Some(&import.comments),
+            );
+            match import.kind {
+                ImportKind::Public => descriptor.public_dependencies.push(index),
+                ImportKind::Weak => descriptor.weak_dependencies.push(index),
+                ImportKind::Default => {}
+            }
+            descriptor.dependencies.push(import.path.value.clone());
+        }
+        for element in &ast.package.named_elements {
+            match &element.type_t {
+                ElementType::Message(message) => {
+                    let path = [
+                        file_descriptor_proto::MESSAGE_TYPE as i32,
+                        descriptor.messages.len() as i32,
+                    ];
+                    let message = self.lower_message(&package, element, message, &path);
+                    descriptor.messages.push(message);
+                }
+                ElementType::Enum(enum_payload) => {
+                    let path = [
+                        file_descriptor_proto::ENUM_TYPE as i32,
+                        descriptor.enums.len() as i32,
+                    ];
+                    let enum_descriptor = self.lower_enum(&package, element, enum_payload, &path);
+                    descriptor.enums.push(enum_descriptor);
+                }
+                ElementType::Service(service) => {
+                    let path = [
+                        file_descriptor_proto::SERVICE as i32,
+                        descriptor.services.len() as i32,
+                    ];
+                    let service = self.lower_service(&package, element, service, &path);
+                    descriptor.services.push(service);
+                }
+                ElementType::Extension(extension) => {
+                    self.add_location(
+                        &[file_descriptor_proto::EXTENSION as i32],
+                        span_range(&element.start, &element.end),
+                        Some(&element.comments),
+                    );
+                    let extendee =
+                        self.resolve_message_type(&package, &element.name, &element.metadata);
+                    for field in &extension.fields {
+                        if let ElementType::Field(payload) = &field.type_t {
+                            let path = [
+                                file_descriptor_proto::EXTENSION as i32,
+                                descriptor.extensions.len() as i32,
+                            ];
+                            let mut lowered = self.lower_field(
+                                &package,
+                                field,
+                                payload,
+                                &path,
+                                FieldParent::Extend(&element.metadata),
+                            );
+                            lowered.extendee = Some(extendee.clone());
+                            descriptor.extensions.push(lowered);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        descriptor.source_code_info = self.take_locations(file.source.text());
+        descriptor
+    }
+
+    fn lower_message(
+        &mut self,
+        scope: &str,
+        element: &NamedElement,
+        payload: &MessagePayload,
+        path: &[i32],
+    ) -> MessageDescriptor {
+        self.add_declaration_locations(path, element, descriptor_proto::NAME);
+        let full_name = qualified_name(scope, &element.name);
+        let mut message = MessageDescriptor {
+            name: element.name.clone(),
+            full_name: full_name.clone(),
+            fields: Vec::new(),
+            nested_messages: Vec::new(),
+            enums: Vec::new(),
+            extensions: Vec::new(),
+            oneofs: Vec::new(),
+            extension_ranges: payload
+                .extension_ranges
+                .iter()
+                .map(|range| range.value)
+                .collect(),
+            reserved_ranges: Vec::new(),
+            reserved_names: Vec::new(),
+            options: self.lower_options(
+                &full_name,
+                &payload.options,
+                OptionsKind::Message,
+                &child_path(path, descriptor_proto::OPTIONS),
+                None,
+            ),
+        };
+        for reserved in &payload.reserved {
+            match reserved {
+                Reserved::Range(range) => message.reserved_ranges.push(range.value),
+                Reserved::Name(name) => message.reserved_names.push(name.value.clone()),
+            }
+        }
+        let child_path = |field_number: u64, index: usize| -> Vec<i32> {
+            let mut child_path = child_path(path, field_number);
+            child_path.push(index as i32);
+            child_path
+        };
+        for element in &payload.named_elements {
+            match &element.type_t {
+                ElementType::Field(field) => {
+                    let field_path = child_path(descriptor_proto::FIELD, message.fields.len());
+                    let field = self.lower_field(
+                        &full_name,
+                        element,
+                        field,
+                        &field_path,
+                        FieldParent::Message(&mut message),
+                    );
+                    message.fields.push(field);
+                }
+                ElementType::OneOf(oneof) => {
+                    let oneof_index = message.oneofs.len();
+                    let oneof_path = child_path(descriptor_proto::ONEOF_DECL, oneof_index);
+                    self.add_declaration_locations(
+                        &oneof_path,
+                        element,
+                        oneof_descriptor_proto::NAME,
+                    );
+                    let options = self.lower_options(
+                        &full_name,
+                        &oneof.options,
+                        OptionsKind::Oneof,
+                        &self::child_path(&oneof_path, oneof_descriptor_proto::OPTIONS),
+                        None,
+                    );
+                    message.oneofs.push(OneofDescriptor {
+                        name: element.name.clone(),
+                        options,
+                    });
+                    for oneof_field in &oneof.fields {
+                        if let ElementType::Field(field) = &oneof_field.type_t {
+                            let field_path =
+                                child_path(descriptor_proto::FIELD, message.fields.len());
+                            let mut field = self.lower_field(
+                                &full_name,
+                                oneof_field,
+                                field,
+                                &field_path,
+                                FieldParent::Message(&mut message),
+                            );
+                            field.oneof_index = Some(oneof_index);
+                            message.fields.push(field);
+                        }
+                    }
+                }
+                ElementType::Message(nested) => {
+                    let nested_path =
+                        child_path(descriptor_proto::NESTED_TYPE, message.nested_messages.len());
+                    let nested = self.lower_message(&full_name, element, nested, &nested_path);
+                    message.nested_messages.push(nested);
+                }
+                ElementType::Enum(enum_payload) => {
+                    let enum_path = child_path(descriptor_proto::ENUM_TYPE, message.enums.len());
+                    let enum_descriptor =
+                        self.lower_enum(&full_name, element, enum_payload, &enum_path);
+                    message.enums.push(enum_descriptor);
+                }
+                ElementType::Extension(extension) => {
+                    self.add_location(
+                        &self::child_path(path, descriptor_proto::EXTENSION),
+                        span_range(&element.start, &element.end),
+                        Some(&element.comments),
+                    );
+                    let extendee =
+                        self.resolve_message_type(&full_name, &element.name, &element.metadata);
+                    for field in &extension.fields {
+                        if let ElementType::Field(payload) = &field.type_t {
+                            let field_path =
+                                child_path(descriptor_proto::EXTENSION, message.extensions.len());
+                            let mut lowered = self.lower_field(
+                                &full_name,
+                                field,
+                                payload,
+                                &field_path,
+                                FieldParent::Extend(&element.metadata),
+                            );
+                            lowered.extendee = Some(extendee.clone());
+                            message.extensions.push(lowered);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        // proto3 optional fields are wrapped in synthetic oneofs that follow every real oneof
+        for field in message.fields.iter_mut() {
+            if field.proto3_optional {
+                field.oneof_index = Some(message.oneofs.len());
+                message.oneofs.push(OneofDescriptor {
+                    name: format!("_{}", field.name),
+                    options: Vec::new(),
+                });
+            }
+        }
+        message
+    }
+
+    fn lower_field(
+        &mut self,
+        scope: &str,
+        element: &NamedElement,
+        payload: &FieldPayload,
+        path: &[i32],
+        parent: FieldParent,
+    ) -> FieldDescriptor {
+        self.add_location(
+            path,
+            span_range(&element.start, &element.end),
+            Some(&element.comments),
+        );
+        if let FieldParent::Extend(extendee) = &parent {
+            self.add_location_at(
+                element.start.offset(),
+                &child_path(path, field_descriptor_proto::EXTENDEE),
+                token_range(extendee),
+                None,
+            );
+        }
+        if payload.label != FieldLabel::None {
+            self.add_location(
+                &child_path(path, field_descriptor_proto::LABEL),
+                token_range(&element.start),
+                None,
+            );
+        }
+        let type_field = match payload.field_type.value {
+            crate::ast_elements::FieldType::Scalar(_) => field_descriptor_proto::TYPE,
+            _ => field_descriptor_proto::TYPE_NAME,
+        };
+        self.add_location(
+            &child_path(path, type_field),
+            token_range(&payload.field_type.metadata),
+            None,
+        );
+        self.add_location(
+            &child_path(path, field_descriptor_proto::NAME),
+            token_range(&element.metadata),
+            None,
+        );
+        self.add_location(
+            &child_path(path, field_descriptor_proto::NUMBER),
+            token_range(&payload.number.metadata),
+            None,
+        );
+        let mut label = match payload.label {
+            FieldLabel::None | FieldLabel::Optional => Label::Optional,
+            FieldLabel::Repeated => Label::Repeated,
+            FieldLabel::Required => Label::Required,
+        };
+        let field_type = match &payload.field_type.value {
+            crate::ast_elements::FieldType::Scalar(scalar_type) => FieldType::Scalar(*scalar_type),
+            crate::ast_elements::FieldType::Named(name) => self
+                .resolve_type(scope, name, &payload.field_type.metadata)
+                .unwrap_or_else(|| FieldType::Message(name.clone())),
+            crate::ast_elements::FieldType::Map(key_type, value_type) => {
+                let entry_name = map_entry_name(&element.name);
+                let entry_full_name = qualified_name(scope, &entry_name);
+                let key_type = match &key_type.value {
+                    crate::ast_elements::FieldType::Scalar(scalar_type) => {
+                        FieldType::Scalar(*scalar_type)
+                    }
+                    _ => FieldType::Message(String::new()), // Rejected by the validator
+                };
+                let value_type = match &value_type.value {
+                    crate::ast_elements::FieldType::Scalar(scalar_type) => {
+                        FieldType::Scalar(*scalar_type)
+                    }
+                    crate::ast_elements::FieldType::Named(name) => self
+                        .resolve_type(scope, name, &value_type.metadata)
+                        .unwrap_or_else(|| FieldType::Message(name.clone())),
+                    crate::ast_elements::FieldType::Map(_, _) => {
+                        self.report(
+                            &value_type.metadata,
+                            "Map values cannot be maps".to_string(),
+                        );
+                        FieldType::Message(String::new())
+                    }
+                };
+                let entry_field =
+                    |name: &str, number: i32, field_type: FieldType| FieldDescriptor {
+                        name: name.to_string(),
+                        number,
+                        label: Label::Optional,
+                        field_type,
+                        json_name: name.to_string(),
+                        oneof_index: None,
+                        proto3_optional: false,
+                        extendee: None,
+                        default_value: None,
+                        packed: false,
+                        options: Vec::new(),
+                    };
+                match parent {
+                    FieldParent::Message(message) => {
+                        message.nested_messages.push(MessageDescriptor {
+                            name: entry_name,
+                            full_name: entry_full_name.clone(),
+                            fields: vec![
+                                entry_field("key", 1, key_type),
+                                entry_field("value", 2, value_type),
+                            ],
+                            nested_messages: Vec::new(),
+                            enums: Vec::new(),
+                            extensions: Vec::new(),
+                            oneofs: Vec::new(),
+                            extension_ranges: Vec::new(),
+                            reserved_ranges: Vec::new(),
+                            reserved_names: Vec::new(),
+                            options: vec![SchemaOption {
+                                name: vec![OptionNamePart::Simple("map_entry".to_string())],
+                                value: Constant::Bool(true),
+                                custom: None,
+                            }],
+                        })
+                    }
+                    FieldParent::Extend(_) => self.report(
+                        &payload.field_type.metadata,
+                        "Map fields are not allowed in extensions".to_string(),
+                    ),
+                }
+                label = Label::Repeated;
+                FieldType::Message(entry_full_name)
+            }
+        };
+        let options_path = child_path(path, field_descriptor_proto::OPTIONS);
+        let options = self.lower_options(
+            scope,
+            &payload.options,
+            OptionsKind::Field,
+            &options_path,
+            payload.options_span.as_ref(),
+        );
+        let json_name_option = payload.options.iter().find(|option| {
+            matches!(option.name.value.as_slice(), [OptionNamePart::Simple(name)] if name == "json_name")
+        });
+        if let Some(option) = json_name_option {
+            // The whole assignment, then its value
+            let json_name_path = child_path(path, field_descriptor_proto::JSON_NAME);
+            self.add_location(&json_name_path, token_range(&option.span), None);
+            self.add_location(
+                &json_name_path,
+                option.value.metadata.offset()..option.span.end_offset(),
+                None,
+            );
+        }
+        let default_option = payload.options.iter().find(|option| {
+            matches!(option.name.value.as_slice(), [OptionNamePart::Simple(name)] if name == "default")
+        });
+        let default_value = match default_option {
+            Some(option) => {
+                self.add_location(
+                    &child_path(path, field_descriptor_proto::DEFAULT_VALUE),
+                    token_range(&option.span),
+                    None,
+                );
+                self.lower_default(&field_type, &option.value)
+            }
+            None => None,
+        };
+        let json_name = match find_option(&payload.options, "json_name") {
+            Some(custom) => match &custom.value {
+                Constant::String(custom) => custom.clone(),
+                _ => validator::json_name(&element.name), // Rejected by the validator
+            },
+            None => validator::json_name(&element.name),
+        };
+        FieldDescriptor {
+            name: element.name.clone(),
+            number: payload.number.value as i32,
+            label,
+            packed: is_packed(self.syntax, label, &field_type, &options),
+            field_type,
+            json_name,
+            oneof_index: None,
+            proto3_optional: self.syntax == Syntax::Proto3 && payload.label == FieldLabel::Optional,
+            extendee: None,
+            default_value,
+            options,
+        }
+    }
+
+    /// Check the `default` option of a field against its type and write it the way
+    /// FieldDescriptorProto.default_value holds it
+    fn lower_default(
+        &mut self,
+        field_type: &FieldType,
+        default: &Spanned<Constant>,
+    ) -> Option<String> {
+        let expected = match (field_type, &default.value) {
+            (FieldType::Message(_), _) => {
+                self.report(
+                    &default.metadata,
+                    "Messages can't have default values".to_string(),
+                );
+                return None;
+            }
+            (FieldType::Enum(enum_name), constant) => {
+                let values = self.option_types.enums.get(enum_name)?;
+                if let Constant::Identifier(identifier) = constant {
+                    if values.iter().any(|(name, _)| name == identifier) {
+                        return Some(identifier.clone());
+                    }
+                }
+                values
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            }
+            (FieldType::Scalar(scalar_type), constant) => {
+                match check_scalar(*scalar_type, constant) {
+                    Ok(Constant::Integer(value)) => return Some(value.to_string()),
+                    // protoc keeps floating point defaults as written
+                    Ok(Constant::Float(_)) => {
+                        let text = self.current_source.as_ref()?.text();
+                        let text = &text[default.metadata.offset()..default.metadata.end_offset()];
+                        return Some(text.split_whitespace().collect());
+                    }
+                    Ok(Constant::Bool(value)) => return Some(value.to_string()),
+                    Ok(Constant::String(text)) if *scalar_type == ScalarType::Bytes => {
+                        return Some(c_escape(text.as_bytes()))
+                    }
+                    Ok(Constant::String(text)) => return Some(text),
+                    Ok(Constant::Bytes(bytes)) => return Some(c_escape(&bytes)),
+                    Ok(_) => return None,
+                    Err(expected) => expected,
+                }
+            }
+        };
+        self.report(
+            &default.metadata,
+            format!("Default value must be {}", expected),
+        );
+        None
+    }
+
+    fn lower_enum(
+        &mut self,
+        scope: &str,
+        element: &NamedElement,
+        payload: &EnumPayload,
+        path: &[i32],
+    ) -> EnumDescriptor {
+        self.add_declaration_locations(path, element, enum_descriptor_proto::NAME);
+        let mut enum_descriptor = EnumDescriptor {
+            name: element.name.clone(),
+            full_name: qualified_name(scope, &element.name),
+            values: Vec::new(),
+            reserved_ranges: Vec::new(),
+            reserved_names: Vec::new(),
+            options: self.lower_options(
+                scope,
+                &payload.options,
+                OptionsKind::Enum,
+                &child_path(path, enum_descriptor_proto::OPTIONS),
+                None,
+            ),
+        };
+        for reserved in &payload.reserved {
+            match reserved {
+                Reserved::Range(range) => enum_descriptor.reserved_ranges.push(range.value),
+                Reserved::Name(name) => enum_descriptor.reserved_names.push(name.value.clone()),
+            }
+        }
+        for value in &payload.values {
+            if let ElementType::EnumValue(value_payload) = &value.type_t {
+                let mut value_path = child_path(path, enum_descriptor_proto::VALUE);
+                value_path.push(enum_descriptor.values.len() as i32);
+                self.add_declaration_locations(
+                    &value_path,
+                    value,
+                    enum_value_descriptor_proto::NAME,
+                );
+                self.add_location(
+                    &child_path(&value_path, enum_value_descriptor_proto::NUMBER),
+                    token_range(&value_payload.number.metadata),
+                    None,
+                );
+                enum_descriptor.values.push(EnumValueDescriptor {
+                    name: value.name.clone(),
+                    number: value_payload.number.value as i32,
+                    options: self.lower_options(
+                        scope,
+                        &value_payload.options,
+                        OptionsKind::EnumValue,
+                        &child_path(&value_path, enum_value_descriptor_proto::OPTIONS),
+                        value_payload.options_span.as_ref(),
+                    ),
+                });
+            }
+        }
+        enum_descriptor
+    }
+
+    fn lower_service(
+        &mut self,
+        scope: &str,
+        element: &NamedElement,
+        payload: &ServicePayload,
+        path: &[i32],
+    ) -> ServiceDescriptor {
+        self.add_declaration_locations(path, element, service_descriptor_proto::NAME);
+        let mut service = ServiceDescriptor {
+            name: element.name.clone(),
+            full_name: qualified_name(scope, &element.name),
+            methods: Vec::new(),
+            options: self.lower_options(
+                scope,
+                &payload.options,
+                OptionsKind::Service,
+                &child_path(path, service_descriptor_proto::OPTIONS),
+                None,
+            ),
+        };
+        for method in &payload.methods {
+            let mut method_path = child_path(path, service_descriptor_proto::METHOD);
+            method_path.push(service.methods.len() as i32);
+            self.add_location(
+                &method_path,
+                span_range(&method.start, &method.end),
+                Some(&method.comments),
+            );
+            for (field_number, name) in [
+                (method_descriptor_proto::NAME, &method.name.metadata),
+                (
+                    method_descriptor_proto::INPUT_TYPE,
+                    &method.input_type.metadata,
+                ),
+                (
+                    method_descriptor_proto::OUTPUT_TYPE,
+                    &method.output_type.metadata,
+                ),
+            ] {
+                self.add_location(
+                    &child_path(&method_path, field_number),
+                    token_range(name),
+                    None,
+                );
+            }
+            let input_type = self.resolve_message_type(
+                scope,
+                &method.input_type.value,
+                &method.input_type.metadata,
+            );
+            let output_type = self.resolve_message_type(
+                scope,
+                &method.output_type.value,
+                &method.output_type.metadata,
+            );
+            service.methods.push(MethodDescriptor {
+                name: method.name.value.clone(),
+                input_type,
+                output_type,
+                client_streaming: method.client_streaming,
+                server_streaming: method.server_streaming,
+                options: self.lower_options(
+                    scope,
+                    &method.options,
+                    OptionsKind::Method,
+                    &child_path(&method_path, method_descriptor_proto::OPTIONS),
+                    None,
+                ),
+            });
+        }
+        service
+    }
+}
+
+/// Normalize a scalar option value, or describe the values expected for `scalar_type`
+/// Infinity and NaN are written on purpose, other values must fit in a float
+fn check_float(scalar_type: ScalarType, value: f64) -> Result<Constant, String> {
+    if scalar_type == ScalarType::Float && value.is_finite() && value.abs() > f32::MAX as f64 {
+        return Err(format!(
+            "a number between {:e} and {:e}",
+            f32::MIN,
+            f32::MAX
+        ));
+    }
+    Ok(Constant::Float(value))
+}
+
+pub(crate) fn check_scalar(
+    scalar_type: ScalarType,
+    constant: &Constant,
+) -> Result<Constant, String> {
+    let integer_range = match scalar_type {
+        ScalarType::Int32 | ScalarType::Sint32 | ScalarType::SFixed32 => {
+            Some((i32::MIN as i128, i32::MAX as i128))
+        }
+        ScalarType::Uint32 | ScalarType::Fixed32 => Some((0, u32::MAX as i128)),
+        ScalarType::Int64 | ScalarType::Sint64 | ScalarType::SFixed64 => {
+            Some((i64::MIN as i128, i64::MAX as i128))
+        }
+        ScalarType::Uint64 | ScalarType::Fixed64 => Some((0, u64::MAX as i128)),
+        _ => None,
+    };
+    if let (Some((min, max)), Constant::Integer(value)) = (integer_range, constant) {
+        return if (min..=max).contains(value) {
+            Ok(constant.clone())
+        } else {
+            Err(format!("an integer between {} and {}", min, max))
+        };
+    }
+    match (scalar_type, constant) {
+        (ScalarType::Double | ScalarType::Float, Constant::Integer(value)) => {
+            check_float(scalar_type, *value as f64)
+        }
+        (ScalarType::Double | ScalarType::Float, Constant::Float(value)) => {
+            check_float(scalar_type, *value)
+        }
+        (ScalarType::Double | ScalarType::Float, Constant::Identifier(identifier))
+            if identifier == "nan" =>
+        {
+            Ok(Constant::Float(f64::NAN))
+        }
+        (ScalarType::Bool, Constant::Bool(_)) => Ok(constant.clone()),
+        (ScalarType::String | ScalarType::Bytes, Constant::String(_)) => Ok(constant.clone()),
+        (ScalarType::Bytes, Constant::Bytes(_)) => Ok(constant.clone()),
+        (ScalarType::String, Constant::Bytes(_)) => Err("valid UTF-8".to_string()),
+        _ => Err(match integer_range {
+            Some(_) => format!("an integer for {}", scalar_type.as_str()),
+            None => match scalar_type {
+                ScalarType::Bool => "\"true\" or \"false\"".to_string(),
+                ScalarType::String | ScalarType::Bytes => "a string".to_string(),
+                _ => "a number".to_string(),
+            },
+        }),
+    }
+}
+
+/// Escape bytes the way protoc writes the default values of bytes fields
+fn c_escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for byte in bytes {
+        match byte {
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            b'"' => escaped.push_str("\\\""),
+            b'\'' => escaped.push_str("\\'"),
+            b'\\' => escaped.push_str("\\\\"),
+            0x20..=0x7e => escaped.push(*byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
+fn option_name_to_string(name: &[OptionNamePart]) -> String {
+    name.iter()
+        .map(|part| match part {
+            OptionNamePart::Simple(name) => name.clone(),
+            OptionNamePart::Extension(name) => format!("({})", name),
+        })
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{diagnostic::DiagnosticFormat, source_text::VirtualFileSystem};
+
+    fn load(sources: &[(&str, &str)]) -> Vec<LoadedFile> {
+        sources
+            .iter()
+            .map(|(name, text)| LoadedFile::from_source(name, text.to_string()).unwrap())
+            .collect()
+    }
+
+    fn resolve_errors(sources: &[(&str, &str)]) -> Vec<String> {
+        match resolve(&load(sources)) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn resolver_scoping_test() {
+        let schema = resolve(&load(&[
+            (
+                "common.proto",
+                r#"syntax = "proto3"; package foo.common; message Id { string value = 1; }"#,
+            ),
+            (
+                "main.proto",
+                r#"syntax = "proto3";
+                package foo.bar;
+                import "common.proto";
+                message Outer {
+                    message Inner { common.Id id = 1; }
+                    Inner inner = 1;
+                    .foo.common.Id id = 2;
+                    State state = 3;
+                }
+                enum State { STATE_UNKNOWN = 0; }"#,
+            ),
+        ]))
+        .unwrap();
+        let outer = &schema.file("main.proto").unwrap().messages[0];
+        assert_eq!(outer.full_name, "foo.bar.Outer");
+        assert_eq!(
+            outer.nested_messages[0].fields[0].field_type,
+            FieldType::Message("foo.common.Id".to_string())
+        );
+        assert_eq!(
+            outer.fields[0].field_type,
+            FieldType::Message("foo.bar.Outer.Inner".to_string())
+        );
+        assert_eq!(
+            outer.fields[1].field_type,
+            FieldType::Message("foo.common.Id".to_string())
+        );
+        assert_eq!(
+            outer.fields[2].field_type,
+            FieldType::Enum("foo.bar.State".to_string())
+        );
+    }
+
+    #[test]
+    fn resolver_map_and_optional_test() {
+        let schema = resolve(&load(&[(
+            "main.proto",
+            r#"syntax = "proto3";
+            message M {
+                map<string, M> children = 1;
+                optional int32 count = 2;
+                oneof choice { string a = 3; }
+            }"#,
+        )]))
+        .unwrap();
+        let message = &schema.files[0].messages[0];
+        let entry = &message.nested_messages[0];
+        assert_eq!(entry.name, "ChildrenEntry");
+        assert!(entry.is_map_entry());
+        assert_eq!(
+            entry.fields[1].field_type,
+            FieldType::Message("M".to_string())
+        );
+        assert_eq!(message.fields[0].label, Label::Repeated);
+        assert_eq!(
+            message.fields[0].field_type,
+            FieldType::Message("M.ChildrenEntry".to_string())
+        );
+        // The synthetic oneof comes after the real one
+        assert_eq!(message.oneofs[0].name, "choice");
+        assert_eq!(message.oneofs[1].name, "_count");
+        assert_eq!(message.fields[1].oneof_index, Some(1));
+        assert!(message.fields[1].proto3_optional);
+        assert_eq!(message.fields[2].oneof_index, Some(0));
+    }
+
+    #[test]
+    fn resolver_proto2_test() {
+        let schema = resolve(&load(&[(
+            "main.proto",
+            r#"syntax = "proto2";
+            message M {
+                optional int32 count = 1 [default = -0x10];
+                optional double ratio = 2 [default = - 1.5e3];
+                optional float limit = 3 [default = -inf];
+                optional bytes data = 4 [default = "a\n\"\001"];
+                optional string name = 5 [default = "x\ty"];
+                optional E kind = 6 [default = E_TWO];
+                optional bool flag = 7 [default = true];
+                repeated int32 unpacked = 8;
+                repeated int32 packed = 9 [packed = true];
+            }
+            enum E { E_ONE = 1; E_TWO = 2; }"#,
+        )]))
+        .unwrap();
+        let file = &schema.files[0];
+        assert_eq!(file.syntax, Syntax::Proto2);
+        let fields = &file.messages[0].fields;
+        let defaults: Vec<Option<&str>> = fields
+            .iter()
+            .map(|field| field.default_value.as_deref())
+            .collect();
+        assert_eq!(
+            defaults,
+            [
+                Some("-16"),
+                Some("-1.5e3"),
+                Some("-inf"),
+                Some("a\\n\\\"\\001"),
+                Some("x\ty"),
+                Some("E_TWO"),
+                Some("true"),
+                None,
+                None
+            ]
+        );
+        assert!(fields
+            .iter()
+            .all(|field| field.options.is_empty() || field.name == "packed"));
+        assert!(!fields[0].proto3_optional);
+        assert!(!fields[7].packed);
+        assert!(fields[8].packed);
+
+        let errors = resolve_errors(&[(
+            "main.proto",
+            r#"syntax = "proto2";
+            message M {
+                optional uint32 a = 1 [default = -1];
+                optional E b = 2 [default = E_THREE];
+                optional M c = 3 [default = 1];
+            }
+            enum E { E_ONE = 1; E_TWO = 2; }"#,
+        )]);
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].contains("Default value must be an integer between 0 and 4294967295"));
+        assert!(errors[1].contains("Default value must be E_ONE, E_TWO"));
+        assert!(errors[2].contains("Messages can't have default values"));
+    }
+
+    #[test]
+    fn resolver_error_test() {
+        let errors = resolve_errors(&[(
+            "main.proto",
+            r#"syntax = "proto3"; message M { Missing m = 1; }"#,
+        )]);
+        assert!(errors[0].contains("\"Missing\" is not defined"));
+
+        let errors = resolve_errors(&[(
+            "main.proto",
+            r#"syntax = "proto3"; message M {} enum M { A = 0; }"#,
+        )]);
+        assert!(errors[0].contains("\"M\" is already defined"));
+
+        let errors = resolve_errors(&[(
+            "main.proto",
+            r#"syntax = "proto3"; option java_multiple_files = "yes"; option no_such_option = 1;"#,
+        )]);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("Value must be"));
+        assert!(errors[1].contains("Option \"no_such_option\" unknown"));
+
+        let errors = resolve_errors(&[(
+            "main.proto",
+            r#"syntax = "proto3"; option java_package = "\xc3";"#,
+        )]);
+        assert!(errors[0].contains("Value must be valid UTF-8 for option \"java_package\""));
+    }
+
+    #[test]
+    fn loader_sources_test() {
+        let mut file_system = VirtualFileSystem::default();
+        file_system.insert(
+            "protos/main.proto",
+            "syntax = \"proto3\";\nimport \"dep.proto\";\nmessage M { Dep dep = 1; }\n",
+        );
+        file_system.insert(
+            "protos/dep.proto",
+            "syntax = \"proto3\";\n\nmessage Dep { int32 = 1; }\n",
+        );
+        let mut loader = Loader::with_sources(
+            vec![PathBuf::from("protos")],
+            SourceRegistry::new(file_system),
+        );
+        loader.load("main.proto");
+        let (files, errors, sources) = loader.into_parts();
+        assert_eq!(files.len(), 1);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].file_name, "dep.proto");
+        assert_eq!(errors[1].file_name, "main.proto");
+
+        let main = sources.find("main.proto").unwrap();
+        let dep = sources.find("dep.proto").unwrap();
+        assert_ne!(main.id(), dep.id());
+        assert_eq!(dep.path(), Path::new("protos/dep.proto"));
+        assert_eq!(files[0].source.id(), main.id());
+        assert_eq!(
+            files[0].ast.syntax.as_ref().unwrap().metadata.file(),
+            main.id()
+        );
+        let label = errors[0].error.to_diagnostic().labels.remove(0);
+        assert_eq!(label.metadata.file(), dep.id());
+        assert_eq!(
+            errors[0].format_in(DiagnosticFormat::Gcc, &sources, false),
+            "dep.proto:3:21: Expected field name but found \"=\"\n"
+        );
+        assert_eq!(
+            errors[1].format_in(DiagnosticFormat::Gcc, &sources, false),
+            "main.proto:2:8: Import \"dep.proto\" was not found or had errors\n"
+        );
+    }
+
+    #[test]
+    fn loader_builtin_files_test() {
+        let mut file_system = VirtualFileSystem::default();
+        file_system.insert(
+            "protos/main.proto",
+            r#"syntax = "proto3";
+            import "google/protobuf/any.proto";
+            import "google/protobuf/descriptor.proto";
+            import "google/protobuf/duration.proto";
+            import "google/protobuf/empty.proto";
+            import "google/protobuf/field_mask.proto";
+            import "google/protobuf/struct.proto";
+            import "google/protobuf/timestamp.proto";
+            import "google/protobuf/wrappers.proto";"#,
+        );
+        // Files on the include paths win over the built-in ones
+        file_system.insert(
+            "protos/google/protobuf/empty.proto",
+            r#"syntax = "proto3"; package google.protobuf; message Empty { int32 own = 1; }"#,
+        );
+        let mut loader = Loader::with_sources(
+            vec![PathBuf::from("protos")],
+            SourceRegistry::new(file_system),
+        );
+        loader.load("main.proto");
+        let schema = resolve(&loader.finish().unwrap()).unwrap();
+        assert_eq!(schema.files.len(), 9);
+        for name in [
+            "google.protobuf.Any",
+            "google.protobuf.FileDescriptorSet",
+            "google.protobuf.Duration",
+            "google.protobuf.FieldMask",
+            "google.protobuf.Value",
+            "google.protobuf.Timestamp",
+            "google.protobuf.BytesValue",
+        ] {
+            assert!(schema.find_message(name).is_some(), "{}", name);
+        }
+        let empty = schema.find_message("google.protobuf.Empty").unwrap();
+        assert_eq!(empty.fields[0].name, "own");
+        // descriptor.proto is the proto2 original, validated like the other files
+        let descriptor = schema.file("google/protobuf/descriptor.proto").unwrap();
+        assert_eq!(descriptor.syntax, Syntax::Proto2);
+        let file_options = schema.find_message("google.protobuf.FileOptions").unwrap();
+        let optimize_for = file_options
+            .fields
+            .iter()
+            .find(|field| field.name == "optimize_for")
+            .unwrap();
+        assert_eq!(optimize_for.default_value.as_deref(), Some("SPEED"));
+    }
+
+    #[test]
+    fn resolver_service_test() {
+        let schema = resolve(&load(&[
+            (
+                "common.proto",
+                r#"syntax = "proto3"; package common; message Empty {}"#,
+            ),
+            (
+                "main.proto",
+                r#"syntax = "proto3";
+                package foo.v1;
+                import "common.proto";
+                message Chunk { bytes data = 1; }
+                service Storage {
+                    rpc Get(common.Empty) returns (Chunk);
+                    rpc Upload(stream Chunk) returns (common.Empty) { option deprecated = true; }
+                    rpc Download(common.Empty) returns (stream Chunk);
+                    rpc Sync(stream Chunk) returns (stream Chunk);
+                }"#,
+            ),
+        ]))
+        .unwrap();
+        let routes: Vec<(String, bool, bool)> = schema
+            .methods()
+            .map(|route| {
+                (
+                    route.path,
+                    route.method.client_streaming,
+                    route.method.server_streaming,
+                )
+            })
+            .collect();
+        assert_eq!(
+            routes,
+            [
+                ("/foo.v1.Storage/Get".to_string(), false, false),
+                ("/foo.v1.Storage/Upload".to_string(), true, false),
+                ("/foo.v1.Storage/Download".to_string(), false, true),
+                ("/foo.v1.Storage/Sync".to_string(), true, true),
+            ]
+        );
+        let upload = schema.find_method("/foo.v1.Storage/Upload").unwrap();
+        assert_eq!(upload.service.full_name, "foo.v1.Storage");
+        assert_eq!(upload.method.input_type, "foo.v1.Chunk");
+        assert_eq!(upload.method.output_type, "common.Empty");
+        assert_eq!(upload.method.options.len(), 1);
+        assert!(schema.find_method("/foo.v1.Storage/Delete").is_none());
+        assert!(schema.find_method("foo.v1.Storage/Get").is_none());
+    }
+
+    /// Load `sources` from an include path the way the compiler does, with the built-in
+    /// descriptor.proto
+    fn load_protos(sources: &[(&str, &str)]) -> std::result::Result<Schema, Vec<String>> {
+        let mut file_system = VirtualFileSystem::default();
+        for (name, text) in sources {
+            file_system.insert(Path::new("protos").join(name), *text);
+        }
+        let input_files: Vec<String> = sources
+            .iter()
+            .map(|(name, _)| format!("protos/{}", name))
+            .collect();
+        let mut registry = SourceRegistry::new(file_system);
+        match load_schema(&input_files, &[PathBuf::from("protos")], &mut registry) {
+            Ok(loaded) => Ok(loaded.schema),
+            Err(errors) => Err(errors.iter().map(|error| error.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn resolver_custom_option_test() {
+        let schema = load_protos(&[(
+            "main.proto",
+            r#"syntax = "proto3";
+                package foo;
+                import "google/protobuf/descriptor.proto";
+                option (file_level) = 7;
+                message M {
+                    option (rules) = { level: HIGH tags: ["a", "b"] limits { upper: 2.5 } };
+                    option (rules).limits.upper = 1;
+                    int32 a = 1 [(field_level) = -3];
+                }
+                enum Level { LOW = 0; HIGH = 1; }
+                message Rules {
+                    message Limits { double upper = 1; }
+                    Level level = 1;
+                    repeated string tags = 2;
+                    Limits limits = 3;
+                }
+                extend google.protobuf.FileOptions { uint32 file_level = 50000; }
+                extend google.protobuf.MessageOptions { Rules rules = 50001; }
+                extend google.protobuf.FieldOptions { sint64 field_level = 50002; }"#,
+        )])
+        .unwrap();
+        let descriptor = schema.file("google/protobuf/descriptor.proto").unwrap();
+        assert_eq!(descriptor.package, "google.protobuf");
+        let file = schema.file("main.proto").unwrap();
+        assert_eq!(
+            file.options[0].custom,
+            Some((
+                50000,
+                OptionValue::Scalar(ScalarType::Uint32, Constant::Integer(7))
+            ))
+        );
+        let message = &file.messages[0];
+        let string = |value: &str| {
+            OptionValue::Scalar(ScalarType::String, Constant::String(value.to_string()))
+        };
+        let limits = |max: f64| {
+            OptionValue::Message(vec![(
+                1,
+                OptionValue::Scalar(ScalarType::Double, Constant::Float(max)),
+            )])
+        };
+        assert_eq!(
+            message.options[0].custom,
+            Some((
+                50001,
+                OptionValue::Message(vec![
+                    (1, OptionValue::Enum(1)),
+                    (2, string("a")),
+                    (2, string("b")),
+                    (3, limits(2.5)),
+                ])
+            ))
+        );
+        assert_eq!(
+            message.options[1].custom,
+            Some((50001, OptionValue::Message(vec![(3, limits(1.0))])))
+        );
+        assert_eq!(
+            message.fields[0].options[0].custom,
+            Some((
+                50002,
+                OptionValue::Scalar(ScalarType::Sint64, Constant::Integer(-3))
+            ))
+        );
+    }
+
+    #[test]
+    fn resolver_custom_option_error_test() {
+        let errors = load_protos(&[(
+            "main.proto",
+            r#"syntax = "proto3";
+                import "google/protobuf/descriptor.proto";
+                option (missing) = 1;
+                option (small) = -1;
+                option (field_only) = true;
+                message M {
+                    option (nested) = { count: "three" unknown: 1 };
+                    option (nested).count.value = 1;
+                }
+                message Nested { int32 count = 1; }
+                extend google.protobuf.FileOptions { uint32 small = 50000; }
+                extend google.protobuf.FieldOptions { bool field_only = 50001; }
+                extend google.protobuf.MessageOptions { Nested nested = 50002; }"#,
+        )])
+        .unwrap_err();
+        assert_eq!(errors.len(), 6, "{:#?}", errors);
+        assert!(errors[0].contains("Option \"(missing)\" unknown"));
+        assert!(errors[1].contains("Value must be an integer between 0 and 4294967295"));
+        assert!(errors[2].contains("cannot be used where a \"google.protobuf.FileOptions\""));
+        assert!(errors[3].contains("Value must be an integer for int32 for option \"count\""));
+        assert!(errors[4].contains("Message \"Nested\" has no field \"unknown\""));
+        assert!(errors[5].contains("Option \"(nested).count\" is not a message"));
+    }
+
+    #[test]
+    fn resolver_numeric_range_test() {
+        let errors = load_protos(&[(
+            "main.proto",
+            r#"syntax = "proto3";
+                import "google/protobuf/descriptor.proto";
+                option (small) = 1e39;
+                option (small) = -inf;
+                option (large) = 1e300;
+                option (count) = 18446744073709551616;
+                option (count) = -9223372036854775808;
+                option (unsigned) = -1;
+                extend google.protobuf.FileOptions {
+                    float small = 50000;
+                    double large = 50001;
+                    int64 count = 50002;
+                    uint32 unsigned = 50003;
+                }"#,
+        )])
+        .unwrap_err();
+        assert_eq!(errors.len(), 3, "{:#?}", errors);
+        assert!(errors[0].contains("Value must be a number between -3.4028235e38 and 3.4028235e38"));
+        assert!(errors[1].contains(
+            "Value must be an integer between -9223372036854775808 and 9223372036854775807"
+        ));
+        assert!(errors[2].contains("Value must be an integer between 0 and 4294967295"));
+    }
+
+    #[test]
+    fn resolver_source_code_info_test() {
+        // Expected locations are protoc's, the field is indented with a tab
+        let main = concat!(
+            "syntax = \"proto3\";\n",
+            "\n",
+            "package demo;\n",
+            "\n",
+            "import \"common.proto\";\n",
+            "\n",
+            "option java_package = \"com.demo\";\n",
+            "\n",
+            "// A message\n",
+            "message Outer {\n",
+            "\trepeated common.Id ids = 1 [deprecated = true, json_name = \"IDs\"];\n",
+            "  map<string, int32> counts = 2;\n",
+            "  oneof choice {\n",
+            "    string name = 3;\n",
+            "  }\n",
+            "  option deprecated = true;\n",
+            "}\n",
+            "\n",
+            "enum State {\n",
+            "  STATE_UNKNOWN = 0;\n",
+            "  STATE_GONE = -1 [deprecated = true];\n",
+            "}\n",
+            "\n",
+            "service Api {\n",
+            "  rpc Get(Outer)\n",
+            "      returns (Outer);\n",
+            "}\n",
+        );
+        let schema = resolve(&load(&[
+            (
+                "common.proto",
+                r#"syntax = "proto3"; package common; message Id {}"#,
+            ),
+            ("main.proto", main),
+        ]))
+        .unwrap();
+        let file = schema.file("main.proto").unwrap();
+        let locations: Vec<(&[i32], &[i32])> = file
+            .source_code_info
+            .iter()
+            .map(|location| (location.path.as_slice(), location.span.as_slice()))
+            .collect();
+        let expected: Vec<(&[i32], &[i32])> = vec![
+            (&[], &[0, 0, 26, 1]),
+            (&[12], &[0, 0, 18]),
+            (&[2], &[2, 0, 13]),
+            (&[3, 0], &[4, 0, 22]),
+            (&[8], &[6, 0, 33]),
+            (&[8, 1], &[6, 0, 33]),
+            (&[4, 0], &[9, 0, 16, 1]),
+            (&[4, 0, 1], &[9, 8, 13]),
+            (&[4, 0, 2, 0], &[10, 8, 74]),
+            (&[4, 0, 2, 0, 4], &[10, 8, 16]),
+            (&[4, 0, 2, 0, 6], &[10, 17, 26]),
+            (&[4, 0, 2, 0, 1], &[10, 27, 30]),
+            (&[4, 0, 2, 0, 3], &[10, 33, 34]),
+            (&[4, 0, 2, 0, 8], &[10, 35, 73]),
+            (&[4, 0, 2, 0, 8, 3], &[10, 36, 53]),
+            (&[4, 0, 2, 0, 10], &[10, 55, 72]),
+            (&[4, 0, 2, 0, 10], &[10, 67, 72]),
+            (&[4, 0, 2, 1], &[11, 2, 32]),
+            (&[4, 0, 2, 1, 6], &[11, 2, 20]),
+            (&[4, 0, 2, 1, 1], &[11, 21, 27]),
+            (&[4, 0, 2, 1, 3], &[11, 30, 31]),
+            (&[4, 0, 8, 0], &[12, 2, 14, 3]),
+            (&[4, 0, 8, 0, 1], &[12, 8, 14]),
+            (&[4, 0, 2, 2], &[13, 4, 20]),
+            (&[4, 0, 2, 2, 5], &[13, 4, 10]),
+            (&[4, 0, 2, 2, 1], &[13, 11, 15]),
+            (&[4, 0, 2, 2, 3], &[13, 18, 19]),
+            (&[4, 0, 7], &[15, 2, 27]),
+            (&[4, 0, 7, 3], &[15, 2, 27]),
+            (&[5, 0], &[18, 0, 21, 1]),
+            (&[5, 0, 1], &[18, 5, 10]),
+            (&[5, 0, 2, 0], &[19, 2, 20]),
+            (&[5, 0, 2, 0, 1], &[19, 2, 15]),
+            (&[5, 0, 2, 0, 2], &[19, 18, 19]),
+            (&[5, 0, 2, 1], &[20, 2, 38]),
+            (&[5, 0, 2, 1, 1], &[20, 2, 12]),
+            (&[5, 0, 2, 1, 2], &[20, 15, 17]),
+            (&[5, 0, 2, 1, 3], &[20, 18, 37]),
+            (&[5, 0, 2, 1, 3, 1], &[20, 19, 36]),
+            (&[6, 0], &[23, 0, 26, 1]),
+            (&[6, 0, 1], &[23, 8, 11]),
+            (&[6, 0, 2, 0], &[24, 2, 25, 22]),
+            (&[6, 0, 2, 0, 1], &[24, 6, 9]),
+            (&[6, 0, 2, 0, 2], &[24, 10, 15]),
+            (&[6, 0, 2, 0, 3], &[25, 15, 20]),
+        ];
+        assert_eq!(locations, expected);
+        assert_eq!(
+            file.source_code_info[6].leading_comments.as_deref(),
+            Some(" A message\n")
+        );
+    }
+
+    #[test]
+    fn load_schema_missing_input_test() {
+        let mut file_system = VirtualFileSystem::default();
+        file_system.insert("protos/present.proto", "syntax = \"proto3\";");
+        file_system.insert("elsewhere/outside.proto", "syntax = \"proto3\";");
+        let mut registry = SourceRegistry::new(file_system);
+        let input_files = [
+            "protos/missing.proto".to_string(),
+            "elsewhere/outside.proto".to_string(),
+        ];
+        let errors = match load_schema(&input_files, &[PathBuf::from("protos")], &mut registry) {
+            Ok(_) => panic!("Expected the missing input to be reported"),
+            Err(errors) => errors,
+        };
+        let errors: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|error| match &error.error {
+                RsProtocError::FilesystemError(message) => {
+                    (error.file_name.as_str(), message.as_str())
+                }
+                _ => panic!("Expected a filesystem error"),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("protos/missing.proto", "No such file or directory"),
+                (
+                    "elsewhere/outside.proto",
+                    "File does not reside within any path specified using --proto_path"
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn map_entry_name_test() {
+        assert_eq!(map_entry_name("foo_bar"), "FooBarEntry");
+        assert_eq!(map_entry_name("fooBar"), "FooBarEntry");
+    }
+}
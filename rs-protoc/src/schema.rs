@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast_elements::{Constant, NumberRange, OptionNamePart, ScalarType},
+    descriptor::{
+        descriptor_proto, enum_descriptor_proto, file_descriptor_proto, service_descriptor_proto,
+    },
+    source_text::FileId,
+};
+
+// Resolved view of a set of .proto files. Every type reference is fully-qualified
+// (without the leading '.') and every element knows its full name, so consumers do not need
+// to repeat the scoping rules. Produced by the resolver from parsed files.
+
+#[derive(Debug, Default)]
+pub struct Schema {
+    /// Files in dependency order, every file appears after the files it imports
+    pub files: Vec<FileDescriptor>,
+}
+
+impl Schema {
+    pub fn file(&self, name: &str) -> Option<&FileDescriptor> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    /// Every method of every service, in file and declaration order
+    pub fn methods(&self) -> impl Iterator<Item = MethodRoute<'_>> {
+        self.files.iter().flat_map(|file| {
+            file.services.iter().flat_map(|service| {
+                service.methods.iter().map(move |method| MethodRoute {
+                    path: service.method_path(method),
+                    service,
+                    method,
+                })
+            })
+        })
+    }
+
+    /// Find a method by its `/package.Service/Method` path
+    pub fn find_method(&self, path: &str) -> Option<MethodRoute<'_>> {
+        let (service_name, method_name) = path.strip_prefix('/')?.split_once('/')?;
+        self.files
+            .iter()
+            .flat_map(|file| &file.services)
+            .filter(|service| service.full_name == service_name)
+            .find_map(|service| {
+                let method = service
+                    .methods
+                    .iter()
+                    .find(|method| method.name == method_name)?;
+                Some(MethodRoute {
+                    path: path.to_string(),
+                    service,
+                    method,
+                })
+            })
+    }
+
+    /// Find a message, nested or not, by its full name
+    pub fn find_message(&self, full_name: &str) -> Option<&MessageDescriptor> {
+        self.files
+            .iter()
+            .find_map(|file| find_nested_message(&file.messages, full_name))
+    }
+
+    /// Find an enum, nested or not, by its full name
+    pub fn find_enum(&self, full_name: &str) -> Option<&EnumDescriptor> {
+        self.files.iter().find_map(|file| {
+            file.enums
+                .iter()
+                .find(|enum_descriptor| enum_descriptor.full_name == full_name)
+                .or_else(|| find_nested_enum(&file.messages, full_name))
+        })
+    }
+
+    /// Every extension of the message `extendee` along with the full name of the extension,
+    /// in file and declaration order
+    pub fn extensions_of(&self, extendee: &str) -> Vec<(String, &FieldDescriptor)> {
+        let mut extensions = Vec::new();
+        for file in &self.files {
+            collect_extensions(&file.package, &file.extensions, extendee, &mut extensions);
+            collect_nested_extensions(&file.messages, extendee, &mut extensions);
+        }
+        extensions
+    }
+
+    /// Leading and trailing comments of the elements of every file keyed by
+    /// `FileDescriptor::element_name`, separated by a blank line when an element has both
+    pub fn element_docs(&self) -> HashMap<String, String> {
+        let mut docs = HashMap::new();
+        for file in &self.files {
+            for location in &file.source_code_info {
+                let comments: Vec<&str> = [&location.leading_comments, &location.trailing_comments]
+                    .into_iter()
+                    .flatten()
+                    .map(|comment| comment.trim_end_matches('\n'))
+                    .collect();
+                if comments.is_empty() {
+                    continue;
+                }
+                if let Some(name) = file.element_name(&location.path) {
+                    docs.insert(name, comments.join("\n\n"));
+                }
+            }
+        }
+        docs
+    }
+}
+
+fn find_nested_message<'a>(
+    messages: &'a [MessageDescriptor],
+    full_name: &str,
+) -> Option<&'a MessageDescriptor> {
+    messages.iter().find_map(|message| {
+        if message.full_name == full_name {
+            return Some(message);
+        }
+        let rest = full_name.strip_prefix(message.full_name.as_str())?;
+        rest.starts_with('.')
+            .then(|| find_nested_message(&message.nested_messages, full_name))?
+    })
+}
+
+fn find_nested_enum<'a>(
+    messages: &'a [MessageDescriptor],
+    full_name: &str,
+) -> Option<&'a EnumDescriptor> {
+    messages.iter().find_map(|message| {
+        message
+            .enums
+            .iter()
+            .find(|enum_descriptor| enum_descriptor.full_name == full_name)
+            .or_else(|| find_nested_enum(&message.nested_messages, full_name))
+    })
+}
+
+/// Add the extensions of `extendee` declared in `scope`, a package or message name
+fn collect_extensions<'a>(
+    scope: &str,
+    declared: &'a [FieldDescriptor],
+    extendee: &str,
+    extensions: &mut Vec<(String, &'a FieldDescriptor)>,
+) {
+    for extension in declared {
+        if extension.extendee.as_deref() != Some(extendee) {
+            continue;
+        }
+        let full_name = match scope {
+            "" => extension.name.clone(),
+            scope => format!("{}.{}", scope, extension.name),
+        };
+        extensions.push((full_name, extension));
+    }
+}
+
+fn collect_nested_extensions<'a>(
+    messages: &'a [MessageDescriptor],
+    extendee: &str,
+    extensions: &mut Vec<(String, &'a FieldDescriptor)>,
+) {
+    for message in messages {
+        collect_extensions(
+            &message.full_name,
+            &message.extensions,
+            extendee,
+            extensions,
+        );
+        collect_nested_extensions(&message.nested_messages, extendee, extensions);
+    }
+}
+
+/// An option with its value, as written in the source
+#[derive(Clone, PartialEq, Debug)]
+pub struct SchemaOption {
+    pub name: Vec<OptionNamePart>,
+    pub value: Constant,
+    /// Extension number and typed value of a custom option, filled in by the resolver
+    pub custom: Option<(i32, OptionValue)>,
+}
+
+/// A custom option value checked against the field types of its extension
+#[derive(Clone, PartialEq, Debug)]
+pub enum OptionValue {
+    /// Integers are `Constant::Integer` within the range of the type and floating point
+    /// values are always `Constant::Float`
+    Scalar(ScalarType, Constant),
+    /// Number of the enum value
+    Enum(i32),
+    /// Field numbers and values in source order, repeated fields appear once per element
+    Message(Vec<(i32, OptionValue)>),
+}
+
+/// Returns the value of the built-in option `name` if it is set
+pub fn find_schema_option<'a>(options: &'a [SchemaOption], name: &str) -> Option<&'a Constant> {
+    options
+        .iter()
+        .find(|option| matches!(option.name.as_slice(), [OptionNamePart::Simple(simple)] if simple == name))
+        .map(|option| &option.value)
+}
+
+/// The language version a file is written in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Syntax {
+    Proto2,
+    Proto3,
+}
+
+#[derive(Debug)]
+pub struct FileDescriptor {
+    /// Path of the file relative to the include path it was found in
+    pub name: String,
+    /// Empty when the file has no package declaration
+    pub package: String,
+    pub syntax: Syntax,
+    pub dependencies: Vec<String>,
+    /// Indices into `dependencies`
+    pub public_dependencies: Vec<usize>,
+    /// Indices into `dependencies`
+    pub weak_dependencies: Vec<usize>,
+    pub messages: Vec<MessageDescriptor>,
+    pub enums: Vec<EnumDescriptor>,
+    pub services: Vec<ServiceDescriptor>,
+    pub extensions: Vec<FieldDescriptor>,
+    pub options: Vec<SchemaOption>,
+    pub source_code_info: Vec<SourceLocation>,
+    /// The text the file was compiled from, the default id for files decoded from descriptors
+    pub source_file: FileId,
+}
+
+impl FileDescriptor {
+    /// Name of the element at `path` of a source location: the full name of messages, enums and
+    /// services, the full name of the parent followed by the element's name for fields, oneofs,
+    /// enum values and methods. `None` for paths to any other element.
+    pub fn element_name(&self, path: &[i32]) -> Option<String> {
+        let path: Vec<u64> = path.iter().map(|value| *value as u64).collect();
+        match *path.as_slice() {
+            [file_descriptor_proto::MESSAGE_TYPE, index, ref rest @ ..] => {
+                message_element_name(self.messages.get(index as usize)?, rest)
+            }
+            [file_descriptor_proto::ENUM_TYPE, index, ref rest @ ..] => {
+                enum_element_name(self.enums.get(index as usize)?, rest)
+            }
+            [file_descriptor_proto::SERVICE, index] => {
+                Some(self.services.get(index as usize)?.full_name.clone())
+            }
+            [file_descriptor_proto::SERVICE, index, service_descriptor_proto::METHOD, method] => {
+                let service = self.services.get(index as usize)?;
+                Some(format!(
+                    "{}.{}",
+                    service.full_name,
+                    service.methods.get(method as usize)?.name
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn message_element_name(message: &MessageDescriptor, path: &[u64]) -> Option<String> {
+    use descriptor_proto::*;
+    let name = match *path {
+        [] => return Some(message.full_name.clone()),
+        [FIELD, index] => &message.fields.get(index as usize)?.name,
+        [ONEOF_DECL, index] => &message.oneofs.get(index as usize)?.name,
+        [NESTED_TYPE, index, ref rest @ ..] => {
+            return message_element_name(message.nested_messages.get(index as usize)?, rest)
+        }
+        [ENUM_TYPE, index, ref rest @ ..] => {
+            return enum_element_name(message.enums.get(index as usize)?, rest)
+        }
+        _ => return None,
+    };
+    Some(format!("{}.{}", message.full_name, name))
+}
+
+fn enum_element_name(enum_descriptor: &EnumDescriptor, path: &[u64]) -> Option<String> {
+    match *path {
+        [] => Some(enum_descriptor.full_name.clone()),
+        [enum_descriptor_proto::VALUE, index] => Some(format!(
+            "{}.{}",
+            enum_descriptor.full_name,
+            enum_descriptor.values.get(index as usize)?.name
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub struct MessageDescriptor {
+    pub name: String,
+    pub full_name: String,
+    /// Fields in declaration order, including the fields of oneofs
+    pub fields: Vec<FieldDescriptor>,
+    pub nested_messages: Vec<MessageDescriptor>,
+    pub enums: Vec<EnumDescriptor>,
+    pub extensions: Vec<FieldDescriptor>,
+    pub oneofs: Vec<OneofDescriptor>,
+    pub extension_ranges: Vec<NumberRange>,
+    pub reserved_ranges: Vec<NumberRange>,
+    pub reserved_names: Vec<String>,
+    pub options: Vec<SchemaOption>,
+}
+
+impl MessageDescriptor {
+    /// True for the synthesized `<Field>Entry` messages backing map fields
+    pub fn is_map_entry(&self) -> bool {
+        matches!(
+            find_schema_option(&self.options, "map_entry"),
+            Some(Constant::Bool(true))
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Label {
+    Optional,
+    Required,
+    Repeated,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum FieldType {
+    Scalar(ScalarType),
+    /// Full name of the message type
+    Message(String),
+    /// Full name of the enum type
+    Enum(String),
+}
+
+impl FieldType {
+    /// True for the types whose repeated values can be written in a single packed record
+    pub fn is_packable(&self) -> bool {
+        !matches!(
+            self,
+            FieldType::Scalar(ScalarType::String | ScalarType::Bytes) | FieldType::Message(_)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct FieldDescriptor {
+    pub name: String,
+    pub number: i32,
+    pub label: Label,
+    pub field_type: FieldType,
+    pub json_name: String,
+    /// Index into the containing message's `oneofs`
+    pub oneof_index: Option<usize>,
+    /// Set for proto3 `optional` fields, which live in a synthetic oneof
+    pub proto3_optional: bool,
+    /// Full name of the extended message for extension fields
+    pub extendee: Option<String>,
+    /// The `default` option of proto2 fields, written the way FieldDescriptorProto holds it
+    pub default_value: Option<String>,
+    /// True for repeated fields encoded packed, see `is_packed`
+    pub packed: bool,
+    pub options: Vec<SchemaOption>,
+}
+
+/// Whether a field is encoded packed: repeated fields of packable types are packed by default
+/// in proto3 and only with `packed = true` in proto2
+pub fn is_packed(
+    syntax: Syntax,
+    label: Label,
+    field_type: &FieldType,
+    options: &[SchemaOption],
+) -> bool {
+    label == Label::Repeated
+        && field_type.is_packable()
+        && match find_schema_option(options, "packed") {
+            Some(Constant::Bool(packed)) => *packed,
+            _ => syntax == Syntax::Proto3,
+        }
+}
+
+#[derive(Debug)]
+pub struct OneofDescriptor {
+    pub name: String,
+    pub options: Vec<SchemaOption>,
+}
+
+#[derive(Debug)]
+pub struct EnumDescriptor {
+    pub name: String,
+    pub full_name: String,
+    pub values: Vec<EnumValueDescriptor>,
+    pub reserved_ranges: Vec<NumberRange>,
+    pub reserved_names: Vec<String>,
+    pub options: Vec<SchemaOption>,
+}
+
+#[derive(Debug)]
+pub struct EnumValueDescriptor {
+    pub name: String,
+    pub number: i32,
+    pub options: Vec<SchemaOption>,
+}
+
+#[derive(Debug)]
+pub struct ServiceDescriptor {
+    pub name: String,
+    pub full_name: String,
+    pub methods: Vec<MethodDescriptor>,
+    pub options: Vec<SchemaOption>,
+}
+
+impl ServiceDescriptor {
+    /// The `/package.Service/Method` path gRPC requests for `method` are sent to
+    pub fn method_path(&self, method: &MethodDescriptor) -> String {
+        format!("/{}/{}", self.full_name, method.name)
+    }
+}
+
+/// A method along with its service and the path it is called through
+#[derive(Clone, Debug)]
+pub struct MethodRoute<'a> {
+    /// `/package.Service/Method`
+    pub path: String,
+    pub service: &'a ServiceDescriptor,
+    pub method: &'a MethodDescriptor,
+}
+
+#[derive(Debug)]
+pub struct MethodDescriptor {
+    pub name: String,
+    /// Full name of the request message
+    pub input_type: String,
+    /// Full name of the response message
+    pub output_type: String,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+    pub options: Vec<SchemaOption>,
+}
+
+/// Mirrors `google.protobuf.SourceCodeInfo.Location`
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct SourceLocation {
+    /// Field numbers and indices leading from the FileDescriptorProto to the element
+    pub path: Vec<i32>,
+    /// `[start_line, start_column, end_line, end_column]` or, when the element spans a single
+    /// line, `[start_line, start_column, end_column]`. All values are zero based.
+    pub span: Vec<i32>,
+    pub leading_comments: Option<String>,
+    pub trailing_comments: Option<String>,
+    pub leading_detached_comments: Vec<String>,
+}
@@ -0,0 +1,232 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+// Tokens locate themselves with byte offsets into the source text. Turning an offset into a line
+// and column depends on who asks: protoc and descriptors count bytes, LSP clients count UTF-16
+// code units and a terminal shows one glyph per grapheme cluster. Lines end at "\n", a "\r"
+// before it belongs to the line break.
+
+/// protoc's tokenizer advances the column to the next multiple of 8 on tabs
+const PROTOC_TAB_WIDTH: usize = 8;
+
+/// Unit in which the columns of a line are counted
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColumnUnit {
+    /// Bytes of UTF-8, like protoc
+    Utf8,
+    /// UTF-16 code units, like LSP positions
+    Utf16,
+    /// Extended grapheme clusters, what is displayed as one character
+    Grapheme,
+}
+
+/// Zero based line and column of a place in a source text
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct LineColumn {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where the lines of a source text start, to go from byte offsets to lines and columns and back.
+/// The text is either borrowed or owned, for maps kept along with what was parsed from it.
+#[derive(Clone, Debug)]
+pub struct SourceMap<T: AsRef<str>> {
+    text: T,
+    /// Byte offset of the first character of every line
+    line_starts: Vec<usize>,
+}
+
+impl<T: AsRef<str>> SourceMap<T> {
+    pub fn new(text: T) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(
+                text.as_ref()
+                    .match_indices('\n')
+                    .map(|(index, _)| index + 1),
+            )
+            .collect();
+        SourceMap { text, line_starts }
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_ref()
+    }
+
+    /// Number of lines, a text ending with a line break has an empty last line
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Zero based line holding the byte at `offset`, the end of the text is on the last line
+    pub fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset) - 1
+    }
+
+    /// Byte offset of the start of `line`, or the end of the text past the last line
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.text().len())
+    }
+
+    /// Text of `line` without its line break
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_start(line);
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.text().len(), |next| next - 1);
+        let text = &self.text()[start..end.max(start)];
+        text.strip_suffix('\r').unwrap_or(text)
+    }
+
+    /// Line and column of the byte at `offset`, an offset inside a character counts as the
+    /// start of that character
+    pub fn line_column(&self, offset: usize, unit: ColumnUnit) -> LineColumn {
+        let offset = floor_char_boundary(self.text(), offset);
+        let line = self.line(offset);
+        let start = self.line_start(line);
+        LineColumn {
+            line,
+            column: width(&self.text()[start..offset], unit),
+        }
+    }
+
+    /// Line and column of the byte at `offset` the way protoc counts them, in bytes with tab stops
+    pub fn protoc_line_column(&self, offset: usize) -> LineColumn {
+        let position = self.line_column(offset, ColumnUnit::Utf8);
+        let start = self.line_start(position.line);
+        LineColumn {
+            line: position.line,
+            column: self.text()[start..start + position.column]
+                .bytes()
+                .fold(0, |column, byte| match byte {
+                    b'\t' => column + PROTOC_TAB_WIDTH - column % PROTOC_TAB_WIDTH,
+                    _ => column + 1,
+                }),
+        }
+    }
+
+    /// Byte offset of `position` counted the way protoc does, the inverse of `protoc_line_column`.
+    /// A column inside a tab counts as the tab.
+    pub fn protoc_offset(&self, position: LineColumn) -> usize {
+        if position.line >= self.line_count() {
+            return self.text().len();
+        }
+        let start = self.line_start(position.line);
+        let mut column = 0;
+        let end = self
+            .line_text(position.line)
+            .bytes()
+            .position(|byte| {
+                column += match byte {
+                    b'\t' => PROTOC_TAB_WIDTH - column % PROTOC_TAB_WIDTH,
+                    _ => 1,
+                };
+                column > position.column
+            })
+            .unwrap_or(self.line_text(position.line).len());
+        start + end
+    }
+
+    /// Byte offset of `position`, columns past the end of the line stop at its line break and
+    /// lines past the end of the text at its end
+    pub fn offset(&self, position: LineColumn, unit: ColumnUnit) -> usize {
+        if position.line >= self.line_count() {
+            return self.text().len();
+        }
+        let start = self.line_start(position.line);
+        let line = self.line_text(position.line);
+        let mut column = 0;
+        let mut step = |text: &str| {
+            let next = column + width(text, unit);
+            std::mem::replace(&mut column, next) < position.column
+        };
+        let end = match unit {
+            ColumnUnit::Grapheme => line
+                .grapheme_indices(true)
+                .find(|(_, grapheme)| !step(grapheme))
+                .map(|(index, _)| index),
+            _ => line
+                .char_indices()
+                .find(|(index, ch)| !step(&line[*index..*index + ch.len_utf8()]))
+                .map(|(index, _)| index),
+        };
+        start + end.unwrap_or(line.len())
+    }
+}
+
+/// Length of `text` counted in `unit`
+pub fn width(text: &str, unit: ColumnUnit) -> usize {
+    match unit {
+        ColumnUnit::Utf8 => text.len(),
+        ColumnUnit::Utf16 => text.encode_utf16().count(),
+        ColumnUnit::Grapheme => text.graphemes(true).count(),
+    }
+}
+
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_map_test() {
+        // "é" is two bytes, "𝄞" four bytes and two UTF-16 code units, "e\u{301}" one grapheme
+        let text = "a = \"é𝄞\";\r\n\tb = \"e\u{301}x\";\n";
+        let map = SourceMap::new(text);
+        assert_eq!(map.line_count(), 3);
+        assert_eq!(map.line_text(0), "a = \"é𝄞\";");
+        assert_eq!(map.line_text(1), "\tb = \"e\u{301}x\";");
+        assert_eq!(map.line_text(2), "");
+
+        let semicolon = text.find(';').unwrap();
+        let at = |offset, unit| {
+            let position = map.line_column(offset, unit);
+            (position.line, position.column)
+        };
+        assert_eq!(at(semicolon, ColumnUnit::Utf8), (0, 12));
+        assert_eq!(at(semicolon, ColumnUnit::Utf16), (0, 9));
+        assert_eq!(at(semicolon, ColumnUnit::Grapheme), (0, 8));
+        let x = text.find('x').unwrap();
+        assert_eq!(at(x, ColumnUnit::Utf8), (1, 9));
+        assert_eq!(at(x, ColumnUnit::Utf16), (1, 8));
+        assert_eq!(at(x, ColumnUnit::Grapheme), (1, 7));
+        // Inside a character, and at the very end
+        assert_eq!(at(semicolon - 2, ColumnUnit::Utf16), (0, 6));
+        assert_eq!(at(text.len(), ColumnUnit::Grapheme), (2, 0));
+        assert_eq!(
+            map.protoc_line_column(x),
+            LineColumn {
+                line: 1,
+                column: 16
+            }
+        );
+        for offset in [0, semicolon, x, text.len()] {
+            assert_eq!(map.protoc_offset(map.protoc_line_column(offset)), offset);
+        }
+        let tab = text.find('\t').unwrap();
+        assert_eq!(map.protoc_offset(LineColumn { line: 1, column: 3 }), tab);
+
+        for unit in [ColumnUnit::Utf8, ColumnUnit::Utf16, ColumnUnit::Grapheme] {
+            for offset in [0, semicolon, x, text.len()] {
+                assert_eq!(map.offset(map.line_column(offset, unit), unit), offset);
+            }
+            // Past the end of a line, and of the text
+            let past = LineColumn {
+                line: 0,
+                column: 100,
+            };
+            assert_eq!(map.offset(past, unit), semicolon + 1);
+            let past = LineColumn { line: 5, column: 0 };
+            assert_eq!(map.offset(past, unit), text.len());
+        }
+    }
+}
@@ -1,17 +1,176 @@
-use crate::error::Result;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::error::{Result, RsProtocError};
+
+// Every file read during a compilation is registered once and gets a `FileId`. Tokens carry the
+// id of the file they were lexed from, so a diagnostic can name and quote the right file for
+// each of its labels even when they point into imports. Reading goes through a `FileSystem`,
+// tests use a `VirtualFileSystem` instead of the disk.
+
+/// Identity of a file in a `SourceRegistry`, the default id is for text that was not registered
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Default)]
+pub struct FileId(u32);
+
+/// Where the files of a compilation are read from
+pub trait FileSystem {
+    fn is_file(&self, path: &Path) -> bool;
+    fn read_to_string(&self, path: &Path) -> std::io::Result<String>;
+}
+
+/// The files on disk
+pub struct DiskFileSystem;
+
+impl FileSystem for DiskFileSystem {
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// Files kept in memory, keyed by path
+#[derive(Clone, Default)]
+pub struct VirtualFileSystem {
+    files: HashMap<PathBuf, String>,
+}
+
+impl VirtualFileSystem {
+    pub fn insert(&mut self, path: impl Into<PathBuf>, text: impl Into<String>) {
+        self.files.insert(path.into(), text.into());
+    }
+}
+
+impl FileSystem for VirtualFileSystem {
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        self.files.get(path).cloned().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "No such file in memory")
+        })
+    }
+}
 
 pub struct SourceBuffer {
+    id: FileId,
+    /// Name of the file within its include path, how imports and diagnostics refer to it
+    name: String,
+    path: PathBuf,
     storage: String,
 }
 
 impl<'a> SourceBuffer {
     pub fn new_from_file(filename: &str) -> Result<Self> {
-        todo!()
+        match std::fs::read_to_string(filename) {
+            Ok(storage) => Ok(SourceBuffer {
+                id: FileId::default(),
+                name: filename.to_string(),
+                path: PathBuf::from(filename),
+                storage,
+            }),
+            Err(err) => Err(RsProtocError::FilesystemError(format!(
+                "Failed to read \"{}\": {}",
+                filename, err
+            ))),
+        }
     }
     pub fn new(external: String) -> Result<Self> {
-        Ok(SourceBuffer { storage: external })
+        Ok(SourceBuffer {
+            id: FileId::default(),
+            name: String::new(),
+            path: PathBuf::new(),
+            storage: external,
+        })
     }
     pub fn text(&'a self) -> &'a str {
         self.storage.as_str()
     }
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The files read during a compilation, each with its own `FileId`
+pub struct SourceRegistry {
+    file_system: Box<dyn FileSystem>,
+    buffers: Vec<Arc<SourceBuffer>>,
+}
+
+impl Default for SourceRegistry {
+    fn default() -> Self {
+        SourceRegistry::new(DiskFileSystem)
+    }
+}
+
+impl SourceRegistry {
+    pub fn new(file_system: impl FileSystem + 'static) -> Self {
+        SourceRegistry {
+            file_system: Box::new(file_system),
+            buffers: Vec::new(),
+        }
+    }
+
+    pub fn is_file(&self, path: &Path) -> bool {
+        self.file_system.is_file(path)
+    }
+
+    /// Read the file at `path` and register it as `name`, a file already registered under that
+    /// name and path is not read again
+    pub fn load(&mut self, name: &str, path: &Path) -> Result<Arc<SourceBuffer>> {
+        if let Some(buffer) = self
+            .buffers
+            .iter()
+            .find(|buffer| buffer.name == name && buffer.path == path)
+        {
+            return Ok(buffer.clone());
+        }
+        match self.file_system.read_to_string(path) {
+            Ok(text) => Ok(self.add(name, path, text)),
+            Err(error) => Err(RsProtocError::FilesystemError(format!(
+                "Failed to read \"{}\": {}",
+                path.display(),
+                error
+            ))),
+        }
+    }
+
+    /// Register text that does not come from the file system, like an unsaved editor buffer
+    pub fn add(&mut self, name: &str, path: &Path, text: String) -> Arc<SourceBuffer> {
+        let buffer = Arc::new(SourceBuffer {
+            // Ids start at 1, the default id is for unregistered text
+            id: FileId(self.buffers.len() as u32 + 1),
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            storage: text,
+        });
+        self.buffers.push(buffer.clone());
+        buffer
+    }
+
+    pub fn get(&self, id: FileId) -> Option<&SourceBuffer> {
+        let index = (id.0 as usize).checked_sub(1)?;
+        self.buffers.get(index).map(|buffer| buffer.as_ref())
+    }
+
+    /// The last file registered as `name`
+    pub fn find(&self, name: &str) -> Option<&SourceBuffer> {
+        self.buffers
+            .iter()
+            .rev()
+            .find(|buffer| buffer.name == name)
+            .map(|buffer| buffer.as_ref())
+    }
 }
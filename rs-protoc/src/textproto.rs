@@ -0,0 +1,606 @@
+use std::fmt::Write;
+
+use crate::{
+    ast_elements::{Constant, MessageLiteralField, OptionNamePart, ScalarType, Spanned},
+    dynamic::{DynamicMessage, Value},
+    error::RsProtocError,
+    formatter::string_literal,
+    lexer::TokenMetadata,
+    parser::Parser,
+    resolver::check_scalar,
+    schema::{FieldDescriptor, FieldType, Label, MessageDescriptor, Schema},
+};
+
+// The protobuf text format, as found in .txtpb and .textproto files. Documents are parsed with
+// the message literal grammar of option values, plus `#` comments, and then checked against the
+// schema into dynamic messages. Printing goes the other way and can be parsed back.
+// https://protobuf.dev/reference/protobuf/textformat-spec/
+
+const ANY: &str = "google.protobuf.Any";
+const ANY_TYPE_URL: i32 = 1;
+const ANY_VALUE: i32 = 2;
+
+/// Parse a text format document holding a message of the type `message_name`, reporting every
+/// field that does not match the schema
+pub fn parse(
+    schema: &Schema,
+    message_name: &str,
+    text: &str,
+) -> Result<DynamicMessage, Vec<RsProtocError>> {
+    let Some(descriptor) = schema.find_message(message_name) else {
+        return Err(vec![RsProtocError::EncodingError(format!(
+            "Unknown message type \"{}\"",
+            message_name
+        ))]);
+    };
+    let fields = Parser::for_text_format(text).parse_text_format()?;
+    let mut checker = Checker {
+        schema,
+        errors: Vec::new(),
+    };
+    let message = checker.message(descriptor, &fields);
+    match message {
+        Some(message) if checker.errors.is_empty() => Ok(message),
+        _ => Err(checker.errors),
+    }
+}
+
+/// Print `message` in the text format, one field per line and nested messages indented
+pub fn print(schema: &Schema, message: &DynamicMessage) -> String {
+    let mut text = String::new();
+    print_fields(schema, message, 0, &mut text);
+    text
+}
+
+struct Checker<'s> {
+    schema: &'s Schema,
+    errors: Vec<RsProtocError>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, metadata: &TokenMetadata, message: String) {
+        self.errors
+            .push(RsProtocError::ValidationError(message, metadata.clone()));
+    }
+
+    fn message(
+        &mut self,
+        descriptor: &MessageDescriptor,
+        literal_fields: &[MessageLiteralField],
+    ) -> Option<DynamicMessage> {
+        let mut message = DynamicMessage::new(&descriptor.full_name);
+        let mut is_valid = true;
+        // Names of the singular fields set so far, with the oneof they belong to
+        let mut set_fields: Vec<(i32, Option<usize>, String)> = Vec::new();
+        for literal_field in literal_fields {
+            let (field_name, field) = match &literal_field.name.value {
+                OptionNamePart::Extension(type_url)
+                    if descriptor.full_name == ANY && type_url.contains('/') =>
+                {
+                    match self.any(type_url, &literal_field.value) {
+                        Some(value) if message.fields.is_empty() => {
+                            message.set(ANY_TYPE_URL, Value::String(type_url.clone()));
+                            message.set(ANY_VALUE, value);
+                        }
+                        Some(_) => {
+                            self.report(
+                                &literal_field.name.metadata,
+                                format!("Field \"[{}]\" was already set", type_url),
+                            );
+                            is_valid = false;
+                        }
+                        None => is_valid = false,
+                    }
+                    continue;
+                }
+                OptionNamePart::Simple(name) => (
+                    name.clone(),
+                    descriptor.fields.iter().find(|field| field.name == *name),
+                ),
+                OptionNamePart::Extension(name) => {
+                    let name = name.strip_prefix('.').unwrap_or(name);
+                    let extension = self
+                        .schema
+                        .extensions_of(&descriptor.full_name)
+                        .into_iter()
+                        .find(|(full_name, _)| full_name == name)
+                        .map(|(_, extension)| extension);
+                    (format!("[{}]", name), extension)
+                }
+            };
+            let Some(field) = field else {
+                self.report(
+                    &literal_field.name.metadata,
+                    format!(
+                        "Message \"{}\" has no field \"{}\"",
+                        descriptor.full_name, field_name
+                    ),
+                );
+                is_valid = false;
+                continue;
+            };
+            if field.label != Label::Repeated {
+                let oneof = field.oneof_index.filter(|_| !field.proto3_optional);
+                let conflict = set_fields.iter().find(|(number, other_oneof, _)| {
+                    *number == field.number || (oneof.is_some() && *other_oneof == oneof)
+                });
+                if let Some((number, _, other_name)) = conflict {
+                    let message = if *number == field.number {
+                        format!("Field \"{}\" was already set", field_name)
+                    } else {
+                        format!(
+                            "Field \"{}\" and field \"{}\" are members of the same oneof",
+                            field_name, other_name
+                        )
+                    };
+                    self.report(&literal_field.name.metadata, message);
+                    is_valid = false;
+                    continue;
+                }
+                set_fields.push((field.number, oneof, field_name.clone()));
+            }
+            let elements = match &literal_field.value.value {
+                Constant::List(elements) if field.label == Label::Repeated => {
+                    elements.iter().collect()
+                }
+                Constant::List(_) => {
+                    self.report(
+                        &literal_field.value.metadata,
+                        format!(
+                            "Field \"{}\" is not repeated, it cannot be a list",
+                            field_name
+                        ),
+                    );
+                    is_valid = false;
+                    continue;
+                }
+                _ => vec![&literal_field.value],
+            };
+            for element in elements {
+                match self.value(field, &field_name, element) {
+                    Some(value) => message.push(field.number, value),
+                    None => is_valid = false,
+                }
+            }
+        }
+        is_valid.then_some(message)
+    }
+
+    /// Check a single value of `field`, `name` is how the field is referred to in errors
+    fn value(
+        &mut self,
+        field: &FieldDescriptor,
+        name: &str,
+        value: &Spanned<Constant>,
+    ) -> Option<Value> {
+        let expected = match (&field.field_type, &value.value) {
+            (FieldType::Scalar(scalar_type), constant) => {
+                let constant = text_format_scalar(*scalar_type, constant);
+                match check_scalar(*scalar_type, constant.as_ref().unwrap_or(&value.value)) {
+                    Ok(constant) => return Some(Value::from_constant(*scalar_type, constant)),
+                    Err(expected) => expected,
+                }
+            }
+            (FieldType::Enum(enum_name), constant) => {
+                let enum_descriptor = self.schema.find_enum(enum_name)?;
+                match constant {
+                    Constant::Identifier(identifier) => {
+                        if let Some(enum_value) = enum_descriptor
+                            .values
+                            .iter()
+                            .find(|enum_value| enum_value.name == *identifier)
+                        {
+                            return Some(Value::Enum(enum_value.number));
+                        }
+                    }
+                    // Numbers that are not declared are kept, proto3 enums are open
+                    Constant::Integer(number) => {
+                        if let Ok(number) = i32::try_from(*number) {
+                            return Some(Value::Enum(number));
+                        }
+                    }
+                    _ => {}
+                }
+                let names: Vec<&str> = enum_descriptor
+                    .values
+                    .iter()
+                    .map(|enum_value| enum_value.name.as_str())
+                    .collect();
+                format!("a number or one of {}", names.join(", "))
+            }
+            (FieldType::Message(message_name), Constant::Message(fields)) => {
+                let descriptor = self.schema.find_message(message_name)?;
+                return self.message(descriptor, fields).map(Value::Message);
+            }
+            (FieldType::Message(_), _) => "a message".to_string(),
+        };
+        self.report(
+            &value.metadata,
+            format!("Value must be {} for field \"{}\"", expected, name),
+        );
+        None
+    }
+
+    /// The `value` field of an `Any` written as the message it holds, under its type URL
+    fn any(&mut self, type_url: &str, value: &Spanned<Constant>) -> Option<Value> {
+        // The message name follows the last "/" of the URL
+        let message_name = &type_url[type_url.rfind('/')? + 1..];
+        let Some(descriptor) = self.schema.find_message(message_name) else {
+            self.report(
+                &value.metadata,
+                format!(
+                    "Unknown message type \"{}\" in type URL \"{}\"",
+                    message_name, type_url
+                ),
+            );
+            return None;
+        };
+        let Constant::Message(fields) = &value.value else {
+            self.report(
+                &value.metadata,
+                format!("Value must be a message for field \"[{}]\"", type_url),
+            );
+            return None;
+        };
+        let message = self.message(descriptor, fields)?;
+        match message.encode(self.schema) {
+            Ok(bytes) => Some(Value::Bytes(bytes)),
+            Err(error) => {
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+}
+
+/// The spellings the text format accepts on top of the constants of .proto files: "t", "True",
+/// 0 and 1 for booleans and infinity and NaN in any case for floating point values
+fn text_format_scalar(scalar_type: ScalarType, constant: &Constant) -> Option<Constant> {
+    match (scalar_type, constant) {
+        (ScalarType::Bool, Constant::Identifier(identifier)) => match identifier.as_str() {
+            "True" | "t" => Some(Constant::Bool(true)),
+            "False" | "f" => Some(Constant::Bool(false)),
+            _ => None,
+        },
+        (ScalarType::Bool, Constant::Integer(value @ (0 | 1))) => Some(Constant::Bool(*value == 1)),
+        (ScalarType::Float | ScalarType::Double, Constant::Identifier(identifier)) => {
+            match identifier.to_ascii_lowercase().as_str() {
+                "inf" | "infinity" => Some(Constant::Float(f64::INFINITY)),
+                "nan" => Some(Constant::Float(f64::NAN)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn print_fields(schema: &Schema, message: &DynamicMessage, indent: usize, text: &mut String) {
+    let Some(descriptor) = schema.find_message(&message.type_name) else {
+        return;
+    };
+    if descriptor.full_name == ANY && print_any(schema, message, indent, text) {
+        return;
+    }
+    for (number, values) in &message.fields {
+        let Some((name, field)) = field_name(schema, descriptor, *number) else {
+            continue;
+        };
+        match &field.field_type {
+            FieldType::Message(_) => {
+                for value in values {
+                    if let Value::Message(nested) = value {
+                        print_message(schema, &name, nested, indent, text);
+                    }
+                }
+            }
+            field_type if field.label == Label::Repeated => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|value| scalar(schema, field_type, value))
+                    .collect();
+                _ = writeln!(text, "{:indent$}{}: [{}]", "", name, values.join(", "));
+            }
+            field_type => {
+                for value in values {
+                    let value = scalar(schema, field_type, value);
+                    _ = writeln!(text, "{:indent$}{}: {}", "", name, value);
+                }
+            }
+        }
+    }
+}
+
+fn print_message(
+    schema: &Schema,
+    name: &str,
+    message: &DynamicMessage,
+    indent: usize,
+    text: &mut String,
+) {
+    _ = writeln!(text, "{:indent$}{} {{", "", name);
+    print_fields(schema, message, indent + 2, text);
+    _ = writeln!(text, "{:indent$}}}", "");
+}
+
+/// Print an `Any` as the message it holds when its type is in the schema
+fn print_any(schema: &Schema, message: &DynamicMessage, indent: usize, text: &mut String) -> bool {
+    let ([Value::String(type_url)], [Value::Bytes(bytes)]) =
+        (message.get(ANY_TYPE_URL), message.get(ANY_VALUE))
+    else {
+        return false;
+    };
+    let Some((_, message_name)) = type_url.rsplit_once('/') else {
+        return false;
+    };
+    let Ok(value) = DynamicMessage::decode(schema, message_name, bytes) else {
+        return false;
+    };
+    print_message(schema, &format!("[{}]", type_url), &value, indent, text);
+    true
+}
+
+/// The field `number` of `descriptor` along with its name, in brackets for extensions
+fn field_name<'s>(
+    schema: &'s Schema,
+    descriptor: &'s MessageDescriptor,
+    number: i32,
+) -> Option<(String, &'s FieldDescriptor)> {
+    if let Some(field) = descriptor
+        .fields
+        .iter()
+        .find(|field| field.number == number)
+    {
+        return Some((field.name.clone(), field));
+    }
+    schema
+        .extensions_of(&descriptor.full_name)
+        .into_iter()
+        .find(|(_, extension)| extension.number == number)
+        .map(|(full_name, extension)| (format!("[{}]", full_name), extension))
+}
+
+fn scalar(schema: &Schema, field_type: &FieldType, value: &Value) -> String {
+    match value {
+        Value::Bool(value) => value.to_string(),
+        Value::I32(value) => value.to_string(),
+        Value::I64(value) => value.to_string(),
+        Value::U32(value) => value.to_string(),
+        Value::U64(value) => value.to_string(),
+        Value::F32(value) => float(*value as f64, value.to_string()),
+        Value::F64(value) => float(*value, value.to_string()),
+        Value::String(value) => string_literal(value.as_bytes()),
+        Value::Bytes(value) => string_literal(value),
+        Value::Enum(number) => {
+            let name = match field_type {
+                FieldType::Enum(enum_name) => schema.find_enum(enum_name).and_then(|descriptor| {
+                    descriptor
+                        .values
+                        .iter()
+                        .find(|enum_value| enum_value.number == *number)
+                }),
+                _ => None,
+            };
+            name.map_or_else(|| number.to_string(), |enum_value| enum_value.name.clone())
+        }
+        Value::Message(_) => String::new(),
+    }
+}
+
+/// `digits` is the shortest text that reads back as the value
+fn float(value: f64, digits: String) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        digits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use crate::{
+        resolver::{resolve, Loader},
+        source_text::{SourceRegistry, VirtualFileSystem},
+    };
+
+    /// Any and Duration are the well-known types the loader provides
+    fn schema() -> Schema {
+        let mut file_system = VirtualFileSystem::default();
+        file_system.insert(
+            "protos/test.proto",
+            r#"syntax = "proto3";
+            package p;
+            import "google/protobuf/any.proto";
+            import "google/protobuf/duration.proto";
+            enum Kind {
+                KIND_UNSPECIFIED = 0;
+                KIND_FIRST = 1;
+            }
+            message M {
+                int32 a = 1;
+                repeated double values = 2;
+                string name = 3;
+                bytes raw = 4;
+                bool flag = 5;
+                Kind kind = 6;
+                repeated N children = 7;
+                N single = 8;
+                map<string, int64> counts = 9;
+                google.protobuf.Any any = 10;
+                oneof choice {
+                    uint32 small = 11;
+                    fixed64 large = 12;
+                }
+                float ratio = 13;
+                extensions 100 to 200;
+            }
+            message N {
+                repeated Kind kinds = 1;
+                M parent = 2;
+            }
+            extend M {
+                sint32 ext = 100;
+            }"#,
+        );
+        let mut loader = Loader::with_sources(
+            vec![PathBuf::from("protos")],
+            SourceRegistry::new(file_system),
+        );
+        loader.load("test.proto");
+        resolve(&loader.finish().unwrap()).unwrap()
+    }
+
+    fn errors(text: &str) -> Vec<String> {
+        match parse(&schema(), "p.M", text) {
+            Ok(message) => panic!("{:?}", message),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let schema = schema();
+        let text = r#"# A comment
+a: -150
+values: [1.5, -inf, 2]
+name: "caf\xc3\xa9\n"
+raw: "\000\377"
+flag: t
+kind: KIND_FIRST
+children {
+  kinds: [KIND_FIRST, 7]
+}
+children <
+  parent { a: 1 }
+>
+single: {}
+counts { key: "one" value: 1 }
+counts { key: "two", value: 2 };
+any {
+  [type.googleapis.com/p.N] { kinds: KIND_FIRST }
+}
+large: 18446744073709551615
+ratio: 0.1
+[p.ext]: -3
+"#;
+        let message = parse(&schema, "p.M", text).unwrap();
+        assert_eq!(message.get(1), [Value::I32(-150)]);
+        assert_eq!(message.get(5), [Value::Bool(true)]);
+        assert_eq!(message.get(100), [Value::I32(-3)]);
+        let printed = print(&schema, &message);
+        assert_eq!(
+            printed,
+            r#"a: -150
+values: [1.5, -inf, 2]
+name: "café\n"
+raw: "\x00\xff"
+flag: true
+kind: KIND_FIRST
+children {
+  kinds: [KIND_FIRST, 7]
+}
+children {
+  parent {
+    a: 1
+  }
+}
+single {
+}
+counts {
+  key: "one"
+  value: 1
+}
+counts {
+  key: "two"
+  value: 2
+}
+any {
+  [type.googleapis.com/p.N] {
+    kinds: [KIND_FIRST]
+  }
+}
+large: 18446744073709551615
+ratio: 0.1
+[p.ext]: -3
+"#
+        );
+        assert_eq!(parse(&schema, "p.M", &printed).unwrap(), message);
+
+        // The Any holds the encoded message
+        let [Value::Message(any)] = message.get(10) else {
+            panic!("{:?}", message);
+        };
+        assert_eq!(
+            any.get(1),
+            [Value::String("type.googleapis.com/p.N".to_string())]
+        );
+        assert_eq!(any.get(2), [Value::Bytes(vec![0x0a, 0x01, 0x01])]);
+    }
+
+    #[test]
+    fn well_known_any_test() {
+        let schema = schema();
+        let text = "any { [type.googleapis.com/google.protobuf.Duration] { seconds: 3 nanos: 5 } }";
+        let message = parse(&schema, "p.M", text).unwrap();
+        assert_eq!(
+            print(&schema, &message),
+            r#"any {
+  [type.googleapis.com/google.protobuf.Duration] {
+    seconds: 3
+    nanos: 5
+  }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn text_format_constants_test() {
+        let schema = schema();
+        let message = parse(&schema, "p.M", "flag: False values: [-Infinity, NaN, 1e3]").unwrap();
+        assert_eq!(message.get(5), [Value::Bool(false)]);
+        let [Value::F64(a), Value::F64(b), Value::F64(c)] = message.get(2) else {
+            panic!("{:?}", message);
+        };
+        assert_eq!((*a, b.is_nan(), *c), (f64::NEG_INFINITY, true, 1000.0));
+        let message = parse(&schema, "p.M", "flag: 1; a: 0x10, small: 3").unwrap();
+        assert_eq!(message.get(5), [Value::Bool(true)]);
+        assert_eq!(message.get(1), [Value::I32(16)]);
+        assert_eq!(message.get(11), [Value::U32(3)]);
+    }
+
+    #[test]
+    fn errors_test() {
+        assert_eq!(
+            errors("b: 1\na: 1 a: 2\nname: [\"x\"]"),
+            [
+                "RsProtocError::ValidationError[1:1: Message \"p.M\" has no field \"b\"]",
+                "RsProtocError::ValidationError[2:6: Field \"a\" was already set]",
+                "RsProtocError::ValidationError[3:7: Field \"name\" is not repeated, it cannot be a list]",
+            ]
+        );
+        assert_eq!(
+            errors("small: 1 large: 2 a: 3000000000 kind: KIND_SECOND"),
+            [
+                "RsProtocError::ValidationError[1:10: Field \"large\" and field \"small\" are members of the same oneof]",
+                "RsProtocError::ValidationError[1:22: Value must be an integer between -2147483648 and 2147483647 for field \"a\"]",
+                "RsProtocError::ValidationError[1:39: Value must be a number or one of KIND_UNSPECIFIED, KIND_FIRST for field \"kind\"]",
+            ]
+        );
+        assert_eq!(
+            errors("[p.other]: 1 any { [example.com/p.Missing] {} } single: 1"),
+            [
+                "RsProtocError::ValidationError[1:1: Message \"p.M\" has no field \"[p.other]\"]",
+                "RsProtocError::ValidationError[1:44: Unknown message type \"p.Missing\" in type URL \"example.com/p.Missing\"]",
+                "RsProtocError::ValidationError[1:57: Value must be a message for field \"single\"]",
+            ]
+        );
+        // Syntax errors stop parsing
+        assert_eq!(errors("a: 1 children {").len(), 1);
+        assert_eq!(errors("a 1").len(), 1);
+    }
+}
@@ -0,0 +1,568 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast_elements::{
+        find_option, Constant, ElementType, EnumPayload, FieldLabel, FieldPayload, FieldType,
+        MessagePayload, NamedElement, ProtoFile, Reserved, ScalarType,
+    },
+    error::RsProtocError,
+    lexer::TokenMetadata,
+    parser::MAX_FIELD_NUMBER,
+};
+
+/// Field numbers reserved for the protocol buffer library implementation
+const IMPLEMENTATION_RESERVED_RANGE: std::ops::RangeInclusive<u64> = 19000..=19999;
+
+/// Run the semantic checks that protoc applies to a parsed file.
+/// Every violation is reported rather than only the first one.
+pub fn validate(file: &ProtoFile) -> std::result::Result<(), Vec<RsProtocError>> {
+    let mut validator = Validator {
+        proto2: matches!(&file.syntax, Some(syntax) if syntax.value == "proto2"),
+        errors: Vec::new(),
+    };
+    let scope = match &file.package.name {
+        Some(name) => name.value.clone(),
+        None => String::new(),
+    };
+    validator.validate_elements(&scope, &file.package.named_elements);
+    if validator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(validator.errors)
+    }
+}
+
+/// Default JSON name of a field, lowerCamelCase with underscores removed
+pub fn json_name(field_name: &str) -> String {
+    let mut output = String::with_capacity(field_name.len());
+    let mut capitalize_next = false;
+    for ch in field_name.chars() {
+        if ch == '_' {
+            capitalize_next = true;
+        } else if capitalize_next {
+            output.push(ch.to_ascii_uppercase());
+            capitalize_next = false;
+        } else {
+            output.push(ch);
+        }
+    }
+    output
+}
+
+fn qualified_name(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+struct Validator {
+    /// The rules of proto2 apply rather than those of proto3
+    proto2: bool,
+    errors: Vec<RsProtocError>,
+}
+
+impl Validator {
+    fn report(&mut self, metadata: &TokenMetadata, message: String) {
+        self.errors
+            .push(RsProtocError::ValidationError(message, metadata.clone()));
+    }
+
+    fn validate_elements(&mut self, scope: &str, elements: &[NamedElement]) {
+        for element in elements {
+            match &element.type_t {
+                ElementType::Message(message) => {
+                    self.validate_message(&qualified_name(scope, &element.name), message)
+                }
+                ElementType::Enum(enum_payload) => {
+                    self.validate_enum(&qualified_name(scope, &element.name), enum_payload)
+                }
+                ElementType::Extension(extension) => {
+                    for field in &extension.fields {
+                        if let ElementType::Field(payload) = &field.type_t {
+                            self.validate_label(field, payload);
+                            self.validate_field(field, payload);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn validate_message(&mut self, full_name: &str, message: &MessagePayload) {
+        let mut fields: Vec<(&NamedElement, &FieldPayload)> = Vec::new();
+        for element in &message.named_elements {
+            match &element.type_t {
+                ElementType::Field(field) => {
+                    self.validate_label(element, field);
+                    fields.push((element, field));
+                }
+                ElementType::OneOf(oneof) => {
+                    for oneof_field in &oneof.fields {
+                        if let ElementType::Field(field) = &oneof_field.type_t {
+                            fields.push((oneof_field, field));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for reserved in &message.reserved {
+            if let Reserved::Range(range) = reserved {
+                if range.value.start < 1 {
+                    self.report(
+                        &range.metadata,
+                        "Reserved numbers must be positive integers".to_string(),
+                    );
+                }
+            }
+        }
+
+        let mut used_numbers: HashMap<u64, &str> = HashMap::new();
+        // Keyed by the JSON name lowercased without underscores, which is how protoc compares
+        // them in proto3
+        let mut used_json_names: HashMap<String, (&str, bool)> = HashMap::new();
+        for (element, field) in fields {
+            self.validate_field(element, field);
+            let number = field.number.value;
+            if let Some(previous_field) = used_numbers.get(&number) {
+                self.report(
+                    &field.number.metadata,
+                    format!(
+                        "Field number {} has already been used in \"{}\" by field \"{}\"",
+                        number, full_name, previous_field
+                    ),
+                );
+            } else {
+                used_numbers.insert(number, &element.name);
+            }
+            for reserved in &message.reserved {
+                match reserved {
+                    Reserved::Range(range) if range.value.contains(number as i64) => self.report(
+                        &field.number.metadata,
+                        format!("Field \"{}\" uses reserved number {}", element.name, number),
+                    ),
+                    Reserved::Name(name) if name.value == element.name => self.report(
+                        &element.metadata,
+                        format!("Field name \"{}\" is reserved", element.name),
+                    ),
+                    _ => {}
+                }
+            }
+
+            let (field_json_name, is_custom) = match find_option(&field.options, "json_name") {
+                Some(custom) => match &custom.value {
+                    Constant::String(custom) => (custom.clone(), true),
+                    _ => {
+                        self.report(
+                            &custom.metadata,
+                            "Option \"json_name\" must be a string".to_string(),
+                        );
+                        continue;
+                    }
+                },
+                None => (json_name(&element.name), false),
+            };
+            // Like protoc, JSON names are only required to be unique in proto3
+            if self.proto2 {
+                continue;
+            }
+            let conflict_key = field_json_name.to_ascii_lowercase().replace('_', "");
+            if let Some((previous_field, previous_is_custom)) = used_json_names.get(&conflict_key) {
+                let describe = |is_custom: bool| if is_custom { "custom" } else { "default" };
+                self.report(
+                    &element.metadata,
+                    format!(
+                        "The {} JSON name of field \"{}\" (\"{}\") conflicts with the {} JSON name of field \"{}\"",
+                        describe(is_custom),
+                        element.name,
+                        field_json_name,
+                        describe(*previous_is_custom),
+                        previous_field
+                    ),
+                );
+            } else {
+                used_json_names.insert(conflict_key, (&element.name, is_custom));
+            }
+        }
+
+        self.validate_elements(full_name, &message.named_elements);
+    }
+
+    /// Fields outside of oneofs need a label in proto2, except for maps
+    fn validate_label(&mut self, element: &NamedElement, field: &FieldPayload) {
+        if self.proto2
+            && field.label == FieldLabel::None
+            && !matches!(field.field_type.value, FieldType::Map(_, _))
+        {
+            self.report(
+                &element.start,
+                "Expected \"required\", \"optional\", or \"repeated\"".to_string(),
+            );
+        }
+    }
+
+    fn validate_field(&mut self, element: &NamedElement, field: &FieldPayload) {
+        if field.label == FieldLabel::Required && !self.proto2 {
+            self.report(
+                &element.metadata,
+                "Required fields are not allowed in proto3".to_string(),
+            );
+        }
+        if let Some(default) = find_option(&field.options, "default") {
+            if !self.proto2 {
+                self.report(
+                    &default.metadata,
+                    "Explicit default values are not allowed in proto3".to_string(),
+                );
+            } else if field.label == FieldLabel::Repeated {
+                self.report(
+                    &default.metadata,
+                    "Repeated fields can't have default values".to_string(),
+                );
+            }
+        }
+        let number = field.number.value;
+        if number == 0 {
+            self.report(
+                &field.number.metadata,
+                "Field numbers must be positive integers".to_string(),
+            );
+        } else if number > MAX_FIELD_NUMBER as u64 {
+            self.report(
+                &field.number.metadata,
+                format!("Field numbers cannot be greater than {}", MAX_FIELD_NUMBER),
+            );
+        } else if IMPLEMENTATION_RESERVED_RANGE.contains(&number) {
+            self.report(
+                &field.number.metadata,
+                format!(
+                    "Field numbers {} through {} are reserved for the protocol buffer library implementation",
+                    IMPLEMENTATION_RESERVED_RANGE.start(),
+                    IMPLEMENTATION_RESERVED_RANGE.end()
+                ),
+            );
+        }
+        if let FieldType::Map(key_type, _) = &field.field_type.value {
+            let is_valid_key = matches!(
+                key_type.value,
+                FieldType::Scalar(
+                    ScalarType::Int32
+                        | ScalarType::Int64
+                        | ScalarType::Uint32
+                        | ScalarType::Uint64
+                        | ScalarType::Sint32
+                        | ScalarType::Sint64
+                        | ScalarType::Fixed32
+                        | ScalarType::Fixed64
+                        | ScalarType::SFixed32
+                        | ScalarType::SFixed64
+                        | ScalarType::Bool
+                        | ScalarType::String
+                )
+            );
+            if !is_valid_key {
+                self.report(
+                    &key_type.metadata,
+                    "Key in map fields cannot be float/double, bytes, enum or message types"
+                        .to_string(),
+                );
+            }
+        }
+    }
+
+    fn validate_enum(&mut self, full_name: &str, enum_payload: &EnumPayload) {
+        let allow_alias = matches!(
+            find_option(&enum_payload.options, "allow_alias").map(|option| &option.value),
+            Some(Constant::Bool(true))
+        );
+        let mut used_numbers: HashMap<i64, &str> = HashMap::new();
+        for (index, element) in enum_payload.values.iter().enumerate() {
+            let ElementType::EnumValue(value) = &element.type_t else {
+                continue;
+            };
+            let number = value.number.value;
+            if i32::try_from(number).is_err() {
+                self.report(
+                    &value.number.metadata,
+                    format!(
+                        "Enum value numbers must be between {} and {}",
+                        i32::MIN,
+                        i32::MAX
+                    ),
+                );
+            }
+            if index == 0 && number != 0 && !self.proto2 {
+                self.report(
+                    &value.number.metadata,
+                    format!(
+                        "The first enum value of \"{}\" must be zero in proto3",
+                        full_name
+                    ),
+                );
+            }
+            if let Some(previous_value) = used_numbers.get(&number) {
+                if !allow_alias {
+                    self.report(
+                        &value.number.metadata,
+                        format!(
+                            "\"{}\" uses the same enum value as \"{}\". If this is intended, set 'option allow_alias = true;' to the enum definition",
+                            element.name, previous_value
+                        ),
+                    );
+                }
+            } else {
+                used_numbers.insert(number, &element.name);
+            }
+            for reserved in &enum_payload.reserved {
+                match reserved {
+                    Reserved::Range(range) if range.value.contains(number) => self.report(
+                        &value.number.metadata,
+                        format!(
+                            "Enum value \"{}\" uses reserved number {}",
+                            element.name, number
+                        ),
+                    ),
+                    Reserved::Name(name) if name.value == element.name => self.report(
+                        &element.metadata,
+                        format!("Enum value \"{}\" is reserved", element.name),
+                    ),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn validation_errors(source: &str) -> Vec<String> {
+        syntax_validation_errors("proto3", source)
+    }
+
+    fn syntax_validation_errors(syntax: &str, source: &str) -> Vec<String> {
+        let source = format!("syntax = \"{}\";\npackage test;\n{}", syntax, source);
+        let file = Parser::new(&source).parse().unwrap();
+        match validate(&file) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| match error {
+                    RsProtocError::ValidationError(message, _) => message,
+                    _ => panic!("Expected a validation error"),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let errors = validation_errors(
+            r#"
+            message Valid {
+                int32 first = 1;
+                map<string, Valid> children = 2;
+                oneof choice {
+                    string name = 3;
+                    Kind kind = 4;
+                }
+                reserved 5 to 10;
+                reserved "removed";
+                enum Kind {
+                    option allow_alias = true;
+                    KIND_UNSPECIFIED = 0;
+                    KIND_DEFAULT = 0;
+                }
+            }
+            "#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_field_numbers() {
+        let errors = validation_errors(
+            r#"
+            message Numbers {
+                int32 a = 1;
+                int32 b = 1;
+                int32 c = 0;
+                int32 d = 536870912;
+                int32 e = 19500;
+                oneof choice {
+                    int32 f = 1;
+                }
+            }
+            "#,
+        );
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+        assert!(errors[0].contains("has already been used"));
+        assert!(errors[1].contains("positive integers"));
+        assert!(errors[2].contains("cannot be greater than"));
+        assert!(errors[3].contains("19000 through 19999"));
+        assert!(errors[4].contains("has already been used"));
+    }
+
+    #[test]
+    fn test_required_label() {
+        let errors = validation_errors(
+            r#"
+            message M {
+                required int32 a = 1;
+            }
+            extend M {
+                required int32 b = 100;
+            }
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "Required fields are not allowed in proto3",
+                "Required fields are not allowed in proto3"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_proto2_rules() {
+        let errors = syntax_validation_errors(
+            "proto2",
+            r#"
+            message M {
+                required int32 a = 1;
+                int32 b = 2;
+                optional int32 c = 3 [default = 5];
+                repeated int32 d = 4 [default = 5];
+                map<string, int32> e = 5;
+                optional int32 foo_bar = 6;
+                optional int32 fooBar = 7;
+                oneof choice {
+                    string f = 8;
+                }
+            }
+            extend M {
+                int32 g = 100;
+            }
+            enum E {
+                E_ONE = 1;
+            }
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "Expected \"required\", \"optional\", or \"repeated\"",
+                "Repeated fields can't have default values",
+                "Expected \"required\", \"optional\", or \"repeated\"",
+            ]
+        );
+        let errors = validation_errors("message M { optional int32 a = 1 [default = 5]; }");
+        assert_eq!(
+            errors,
+            ["Explicit default values are not allowed in proto3"]
+        );
+    }
+
+    #[test]
+    fn test_reserved() {
+        let errors = validation_errors(
+            r#"
+            message Reservations {
+                reserved 2, 10 to max;
+                reserved "old";
+                int32 old = 1;
+                int32 b = 2;
+                int32 c = 100;
+            }
+            enum Status {
+                reserved 1;
+                reserved "STATUS_OLD";
+                STATUS_UNSPECIFIED = 0;
+                STATUS_OLD = 1;
+            }
+            "#,
+        );
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+        assert!(errors[0].contains("Field name \"old\" is reserved"));
+        assert!(errors[1].contains("uses reserved number 2"));
+        assert!(errors[2].contains("uses reserved number 100"));
+        assert!(errors[3].contains("uses reserved number 1"));
+        assert!(errors[4].contains("\"STATUS_OLD\" is reserved"));
+    }
+
+    #[test]
+    fn test_negative_reserved() {
+        let errors = validation_errors("message Negative { reserved -5 to -1, 0, 3; }");
+        assert_eq!(
+            errors,
+            [
+                "Reserved numbers must be positive integers",
+                "Reserved numbers must be positive integers"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_enums() {
+        let errors = validation_errors(
+            r#"
+            enum NonZero {
+                NON_ZERO_FIRST = 1;
+                NON_ZERO_SECOND = 1;
+            }
+            "#,
+        );
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].contains("must be zero"));
+        assert!(errors[1].contains("allow_alias"));
+
+        let errors = validation_errors("enum Big { ZERO = 0; BIG = 2147483648; }");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("must be between -2147483648 and 2147483647"));
+    }
+
+    #[test]
+    fn test_map_keys_and_json_names() {
+        let errors = validation_errors(
+            r#"
+            message Other {}
+            message Maps {
+                map<float, string> by_float = 1;
+                map<bytes, string> by_bytes = 2;
+                map<Other, string> by_message = 3;
+                string foo_bar = 4;
+                string fooBar = 5;
+                string baz = 6 [json_name = "fooBar"];
+                int32 FooBar = 7;
+            }
+            "#,
+        );
+        assert_eq!(errors.len(), 6, "{:?}", errors);
+        assert!(errors[0].starts_with("Key in map fields"));
+        assert!(errors[3].contains("default JSON name of field \"fooBar\""));
+        assert!(errors[4].contains("custom JSON name of field \"baz\""));
+        // Compared lowercased, "FooBar" and "fooBar" conflict
+        assert!(errors[5].contains("default JSON name of field \"FooBar\" (\"FooBar\")"));
+        assert_eq!(json_name("foo_bar_baz"), "fooBarBaz");
+    }
+
+    #[test]
+    fn test_error_metadata() {
+        let source = "syntax = \"proto3\";\nmessage M {\n  int32 a = 0;\n}\n";
+        let file = Parser::new(source).parse().unwrap();
+        let errors = validate(&file).unwrap_err();
+        match &errors[0] {
+            RsProtocError::ValidationError(_, metadata) => {
+                assert!(metadata.to_string().starts_with("3:"))
+            }
+            _ => panic!("Expected a validation error"),
+        }
+    }
+}
@@ -0,0 +1,7 @@
+// This file is @generated by rs-protoc.
+pub mod names {
+    include!("names.rs");
+    pub mod everything {
+        include!("names.everything.rs");
+    }
+}
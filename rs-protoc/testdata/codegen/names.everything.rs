@@ -0,0 +1,44 @@
+// This file is @generated by rs-protoc.
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Bar {
+    pub option: ::core::option::Option<super::Option>,
+    pub choice: ::core::option::Option<super::Foo_>,
+}
+
+impl Bar {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        let mut writer = ::encoding::message::MessageWriter::new();
+        if let Some(value) = &self.option {
+            writer.write_message(1, &value.encode()?)?;
+        }
+        if let Some(value) = &self.choice {
+            writer.write_message(2, &value.encode()?)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while let Some((field_number, value)) = reader.read_field()? {
+            match field_number {
+                1 => self.option.get_or_insert_with(::core::default::Default::default).merge(value.as_bytes()?)?,
+                2 => self.choice.get_or_insert_with(::core::default::Default::default).merge(value.as_bytes()?)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,668 @@
+// This file is @generated by rs-protoc.
+
+/// Named like the types of the standard library the generated code uses
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Option {
+    pub value: i32,
+}
+
+impl Option {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        let mut writer = ::encoding::message::MessageWriter::new();
+        if self.value != 0 {
+            writer.write_uint64(1, self.value as i64 as u64)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    #[allow(clippy::single_match)]
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while let Some((field_number, value)) = reader.read_field()? {
+            match field_number {
+                1 => self.value = value.as_i64()? as i32,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Vec {
+    pub values: ::std::vec::Vec<Option>,
+}
+
+impl Vec {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        let mut writer = ::encoding::message::MessageWriter::new();
+        for value in &self.values {
+            writer.write_message(1, &value.encode()?)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    #[allow(clippy::single_match)]
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while let Some((field_number, value)) = reader.read_field()? {
+            match field_number {
+                1 => self.values.push(Option::decode(value.as_bytes()?)?),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct String {
+    pub value: ::std::string::String,
+}
+
+impl String {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        let mut writer = ::encoding::message::MessageWriter::new();
+        if !self.value.is_empty() {
+            writer.write_string(1, &self.value)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    #[allow(clippy::single_match)]
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while let Some((field_number, value)) = reader.read_field()? {
+            match field_number {
+                1 => self.value = ::std::borrow::ToOwned::to_owned(value.as_str()?),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Box {
+    pub next: ::core::option::Option<::std::boxed::Box<Box>>,
+}
+
+impl Box {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        let mut writer = ::encoding::message::MessageWriter::new();
+        if let Some(value) = &self.next {
+            writer.write_message(1, &value.encode()?)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    #[allow(clippy::single_match)]
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while let Some((field_number, value)) = reader.read_field()? {
+            match field_number {
+                1 => self.next.get_or_insert_with(::core::default::Default::default).merge(value.as_bytes()?)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Result {
+}
+
+impl Result {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        Ok(::encoding::message::MessageWriter::new())
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while reader.read_field()?.is_some() {}
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Some {
+}
+
+impl Some {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        Ok(::encoding::message::MessageWriter::new())
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while reader.read_field()?.is_some() {}
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Default {
+}
+
+impl Default {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        Ok(::encoding::message::MessageWriter::new())
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while reader.read_field()?.is_some() {}
+        Ok(())
+    }
+}
+
+/// `Foo` and `foo` are both `Foo` as a type
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Foo {
+    /// Holds values of `foo::Kind`
+    pub kind: i32,
+}
+
+impl Foo {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        let mut writer = ::encoding::message::MessageWriter::new();
+        if self.kind != 0 {
+            writer.write_uint64(1, self.kind as i64 as u64)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    #[allow(clippy::single_match)]
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while let Some((field_number, value)) = reader.read_field()? {
+            match field_number {
+                1 => self.kind = value.as_i64()? as i32,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Nested messages, enums and oneofs of `Foo`
+pub mod foo {
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+    #[repr(i32)]
+    pub enum Kind {
+        #[default]
+        Unknown = 0,
+        A = 1,
+        A_ = 2,
+    }
+
+    impl Kind {
+        /// The value with the given number, if there is one
+        pub fn from_i32(value: i32) -> ::core::option::Option<Self> {
+            match value {
+                0 => Some(Self::Unknown),
+                1 => Some(Self::A),
+                2 => Some(Self::A_),
+                _ => None,
+            }
+        }
+
+        /// The name of the value in the .proto file
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::Unknown => "KIND_UNKNOWN",
+                Self::A => "KIND_A",
+                Self::A_ => "A",
+            }
+        }
+    }
+}
+
+/// The oneof `Choice` takes the field name `choice` after the field of the same name
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Foo_ {
+    pub choice: i32,
+    pub choice_: ::core::option::Option<foo_::Choice>,
+}
+
+impl Foo_ {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        let mut writer = ::encoding::message::MessageWriter::new();
+        if self.choice != 0 {
+            writer.write_uint64(1, self.choice as i64 as u64)?;
+        }
+        if let Some(foo_::Choice::A(value)) = &self.choice_ {
+            writer.write_string(2, value)?;
+        }
+        if let Some(foo_::Choice::B(value)) = &self.choice_ {
+            writer.write_uint64(3, *value as i64 as u64)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while let Some((field_number, value)) = reader.read_field()? {
+            match field_number {
+                1 => self.choice = value.as_i64()? as i32,
+                2 => self.choice_ = Some(foo_::Choice::A(::std::borrow::ToOwned::to_owned(value.as_str()?))),
+                3 => self.choice_ = Some(foo_::Choice::B(value.as_i64()? as i32)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Nested messages, enums and oneofs of `Foo_`
+pub mod foo_ {
+    #[derive(Clone, PartialEq, Debug)]
+    pub enum Choice {
+        A(::std::string::String),
+        B(i32),
+    }
+}
+
+/// The module of the nested enum would shadow the primitive type
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct I32 {
+    /// Holds values of `i32_::E`
+    pub e: i32,
+    pub value: i32,
+}
+
+impl I32 {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        let mut writer = ::encoding::message::MessageWriter::new();
+        if self.e != 0 {
+            writer.write_uint64(1, self.e as i64 as u64)?;
+        }
+        if self.value != 0 {
+            writer.write_uint64(2, self.value as i64 as u64)?;
+        }
+        Ok(writer)
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while let Some((field_number, value)) = reader.read_field()? {
+            match field_number {
+                1 => self.e = value.as_i64()? as i32,
+                2 => self.value = value.as_i64()? as i32,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Nested messages, enums and oneofs of `I32`
+pub mod i32_ {
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+    #[repr(i32)]
+    pub enum E {
+        #[default]
+        Unknown = 0,
+    }
+
+    impl E {
+        /// The value with the given number, if there is one
+        pub fn from_i32(value: i32) -> ::core::option::Option<Self> {
+            match value {
+                0 => Some(Self::Unknown),
+                _ => None,
+            }
+        }
+
+        /// The name of the value in the .proto file
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::Unknown => "E_UNKNOWN",
+            }
+        }
+    }
+}
+
+/// The package `names.everything` takes the module of the oneof
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Everything {
+    pub double_value: f64,
+    pub float_value: f32,
+    pub int32_value: i32,
+    pub int64_value: i64,
+    pub uint32_value: u32,
+    pub uint64_value: u64,
+    pub sint32_value: i32,
+    pub sint64_value: i64,
+    pub fixed32_value: u32,
+    pub fixed64_value: u64,
+    pub sfixed32_value: i32,
+    pub sfixed64_value: i64,
+    pub bool_value: bool,
+    pub string_value: ::std::string::String,
+    pub bytes_value: ::std::vec::Vec<u8>,
+    /// Holds values of `foo::Kind`
+    pub kind: i32,
+    pub optional_value: ::core::option::Option<i32>,
+    pub option: ::core::option::Option<Option>,
+    pub packed: ::std::vec::Vec<i32>,
+    pub unpacked: ::std::vec::Vec<f64>,
+    pub strings: ::std::vec::Vec<::std::string::String>,
+    pub options: ::std::collections::HashMap<::std::string::String, Option>,
+    pub kinds: ::std::collections::HashMap<i32, i32>,
+    pub choice: ::core::option::Option<everything_::Choice>,
+}
+
+impl Everything {
+    /// The fields that are set, in field number order
+    pub fn encode(&self) -> ::encoding::Result<::encoding::message::MessageWriter> {
+        let mut writer = ::encoding::message::MessageWriter::new();
+        if f64::to_bits(self.double_value) != 0 {
+            writer.write_fixed64(1, f64::to_bits(self.double_value))?;
+        }
+        if f32::to_bits(self.float_value) != 0 {
+            writer.write_fixed32(2, f32::to_bits(self.float_value))?;
+        }
+        if self.int32_value != 0 {
+            writer.write_uint64(3, self.int32_value as i64 as u64)?;
+        }
+        if self.int64_value != 0 {
+            writer.write_uint64(4, self.int64_value as u64)?;
+        }
+        if self.uint32_value != 0 {
+            writer.write_uint64(5, self.uint32_value as u64)?;
+        }
+        if self.uint64_value != 0 {
+            writer.write_uint64(6, self.uint64_value)?;
+        }
+        if self.sint32_value != 0 {
+            writer.write_sint64(7, self.sint32_value as i64)?;
+        }
+        if self.sint64_value != 0 {
+            writer.write_sint64(8, self.sint64_value)?;
+        }
+        if self.fixed32_value != 0 {
+            writer.write_fixed32(9, self.fixed32_value)?;
+        }
+        if self.fixed64_value != 0 {
+            writer.write_fixed64(10, self.fixed64_value)?;
+        }
+        if self.sfixed32_value != 0 {
+            writer.write_fixed32(11, self.sfixed32_value as u32)?;
+        }
+        if self.sfixed64_value != 0 {
+            writer.write_fixed64(12, self.sfixed64_value as u64)?;
+        }
+        if self.bool_value {
+            writer.write_uint64(13, self.bool_value as u64)?;
+        }
+        if !self.string_value.is_empty() {
+            writer.write_string(14, &self.string_value)?;
+        }
+        if !self.bytes_value.is_empty() {
+            writer.write_bytes(15, &self.bytes_value)?;
+        }
+        if self.kind != 0 {
+            writer.write_uint64(16, self.kind as i64 as u64)?;
+        }
+        if let Some(value) = &self.optional_value {
+            writer.write_uint64(17, *value as i64 as u64)?;
+        }
+        if let Some(value) = &self.option {
+            writer.write_message(18, &value.encode()?)?;
+        }
+        if !self.packed.is_empty() {
+            let mut elements = ::encoding::message::MessageWriter::new();
+            for value in &self.packed {
+                elements.push_sint64(*value as i64)?;
+            }
+            writer.write_message(19, &elements)?;
+        }
+        for value in &self.unpacked {
+            writer.write_fixed64(20, f64::to_bits(*value))?;
+        }
+        for value in &self.strings {
+            writer.write_string(21, value)?;
+        }
+        for (key, value) in &self.options {
+            let mut entry = ::encoding::message::MessageWriter::new();
+            entry.write_string(1, key)?;
+            entry.write_message(2, &value.encode()?)?;
+            writer.write_message(22, &entry)?;
+        }
+        for (key, value) in &self.kinds {
+            let mut entry = ::encoding::message::MessageWriter::new();
+            entry.write_uint64(1, *key as i64 as u64)?;
+            entry.write_uint64(2, *value as i64 as u64)?;
+            writer.write_message(23, &entry)?;
+        }
+        if let Some(everything_::Choice::Recursive(value)) = &self.choice {
+            writer.write_message(24, &value.encode()?)?;
+        }
+        if let Some(everything_::Choice::Number(value)) = &self.choice {
+            writer.write_fixed64(25, f64::to_bits(*value))?;
+        }
+        Ok(writer)
+    }
+
+    pub fn encode_to_vec(&self) -> ::encoding::Result<::std::vec::Vec<u8>> {
+        Ok(self.encode()?.into_bytes())
+    }
+
+    pub fn decode(bytes: &[u8]) -> ::encoding::Result<Self> {
+        let mut message = <Self as ::core::default::Default>::default();
+        message.merge(bytes)?;
+        Ok(message)
+    }
+
+    /// Merge the fields of an encoded message into `self`, unknown fields are skipped
+    pub fn merge(&mut self, bytes: &[u8]) -> ::encoding::Result<()> {
+        let mut reader = ::encoding::message::MessageReader::new(bytes);
+        while let Some((field_number, value)) = reader.read_field()? {
+            match field_number {
+                1 => self.double_value = f64::from_bits(value.as_fixed64()?),
+                2 => self.float_value = f32::from_bits(value.as_fixed32()?),
+                3 => self.int32_value = value.as_i64()? as i32,
+                4 => self.int64_value = value.as_i64()?,
+                5 => self.uint32_value = value.as_u64()? as u32,
+                6 => self.uint64_value = value.as_u64()?,
+                7 => self.sint32_value = value.as_sint64()? as i32,
+                8 => self.sint64_value = value.as_sint64()?,
+                9 => self.fixed32_value = value.as_fixed32()?,
+                10 => self.fixed64_value = value.as_fixed64()?,
+                11 => self.sfixed32_value = value.as_fixed32()? as i32,
+                12 => self.sfixed64_value = value.as_fixed64()? as i64,
+                13 => self.bool_value = value.as_bool()?,
+                14 => self.string_value = ::std::borrow::ToOwned::to_owned(value.as_str()?),
+                15 => self.bytes_value = value.as_bytes()?.to_vec(),
+                16 => self.kind = value.as_i64()? as i32,
+                17 => self.optional_value = Some(value.as_i64()? as i32),
+                18 => self.option.get_or_insert_with(::core::default::Default::default).merge(value.as_bytes()?)?,
+                19 => match value {
+                    ::encoding::message::FieldValue::Len(bytes) => {
+                        let mut elements = ::encoding::message::MessageReader::new(bytes);
+                        while !elements.is_empty() {
+                            let value = elements.read_value(::encoding::wire_types::WireTypeEnum::Varint)?;
+                            self.packed.push(value.as_sint64()? as i32);
+                        }
+                    }
+                    value => self.packed.push(value.as_sint64()? as i32),
+                },
+                20 => match value {
+                    ::encoding::message::FieldValue::Len(bytes) => {
+                        let mut elements = ::encoding::message::MessageReader::new(bytes);
+                        while !elements.is_empty() {
+                            let value = elements.read_value(::encoding::wire_types::WireTypeEnum::I64)?;
+                            self.unpacked.push(f64::from_bits(value.as_fixed64()?));
+                        }
+                    }
+                    value => self.unpacked.push(f64::from_bits(value.as_fixed64()?)),
+                },
+                21 => self.strings.push(::std::borrow::ToOwned::to_owned(value.as_str()?)),
+                22 => {
+                    let mut key: ::std::string::String = ::core::default::Default::default();
+                    let mut entry_value: Option = ::core::default::Default::default();
+                    let mut entry = ::encoding::message::MessageReader::new(value.as_bytes()?);
+                    while let Some((field_number, value)) = entry.read_field()? {
+                        match field_number {
+                            1 => key = ::std::borrow::ToOwned::to_owned(value.as_str()?),
+                            2 => entry_value.merge(value.as_bytes()?)?,
+                            _ => {}
+                        }
+                    }
+                    self.options.insert(key, entry_value);
+                }
+                23 => {
+                    let mut key: i32 = ::core::default::Default::default();
+                    let mut entry_value: i32 = ::core::default::Default::default();
+                    let mut entry = ::encoding::message::MessageReader::new(value.as_bytes()?);
+                    while let Some((field_number, value)) = entry.read_field()? {
+                        match field_number {
+                            1 => key = value.as_i64()? as i32,
+                            2 => entry_value = value.as_i64()? as i32,
+                            _ => {}
+                        }
+                    }
+                    self.kinds.insert(key, entry_value);
+                }
+                24 => match &mut self.choice {
+                    Some(everything_::Choice::Recursive(target)) => target.merge(value.as_bytes()?)?,
+                    _ => self.choice = Some(everything_::Choice::Recursive(::std::boxed::Box::new(Everything::decode(value.as_bytes()?)?))),
+                },
+                25 => self.choice = Some(everything_::Choice::Number(f64::from_bits(value.as_fixed64()?))),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Nested messages, enums and oneofs of `Everything`
+pub mod everything_ {
+    #[derive(Clone, PartialEq, Debug)]
+    pub enum Choice {
+        Recursive(::std::boxed::Box<super::Everything>),
+        Number(f64),
+    }
+}